@@ -2,24 +2,34 @@ mod setup_config;
 
 use anyhow::{anyhow, bail, Context};
 use clap::Parser;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::prelude::*;
 use tracing::level_filters::LevelFilter;
 use kvm_compose_lib::server_web_client::client;
-use kvm_compose_schemas::cli_models::{Opts, SubCommand};
-use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt_image_download::OnlineCloudImage;
+use kvm_compose_schemas::cli_models::{Opts, PlanCmd, PlanOutputFormat, SubCommand};
+use kvm_compose_schemas::kvm_compose_yaml::Config;
+use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt_image_download::CloudImageCatalog;
 use reqwest::Client;
 use crate::setup_config::setup_config;
 
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
 async fn main() {
-    std::process::exit(match run_app().await {
+    let result = run_app().await;
+    if let Err(err) = &result {
+        tracing::error!("ERROR: {}", err);
+        err.chain().skip(1).for_each(|cause| tracing::error!("because: {}", cause));
+    }
+    std::process::exit(exit_code(&result));
+}
+
+/// A failed command exits non-zero so that commands such as `verify` can be used in scripts and CI
+fn exit_code(result: &anyhow::Result<()>) -> i32 {
+    match result {
         Ok(_) => 0,
-        Err(err) => {
-            tracing::error!("{:#}", err);
-            1
-        }
-    });
+        Err(_) => 1,
+    }
 }
 
 fn log_level(s: &str) -> anyhow::Result<LevelFilter> {
@@ -52,19 +62,27 @@ pub async fn run_app() -> Result<(), anyhow::Error> {
     };
     e.map(|e| tracing::warn!("{}", e));
 
-    let stdout_log = tracing_subscriber::fmt::layer();
+    let writer = log_writer(&opts.sub_command, std::io::stdout);
+    let stdout_log = tracing_subscriber::fmt::layer().with_writer(writer);
     tracing_subscriber::registry()
         .with(stdout_log.with_filter(level))
         .init();
 
-    match parse_command(opts).await {
-        Ok(_) => {}
-        Err(err) => {
-            tracing::error!("ERROR: {}", err);
-            err.chain().skip(1).for_each(|cause| tracing::error!("because: {}", cause));
-        }
+    parse_command(opts).await
+}
+
+/// Live captures and JSON output are written to stdout so they can be piped into other tools, so
+/// the logs for these commands go to stderr rather than being mixed into the output
+fn log_writer<W>(sub_command: &SubCommand, stdout: W) -> BoxMakeWriter
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    match sub_command {
+        SubCommand::Capture(_)
+        | SubCommand::Schema
+        | SubCommand::Plan(PlanCmd { output: PlanOutputFormat::Json }) => BoxMakeWriter::new(std::io::stderr),
+        _ => BoxMakeWriter::new(stdout),
     }
-    Ok(())
 }
 
 /// This is the entrypoint for all commands
@@ -72,7 +90,14 @@ pub async fn parse_command(opts: Opts) -> anyhow::Result<()> {
     // first check if it was a command that does not need the server
     match &opts.sub_command {
         SubCommand::CloudImages => {
-            OnlineCloudImage::print_image_list();
+            let catalog = CloudImageCatalog::load(Some(&std::env::current_dir()?)).await
+                .context("loading the cloud image catalog")?;
+            catalog.print_image_list();
+            return Ok(());
+        }
+        SubCommand::Schema => {
+            // print directly to stdout so that the schema can be redirected to a file
+            println!("{}", serde_json::to_string_pretty(&Config::json_schema())?);
             return Ok(());
         }
         SubCommand::SetupConfig => {
@@ -101,12 +126,84 @@ pub async fn parse_command(opts: Opts) -> anyhow::Result<()> {
         SubCommand::Up(_) => client::orchestration_action(&client, opts).await,
         SubCommand::Down => client::orchestration_action(&client, opts).await,
         SubCommand::Snapshot(_) => client::orchestration_action(&client, opts).await,
-        SubCommand::AnalysisTools(_) => unimplemented!(), // unimplemented while we are reworking tcpdump
+        SubCommand::AnalysisTools(_) => client::orchestration_action(&client, opts).await,
         SubCommand::TestbedSnapshot(_) => client::orchestration_action(&client, opts).await,
         SubCommand::Exec(_) => client::orchestration_action(&client, opts).await,
+        SubCommand::Net(_) => client::orchestration_action(&client, opts).await,
+        SubCommand::Trace(_) => client::orchestration_action(&client, opts).await,
+        SubCommand::Verify(_) => client::orchestration_action(&client, opts).await,
+        SubCommand::Capture(capture_cmd) => client::capture_action(&client, &opts, capture_cmd).await,
+        SubCommand::Plan(plan_cmd) => client::plan_action(&client, &opts, plan_cmd).await,
+        SubCommand::Validate => client::validate_action(&client, &opts).await,
+        SubCommand::Images(images_cmd) => client::images_action(&client, &opts, images_cmd).await,
         _ => bail!("command not matched, please raise an issue"),
     };
     sub_command
         .context("running CLI command")?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use kvm_compose_lib::net::verify::{check_probe_results, Probe, ProbeExpectation};
+    use kvm_compose_lib::state::plan::Plan;
+    use super::*;
+
+    /// Stands in for stdout so the test can read back what would have been printed
+    #[derive(Clone, Default)]
+    struct Stdout(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Stdout {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Log like a command would, then print the document to the same stdout
+    fn run_with_logs(args: &[&str], document: &str) -> String {
+        let opts = Opts::try_parse_from(args).unwrap();
+        let stdout = Stdout::default();
+        let writer = stdout.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_subscriber::fmt::layer().with_writer(log_writer(&opts.sub_command, move || writer.clone())));
+        tracing::subscriber::with_default(subscriber, || tracing::info!("comparing against the stored state"));
+        writeln!(stdout.clone(), "{document}").unwrap();
+        let output = stdout.0.lock().unwrap().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_json_output_parses_cleanly() {
+        let plan = serde_json::to_string_pretty(&Plan::default()).unwrap();
+        let output = run_with_logs(&["kvm-compose", "plan", "--output", "json"], &plan);
+        assert!(serde_json::from_str::<serde_json::Value>(&output).is_ok());
+
+        let schema = serde_json::to_string_pretty(&Config::json_schema()).unwrap();
+        let output = run_with_logs(&["kvm-compose", "schema"], &schema);
+        assert!(serde_json::from_str::<serde_json::Value>(&output).is_ok());
+
+        // the text plan is logged, so the logs stay on stdout
+        let output = run_with_logs(&["kvm-compose", "plan"], "");
+        assert!(output.contains("comparing against the stored state"));
+    }
+
+    #[test]
+    fn test_verify_mismatch_exit_code() {
+        let probe = Probe {
+            source: "client".to_string(),
+            destination: "web".to_string(),
+            interface: 0,
+            port: None,
+            expected: Some(ProbeExpectation { reachable: false, reason: "expectations[0]".to_string() }),
+        };
+        assert_eq!(exit_code(&check_probe_results(&[(probe.clone(), false)], 0)), 0);
+        let mismatch = check_probe_results(&[(probe, true)], 0).context("running CLI command");
+        assert_eq!(exit_code(&mismatch), 1);
+    }
+}
@@ -0,0 +1,24 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Capture the traffic of a guest interface on a running deployment
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct CaptureCmd {
+    #[clap(long, action, help = "Stream the capture as pcapng to stdout, such as into wireshark -k -i -")]
+    pub live: bool,
+    #[clap(index = 1)]
+    pub guest_name: String,
+    #[clap(long, default_value_t = 0, help = "Index of the interface in the guest's network definition")]
+    pub interface: usize,
+    #[clap(long, help = "Capture filter, such as \"tcp port 80\"")]
+    pub filter: Option<String>,
+}
+
+/// Sent by the client as the first message on the capture websocket to start a live capture
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct LiveCaptureRequest {
+    pub project_name: String,
+    pub capture: CaptureCmd,
+}
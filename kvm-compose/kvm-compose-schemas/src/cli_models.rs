@@ -1,7 +1,10 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use crate::exec::ExecCmd;
+use crate::net::NetCmd;
+use crate::capture::CaptureCmd;
+use crate::trace::TraceCmd;
 use nix::unistd::{Gid, Uid};
 use crate::kvm_compose_yaml::Config;
 use crate::settings::TestbedClusterConfig;
@@ -12,6 +15,8 @@ use crate::TESTBED_SETTINGS_FOLDER;
 pub struct Opts {
     #[arg(long, default_value = "kvm-compose.yaml", help = "Configuration file")]
     pub input: String,
+    #[arg(long, help = "File of KEY=VALUE variables to interpolate into the configuration file, environment variables take precedence")]
+    pub var_file: Option<String>,
     #[arg(long, help = "Defaults to the current folder name")]
     pub project_name: Option<String>,
     #[arg(short, long)]
@@ -52,6 +57,22 @@ pub enum SubCommand {
     TestbedSnapshot(TestbedSnapshotCmd),
     #[command(about = "Execute a command against a guest")]
     Exec(ExecCmd),
+    #[command(about = "Show the changes that up would make to the deployment, without making them")]
+    Plan(PlanCmd),
+    #[command(about = "Validate the kvm-compose.yaml, listing every error found")]
+    Validate,
+    #[command(about = "Print the JSON Schema of the kvm-compose.yaml, for use in editors")]
+    Schema,
+    #[command(about = "Manage the images stored on the testbed")]
+    Images(ImagesCmd),
+    #[command(about = "Change the network of a running deployment")]
+    Net(NetCmd),
+    #[command(about = "Capture the traffic of a guest on a running deployment")]
+    Capture(CaptureCmd),
+    #[command(about = "Explain the path of a packet between guests with ovn-trace")]
+    Trace(TraceCmd),
+    #[command(about = "Probe the reachability between guests and compare it against the expectations and policies")]
+    Verify(VerifyCmd),
 }
 
 impl SubCommand {
@@ -68,6 +89,14 @@ impl SubCommand {
             SubCommand::AnalysisTools(_) => "analysis tools".into(),
             SubCommand::TestbedSnapshot(_) => "testbed snapshot".into(),
             SubCommand::Exec(_) => "exec".into(),
+            SubCommand::Plan(_) => "plan".into(),
+            SubCommand::Validate => "validate".into(),
+            SubCommand::Schema => "schema".into(),
+            SubCommand::Images(_) => "images".into(),
+            SubCommand::Net(_) => "net".into(),
+            SubCommand::Capture(_) => "capture".into(),
+            SubCommand::Trace(_) => "trace".into(),
+            SubCommand::Verify(_) => "verify".into(),
         }
     }
 }
@@ -115,6 +144,33 @@ pub struct UpCmd {
     pub rerun_scripts: bool,
     #[clap(long, short='a', action, conflicts_with_all = &["provision", "rerun_scripts"], help = "Reapply ACL section of the yaml, removing old rules")]
     pub reapply_acl: bool,
+    #[clap(long, action, help = "Refuse to deploy if the inputs differ from kvm-compose.lock")]
+    #[serde(default)]
+    pub locked: bool,
+}
+
+/// Plan command to compare the kvm-compose.yaml against the stored state of the deployment
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct PlanCmd {
+    #[clap(long, value_enum, default_value = "text", help = "Output format of the plan")]
+    pub output: PlanOutputFormat,
+}
+
+/// Verify command to probe the reachability between the guests of a running deployment
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct VerifyCmd {
+    #[clap(long, action, help = "Only probe the guests that have an expectation or policy, rather than every pair of guests")]
+    pub expected_only: bool,
+}
+
+/// The format to print the plan in
+#[derive(ValueEnum, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanOutputFormat {
+    Text,
+    Json,
 }
 
 /// Snapshot testbed command to provide the minimal required artefacts for sharing and reproducing
@@ -253,6 +309,46 @@ pub enum DeploymentSubCommand {
     ResetState(DeploymentName),
 }
 
+/// Images sub command to manage the image store and deployment artefact images
+#[derive(Parser, Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct ImagesCmd {
+    #[command(subcommand)]
+    pub sub_command: ImagesSubCommand,
+}
+
+/// Images sub command to manage the image store and deployment artefact images
+#[derive(Parser, Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ImagesSubCommand {
+    /// List the images and the deployments that reference them
+    List,
+    /// Copy an image into the image store and add it to the testbed cloud image catalog
+    Import {
+        /// The image file to import
+        file: String,
+        /// The name in the catalog, defaults to the file name without the extension
+        #[arg(long)]
+        name: Option<String>,
+        /// The libosinfo short id of the operating system i.e. ubuntujammy
+        #[arg(long)]
+        os_variant: Option<String>,
+    },
+    /// Remove the images that are not referenced by any deployment
+    Prune {
+        /// Only list the images that would be removed
+        #[arg(long)]
+        dry_run: bool,
+        /// Also remove unreferenced images in deployment artefact folders
+        #[arg(long)]
+        include_artefacts: bool,
+    },
+    /// Remove an unreferenced image from the image store
+    Rm {
+        name: String,
+    },
+}
+
 /// This is the name of the deployment that is passed to the deployment commands
 #[derive(Parser, Deserialize, Serialize, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
@@ -279,12 +375,23 @@ impl AnalysisToolsCmd {
 #[serde(rename_all = "snake_case")]
 pub enum AnalysisToolsSubCmd {
     // #[clap(trailing_var_arg=true)] // TODO this doesnt seem to remove the need for -- in cli args
+    /// Capture packets on a guest's interface or an OVS port with tcpdump
     TcpDump {
-        /// Specify either an OVS port or a guest interface
+        /// Specify a guest, a guest interface or an OVS port
         port_or_iface: String,
-        /// Specify the name of the output file for the capture
+        /// Specify the name of the output file for the capture, relative to the project folder
         output_file: String,
-        // /// Arguments to pass through to tcpdump such as filters, don't pass -w or -i
-        // tcpdump_args: Vec<String>,
+        /// Index of the interface in the guest's network definition, if a guest is given
+        #[clap(long, default_value_t = 0)]
+        interface: usize,
+        /// BPF filter for the capture, such as "tcp port 80"
+        #[clap(long)]
+        filter: Option<String>,
+        /// Stop the capture after this many seconds
+        #[clap(long, required_unless_present = "count")]
+        duration: Option<u32>,
+        /// Stop the capture after this many packets
+        #[clap(long)]
+        count: Option<u32>,
     },
 }
@@ -1,10 +1,12 @@
-use crate::cli_models::{AnalysisToolsCmd, SnapshotSubCommand, UpCmd};
+use crate::cli_models::{AnalysisToolsCmd, SnapshotSubCommand, UpCmd, VerifyCmd};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Formatter;
 use chrono::{DateTime, Utc};
 use crate::exec::ExecCmd;
+use crate::net::NetCmd;
+use crate::trace::TraceCmd;
 
 #[derive(Deserialize, Serialize, Debug, Default, Clone)]
 #[serde(rename_all = "snake_case")]
@@ -99,6 +101,17 @@ pub enum DeploymentCommand {
     AnalysisTool(AnalysisToolsCmd),
     Exec(ExecCmd),
     ListCloudImages,
+    Net(NetCmd),
+    Trace(TraceCmd),
+    Verify(VerifyCmd),
+}
+
+impl DeploymentCommand {
+    /// True if the command only inspects the testbed, a failure of these leaves the deployment in
+    /// the state it was in
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, Self::Verify(_) | Self::Trace(_) | Self::Exec(_) | Self::ListCloudImages)
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug)]
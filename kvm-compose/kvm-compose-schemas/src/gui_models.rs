@@ -7,7 +7,10 @@ use crate::cli_models::SubCommand;
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GUICommand {
     pub project_name: String,
-    pub sub_command: SubCommand
+    pub sub_command: SubCommand,
+    /// Var file used to interpolate the kvm-compose.yaml, relative to the project folder
+    #[serde(default)]
+    pub var_file: Option<String>,
 }
 
 impl GUICommand {
@@ -0,0 +1,133 @@
+use std::fmt;
+use std::fmt::Formatter;
+use serde::{Deserialize, Serialize};
+
+/// Where on the testbed host an image is stored
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageLocation {
+    /// The testbed image store, where cloud images are downloaded and imported to
+    Store,
+    /// The artefacts folder of a deployment, where the guest disks are created
+    Artefacts { deployment: String },
+}
+
+/// Something that is using an image, an image with any references must not be removed
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct ImageReference {
+    /// The deployment that uses the image, or `None` if the image is the backing file of another
+    /// image
+    pub deployment: Option<String>,
+    pub reason: String,
+}
+
+/// An image file on the testbed host
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct StoredImage {
+    pub name: String,
+    pub path: String,
+    pub location: ImageLocation,
+    pub size_bytes: u64,
+    pub references: Vec<ImageReference>,
+}
+
+impl StoredImage {
+    pub fn is_referenced(&self) -> bool {
+        !self.references.is_empty()
+    }
+}
+
+/// All the images in the image store and in the deployment artefact folders
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct ImageList {
+    pub images: Vec<StoredImage>,
+}
+
+impl ImageList {
+    pub fn total_bytes(&self) -> u64 {
+        self.images.iter().map(|i| i.size_bytes).sum()
+    }
+
+    /// Get a line per image, followed by a line per reference to the image
+    pub fn pretty_to_string(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for image in &self.images {
+            let location = match &image.location {
+                ImageLocation::Store => "store".to_string(),
+                ImageLocation::Artefacts { deployment } => format!("artefacts of {deployment}"),
+            };
+            lines.push(format!(
+                "{} ({}, {location}, {} references) {}",
+                image.name,
+                human_readable_size(image.size_bytes),
+                image.references.len(),
+                image.path,
+            ));
+            for reference in &image.references {
+                match &reference.deployment {
+                    Some(deployment) => lines.push(format!("    {deployment}: {}", reference.reason)),
+                    None => lines.push(format!("    {}", reference.reason)),
+                }
+            }
+        }
+        lines
+    }
+}
+
+impl fmt::Display for ImageList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&serde_json::to_string_pretty(&self).unwrap())
+            .expect("image list to json via serde failed");
+        Ok(())
+    }
+}
+
+/// Request to copy an image file on the testbed host into the image store and add it to the
+/// testbed cloud image catalog
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct ImportImage {
+    /// Absolute path of the image file on the testbed host
+    pub path: String,
+    /// The name in the catalog, defaults to the file name without the extension
+    pub name: Option<String>,
+    /// The libosinfo short id of the operating system i.e. `ubuntujammy`
+    pub os_variant: Option<String>,
+}
+
+/// Request to remove the images that are not referenced by any deployment
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct PruneImages {
+    /// Only report what would be removed
+    pub dry_run: bool,
+    /// Also remove unreferenced images in the deployment artefact folders, not just the store
+    pub include_artefacts: bool,
+}
+
+/// The images removed, or that would be removed in a dry run
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "snake_case")]
+pub struct PruneResult {
+    pub removed: Vec<StoredImage>,
+    pub freed_bytes: u64,
+}
+
+/// Format a number of bytes with a binary unit i.e. `1.5 GiB`
+pub fn human_readable_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
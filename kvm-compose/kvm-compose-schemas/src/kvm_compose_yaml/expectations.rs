@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+/// The reachability between guests that `kvm-compose verify` checks on the running deployment
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct Expectation {
+    /// The guest or clone group that the probe is sent from
+    pub from: String,
+    /// The guest or clone group that is probed
+    pub to: String,
+    /// Only probe this interface of the `to` guests, default is every interface
+    pub interface: Option<usize>,
+    /// The protocol to probe with, default is a ping
+    #[serde(default)]
+    pub proto: ExpectationProtocol,
+    /// The ports to connect to, only for tcp
+    pub ports: Option<Vec<u16>>,
+    pub reachable: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExpectationProtocol {
+    #[default]
+    Icmp,
+    Tcp,
+}
@@ -1,10 +1,11 @@
 use std::collections::HashMap;
 use std::path::PathBuf;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use validator::Validate;
 use crate::kvm_compose_yaml::machines::ConfigScalingInterface;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ConfigAVDMachine {
     // while this is not the ip of the android device, its the ip of the veth in the namespace
     pub static_ip: Option<String>,
@@ -15,7 +16,7 @@ pub struct ConfigAVDMachine {
     pub scaling: Option<AndroidScaling>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum AVDGuestOptions {
     Avd {
@@ -32,7 +33,7 @@ pub enum AVDGuestOptions {
 //     Android28,
 // }
 
-#[derive(Deserialize, Serialize, Debug, Validate, Clone)]
+#[derive(Deserialize, Serialize, Debug, Validate, Clone, JsonSchema)]
 pub struct AndroidScaling {
     #[validate(range(min = 1))]
     pub count: u32,
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use std::collections::{BTreeMap, HashMap};
 use validator::Validate;
 use crate::kvm_compose_yaml::machines::ConfigScalingInterface;
@@ -13,7 +14,7 @@ use crate::kvm_compose_yaml::machines::ConfigScalingInterface;
 /// one format.
 /// As more arguments are needed, they can be added here and implemented in `create_artefact` for
 /// `DockerGuest`.
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ConfigDockerMachine {
     pub image: String,
     pub command: Option<String>,
@@ -26,6 +27,7 @@ pub struct ConfigDockerMachine {
     pub user: Option<String>,
     pub device: Option<Vec<String>>,
     #[serde(skip_deserializing)]
+    #[schemars(skip)]
     pub hostname: String,
 
     // TODO depends on is a useful feature of docker-compose that users may want here, we should
@@ -36,14 +38,14 @@ pub struct ConfigDockerMachine {
     pub static_ip: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct Volume {
     // TODO - support docker notation for read only ":ro" etc, volume drivers etc
     pub source: String,
     pub target: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Validate, Clone)]
+#[derive(Deserialize, Serialize, Debug, Validate, Clone, JsonSchema)]
 pub struct DockerScaling {
     #[validate(range(min = 1))]
     pub count: u32,
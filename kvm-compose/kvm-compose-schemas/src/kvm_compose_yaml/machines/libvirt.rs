@@ -1,12 +1,12 @@
-use crate::kvm_compose_yaml::machines::libvirt_image_download::OnlineCloudImage;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use validator::Validate;
 use crate::kvm_compose_yaml::machines::ConfigScalingInterface;
 
 /// Contains the shared config for all libvirt guest types
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ConfigLibvirtMachine {
     pub memory_mb: Option<u32>,
     pub cpus: Option<u32>,
@@ -14,8 +14,10 @@ pub struct ConfigLibvirtMachine {
     pub username: Option<String>,
     pub password: Option<String>,
     #[serde(skip_deserializing)]
+    #[schemars(skip)]
     pub hostname: String,
     #[serde(skip_deserializing)]
+    #[schemars(skip)]
     pub ssh_address: String,
     // #[serde(default)]
     // pub extended_graphics_support: bool,
@@ -23,17 +25,19 @@ pub struct ConfigLibvirtMachine {
     // #[serde(skip_deserializing)]
     pub is_clone_of: Option<String>,
     #[serde(skip_deserializing)]
+    #[schemars(skip)]
     pub tcp_tty_port: Option<u32>,
     pub static_ip: Option<String>,
 }
 
 /// This is a further specialisation for libvirt guests, any options that are specific to the guest
 /// type are placed here that are not applicable to the other guest types
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum LibvirtGuestOptions {
     CloudImage {
-        name: OnlineCloudImage,
+        /// The name of an image in the cloud image catalog
+        name: String,
         expand_gigabytes: Option<u16>,
         // #[serde(skip_deserializing)]
         path: Option<PathBuf>,
@@ -71,7 +75,7 @@ pub enum LibvirtGuestOptions {
     },
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum DiskDriverType {
     Raw,
@@ -84,7 +88,7 @@ impl Default for DiskDriverType {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum DiskDeviceType {
     Disk,
@@ -97,7 +101,7 @@ impl Default for DiskDeviceType {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Validate, Clone)]
+#[derive(Deserialize, Serialize, Debug, Validate, Clone, JsonSchema)]
 pub struct ConfigScaling {
     #[validate(range(min = 1))]
     pub count: u32,
@@ -109,19 +113,19 @@ pub struct ConfigScaling {
     // pub clone_static_ip: Option<Vec<ConfigScalingIp>>
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ConfigScalingSetup {
     pub script: PathBuf,
     pub clones: Vec<u32>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ConfigScalingRun {
     pub script: PathBuf,
     pub clones: Vec<u32>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ConfigScalingIp {
     pub clone: u32,
     pub ip: String,
@@ -1,99 +1,464 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::path::PathBuf;
+use anyhow::{bail, Context};
 use futures::StreamExt;
-use strum::IntoEnumIterator;
-use strum_macros::EnumIter;
-
-#[derive(Debug, Deserialize, Serialize, EnumIter, Clone)]
-#[serde(rename_all = "lowercase")]
-#[allow(non_camel_case_types)]
-// Currently supported image types
-pub enum OnlineCloudImage {
-    Ubuntu_18_04,
-    Ubuntu_20_04,
-    Ubuntu_22_04,
-    Cirros_0_6_2,
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::kvm_compose_yaml::Config;
+use crate::kvm_compose_yaml::machines::GuestType;
+use crate::kvm_compose_yaml::machines::libvirt::LibvirtGuestOptions;
+use crate::TESTBED_SETTINGS_FOLDER;
+
+/// The name of the catalog file, both in the testbed settings folder and in the project folder
+pub const CLOUD_IMAGE_CATALOG_FILE: &str = "cloud-images.json";
+
+/// Where to get the cloud image from
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CloudImageSource {
+    /// Download the image, this is only done once and the image is kept in the testbed images folder
+    Url(String),
+    /// Use an image already on the testbed host, relative paths are relative to the catalog file
+    Path(PathBuf),
 }
 
-impl OnlineCloudImage {
-    // For any item within the enum provide a url to fetch the disk image
-    fn get_url(&self) -> &str {
-        match &self {
-            OnlineCloudImage::Ubuntu_18_04 => {
-                "https://cloud-images.ubuntu.com/bionic/20230607/bionic-server-cloudimg-amd64.img"
+/// The type of cloud-init the image uses to receive the guest's configuration
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Default, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CloudInitFlavour {
+    /// The cloud-config user data, for images with cloud-init installed
+    #[default]
+    CloudInit,
+    /// A user data script run by the cirros init, as cirros does not read cloud-config
+    CirrosInit,
+}
+
+/// An entry in the cloud image catalog. The name is what is used for `libvirt_type.cloud_image.name`
+/// in the kvm-compose.yaml.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, JsonSchema)]
+pub struct CloudImage {
+    pub name: String,
+    #[serde(flatten)]
+    pub source: CloudImageSource,
+    /// The expected SHA256 of the image, if given the image will be verified when it is downloaded
+    pub sha256: Option<String>,
+    /// The url of a `SHA256SUMS` file that lists the image, used to verify downloads from a url
+    /// when there is no `sha256`
+    #[serde(default)]
+    pub sha256_url: Option<String>,
+    /// The libosinfo short id of the operating system i.e. `ubuntujammy`
+    pub os_variant: Option<String>,
+    #[serde(default)]
+    pub cloud_init: CloudInitFlavour,
+}
+
+impl CloudImage {
+    /// An image from a release folder that has a `SHA256SUMS` file next to the image
+    fn new(
+        name: &str,
+        release_url: &str,
+        file_name: &str,
+        os_variant: Option<&str>,
+        cloud_init: CloudInitFlavour,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            source: CloudImageSource::Url(format!("{release_url}/{file_name}")),
+            sha256: None,
+            sha256_url: Some(format!("{release_url}/SHA256SUMS")),
+            os_variant: os_variant.map(str::to_string),
+            cloud_init,
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        // the name is used for the file name of the downloaded image
+        if self.name.is_empty() || !self.name.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c)) {
+            bail!("cloud image name '{}' must only contain letters, numbers, '.', '_' or '-'", self.name);
+        }
+        if let Some(sha256) = &self.sha256 {
+            if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+                bail!("cloud image '{}' sha256 is not a valid SHA256 hex digest", self.name);
             }
-            OnlineCloudImage::Ubuntu_20_04 => {
-                "https://cloud-images.ubuntu.com/focal/20240430/focal-server-cloudimg-amd64.img"
+        }
+        Ok(())
+    }
+
+    /// Get where the image is on the testbed host, for images from a url this is where the image is,
+    /// or will be, downloaded to in the storage location
+    pub fn store_path(&self, storage_location: &Path) -> PathBuf {
+        match &self.source {
+            CloudImageSource::Path(path) => path.clone(),
+            CloudImageSource::Url(_) => storage_location.join(format!("{}.img", self.name)),
+        }
+    }
+
+    /// Get the path to the image, downloading it into the storage location if the image comes from
+    /// a url and hasn't been downloaded yet. If the image has a SHA256, the image is verified.
+    pub async fn resolve_path(&self, storage_location: PathBuf) -> anyhow::Result<PathBuf> {
+        let expected = self.sha256.as_ref().map(|s| s.to_lowercase());
+        match &self.source {
+            CloudImageSource::Path(path) => {
+                if !path.is_file() {
+                    bail!("cloud image '{}' file {path:?} does not exist", self.name);
+                }
+                if let Some(expected) = &expected {
+                    let actual = sha256_file(path).await?;
+                    if !actual.eq(expected) {
+                        bail!("cloud image '{}' file {path:?} has SHA256 {actual} but expected {expected}", self.name);
+                    }
+                }
+                Ok(path.clone())
             }
-            OnlineCloudImage::Ubuntu_22_04 => {
-                "https://cloud-images.ubuntu.com/jammy/20240426/jammy-server-cloudimg-amd64.img"
+            CloudImageSource::Url(url) => {
+                let name = self.store_path(&storage_location);
+                let expected = match (expected, &self.sha256_url) {
+                    (Some(expected), _) => Some(expected),
+                    (None, Some(sha256_url)) => match tokio::fs::read_to_string(checksum_marker(&name)).await {
+                        // the image was checked against the published SHA256 when it was downloaded
+                        Ok(recorded) if name.is_file() => Some(recorded.trim().to_string()),
+                        _ => Some(published_sha256(sha256_url, url).await?),
+                    },
+                    (None, None) => None,
+                };
+                if name.is_file() && self.is_verified(&name, expected.as_ref()).await? {
+                    return Ok(name);
+                }
+                if expected.is_none() {
+                    tracing::warn!("cloud image '{}' has no sha256 in the catalog, its integrity cannot be verified", self.name);
+                }
+                download_file(url, &name, expected.as_deref()).await?;
+                Ok(name)
             }
-            OnlineCloudImage::Cirros_0_6_2 => {
-                "https://download.cirros-cloud.net/0.6.2/cirros-0.6.2-x86_64-disk.img"
+        }
+    }
+
+    /// Check an already downloaded image matches the SHA256 in the catalog. The SHA256 of a
+    /// verified image is written next to it so that the image only needs to be hashed once.
+    async fn is_verified(&self, image: &Path, expected: Option<&String>) -> anyhow::Result<bool> {
+        let Some(expected) = expected else {
+            // nothing to check against
+            return Ok(true);
+        };
+        let marker = checksum_marker(image);
+        if let Ok(recorded) = tokio::fs::read_to_string(&marker).await {
+            if recorded.trim().eq(expected) {
+                return Ok(true);
             }
         }
+        let actual = sha256_file(image).await?;
+        if actual.eq(expected) {
+            tokio::fs::write(&marker, &actual).await?;
+            Ok(true)
+        } else {
+            tracing::warn!("cloud image '{}' at {image:?} does not match the catalog SHA256, downloading again", self.name);
+            Ok(false)
+        }
     }
+}
 
-    pub fn get_os_variant(&self) -> String {
-        match &self {
-            OnlineCloudImage::Ubuntu_18_04 => "ubuntubionic".to_string(),
-            OnlineCloudImage::Ubuntu_20_04 => "ubuntufocal".to_string(),
-            OnlineCloudImage::Ubuntu_22_04 => "ubuntujammy".to_string(),
-            OnlineCloudImage::Cirros_0_6_2 => unimplemented!(),
+/// The cloud images that can be used by libvirt guests. The catalog is made from the default
+/// images, which are replaced by the catalog file in the testbed settings folder if it exists,
+/// and then any entries in the project's catalog file are added on top.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, JsonSchema)]
+pub struct CloudImageCatalog {
+    pub images: Vec<CloudImage>,
+}
+
+impl CloudImageCatalog {
+    /// The images available when there is no catalog file in the testbed settings folder
+    pub fn default_images() -> Self {
+        Self {
+            images: vec![
+                CloudImage::new(
+                    "ubuntu_18_04",
+                    "https://cloud-images.ubuntu.com/bionic/20230607",
+                    "bionic-server-cloudimg-amd64.img",
+                    Some("ubuntubionic"),
+                    CloudInitFlavour::CloudInit,
+                ),
+                CloudImage::new(
+                    "ubuntu_20_04",
+                    "https://cloud-images.ubuntu.com/focal/20240430",
+                    "focal-server-cloudimg-amd64.img",
+                    Some("ubuntufocal"),
+                    CloudInitFlavour::CloudInit,
+                ),
+                CloudImage::new(
+                    "ubuntu_22_04",
+                    "https://cloud-images.ubuntu.com/jammy/20240426",
+                    "jammy-server-cloudimg-amd64.img",
+                    Some("ubuntujammy"),
+                    CloudInitFlavour::CloudInit,
+                ),
+                CloudImage::new(
+                    "cirros_0_6_2",
+                    "https://download.cirros-cloud.net/0.6.2",
+                    "cirros-0.6.2-x86_64-disk.img",
+                    None,
+                    CloudInitFlavour::CirrosInit,
+                ),
+            ],
         }
     }
 
-    pub async fn resolve_path(&self, storage_location: PathBuf) -> anyhow::Result<PathBuf> {
-        let mut name = storage_location;
-        name.push(format!("{}.img", serde_plain::to_string(self)?));
-        // TODO - check file integrity in case the download didnt work
-        if !name.is_file() {
-            download_file(self.get_url(), &name).await?;
+    /// Load the catalog for a project, if no project folder is given then only the testbed catalog
+    /// is loaded
+    pub async fn load(project_folder: Option<&Path>) -> anyhow::Result<Self> {
+        let testbed_catalog = PathBuf::from(TESTBED_SETTINGS_FOLDER).join(CLOUD_IMAGE_CATALOG_FILE);
+        let mut catalog = if testbed_catalog.is_file() {
+            Self::read(&testbed_catalog).await?
+        } else {
+            Self::default_images()
+        };
+        if let Some(project_folder) = project_folder {
+            let project_catalog = project_folder.join(CLOUD_IMAGE_CATALOG_FILE);
+            if project_catalog.is_file() {
+                catalog.merge(Self::read(&project_catalog).await?);
+            }
         }
-        Ok(name)
+        Ok(catalog)
     }
 
-    pub fn print_image_list() {
-        tracing::info!("Available cloud images:");
-        for i in Self::iter() {
-            tracing::info!("{}", serde_plain::to_string(&i).unwrap());
+    /// Read a catalog file, relative image paths are made relative to the catalog file
+    pub async fn read(path: &Path) -> anyhow::Result<Self> {
+        let text = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("reading cloud image catalog {path:?}"))?;
+        let mut catalog: Self = serde_json::from_str(&text)
+            .with_context(|| format!("parsing cloud image catalog {path:?}"))?;
+        let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut names = Vec::new();
+        for image in catalog.images.iter_mut() {
+            image.validate().with_context(|| format!("in cloud image catalog {path:?}"))?;
+            if names.contains(&image.name) {
+                bail!("cloud image '{}' is in the catalog {path:?} more than once", image.name);
+            }
+            names.push(image.name.clone());
+            if let CloudImageSource::Path(image_path) = &mut image.source {
+                if image_path.is_relative() {
+                    *image_path = parent.join(&image_path);
+                }
+            }
         }
+        Ok(catalog)
+    }
 
+    /// Add the images from the other catalog, replacing any images with the same name
+    pub fn merge(&mut self, other: Self) {
+        for image in other.images {
+            match self.images.iter_mut().find(|i| i.name.eq(&image.name)) {
+                Some(existing) => *existing = image,
+                None => self.images.push(image),
+            }
+        }
     }
 
-    /// Used to get the cloud images as a string rather than logging direct
-    pub fn pretty_to_string() -> anyhow::Result<Vec<String>> {
-        let mut images = Vec::new();
-        for i in Self::iter() {
-            images.push(serde_plain::to_string(&i)?);
+    pub fn get(&self, name: &str) -> anyhow::Result<&CloudImage> {
+        match self.images.iter().find(|i| i.name.eq(name)) {
+            Some(image) => Ok(image),
+            None => bail!(
+                "cloud image '{name}' is not in the catalog, available images are: {}",
+                self.names().join(", "),
+            ),
         }
-        Ok(images)
     }
 
-    // pub fn get_cloud_init_type(&self) -> CloudInitType {
-    //     match &self {
-    //         OnlineCloudImage::Cirros_0_5_1 => CloudInitType::CirrosInit,
-    //         _ => CloudInitType::CloudInit,
-    //     }
-    // }
+    pub fn names(&self) -> Vec<String> {
+        self.images.iter()
+            .map(|i| i.name.clone())
+            .collect()
+    }
+
+    /// Make sure every cloud image used in the config is in the catalog
+    pub fn check_config(&self, config: &Config) -> anyhow::Result<()> {
+        for machine in config.machines.iter().flatten() {
+            if let GuestType::Libvirt(libvirt) = &machine.guest_type {
+                if let LibvirtGuestOptions::CloudImage { name, .. } = &libvirt.libvirt_type {
+                    self.get(name)
+                        .with_context(|| format!("checking cloud image for guest {}", &machine.name))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a line per image describing the image, used to list the catalog
+    pub fn pretty_to_string(&self) -> Vec<String> {
+        let mut lines: BTreeMap<&String, String> = BTreeMap::new();
+        for image in &self.images {
+            let source = match &image.source {
+                CloudImageSource::Url(url) => url.clone(),
+                CloudImageSource::Path(path) => path.display().to_string(),
+            };
+            let verified = match (&image.sha256, &image.sha256_url) {
+                (Some(_), _) => "sha256 pinned",
+                (None, Some(_)) => "published sha256",
+                (None, None) => "unverified",
+            };
+            lines.insert(&image.name, format!("{} ({verified}) {source}", image.name));
+        }
+        lines.into_values().collect()
+    }
+
+    pub fn print_image_list(&self) {
+        tracing::info!("Available cloud images:");
+        for line in self.pretty_to_string() {
+            tracing::info!("{line}");
+        }
+    }
+}
+
+/// The file next to the image that records the SHA256 of the image once it has been verified
+pub fn checksum_marker(image: &Path) -> PathBuf {
+    let mut marker = image.as_os_str().to_owned();
+    marker.push(".sha256");
+    PathBuf::from(marker)
+}
+
+/// Get the SHA256 hex digest of the file
+pub async fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    let mut file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("opening {path:?} to verify SHA256"))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
 }
 
-pub async fn download_file(url: &str, destination: &Path) -> anyhow::Result<()> {
+/// Get the SHA256 of the image from the `SHA256SUMS` file it is listed in
+async fn published_sha256(sha256_url: &str, image_url: &str) -> anyhow::Result<String> {
+    let file_name = image_url.rsplit('/').next().unwrap_or(image_url);
+    let sums = reqwest::get(sha256_url)
+        .await?
+        .error_for_status()
+        .with_context(|| format!("downloading {sha256_url}"))?
+        .text()
+        .await?;
+    match parse_sha256sums(&sums, file_name) {
+        Some(sha256) => Ok(sha256),
+        None => bail!("{file_name} is not listed in {sha256_url}"),
+    }
+}
+
+/// Find the SHA256 of the file in the output of `sha256sum`, where binary mode files are marked
+/// with a `*` before the name
+fn parse_sha256sums(sums: &str, file_name: &str) -> Option<String> {
+    sums.lines()
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .find(|(_, name)| name.trim_start().trim_start_matches('*').eq(file_name))
+        .map(|(sha256, _)| sha256.to_lowercase())
+}
+
+/// Download the file into a temporary file next to the destination, then move it to the
+/// destination once the download has finished and the SHA256 matches, if one is given. This
+/// means a failed or interrupted download never leaves a broken image at the destination.
+pub async fn download_file(url: &str, destination: &Path, sha256: Option<&str>) -> anyhow::Result<()> {
     tracing::info!("Downloading cloud image {}", url);
 
     // make sure images folder exists
-    let images_folder = Path::new("/var/lib/testbedos/images/");
-    if !images_folder.exists() {
-        tokio::fs::create_dir(images_folder).await?;
-    }
+    let images_folder = destination.parent()
+        .context("getting the folder to download the image into")?;
+    tokio::fs::create_dir_all(images_folder).await?;
 
-    let mut tmp_file = tokio::fs::File::create(destination).await?;
-    let mut byte_stream = reqwest::get(url).await?.bytes_stream();
+    let tmp_path = tempfile::Builder::new()
+        .prefix(".download-")
+        .tempfile_in(images_folder)?
+        .into_temp_path();
+    let mut tmp_file = tokio::fs::File::create(&tmp_path).await?;
+    let mut hasher = Sha256::new();
+    let mut byte_stream = reqwest::get(url)
+        .await?
+        .error_for_status()
+        .with_context(|| format!("downloading {url}"))?
+        .bytes_stream();
 
     while let Some(item) = byte_stream.next().await {
-        tokio::io::copy(&mut item?.as_ref(), &mut tmp_file).await?;
+        let item = item?;
+        hasher.update(&item);
+        tmp_file.write_all(&item).await?;
     }
+    tmp_file.flush().await?;
+    drop(tmp_file);
 
+    let actual = format!("{:x}", hasher.finalize());
+    if let Some(expected) = sha256 {
+        if !actual.eq_ignore_ascii_case(expected) {
+            bail!("downloaded image from {url} has SHA256 {actual} but expected {expected}");
+        }
+    }
+    tmp_path.persist(destination)
+        .with_context(|| format!("moving downloaded image to {destination:?}"))?;
+    if sha256.is_some() {
+        tokio::fs::write(checksum_marker(destination), &actual).await?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_merge_and_get() {
+        let mut catalog = CloudImageCatalog::default_images();
+        let project: CloudImageCatalog = serde_json::from_str(r#"{"images": [
+            {"name": "ubuntu_22_04", "path": "/images/jammy.img", "os_variant": "ubuntujammy"},
+            {"name": "cirros", "path": "/images/cirros.img", "cloud_init": "cirros_init"},
+            {"name": "debian_12", "url": "https://example.com/debian.qcow2", "sha256": "ab"}
+        ]}"#).unwrap();
+        catalog.merge(project);
+        assert_eq!(catalog.images.len(), 6);
+        assert_eq!(catalog.get("ubuntu_22_04").unwrap().source, CloudImageSource::Path("/images/jammy.img".into()));
+        assert_eq!(catalog.get("ubuntu_22_04").unwrap().os_variant.as_deref(), Some("ubuntujammy"));
+        assert_eq!(catalog.get("cirros").unwrap().cloud_init, CloudInitFlavour::CirrosInit);
+        assert_eq!(catalog.get("debian_12").unwrap().sha256_url, None);
+        assert_eq!(catalog.get("debian_12").unwrap().cloud_init, CloudInitFlavour::CloudInit);
+        assert!(catalog.get("debian_12").unwrap().validate().is_err());
+        assert!(catalog.get("fedora").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_local_image_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let image_path = dir.path().join("image.img");
+        tokio::fs::write(&image_path, b"image").await.unwrap();
+        let mut image = CloudImage {
+            name: "local".to_string(),
+            source: CloudImageSource::Path(image_path.clone()),
+            // sha256 of "image"
+            sha256: Some("6105d6cc76af400325e94d588ce511be5bfdbb73b437dc51eca43917d7a43e3d".to_string()),
+            sha256_url: None,
+            os_variant: None,
+            cloud_init: CloudInitFlavour::CloudInit,
+        };
+        assert_eq!(image.resolve_path(dir.path().to_path_buf()).await.unwrap(), image_path);
+        image.sha256 = Some("0".repeat(64));
+        assert!(image.resolve_path(dir.path().to_path_buf()).await.is_err());
+    }
+
+    #[test]
+    fn test_parse_sha256sums() {
+        let sums = "6105d6cc76af400325e94d588ce511be5bfdbb73b437dc51eca43917d7a43e3d *jammy-server-cloudimg-amd64.img\n\
+            2B2E4D43A0FA2BA5CD8B2F8AF6BAD20A34D64E35C4F0B5ACE3AE1DA8A7F8A5E1  cirros-0.6.2-x86_64-disk.img\n";
+        assert_eq!(
+            parse_sha256sums(sums, "jammy-server-cloudimg-amd64.img").unwrap(),
+            "6105d6cc76af400325e94d588ce511be5bfdbb73b437dc51eca43917d7a43e3d"
+        );
+        assert_eq!(
+            parse_sha256sums(sums, "cirros-0.6.2-x86_64-disk.img").unwrap(),
+            "2b2e4d43a0fa2ba5cd8b2f8af6bad20a34d64e35c4f0b5ace3ae1da8a7f8a5e1"
+        );
+        assert_eq!(parse_sha256sums(sums, "jammy-server-cloudimg-amd64.img.manifest"), None);
+    }
+}
@@ -6,9 +6,11 @@ pub mod libvirt_image_download;
 use crate::kvm_compose_yaml::machines::avd::ConfigAVDMachine;
 use crate::kvm_compose_yaml::machines::docker::ConfigDockerMachine;
 use crate::kvm_compose_yaml::machines::libvirt::ConfigLibvirtMachine;
+use crate::kvm_compose_yaml::network::qos::Qos;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum GuestType {
     Libvirt(ConfigLibvirtMachine),
@@ -26,28 +28,33 @@ impl GuestType {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ConfigScalingInterface {
     pub clones: Vec<u32>,
     pub gateway: Option<String>,
     pub ip_type: ConfigScalingIpType,
     pub mac_range: ConfigScalingMacRange,
+    /// Optional range of IPv6 addresses for dual-stack clones, the same length as the ip range
+    pub ipv6_range: Option<ConfigScalingIpRange>,
+    pub ipv6_gateway: Option<String>,
+    /// Optional traffic shaping and link impairment, applied to every clone on the interface
+    pub qos: Option<Qos>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ConfigScalingIpType {
     IpRange(ConfigScalingIpRange),
     Dynamic,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ConfigScalingIpRange {
     pub from: String,
     pub to: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ConfigScalingMacRange {
     pub from: String,
     pub to: String,
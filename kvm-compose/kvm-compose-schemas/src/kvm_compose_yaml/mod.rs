@@ -1,23 +1,35 @@
+pub mod expectations;
 pub mod machines;
 pub mod network_old;
 pub mod testbed_options;
 pub mod tooling;
 pub mod network;
+pub mod resolve;
+pub mod validation;
 
+use crate::kvm_compose_yaml::expectations::Expectation;
 use crate::kvm_compose_yaml::machines::*;
 use crate::kvm_compose_yaml::network::*;
+use crate::kvm_compose_yaml::network::qos::Qos;
 use crate::kvm_compose_yaml::testbed_options::*;
 use crate::kvm_compose_yaml::tooling::*;
-use anyhow::{Result, Context, Error};
+use crate::kvm_compose_yaml::resolve::{load_variables, resolve_yaml};
+use crate::kvm_compose_yaml::validation::{ConfigValidator, ValidationReport};
+use crate::settings::TestbedClusterConfig;
+use anyhow::{bail, Result, Context, Error};
+use schemars::schema::RootSchema;
+use schemars::schema_for;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use tracing::{info};
 use std::fmt;
 use std::fmt::Formatter;
+use std::net::Ipv6Addr;
 use std::path::Path;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct Config {
     pub machines: Option<Vec<Machine>>,
     // #[serde(flatten)]
@@ -25,40 +37,50 @@ pub struct Config {
     pub tooling: Option<Tooling>,
     #[serde(default)]
     pub testbed_options: TestbedOptions,
+    /// Checks of the reachability between guests, run with `kvm-compose verify`
+    pub expectations: Option<Vec<Expectation>>,
 }
 
 impl Config {
     pub async fn load_from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
-        let text = tokio::fs::read_to_string(path).await.with_context(|| "Reading Config file")?;
-        let value: Self = serde_yaml::from_str(&text).with_context(|| "Parsing Config YAML")?;
-        // // TODO validate i.e. needs cpu and memory etc .. create validation code in other file
+        Self::load_from_file_with_vars(path, None).await
+    }
+
+    /// Load the config, interpolating `${VAR}` from the environment and the optional var file, and
+    /// merging in any files listed under `include:`. The fully resolved config is then validated.
+    pub async fn load_from_file_with_vars<P: AsRef<Path>>(
+        path: P,
+        var_file: Option<&Path>,
+    ) -> anyhow::Result<Self> {
+        let variables = load_variables(var_file).await?;
+        let resolved = resolve_yaml(path.as_ref(), &variables).await?;
+        let value: Self = serde_yaml::from_value(resolved).with_context(|| "Parsing Config YAML")?;
         value.validate().with_context(|| "Validating Config semantics")?;
         Ok(value)
     }
 
     pub fn validate(&self) -> Result<()> {
         info!("Running validation for Config");
-        
-        if let Some(machines) = &self.machines {
-            for machine in machines {
-                match &machine.guest_type {
-                    GuestType::Libvirt(libvirt) => {
-                        if libvirt.cpus.is_none() {
-                            return Err(Error::msg("Machine is missing 'cpus' in libvirt configuration"));
-                        }
-                        if libvirt.memory_mb.is_none() {
-                            return Err(Error::msg("Machine is missing 'memory_mb' in libvirt configuration"));
-                        }
-                    },
-                    // Add validation for other guest types if needed, skip for now
-                    _ => {},
-                }
-            }
+        let report = self.validation_report(None);
+        if !report.is_valid() {
+            return Err(Error::msg(report.to_string()));
         }
-
         Ok(())
     }
 
+    /// Check the semantics of the config, collecting every problem found into the report rather
+    /// than stopping at the first. If the cluster config is given, then the testbed hosts and
+    /// chassis referenced in the network are also checked.
+    pub fn validation_report(&self, cluster_config: Option<&TestbedClusterConfig>) -> ValidationReport {
+        ConfigValidator::new(self, cluster_config).run()
+    }
+
+    /// The JSON Schema of the kvm-compose.yaml, generated from the `Config` type tree. Editors can
+    /// use this to autocomplete and lint the yaml without needing the testbed server.
+    pub fn json_schema() -> RootSchema {
+        schema_for!(Config)
+    }
+
     pub async fn save_to<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
         let mut file = File::create(path).await?;
         let to_string = serde_yaml::to_string(&self)?;
@@ -75,7 +97,7 @@ impl fmt::Display for Config {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct Machine {
     pub name: String,
     pub network: Option<Vec<MachineNetwork>>,
@@ -89,11 +111,72 @@ pub struct Machine {
 //     pub bridge: String,
 // }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct MachineNetwork {
     pub switch: String,
     pub gateway: Option<String>,
-    pub mac: String,
-    pub ip: String,
+    /// If not given, a mac is generated from the project, guest and interface index
+    pub mac: Option<String>,
+    /// A static ip, `dynamic` for DHCP, or if not given a free ip is allocated from the switch's
+    /// subnet
+    pub ip: Option<String>,
+    /// An optional static IPv6 address, with an optional prefix length that defaults to 64, or
+    /// `auto` to configure the address from the router advertisements on the switch
+    pub ipv6: Option<String>,
+    pub ipv6_gateway: Option<String>,
     pub network_name: Option<String>, // provider network
+    /// Optional traffic shaping and link impairment for the interface
+    pub qos: Option<Qos>,
+}
+
+impl MachineNetwork {
+    /// The ip of the interface, this is always set once the addresses have been allocated
+    pub fn ip(&self) -> Result<&String> {
+        self.ip.as_ref()
+            .with_context(|| format!("interface on switch {} has no ip allocated", self.switch))
+    }
+
+    /// The mac of the interface, this is always set once the addresses have been allocated
+    pub fn mac(&self) -> Result<&String> {
+        self.mac.as_ref()
+            .with_context(|| format!("interface on switch {} has no mac allocated", self.switch))
+    }
+
+    /// The static IPv6 address and prefix length of the interface, if it has one
+    pub fn static_ipv6(&self) -> Result<Option<(Ipv6Addr, u8)>> {
+        match self.ipv6.as_deref() {
+            None | Some("auto") => Ok(None),
+            Some(ipv6) => {
+                let (ip, prefix) = match ipv6.split_once('/') {
+                    Some((ip, prefix)) => (ip, prefix.parse::<u8>()
+                        .with_context(|| format!("parsing the prefix length of ipv6 {ipv6}"))?),
+                    None => (ipv6, 64),
+                };
+                let ip = ip.parse::<Ipv6Addr>()
+                    .with_context(|| format!("'{ipv6}' is not a valid ipv6 address"))?;
+                if prefix > 128 {
+                    bail!("the prefix length of ipv6 {ipv6} is larger than 128");
+                }
+                Ok(Some((ip, prefix)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_schema() {
+        let schema = serde_json::to_string(&Config::json_schema()).unwrap();
+        // the enum variants should be listed so that editors can catch typos
+        assert!(schema.contains("\"to-lport\""));
+        assert!(schema.contains("\"dnat_and_snat\""));
+        assert!(schema.contains("\"cloud_image\""));
+        assert!(schema.contains("\"existing_disk\""));
+        // fields that are set by the testbed are not part of the yaml
+        assert!(!schema.contains("\"ssh_address\""));
+        assert!(!schema.contains("\"tcp_tty_port\""));
+    }
 }
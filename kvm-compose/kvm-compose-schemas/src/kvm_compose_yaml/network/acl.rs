@@ -2,18 +2,94 @@ use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Formatter;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+/// The error for `apply_deny_all`, shared by the validation and the network creation
+pub const APPLY_DENY_ALL_NOT_IMPLEMENTED: &str = "apply_deny_all is not yet implemented";
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ACL {
     /// Place a low priority deny all traffic policy, with the expectation that the user will place
     /// rules to selectively allow traffic. Default is false to allow all traffic by default.
     #[serde(default)]
     pub apply_deny_all: bool,
+    #[serde(default)]
     pub switches: HashMap<String, Vec<ACLRule>>,
-    // pub port_group:
+    /// Named groups of guest switch ports, which can be referenced as `@name` in the matches and
+    /// have their own rules. The addresses of the ports can be referenced as `$name_ip4` and
+    /// `$name_ip6`.
+    pub port_groups: Option<HashMap<String, PortGroup>>,
+    /// Named sets of addresses, which can be referenced as `$name` in the matches
+    pub address_sets: Option<HashMap<String, AddressSet>>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+/// The members of a port group, at least one must be given
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct PortGroup {
+    /// Every interface of these guests, or of every clone for the name of a guest with scaling
+    pub guests: Option<Vec<String>>,
+    /// Every interface of a range of clones
+    pub clones: Option<Vec<CloneRange>>,
+    /// Every guest interface on these switches
+    pub switches: Option<Vec<String>>,
+    /// Rules applied to the ports in the group
+    pub rules: Option<Vec<ACLRule>>,
+}
+
+/// The clones `from` to `to` inclusive of the guest with scaling
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct CloneRange {
+    pub guest: String,
+    pub from: u32,
+    pub to: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct AddressSet {
+    /// IPv4 or IPv6 addresses or subnets
+    pub addresses: Option<Vec<String>>,
+    /// The ipv4 addresses of every interface of these guests, or of every clone for the name of a
+    /// guest with scaling
+    pub guests: Option<Vec<String>>,
+}
+
+/// Port group and address set names are used in the matches, so can only contain letters, digits
+/// and underscores, and can't start with a digit
+pub fn is_acl_set_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace the names of the `@port_group` and `$address_set` references in an ACL match with the
+/// result of `f`, which is given the `@` or `$` and the name. Quoted strings are left alone.
+pub fn map_acl_match_references<E>(
+    rule_match: &str,
+    mut f: impl FnMut(char, &str) -> Result<String, E>,
+) -> Result<String, E> {
+    let mut result = String::with_capacity(rule_match.len());
+    let mut chars = rule_match.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        result.push(c);
+        match c {
+            '"' => quoted = !quoted,
+            '@' | '$' if !quoted => {
+                let mut name = String::new();
+                while let Some(next) = chars.next_if(|next| next.is_ascii_alphanumeric() || *next == '_') {
+                    name.push(next);
+                }
+                if !name.is_empty() {
+                    result.push_str(&f(c, &name)?);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(result)
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct ACLRule {
     pub direction: ACLDirection,
     /// Must be number between 0 and 32,767
@@ -23,7 +99,7 @@ pub struct ACLRule {
     pub action: ACLAction,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub enum ACLDirection {
     #[serde(rename = "to-lport")]
     ToLport,
@@ -43,7 +119,7 @@ impl fmt::Display for ACLDirection {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub enum ACLAction {
     #[serde(rename = "allow-related")]
     AllowRelated,
@@ -74,3 +150,20 @@ impl fmt::Display for ACLAction {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_acl_match_references() {
+        let rule_match = r#"outport == @dbs && ip4.src == $web && inport != "a@b""#;
+        let mapped: Result<String, ()> = map_acl_match_references(rule_match, |sigil, name| {
+            Ok(format!("proj_{name}{}", if sigil == '@' { "_pg" } else { "" }))
+        });
+        assert_eq!(mapped.unwrap(), r#"outport == @proj_dbs_pg && ip4.src == $proj_web && inport != "a@b""#);
+        assert!(is_acl_set_name("web_1"));
+        assert!(!is_acl_set_name("1web"));
+        assert!(!is_acl_set_name("web-1"));
+    }
+}
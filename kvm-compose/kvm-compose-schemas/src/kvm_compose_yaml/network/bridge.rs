@@ -0,0 +1,41 @@
+use std::fmt;
+use std::fmt::Formatter;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+/// A plain OVS bridge, guests are attached by setting the bridge name as the `switch` of their
+/// interface
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct Bridge {
+    /// The IPv4 subnet of the guests on the bridge, interfaces without an ip are allocated one
+    /// from it
+    pub subnet: String,
+    /// Optionally pin the bridge to a testbed host, otherwise the bridge is assigned a host by the
+    /// load balancing
+    pub testbed_host: Option<String>,
+}
+
+/// Connect two bridges, with a veth pair if they are on the same testbed host or a tunnel if not
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct BridgeLink {
+    pub source: String,
+    pub target: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TunnelType {
+    #[default]
+    Gre,
+    Vxlan,
+}
+
+impl fmt::Display for TunnelType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TunnelType::Gre => "gre",
+            TunnelType::Vxlan => "vxlan",
+        };
+        f.write_str(text)
+    }
+}
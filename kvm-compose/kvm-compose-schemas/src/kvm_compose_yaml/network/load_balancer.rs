@@ -0,0 +1,54 @@
+use std::fmt;
+use std::fmt::Formatter;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+/// Spread the traffic sent to a virtual ip and port over a set of guests, using OVN's native load
+/// balancing instead of a separate load balancer guest
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct LoadBalancer {
+    /// The virtual ip and port that clients connect to i.e. "10.0.0.100:80"
+    pub vip: String,
+    /// The guests or clone groups that the traffic is sent to, a clone group adds every clone
+    pub backends: Vec<LoadBalancerBackend>,
+    /// Default is tcp
+    #[serde(default)]
+    pub protocol: LoadBalancerProtocol,
+    /// The switches to apply the load balancer on, traffic from guests on these switches to the vip
+    /// is balanced
+    pub switches: Option<Vec<String>>,
+    /// The routers to apply the load balancer on, traffic routed through these routers to the vip is
+    /// balanced. At least one switch or router must be given.
+    pub routers: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct LoadBalancerBackend {
+    /// Guest or clone group name
+    pub guest: String,
+    /// The port the guest listens on
+    pub port: u16,
+    /// The position of the interface in the guest's network definition, default is 0
+    #[serde(default)]
+    pub interface: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadBalancerProtocol {
+    #[default]
+    Tcp,
+    Udp,
+    Sctp,
+}
+
+impl fmt::Display for LoadBalancerProtocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            LoadBalancerProtocol::Tcp => "tcp",
+            LoadBalancerProtocol::Udp => "udp",
+            LoadBalancerProtocol::Sctp => "sctp",
+        };
+        f.write_str(text)
+    }
+}
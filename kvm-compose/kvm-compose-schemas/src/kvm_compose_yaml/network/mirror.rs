@@ -0,0 +1,58 @@
+use std::fmt;
+use std::fmt::Formatter;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+/// Copy the traffic of a set of switch ports to an interface of a monitoring guest, such as an IDS
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct Mirror {
+    pub sources: MirrorSources,
+    /// Which traffic of the source ports is copied, default is both directions
+    #[serde(default)]
+    pub direction: MirrorDirection,
+    pub sink: MirrorSink,
+}
+
+/// The switch ports to mirror, at least one source must be given
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct MirrorSources {
+    /// Mirror every interface of these guests
+    pub guests: Option<Vec<String>>,
+    /// Mirror these switch ports from the network section
+    pub switch_ports: Option<Vec<String>>,
+    /// Mirror every port on these switches, except for the sink
+    pub switches: Option<Vec<String>>,
+}
+
+/// The guest interface that the mirrored traffic is sent to
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct MirrorSink {
+    pub guest: String,
+    /// The position of the interface in the guest's network definition, default is 0
+    #[serde(default)]
+    pub interface: usize,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, JsonSchema, PartialEq)]
+pub enum MirrorDirection {
+    /// Traffic sent by the source ports
+    #[serde(rename = "from-lport")]
+    FromLport,
+    /// Traffic received by the source ports
+    #[serde(rename = "to-lport")]
+    ToLport,
+    #[default]
+    #[serde(rename = "both")]
+    Both,
+}
+
+impl fmt::Display for MirrorDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            MirrorDirection::FromLport => "from-lport",
+            MirrorDirection::ToLport => "to-lport",
+            MirrorDirection::Both => "both",
+        };
+        f.write_str(text)
+    }
+}
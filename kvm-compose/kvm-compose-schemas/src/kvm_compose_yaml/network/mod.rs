@@ -1,30 +1,49 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use std::collections::HashMap;
 use crate::kvm_compose_yaml::network::acl::ACL;
+use crate::kvm_compose_yaml::network::bridge::{Bridge, BridgeLink, TunnelType};
+use crate::kvm_compose_yaml::network::load_balancer::LoadBalancer;
+use crate::kvm_compose_yaml::network::mirror::Mirror;
+use crate::kvm_compose_yaml::network::policy::Policy;
 use crate::kvm_compose_yaml::network::router::Router;
 use crate::kvm_compose_yaml::network::switch::Switch;
 
 pub mod switch;
 pub mod router;
 pub mod acl;
+pub mod qos;
+pub mod mirror;
+pub mod bridge;
+pub mod policy;
+pub mod load_balancer;
 
 // TODO - semantic validation of inputs when converting into "state"
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct OvnNetworkSchema {
     pub switches: Option<HashMap<String, Switch>>,
     pub routers: Option<HashMap<String, Router>>,
     pub acl: Option<ACL>,
+    pub mirrors: Option<HashMap<String, Mirror>>,
+    /// Rules on the traffic between guests, clone groups and switches that are compiled into ACLs,
+    /// these are applied alongside the raw rules in `acl`
+    pub policies: Option<Vec<Policy>>,
+    pub load_balancers: Option<HashMap<String, LoadBalancer>>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct OvsNetwork {
-
+    pub bridges: HashMap<String, Bridge>,
+    pub links: Option<Vec<BridgeLink>>,
+    /// The tunnel used for links between bridges on different testbed hosts, default is GRE
+    #[serde(default)]
+    pub tunnel_type: TunnelType,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum NetworkBackend {
-    Ovn(OvnNetworkSchema),
+    Ovn(Box<OvnNetworkSchema>),
     Ovs(OvsNetwork),
 }
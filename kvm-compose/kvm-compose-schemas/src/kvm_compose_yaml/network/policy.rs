@@ -0,0 +1,70 @@
+use std::fmt;
+use std::fmt::Formatter;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use crate::kvm_compose_yaml::network::acl::ACLAction;
+
+/// A rule on the IPv4 and IPv6 traffic between guests, clone groups or switches, this is compiled into OVN
+/// ACLs so that the logical switch port names and ips don't need to be written by hand
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct Policy {
+    /// The guest, clone group or switch that sends the traffic, if not given the rule matches
+    /// traffic from anywhere
+    pub from: Option<String>,
+    /// The guest, clone group or switch that receives the traffic, if not given the rule matches
+    /// traffic to anywhere. At least one of `from` and `to` must be given.
+    pub to: Option<String>,
+    /// The protocol to match, default is any IPv4 or IPv6 traffic
+    #[serde(default)]
+    pub proto: PolicyProtocol,
+    /// Destination ports to match, only for tcp and udp
+    pub ports: Option<Vec<u16>>,
+    pub action: PolicyAction,
+    /// Must be number between 0 and 32,767, if not given the earlier policies in the list take
+    /// precedence over the later ones
+    pub priority: Option<i16>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyProtocol {
+    Tcp,
+    Udp,
+    Icmp,
+    #[default]
+    Any,
+}
+
+/// The protocol part of an OVN match, these match both address families
+impl fmt::Display for PolicyProtocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            PolicyProtocol::Tcp => "tcp",
+            PolicyProtocol::Udp => "udp",
+            PolicyProtocol::Icmp => "icmp",
+            PolicyProtocol::Any => "ip",
+        };
+        f.write_str(text)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyAction {
+    /// Allow the traffic and the replies to it
+    Allow,
+    /// Silently drop the traffic
+    Deny,
+    /// Drop the traffic and reply with a TCP reset or ICMP unreachable
+    Reject,
+}
+
+impl PolicyAction {
+    pub fn acl_action(&self) -> ACLAction {
+        match self {
+            PolicyAction::Allow => ACLAction::AllowRelated,
+            PolicyAction::Deny => ACLAction::Drop,
+            PolicyAction::Reject => ACLAction::Reject,
+        }
+    }
+}
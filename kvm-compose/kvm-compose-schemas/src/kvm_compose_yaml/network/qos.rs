@@ -0,0 +1,144 @@
+use std::fmt;
+use std::str::FromStr;
+use anyhow::bail;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+/// Traffic shaping and link impairment for a switch port. The rate limit is applied by OVN, the
+/// delay, jitter, loss and duplication are applied with tc netem on the guest's interface.
+#[derive(Args, Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Qos {
+    /// Rate limit in kbps, applied in both directions
+    #[arg(long)]
+    pub rate_kbps: Option<u64>,
+    /// Burst size in kilobits for the rate limit
+    #[arg(long)]
+    pub burst_kbits: Option<u64>,
+    /// Delay in milliseconds added to packets
+    #[arg(long)]
+    pub delay_ms: Option<u32>,
+    /// Random variation in milliseconds of the delay
+    #[arg(long)]
+    pub jitter_ms: Option<u32>,
+    /// Percentage of packets to drop
+    #[arg(long)]
+    pub loss_percent: Option<Percent>,
+    /// Percentage of packets to duplicate
+    #[arg(long)]
+    pub duplicate_percent: Option<Percent>,
+}
+
+impl Qos {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.burst_kbits.is_some() && self.rate_kbps.is_none() {
+            bail!("burst_kbits needs rate_kbps to be set");
+        }
+        if self.rate_kbps == Some(0) {
+            bail!("rate_kbps must be more than 0");
+        }
+        if self.jitter_ms.is_some() && self.delay_ms.is_none() {
+            bail!("jitter_ms needs delay_ms to be set");
+        }
+        Ok(())
+    }
+
+    /// True if any of the settings that are applied with tc netem are set
+    pub fn has_netem(&self) -> bool {
+        self.delay_ms.is_some() || self.loss_percent.is_some() || self.duplicate_percent.is_some()
+    }
+
+    /// The arguments for `tc qdisc ... netem`, or None if there are no netem settings
+    pub fn netem_args(&self) -> Option<Vec<String>> {
+        if !self.has_netem() {
+            return None;
+        }
+        let mut args = vec!["netem".to_string()];
+        if let Some(delay) = self.delay_ms {
+            args.push("delay".into());
+            args.push(format!("{delay}ms"));
+            if let Some(jitter) = self.jitter_ms {
+                args.push(format!("{jitter}ms"));
+            }
+        }
+        if let Some(loss) = self.loss_percent {
+            args.push("loss".into());
+            args.push(format!("{loss}%"));
+        }
+        if let Some(duplicate) = self.duplicate_percent {
+            args.push("duplicate".into());
+            args.push(format!("{duplicate}%"));
+        }
+        Some(args)
+    }
+}
+
+/// A percentage between 0 and 100, checked when it is parsed so it is never NaN
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd, JsonSchema)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct Percent(f64);
+
+// NaN is rejected by `try_from`, so equality is total
+impl Eq for Percent {}
+
+impl TryFrom<f64> for Percent {
+    type Error = String;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if !(0.0..=100.0).contains(&value) {
+            return Err(format!("percentage must be between 0 and 100, got {value}"));
+        }
+        Ok(Self(value))
+    }
+}
+
+impl From<Percent> for f64 {
+    fn from(percent: Percent) -> Self {
+        percent.0
+    }
+}
+
+impl FromStr for Percent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: f64 = s.parse().map_err(|err| format!("{s} is not a number: {err}"))?;
+        Self::try_from(value)
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qos_netem_args() {
+        assert_eq!(Qos::default().netem_args(), None);
+        let qos = Qos {
+            rate_kbps: Some(1000),
+            delay_ms: Some(100),
+            jitter_ms: Some(10),
+            loss_percent: Some(Percent::try_from(0.5).unwrap()),
+            ..Default::default()
+        };
+        qos.validate().unwrap();
+        assert_eq!(qos.netem_args().unwrap().join(" "), "netem delay 100ms 10ms loss 0.5%");
+    }
+
+    #[test]
+    fn test_qos_validate() {
+        assert!(Qos { burst_kbits: Some(100), ..Default::default() }.validate().is_err());
+        assert!(Qos { jitter_ms: Some(10), ..Default::default() }.validate().is_err());
+        assert!(Percent::try_from(101.0).is_err());
+        assert!(Percent::try_from(f64::NAN).is_err());
+        assert!("-1".parse::<Percent>().is_err());
+        assert!(serde_yaml::from_str::<Qos>("loss_percent: 101").is_err());
+    }
+}
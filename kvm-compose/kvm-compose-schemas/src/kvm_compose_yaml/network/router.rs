@@ -1,36 +1,42 @@
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct Router {
     pub ports: Option<Vec<RouterPort>>,
     pub static_routes: Option<Vec<StaticRoutes>>,
     pub nat: Option<Vec<NAT>>,
     pub dhcp: Option<Vec<Dhcp>>,
+    /// IPv6 router advertisements, and DHCPv6 if the address mode uses it
+    pub ipv6_ra: Option<Vec<RouterAdvertisement>>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct RouterPort {
     pub name: String,
     pub mac: String,
     pub gateway_ip: String,
+    /// Optional IPv6 address and prefix of the port i.e. `fd00:10::1/64`
+    pub ipv6_gateway_ip: Option<String>,
     pub switch: String,
     pub set_gateway_chassis: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct StaticRoutes {
     pub prefix: String,
     pub nexthop: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct NAT {
     pub nat_type: NatType, // TODO make this enum
     pub external_ip: String,
     pub logical_ip: String,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum NatType {
     DnatAndSnat,
@@ -38,16 +44,90 @@ pub enum NatType {
     // Dnat,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct Dhcp {
     pub switch: String,
     pub exclude_ips: ExcludeIps,
+    /// Lease time in seconds, default is 3600
+    pub lease_time: Option<u32>,
+    /// The DNS servers given to the guests, default is 8.8.8.8
+    pub dns_servers: Option<Vec<String>>,
+    pub domain_name: Option<String>,
+    pub mtu: Option<u16>,
+    /// Classless static routes given to the guests, guests ignore the default gateway when these are
+    /// given so include a route for `0.0.0.0/0` if the guests need one
+    pub static_routes: Option<Vec<DhcpStaticRoute>>,
+    /// Any other DHCPv4 options supported by OVN, by their OVN name i.e. `ntp_server`. The values are
+    /// passed to OVN as they are, see the `DHCP_Options` table in ovn-nb(5).
+    pub options: Option<BTreeMap<String, String>>,
+    /// Fixed ips for guest interfaces that use DHCP
+    pub reservations: Option<Vec<DhcpReservation>>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct DhcpStaticRoute {
+    /// i.e. `10.1.0.0/16`
+    pub prefix: String,
+    pub via: String,
+}
+
+/// Always give the same ip to an interface of a guest, the interface must have a dynamic ip
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+pub struct DhcpReservation {
+    pub guest: String,
+    /// The position of the interface in the guest's network definition, default is 0
+    #[serde(default)]
+    pub interface: usize,
+    pub ip: String,
+}
+
+impl Dhcp {
+    /// The DHCP options that are set from the other fields, these can't be given in `options`
+    pub const MANAGED_OPTIONS: [&'static str; 8] = [
+        "lease_time", "router", "server_id", "server_mac", "dns_server", "domain_name", "mtu", "classless_static_route",
+    ];
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct ExcludeIps {
     pub from: String,
     pub to: String,
 }
+
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct RouterAdvertisement {
+    pub switch: String,
+    pub address_mode: Ipv6AddressMode,
+    /// Send advertisements periodically rather than only in reply to router solicitations
+    #[serde(default)]
+    pub send_periodic: bool,
+    /// The DNS servers given to the guests by DHCPv6, default is 2001:4860:4860::8888
+    pub dns_servers: Option<Vec<String>>,
+}
+
+/// How guests on the switch get their IPv6 address, this sets the managed and other flags in the
+/// router advertisement
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Ipv6AddressMode {
+    /// Guests configure their own address from the prefix
+    Slaac,
+    /// Guests get their address from DHCPv6
+    Dhcpv6Stateful,
+    /// Guests configure their own address from the prefix and get other options from DHCPv6
+    Dhcpv6Stateless,
+}
+
+impl Ipv6AddressMode {
+    /// The value of `ipv6_ra_configs:address_mode` in OVN
+    pub fn ovn_name(&self) -> &'static str {
+        match self {
+            Ipv6AddressMode::Slaac => "slaac",
+            Ipv6AddressMode::Dhcpv6Stateful => "dhcpv6_stateful",
+            Ipv6AddressMode::Dhcpv6Stateless => "dhcpv6_stateless",
+        }
+    }
+}
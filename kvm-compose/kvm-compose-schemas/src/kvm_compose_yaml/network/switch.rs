@@ -1,12 +1,16 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use crate::kvm_compose_yaml::network::qos::Qos;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct Switch {
     pub subnet: String,
+    /// Optional IPv6 prefix to make the switch dual-stack i.e. `fd00:10::/64`
+    pub ipv6_subnet: Option<String>,
     pub ports: Option<Vec<SwitchPort>>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct SwitchPort {
     pub name: String,
     pub chassis: Option<String>, // optionally bind port to a host
@@ -14,15 +18,18 @@ pub struct SwitchPort {
     // pub mac: Option<String>,
     #[serde(flatten)]
     pub port_type: SwitchPortType,
+    /// Optional rate limit for the port, delay and loss need a guest interface so are not
+    /// supported here
+    pub qos: Option<Qos>,
     // pub options: SwitchPortOptions,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default, JsonSchema)]
 pub struct SwitchPortOptions {
     pub network_name: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum SwitchPortType {
     Internal {
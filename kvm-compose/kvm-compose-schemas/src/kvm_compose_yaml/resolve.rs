@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Context};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde_yaml::Value;
+
+/// The top level key in the kvm-compose.yaml that lists other yaml files to merge in
+const INCLUDE_KEY: &str = "include";
+
+/// Get the variables used to interpolate the kvm-compose.yaml. The variables in the var file are
+/// read first, then the environment variables are added on top so that they take precedence.
+pub async fn load_variables(var_file: Option<&Path>) -> anyhow::Result<HashMap<String, String>> {
+    let mut variables = match var_file {
+        Some(var_file) => {
+            let text = tokio::fs::read_to_string(var_file)
+                .await
+                .with_context(|| format!("Reading var file {var_file:?}"))?;
+            parse_var_file(&text)
+                .with_context(|| format!("Parsing var file {var_file:?}"))?
+        }
+        None => HashMap::new(),
+    };
+    variables.extend(std::env::vars());
+    Ok(variables)
+}
+
+/// Parse a var file, which has a `KEY=VALUE` pair per line. Empty lines and lines starting with
+/// `#` are ignored, and the value can optionally be wrapped in quotes.
+pub fn parse_var_file(text: &str) -> anyhow::Result<HashMap<String, String>> {
+    let mut variables = HashMap::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            bail!("line {} is not in the format KEY=VALUE", idx + 1);
+        };
+        let key = key.trim();
+        if !is_variable_name(key) {
+            bail!("line {}, '{key}' is not a valid variable name", idx + 1);
+        }
+        let value = value.trim();
+        let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'))
+            .or(value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+        variables.insert(key.to_string(), value.to_string());
+    }
+    Ok(variables)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace `${VAR}` and `${VAR:-default}` in the text with the value of the variable. The default
+/// is used if the variable is not set or is empty. `$$` can be used for a literal `$`. Comments are
+/// left as they are. All the variables that are not set are reported together.
+pub fn interpolate(text: &str, variables: &HashMap<String, String>) -> anyhow::Result<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut missing = Vec::new();
+
+    for (idx, line) in text.split_inclusive('\n').enumerate() {
+        let (line, comment) = line.split_at(comment_start(line).unwrap_or(line.len()));
+        let mut rest = line;
+        while let Some(pos) = rest.find('$') {
+            result.push_str(&rest[..pos]);
+            rest = &rest[pos..];
+            if let Some(after) = rest.strip_prefix("$$") {
+                result.push('$');
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("${") {
+                let Some(end) = after.find('}') else {
+                    bail!("line {}, '${{' is not closed with '}}'", idx + 1);
+                };
+                let expression = &after[..end];
+                let (name, default) = match expression.split_once(":-") {
+                    Some((name, default)) => (name, Some(default)),
+                    None => (expression, None),
+                };
+                if !is_variable_name(name) {
+                    bail!("line {}, '{name}' is not a valid variable name", idx + 1);
+                }
+                match (variables.get(name).filter(|v| !v.is_empty()), default) {
+                    (Some(value), _) => result.push_str(value),
+                    (None, Some(default)) => result.push_str(default),
+                    (None, None) => missing.push(format!("{name} (line {})", idx + 1)),
+                }
+                rest = &after[end + 1..];
+            } else {
+                result.push('$');
+                rest = &rest[1..];
+            }
+        }
+        result.push_str(rest);
+        result.push_str(comment);
+    }
+
+    if !missing.is_empty() {
+        bail!("variables are not set and have no default: {}", missing.join(", "));
+    }
+    Ok(result)
+}
+
+/// The position of the `#` that starts a comment on the line, if there is one. The `#` must be at
+/// the start of the line or after whitespace, and not inside a quoted string.
+fn comment_start(line: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut prev = None;
+    for (idx, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => {
+                let token_start = prev.is_none_or(|p: char| p.is_whitespace() || "[{,".contains(p));
+                if c == '#' && prev.is_none_or(char::is_whitespace) {
+                    return Some(idx);
+                } else if (c == '"' || c == '\'') && token_start {
+                    quote = Some(c);
+                }
+            }
+        }
+        prev = Some(c);
+    }
+    None
+}
+
+/// Read the kvm-compose.yaml, interpolate the variables and merge in any files listed under
+/// `include:`. Included files are relative to the file that includes them, and are resolved the
+/// same way so they can also use variables and include other files. The result is the fully
+/// resolved yaml, ready to be parsed into the `Config`.
+pub async fn resolve_yaml(
+    path: &Path,
+    variables: &HashMap<String, String>,
+) -> anyhow::Result<Value> {
+    resolve_yaml_file(path.to_path_buf(), variables, Vec::new()).await
+}
+
+fn resolve_yaml_file<'a>(
+    path: PathBuf,
+    variables: &'a HashMap<String, String>,
+    mut include_chain: Vec<PathBuf>,
+) -> BoxFuture<'a, anyhow::Result<Value>> {
+    async move {
+        // make sure a file doesn't end up including itself
+        let canonical = tokio::fs::canonicalize(&path)
+            .await
+            .with_context(|| format!("Reading Config file {path:?}"))?;
+        if include_chain.contains(&canonical) {
+            bail!("{path:?} is included in a loop");
+        }
+        include_chain.push(canonical);
+
+        let text = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("Reading Config file {path:?}"))?;
+        let text = interpolate(&text, variables)
+            .with_context(|| format!("Interpolating variables in {path:?}"))?;
+        let mut value: Value = serde_yaml::from_str(&text)
+            .with_context(|| format!("Parsing YAML in {path:?}"))?;
+
+        let includes = match &mut value {
+            Value::Mapping(mapping) => mapping.remove(&Value::String(INCLUDE_KEY.to_string())),
+            _ => None,
+        };
+        let Some(includes) = includes else {
+            return Ok(value);
+        };
+        let includes: Vec<String> = serde_yaml::from_value(includes)
+            .with_context(|| format!("'{INCLUDE_KEY}' in {path:?} must be a list of file paths"))?;
+
+        // merge the included files in order, then this file on top
+        let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut merged = Value::Mapping(Default::default());
+        for include in includes {
+            let included = resolve_yaml_file(parent.join(&include), variables, include_chain.clone())
+                .await
+                .with_context(|| format!("Including {include} from {path:?}"))?;
+            merged = merge_yaml(merged, included);
+        }
+        Ok(merge_yaml(merged, value))
+    }.boxed()
+}
+
+/// Merge the `over` yaml on top of the `base` yaml. Mappings are merged key by key and lists are
+/// joined, so that machine lists and network sections from different files are combined. For any
+/// other value, the value in `over` is used.
+fn merge_yaml(base: Value, over: Value) -> Value {
+    match (base, over) {
+        (Value::Mapping(mut base), Value::Mapping(over)) => {
+            for (key, over_value) in over {
+                let merged = match base.remove(&key) {
+                    Some(base_value) => merge_yaml(base_value, over_value),
+                    None => over_value,
+                };
+                base.insert(key, merged);
+            }
+            Value::Mapping(base)
+        }
+        (Value::Sequence(mut base), Value::Sequence(over)) => {
+            base.extend(over);
+            Value::Sequence(base)
+        }
+        (base, Value::Null) => base,
+        (_, over) => over,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> HashMap<String, String> {
+        HashMap::from([
+            ("SUBNET".to_string(), "10.0.0.0/24".to_string()),
+            ("EMPTY".to_string(), "".to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let text = "subnet: ${SUBNET}\ncount: ${COUNT:-3}\nempty: ${EMPTY:-x}\ncost: $$5 $HOME\n# ${NOT_SET}\n";
+        let result = interpolate(text, &variables()).unwrap();
+        assert_eq!(result, "subnet: 10.0.0.0/24\ncount: 3\nempty: x\ncost: $5 $HOME\n# ${NOT_SET}\n");
+    }
+
+    #[test]
+    fn test_interpolate_comments() {
+        let text = "subnet: ${SUBNET} # ${NOT_SET}\nname: \"a # ${SUBNET}\" # ${\nurl: a#${SUBNET}\nit's: ok # ${NOT_SET}\n";
+        let result = interpolate(text, &variables()).unwrap();
+        assert_eq!(result, "subnet: 10.0.0.0/24 # ${NOT_SET}\nname: \"a # 10.0.0.0/24\" # ${\nurl: a#10.0.0.0/24\nit's: ok # ${NOT_SET}\n");
+    }
+
+    #[test]
+    fn test_interpolate_missing() {
+        let err = interpolate("a: ${A}\nb: ${B}\n", &variables()).unwrap_err();
+        assert_eq!(err.to_string(), "variables are not set and have no default: A (line 1), B (line 2)");
+        assert!(interpolate("a: ${A", &variables()).is_err());
+    }
+
+    #[test]
+    fn test_parse_var_file() {
+        let variables = parse_var_file("# comment\n\nSUBNET=10.0.1.0/24\nIMAGE = \"ubuntu_22_04\"\n").unwrap();
+        assert_eq!(variables.get("SUBNET").unwrap(), "10.0.1.0/24");
+        assert_eq!(variables.get("IMAGE").unwrap(), "ubuntu_22_04");
+        assert!(parse_var_file("NOT A VAR").is_err());
+    }
+
+    #[test]
+    fn test_merge_yaml() {
+        let base: Value = serde_yaml::from_str("machines: [a]\nnetwork:\n  ovn:\n    switches:\n      sw0: {subnet: x}\n").unwrap();
+        let over: Value = serde_yaml::from_str("machines: [b]\nnetwork:\n  ovn:\n    switches:\n      sw1: {subnet: y}\n").unwrap();
+        let expected: Value = serde_yaml::from_str("machines: [a, b]\nnetwork:\n  ovn:\n    switches:\n      sw0: {subnet: x}\n      sw1: {subnet: y}\n").unwrap();
+        assert_eq!(merge_yaml(base, over), expected);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_includes() {
+        let dir = tempfile::tempdir().unwrap();
+        tokio::fs::write(dir.path().join("network.yaml"), "network:\n  ovn:\n    switches:\n      sw0:\n        subnet: ${SUBNET}\n").await.unwrap();
+        tokio::fs::write(dir.path().join("kvm-compose.yaml"), "include:\n  - network.yaml\nmachines: []\n").await.unwrap();
+        tokio::fs::write(dir.path().join("loop.yaml"), "include:\n  - loop.yaml\n").await.unwrap();
+
+        let value = resolve_yaml(&dir.path().join("kvm-compose.yaml"), &variables()).await.unwrap();
+        let expected: Value = serde_yaml::from_str("network:\n  ovn:\n    switches:\n      sw0:\n        subnet: 10.0.0.0/24\nmachines: []\n").unwrap();
+        assert_eq!(value, expected);
+        assert!(resolve_yaml(&dir.path().join("loop.yaml"), &variables()).await.is_err());
+    }
+}
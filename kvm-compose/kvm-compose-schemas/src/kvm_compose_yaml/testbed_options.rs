@@ -1,13 +1,68 @@
+use std::fmt;
+use std::fmt::Formatter;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 
-#[derive(Default, Deserialize, Serialize, Debug, Clone)]
+#[derive(Default, Deserialize, Serialize, Debug, Clone, JsonSchema)]
 pub struct TestbedOptions {
     pub load_balancing: LoadBalancing,
+    /// Export flow records of the guest traffic to a collector
+    pub flow_export: Option<FlowExport>,
 }
 
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
 pub enum LoadBalancing {
     #[default]
     NaiveRoundRobin,
 }
 
+/// Flow export is configured on the integration bridge of the testbed hosts
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct FlowExport {
+    /// The ip of the collector, or the name of a guest in which case the ip of its first interface
+    /// is used
+    pub collector: String,
+    /// The port of the collector, defaults to the usual port for the protocol
+    pub port: Option<u16>,
+    pub protocol: FlowExportProtocol,
+    /// Export 1 in this many packets, default is 64. Not used for NetFlow, which exports every flow.
+    #[serde(default = "default_sampling")]
+    pub sampling: u32,
+    /// Only export from the testbed hosts that have ports on these switches, default is all
+    /// testbed hosts
+    pub switches: Option<Vec<String>>,
+}
+
+fn default_sampling() -> u32 {
+    64
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowExportProtocol {
+    Netflow,
+    Ipfix,
+    Sflow,
+}
+
+impl FlowExportProtocol {
+    /// The port collectors usually listen on for the protocol
+    pub fn default_port(&self) -> u16 {
+        match self {
+            FlowExportProtocol::Netflow => 2055,
+            FlowExportProtocol::Ipfix => 4739,
+            FlowExportProtocol::Sflow => 6343,
+        }
+    }
+}
+
+impl fmt::Display for FlowExportProtocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            FlowExportProtocol::Netflow => "netflow",
+            FlowExportProtocol::Ipfix => "ipfix",
+            FlowExportProtocol::Sflow => "sflow",
+        };
+        f.write_str(text)
+    }
+}
@@ -0,0 +1,1640 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::{IpAddr, SocketAddr};
+use serde::{Deserialize, Serialize};
+use crate::kvm_compose_yaml::Config;
+use crate::kvm_compose_yaml::expectations::ExpectationProtocol;
+use crate::kvm_compose_yaml::machines::{ConfigScalingInterface, ConfigScalingIpRange, ConfigScalingIpType, GuestType};
+use crate::kvm_compose_yaml::network::{NetworkBackend, OvnNetworkSchema, OvsNetwork};
+use crate::kvm_compose_yaml::network::acl::{is_acl_set_name, map_acl_match_references, ACLRule, APPLY_DENY_ALL_NOT_IMPLEMENTED};
+use crate::kvm_compose_yaml::network::policy::PolicyProtocol;
+use crate::kvm_compose_yaml::network::qos::Qos;
+use crate::kvm_compose_yaml::network::router::{Dhcp, Ipv6AddressMode};
+use crate::kvm_compose_yaml::network::switch::SwitchPortType;
+use crate::settings::TestbedClusterConfig;
+
+/// A single problem found in the kvm-compose.yaml. The path points to the field with the problem
+/// in the yaml i.e. `machines[3].network[0].ip`, so that it can be highlighted in the GUI.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub path: String,
+    pub message: String,
+}
+
+/// The result of validating the kvm-compose.yaml, this contains every problem found rather than
+/// just the first.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<ValidationError>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn push<P: Into<String>, M: Into<String>>(&mut self, path: P, message: M) {
+        self.errors.push(ValidationError {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_valid() {
+            return f.write_str("kvm-compose.yaml is valid");
+        }
+        writeln!(f, "kvm-compose.yaml has {} error(s):", self.errors.len())?;
+        for (idx, error) in self.errors.iter().enumerate() {
+            if idx > 0 {
+                writeln!(f)?;
+            }
+            if error.path.is_empty() {
+                write!(f, "  {}", error.message)?;
+            } else {
+                write!(f, "  {}: {}", error.path, error.message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An ip address and mask, from the `ip/mask` format used in the yaml
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Subnet {
+    pub ip: IpAddr,
+    pub mask: u8,
+}
+
+impl Subnet {
+    pub fn parse(subnet: &str) -> Result<Self, String> {
+        let (ip, mask) = subnet.split_once('/')
+            .ok_or(format!("'{subnet}' is not in the format 'ip/mask'"))?;
+        let ip = ip.parse::<IpAddr>()
+            .map_err(|_| format!("'{ip}' is not a valid ip address"))?;
+        let mask = mask.parse::<u8>()
+            .map_err(|_| format!("'{mask}' is not a valid mask"))?;
+        let max_mask = if ip.is_ipv4() { 32 } else { 128 };
+        if mask > max_mask {
+            return Err(format!("mask {mask} is larger than {max_mask}"));
+        }
+        Ok(Self { ip, mask })
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.ip, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let shift = 32 - self.mask as u32;
+                u32::from(net).checked_shr(shift).unwrap_or(0) == u32::from(*ip).checked_shr(shift).unwrap_or(0)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let shift = 128 - self.mask as u32;
+                u128::from(net).checked_shr(shift).unwrap_or(0) == u128::from(*ip).checked_shr(shift).unwrap_or(0)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.ip, self.mask)
+    }
+}
+
+fn parse_ip(ip: &str) -> Result<IpAddr, String> {
+    ip.parse::<IpAddr>()
+        .map_err(|_| format!("'{ip}' is not a valid ip address"))
+}
+
+/// Convert a mac address in the format `00:00:00:00:00:00` to its integer value
+fn parse_mac(mac: &str) -> Result<u64, String> {
+    let octets: Vec<_> = mac.split(':').collect();
+    if octets.len() != 6 || octets.iter().any(|o| o.len() != 2) {
+        return Err(format!("'{mac}' is not a valid mac address"));
+    }
+    u64::from_str_radix(&octets.concat(), 16)
+        .map_err(|_| format!("'{mac}' is not a valid mac address"))
+}
+
+fn guest_type_key(guest_type: &GuestType) -> &'static str {
+    match guest_type {
+        GuestType::Libvirt(_) => "libvirt",
+        GuestType::Docker(_) => "docker",
+        GuestType::Android(_) => "android",
+    }
+}
+
+/// This walks the yaml config and records every problem found in the report. The network is
+/// checked first, so that the machines can be checked against the switches and DHCP rules.
+pub(crate) struct ConfigValidator<'a> {
+    config: &'a Config,
+    cluster_config: Option<&'a TestbedClusterConfig>,
+    report: ValidationReport,
+    // switch name to its subnet, if the subnet is valid
+    switches: BTreeMap<String, Option<Subnet>>,
+    // switch name to its ipv6 subnet, for dual-stack switches with a valid ipv6 subnet
+    ipv6_switches: BTreeMap<String, Subnet>,
+    // switches that have a DHCP rule, so can have dynamic ips
+    dhcp_switches: BTreeSet<String>,
+    // switches that have ipv6 router advertisements, so can have auto ipv6 addresses
+    ra_switches: BTreeSet<String>,
+    // the path of the first field to use each ip and mac, to report duplicates
+    ips: HashMap<IpAddr, String>,
+    macs: HashMap<u64, String>,
+}
+
+impl<'a> ConfigValidator<'a> {
+    pub(crate) fn new(config: &'a Config, cluster_config: Option<&'a TestbedClusterConfig>) -> Self {
+        Self {
+            config,
+            cluster_config,
+            report: ValidationReport::default(),
+            switches: BTreeMap::new(),
+            ipv6_switches: BTreeMap::new(),
+            dhcp_switches: BTreeSet::new(),
+            ra_switches: BTreeSet::new(),
+            ips: HashMap::new(),
+            macs: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn run(mut self) -> ValidationReport {
+        match &self.config.network {
+            NetworkBackend::Ovn(ovn) => self.validate_ovn_network(ovn),
+            NetworkBackend::Ovs(ovs) => self.validate_ovs_network(ovs),
+        }
+        self.validate_machines();
+        if let NetworkBackend::Ovn(ovn) = &self.config.network {
+            self.validate_acl_sets(ovn);
+            self.validate_policies(ovn);
+            self.validate_load_balancers(ovn);
+            self.validate_dhcp_reservations(ovn);
+        }
+        self.validate_expectations();
+        self.report
+    }
+
+    /// Make sure the ip is unique in the deployment
+    fn claim_ip(&mut self, ip: IpAddr, path: &str) {
+        match self.ips.get(&ip) {
+            Some(existing) => self.report.push(path, format!("ip {ip} is already used by {existing}")),
+            None => {
+                self.ips.insert(ip, path.to_string());
+            }
+        }
+    }
+
+    /// Make sure the mac is unique in the deployment
+    fn claim_mac(&mut self, mac: u64, mac_string: &str, path: &str) {
+        match self.macs.get(&mac) {
+            Some(existing) => self.report.push(path, format!("mac {mac_string} is already used by {existing}")),
+            None => {
+                self.macs.insert(mac, path.to_string());
+            }
+        }
+    }
+
+    /// Check an ip that belongs on a switch, this can be dynamic if the switch has a DHCP rule
+    fn check_switch_ip(&mut self, ip: &str, switch: &str, path: &str) {
+        if ip.eq("dynamic") {
+            if !self.dhcp_switches.contains(switch) {
+                self.report.push(path, format!("ip is dynamic but there is no DHCP rule for switch '{switch}'"));
+            }
+            return;
+        }
+        match parse_ip(ip) {
+            Ok(parsed) => self.check_ip_in_switch(parsed, switch, path),
+            Err(err) => self.report.push(path, err),
+        }
+    }
+
+    /// Check a static ip is in the switch subnet of the same ip version, and is unique
+    fn check_ip_in_switch(&mut self, ip: IpAddr, switch: &str, path: &str) {
+        match self.switch_subnet(switch, &ip) {
+            Some(subnet) if !subnet.contains(&ip) => {
+                self.report.push(path, format!("ip {ip} is not in the subnet {subnet} of switch '{switch}'"));
+            }
+            None if ip.is_ipv6() && self.switches.contains_key(switch) => {
+                self.report.push(path, format!("switch '{switch}' does not have an ipv6_subnet"));
+            }
+            _ => {}
+        }
+        self.claim_ip(ip, path);
+    }
+
+    /// The subnet of the switch for the version of the ip
+    fn switch_subnet(&self, switch: &str, ip: &IpAddr) -> Option<Subnet> {
+        match ip {
+            IpAddr::V4(_) => self.switches.get(switch).copied().flatten(),
+            IpAddr::V6(_) => self.ipv6_switches.get(switch).copied(),
+        }
+    }
+
+    fn check_qos(&mut self, qos: &Qos, path: &str) {
+        if let Err(err) = qos.validate() {
+            self.report.push(path, format!("{err:#}"));
+        }
+    }
+
+    fn check_ipv6_gateway(&mut self, gateway: &str, path: &str) {
+        match parse_ip(gateway) {
+            Ok(IpAddr::V6(_)) => {}
+            Ok(_) => self.report.push(path, format!("{gateway} is not an ipv6 address")),
+            Err(err) => self.report.push(path, err),
+        }
+    }
+
+    fn check_switch_exists(&mut self, switch: &str, path: &str) -> bool {
+        if self.switches.contains_key(switch) {
+            true
+        } else {
+            self.report.push(path, format!("switch '{switch}' is not defined in the network"));
+            false
+        }
+    }
+
+    fn is_chassis(&self, chassis: &str) -> bool {
+        match self.cluster_config {
+            Some(cluster) => cluster.testbed_host_ssh_config.values()
+                .any(|host| host.ovn.chassis_name.eq(chassis)),
+            None => true,
+        }
+    }
+
+    fn is_testbed_host(&self, host: &str) -> bool {
+        match self.cluster_config {
+            Some(cluster) => cluster.testbed_host_ssh_config.contains_key(host),
+            None => true,
+        }
+    }
+
+    fn validate_ovn_network(&mut self, ovn: &OvnNetworkSchema) {
+        let empty = HashMap::new();
+        let switches: BTreeMap<_, _> = ovn.switches.as_ref().unwrap_or(&empty).iter().collect();
+        let empty = HashMap::new();
+        let routers: BTreeMap<_, _> = ovn.routers.as_ref().unwrap_or(&empty).iter().collect();
+
+        for (name, switch) in &switches {
+            let subnet = match Subnet::parse(&switch.subnet) {
+                Ok(subnet) if subnet.ip.is_ipv6() => {
+                    self.report.push(format!("network.ovn.switches.{name}.subnet"), "subnet must be ipv4, use ipv6_subnet for the ipv6 prefix of the switch");
+                    None
+                }
+                Ok(subnet) => Some(subnet),
+                Err(err) => {
+                    self.report.push(format!("network.ovn.switches.{name}.subnet"), err);
+                    None
+                }
+            };
+            self.switches.insert(name.to_string(), subnet);
+            if let Some(ipv6_subnet) = &switch.ipv6_subnet {
+                let path = format!("network.ovn.switches.{name}.ipv6_subnet");
+                match Subnet::parse(ipv6_subnet) {
+                    Ok(subnet) if subnet.ip.is_ipv4() => self.report.push(path, format!("{subnet} is not an ipv6 subnet")),
+                    // OVN only supports /64 prefixes for assigning addresses on a switch
+                    Ok(subnet) if subnet.mask != 64 => self.report.push(path, "the ipv6 subnet must have a /64 prefix"),
+                    Ok(subnet) => {
+                        self.ipv6_switches.insert(name.to_string(), subnet);
+                    }
+                    Err(err) => self.report.push(path, err),
+                }
+            }
+        }
+        for (router_name, router) in &routers {
+            for (idx, dhcp) in router.dhcp.iter().flatten().enumerate() {
+                let path = format!("network.ovn.routers.{router_name}.dhcp[{idx}]");
+                if self.check_switch_exists(&dhcp.switch, &format!("{path}.switch")) {
+                    self.dhcp_switches.insert(dhcp.switch.clone());
+                }
+            }
+        }
+
+        // switch ports
+        for (switch_name, switch) in &switches {
+            for (idx, port) in switch.ports.iter().flatten().enumerate() {
+                let path = format!("network.ovn.switches.{switch_name}.ports[{idx}]");
+                if let Some(chassis) = &port.chassis {
+                    if !self.is_testbed_host(chassis) {
+                        self.report.push(format!("{path}.chassis"), format!("testbed host '{chassis}' is not in the cluster config"));
+                    }
+                }
+                match &port.port_type {
+                    SwitchPortType::Internal { ip, mac, .. } => {
+                        match ip {
+                            Some(ip) => self.check_switch_ip(ip, switch_name, &format!("{path}.internal.ip")),
+                            None => self.report.push(format!("{path}.internal.ip"), "internal switch ports must have an ip"),
+                        }
+                        match mac {
+                            Some(mac) => match parse_mac(mac) {
+                                Ok(parsed) => self.claim_mac(parsed, mac, &format!("{path}.internal.mac")),
+                                Err(err) => self.report.push(format!("{path}.internal.mac"), err),
+                            },
+                            None => self.report.push(format!("{path}.internal.mac"), "internal switch ports must have a mac"),
+                        }
+                    }
+                    SwitchPortType::Router { .. } => {
+                        self.report.push(format!("{path}.router"), "router ports on the switch are added automatically from the router's ports");
+                    }
+                    SwitchPortType::Localnet { .. } => {}
+                }
+                if let Some(qos) = &port.qos {
+                    self.check_qos(qos, &format!("{path}.qos"));
+                    if qos.has_netem() {
+                        self.report.push(format!("{path}.qos"), "switch ports only support rate_kbps and burst_kbits, delay and loss need a guest interface");
+                    }
+                }
+            }
+        }
+
+        // routers
+        let mut router_port_names: HashMap<&String, String> = HashMap::new();
+        for (router_name, router) in &routers {
+            // the subnets this router is connected to, used to check routes and NAT
+            let mut linked_subnets = Vec::new();
+            let mut linked_switches: HashMap<&String, &String> = HashMap::new();
+            for (idx, port) in router.ports.iter().flatten().enumerate() {
+                let path = format!("network.ovn.routers.{router_name}.ports[{idx}]");
+                match router_port_names.get(&port.name) {
+                    Some(existing) => self.report.push(format!("{path}.name"), format!("router port name '{}' is already used by {existing}", &port.name)),
+                    None => {
+                        router_port_names.insert(&port.name, path.clone());
+                    }
+                }
+                if self.check_switch_exists(&port.switch, &format!("{path}.switch")) {
+                    match linked_switches.get(&port.switch) {
+                        Some(existing) => self.report.push(format!("{path}.switch"), format!("router '{router_name}' is already linked to switch '{}' by port '{existing}'", &port.switch)),
+                        None => {
+                            linked_switches.insert(&port.switch, &port.name);
+                        }
+                    }
+                }
+                match Subnet::parse(&port.gateway_ip) {
+                    Ok(gateway) => {
+                        if let Some(Some(subnet)) = self.switches.get(&port.switch) {
+                            if !subnet.contains(&gateway.ip) {
+                                self.report.push(format!("{path}.gateway_ip"), format!("ip {} is not in the subnet {subnet} of switch '{}'", gateway.ip, &port.switch));
+                            }
+                        }
+                        self.claim_ip(gateway.ip, &format!("{path}.gateway_ip"));
+                        linked_subnets.push(gateway);
+                    }
+                    Err(err) => self.report.push(format!("{path}.gateway_ip"), err),
+                }
+                if let Some(ipv6_gateway_ip) = &port.ipv6_gateway_ip {
+                    match Subnet::parse(ipv6_gateway_ip) {
+                        Ok(gateway) if gateway.ip.is_ipv4() => {
+                            self.report.push(format!("{path}.ipv6_gateway_ip"), format!("{} is not an ipv6 address", gateway.ip));
+                        }
+                        Ok(gateway) => {
+                            self.check_ip_in_switch(gateway.ip, &port.switch, &format!("{path}.ipv6_gateway_ip"));
+                            linked_subnets.push(gateway);
+                        }
+                        Err(err) => self.report.push(format!("{path}.ipv6_gateway_ip"), err),
+                    }
+                }
+                match parse_mac(&port.mac) {
+                    Ok(parsed) => self.claim_mac(parsed, &port.mac, &format!("{path}.mac")),
+                    Err(err) => self.report.push(format!("{path}.mac"), err),
+                }
+                if let Some(chassis) = &port.set_gateway_chassis {
+                    if !self.is_chassis(chassis) {
+                        self.report.push(format!("{path}.set_gateway_chassis"), format!("chassis '{chassis}' is not in the cluster config"));
+                    }
+                }
+            }
+
+            for (idx, route) in router.static_routes.iter().flatten().enumerate() {
+                let path = format!("network.ovn.routers.{router_name}.static_routes[{idx}]");
+                let prefix = Subnet::parse(&route.prefix);
+                if let Err(err) = &prefix {
+                    self.report.push(format!("{path}.prefix"), err);
+                }
+                match parse_ip(&route.nexthop) {
+                    Ok(next_hop) => {
+                        if !linked_subnets.iter().any(|s| s.contains(&next_hop)) {
+                            self.report.push(format!("{path}.nexthop"), format!("next hop {next_hop} is not reachable from any of the ports on router '{router_name}'"));
+                        }
+                        if prefix.is_ok_and(|prefix| prefix.ip.is_ipv4() != next_hop.is_ipv4()) {
+                            self.report.push(format!("{path}.nexthop"), "the prefix and next hop must both be ipv4 or both be ipv6");
+                        }
+                    }
+                    Err(err) => self.report.push(format!("{path}.nexthop"), err),
+                }
+            }
+
+            for (idx, nat) in router.nat.iter().flatten().enumerate() {
+                let path = format!("network.ovn.routers.{router_name}.nat[{idx}]");
+                let external_ip = parse_ip(&nat.external_ip);
+                match &external_ip {
+                    Ok(external_ip) => {
+                        if !linked_subnets.iter().any(|s| s.contains(external_ip)) {
+                            self.report.push(format!("{path}.external_ip"), format!("external ip {external_ip} is not in the subnet of any of the ports on router '{router_name}'"));
+                        }
+                    }
+                    Err(err) => self.report.push(format!("{path}.external_ip"), err),
+                }
+                // this can be an ip or a subnet
+                let logical_ip = Subnet::parse(&nat.logical_ip).map(|s| s.ip)
+                    .or_else(|_| parse_ip(&nat.logical_ip));
+                match (external_ip, logical_ip) {
+                    (_, Err(_)) => {
+                        self.report.push(format!("{path}.logical_ip"), format!("'{}' is not a valid ip address or subnet", &nat.logical_ip));
+                    }
+                    // NAT between ipv4 and ipv6 is not supported by OVN, only NAT44 and NAT66
+                    (Ok(external_ip), Ok(logical_ip)) if external_ip.is_ipv4() != logical_ip.is_ipv4() => {
+                        self.report.push(format!("{path}.logical_ip"), "the external and logical ip must both be ipv4 or both be ipv6");
+                    }
+                    _ => {}
+                }
+            }
+
+            for (idx, dhcp) in router.dhcp.iter().flatten().enumerate() {
+                let path = format!("network.ovn.routers.{router_name}.dhcp[{idx}]");
+                if self.switches.contains_key(&dhcp.switch) && !linked_switches.contains_key(&dhcp.switch) {
+                    self.report.push(format!("{path}.switch"), format!("router '{router_name}' is not linked to switch '{}'", &dhcp.switch));
+                }
+                for (field, ip) in [("from", &dhcp.exclude_ips.from), ("to", &dhcp.exclude_ips.to)] {
+                    match parse_ip(ip) {
+                        Ok(ip) => {
+                            if let Some(Some(subnet)) = self.switches.get(&dhcp.switch) {
+                                if !subnet.contains(&ip) {
+                                    self.report.push(format!("{path}.exclude_ips.{field}"), format!("ip {ip} is not in the subnet {subnet} of switch '{}'", &dhcp.switch));
+                                }
+                            }
+                        }
+                        Err(err) => self.report.push(format!("{path}.exclude_ips.{field}"), err),
+                    }
+                }
+                self.validate_dhcp_options(dhcp, &path);
+            }
+        }
+
+        // ipv6 router advertisements, checked after every router so that a switch can only have one
+        for (router_name, router) in &routers {
+            for (idx, ra) in router.ipv6_ra.iter().flatten().enumerate() {
+                let path = format!("network.ovn.routers.{router_name}.ipv6_ra[{idx}]");
+                if !self.check_switch_exists(&ra.switch, &format!("{path}.switch")) {
+                    continue;
+                }
+                let port = router.ports.iter().flatten().find(|p| p.switch.eq(&ra.switch));
+                match port {
+                    None => self.report.push(format!("{path}.switch"), format!("router '{router_name}' is not linked to switch '{}'", &ra.switch)),
+                    Some(port) if port.ipv6_gateway_ip.is_none() => {
+                        self.report.push(format!("{path}.switch"), format!("router port '{}' on switch '{}' does not have an ipv6_gateway_ip", &port.name, &ra.switch));
+                    }
+                    Some(_) => {}
+                }
+                if !self.ipv6_switches.contains_key(&ra.switch) {
+                    self.report.push(format!("{path}.switch"), format!("switch '{}' does not have an ipv6_subnet", &ra.switch));
+                }
+                if !self.ra_switches.insert(ra.switch.clone()) {
+                    self.report.push(format!("{path}.switch"), format!("switch '{}' already has router advertisements", &ra.switch));
+                }
+                if let Some(dns_servers) = &ra.dns_servers {
+                    if ra.address_mode == Ipv6AddressMode::Slaac {
+                        self.report.push(format!("{path}.dns_servers"), "dns_servers are given by DHCPv6, so need a dhcpv6 address_mode");
+                    }
+                    if dns_servers.is_empty() {
+                        self.report.push(format!("{path}.dns_servers"), "dns_servers must not be empty");
+                    }
+                    for (dns_idx, dns_server) in dns_servers.iter().enumerate() {
+                        match parse_ip(dns_server) {
+                            Ok(ip) if ip.is_ipv4() => self.report.push(format!("{path}.dns_servers[{dns_idx}]"), format!("{ip} is not an ipv6 address")),
+                            Ok(_) => {}
+                            Err(err) => self.report.push(format!("{path}.dns_servers[{dns_idx}]"), err),
+                        }
+                    }
+                }
+            }
+        }
+
+        // acl
+        if let Some(acl) = &ovn.acl {
+            if acl.apply_deny_all {
+                self.report.push("network.ovn.acl.apply_deny_all", APPLY_DENY_ALL_NOT_IMPLEMENTED);
+            }
+            let acl_switches: BTreeMap<_, _> = acl.switches.iter().collect();
+            for (switch, rules) in acl_switches {
+                let path = format!("network.ovn.acl.switches.{switch}");
+                self.check_switch_exists(switch, &path);
+                for (idx, rule) in rules.iter().enumerate() {
+                    if rule.priority < 0 {
+                        self.report.push(format!("{path}[{idx}].priority"), "priority must be between 0 and 32767");
+                    }
+                }
+            }
+        }
+    }
+
+    /// The DHCP options are given to the guests as DHCPv4 options, so the addresses must be ipv4
+    fn validate_dhcp_options(&mut self, dhcp: &Dhcp, path: &str) {
+        for (idx, dns_server) in dhcp.dns_servers.iter().flatten().enumerate() {
+            match parse_ip(dns_server) {
+                Ok(ip) if ip.is_ipv6() => self.report.push(format!("{path}.dns_servers[{idx}]"), format!("{ip} is not an ipv4 address")),
+                Ok(_) => {}
+                Err(err) => self.report.push(format!("{path}.dns_servers[{idx}]"), err),
+            }
+        }
+        if dhcp.dns_servers.as_ref().is_some_and(|dns_servers| dns_servers.is_empty()) {
+            self.report.push(format!("{path}.dns_servers"), "dns_servers must not be empty");
+        }
+        if dhcp.domain_name.as_ref().is_some_and(|domain_name| domain_name.is_empty() || domain_name.contains('"')) {
+            self.report.push(format!("{path}.domain_name"), "domain_name must not be empty or contain quotes");
+        }
+        // the minimum ipv4 mtu
+        if dhcp.mtu.is_some_and(|mtu| mtu < 68) {
+            self.report.push(format!("{path}.mtu"), "mtu must be at least 68");
+        }
+        if dhcp.lease_time == Some(0) {
+            self.report.push(format!("{path}.lease_time"), "lease_time must be more than 0");
+        }
+        for (idx, route) in dhcp.static_routes.iter().flatten().enumerate() {
+            match Subnet::parse(&route.prefix) {
+                Ok(subnet) if subnet.ip.is_ipv6() => self.report.push(format!("{path}.static_routes[{idx}].prefix"), format!("{subnet} is not an ipv4 subnet")),
+                Ok(_) => {}
+                Err(err) => self.report.push(format!("{path}.static_routes[{idx}].prefix"), err),
+            }
+            match parse_ip(&route.via) {
+                Ok(ip) if ip.is_ipv6() => self.report.push(format!("{path}.static_routes[{idx}].via"), format!("{ip} is not an ipv4 address")),
+                Ok(_) => {}
+                Err(err) => self.report.push(format!("{path}.static_routes[{idx}].via"), err),
+            }
+        }
+        for name in dhcp.options.iter().flatten().map(|(name, _)| name) {
+            if Dhcp::MANAGED_OPTIONS.contains(&name.as_str()) {
+                self.report.push(format!("{path}.options.{name}"), format!("option {name} is set by the other DHCP fields"));
+            } else if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                self.report.push(format!("{path}.options.{name}"), format!("'{name}' is not a DHCP option name"));
+            }
+        }
+    }
+
+    /// The reserved ips are for guest interfaces with a dynamic ip, so this needs both the network
+    /// and the machines. The ips are claimed after the guests so that clashes with static ips are
+    /// reported.
+    fn validate_dhcp_reservations(&mut self, ovn: &OvnNetworkSchema) {
+        // a clone group can't have a reservation, as every clone would be given the same ip
+        let guests: BTreeMap<_, _> = self.config.machines.iter().flatten()
+            .filter(|machine| match &machine.guest_type {
+                GuestType::Libvirt(libvirt) => libvirt.scaling.is_none(),
+                GuestType::Docker(docker) => docker.scaling.is_none(),
+                GuestType::Android(android) => android.scaling.is_none(),
+            })
+            .map(|machine| (machine.name.as_str(), machine.network.as_ref()))
+            .collect();
+        let empty = HashMap::new();
+        let routers: BTreeMap<_, _> = ovn.routers.as_ref().unwrap_or(&empty).iter().collect();
+        for (router_name, router) in routers {
+            for (idx, dhcp) in router.dhcp.iter().flatten().enumerate() {
+                for (res_idx, reservation) in dhcp.reservations.iter().flatten().enumerate() {
+                    let path = format!("network.ovn.routers.{router_name}.dhcp[{idx}].reservations[{res_idx}]");
+                    match guests.get(reservation.guest.as_str()) {
+                        None => self.report.push(format!("{path}.guest"), format!("'{}' is not a guest or is a clone group", &reservation.guest)),
+                        Some(network) => match network.and_then(|network| network.get(reservation.interface)) {
+                            None => self.report.push(format!("{path}.interface"), format!("guest '{}' does not have an interface {}", &reservation.guest, reservation.interface)),
+                            Some(interface) if !interface.switch.eq(&dhcp.switch) => {
+                                self.report.push(format!("{path}.interface"), format!("interface {} of guest '{}' is not on switch '{}'", reservation.interface, &reservation.guest, &dhcp.switch));
+                            }
+                            Some(interface) if !interface.ip.as_deref().is_some_and(|ip| ip.eq("dynamic")) => {
+                                self.report.push(format!("{path}.interface"), format!("interface {} of guest '{}' does not have a dynamic ip", reservation.interface, &reservation.guest));
+                            }
+                            Some(_) => {}
+                        }
+                    }
+                    match parse_ip(&reservation.ip) {
+                        Ok(ip) if ip.is_ipv6() => self.report.push(format!("{path}.ip"), format!("{ip} is not an ipv4 address")),
+                        Ok(ip) => self.check_ip_in_switch(ip, &dhcp.switch, &format!("{path}.ip")),
+                        Err(err) => self.report.push(format!("{path}.ip"), err),
+                    }
+                }
+            }
+        }
+    }
+
+    /// The port groups and address sets refer to guests, so this needs both the network and the
+    /// machines. The references in the matches of every rule are checked here too.
+    fn validate_acl_sets(&mut self, ovn: &OvnNetworkSchema) {
+        let Some(acl) = &ovn.acl else { return };
+        // guest name to its scaling count, if it is a clone group
+        let guests: BTreeMap<_, _> = self.config.machines.iter().flatten()
+            .map(|machine| {
+                let count = match &machine.guest_type {
+                    GuestType::Libvirt(libvirt) => libvirt.scaling.as_ref().map(|s| s.count),
+                    GuestType::Docker(docker) => docker.scaling.as_ref().map(|s| s.count),
+                    GuestType::Android(android) => android.scaling.as_ref().map(|s| s.count),
+                };
+                (machine.name.as_str(), count)
+            })
+            .collect();
+
+        let port_groups: BTreeMap<_, _> = acl.port_groups.iter().flatten().collect();
+        let address_sets: BTreeMap<_, _> = acl.address_sets.iter().flatten().collect();
+        for (name, port_group) in &port_groups {
+            let path = format!("network.ovn.acl.port_groups.{name}");
+            if !is_acl_set_name(name) {
+                self.report.push(&path, "port group names can only contain letters, digits and underscores, and can't start with a digit");
+            }
+            if port_group.guests.is_none() && port_group.clones.is_none() && port_group.switches.is_none() {
+                self.report.push(&path, "a port group must have at least one of guests, clones or switches");
+            }
+            for (idx, guest) in port_group.guests.iter().flatten().enumerate() {
+                if !guests.contains_key(guest.as_str()) {
+                    self.report.push(format!("{path}.guests[{idx}]"), format!("guest '{guest}' is not defined in the machines"));
+                }
+            }
+            for (idx, range) in port_group.clones.iter().flatten().enumerate() {
+                let range_path = format!("{path}.clones[{idx}]");
+                match guests.get(range.guest.as_str()) {
+                    Some(Some(count)) => {
+                        if range.from > range.to || range.to >= *count {
+                            self.report.push(&range_path, format!("clones {} to {} are out of range, there are {count} clones", range.from, range.to));
+                        }
+                    }
+                    Some(None) => self.report.push(format!("{range_path}.guest"), format!("guest '{}' does not have scaling", &range.guest)),
+                    None => self.report.push(format!("{range_path}.guest"), format!("guest '{}' is not defined in the machines", &range.guest)),
+                }
+            }
+            for (idx, switch) in port_group.switches.iter().flatten().enumerate() {
+                self.check_switch_exists(switch, &format!("{path}.switches[{idx}]"));
+            }
+        }
+        for (name, address_set) in &address_sets {
+            let path = format!("network.ovn.acl.address_sets.{name}");
+            if !is_acl_set_name(name) {
+                self.report.push(&path, "address set names can only contain letters, digits and underscores, and can't start with a digit");
+            }
+            if port_groups.keys().any(|pg| [format!("{pg}_ip4"), format!("{pg}_ip6")].contains(*name)) {
+                self.report.push(&path, format!("address set '{name}' clashes with the addresses of a port group"));
+            }
+            for (idx, address) in address_set.addresses.iter().flatten().enumerate() {
+                if parse_ip(address).is_err() && Subnet::parse(address).is_err() {
+                    self.report.push(format!("{path}.addresses[{idx}]"), format!("'{address}' is not an ip address or subnet"));
+                }
+            }
+            for (idx, guest) in address_set.guests.iter().flatten().enumerate() {
+                if !guests.contains_key(guest.as_str()) {
+                    self.report.push(format!("{path}.guests[{idx}]"), format!("guest '{guest}' is not defined in the machines"));
+                }
+            }
+        }
+
+        // every reference in a match must be to a port group or address set
+        let mut rules: Vec<(String, &ACLRule)> = Vec::new();
+        for (switch, switch_rules) in acl.switches.iter().collect::<BTreeMap<_, _>>() {
+            rules.extend(switch_rules.iter().enumerate().map(|(idx, rule)| (format!("network.ovn.acl.switches.{switch}[{idx}].match"), rule)));
+        }
+        for (name, port_group) in &port_groups {
+            rules.extend(port_group.rules.iter().flatten().enumerate().map(|(idx, rule)| (format!("network.ovn.acl.port_groups.{name}.rules[{idx}].match"), rule)));
+        }
+        for (path, rule) in rules {
+            let _ = map_acl_match_references(&rule._match, |sigil, name| {
+                let known = match sigil {
+                    '@' => port_groups.contains_key(&name.to_string()),
+                    _ => address_sets.contains_key(&name.to_string())
+                        || name.strip_suffix("_ip4").or(name.strip_suffix("_ip6"))
+                            .is_some_and(|pg| port_groups.contains_key(&pg.to_string())),
+                };
+                if !known {
+                    self.report.push(&path, format!("'{sigil}{name}' is not a port group or address set"));
+                }
+                Ok::<_, ()>(name.to_string())
+            });
+        }
+        for (name, port_group) in &port_groups {
+            for (idx, rule) in port_group.rules.iter().flatten().enumerate() {
+                if rule.priority < 0 {
+                    self.report.push(format!("network.ovn.acl.port_groups.{name}.rules[{idx}].priority"), "priority must be between 0 and 32767");
+                }
+            }
+        }
+    }
+
+    /// The policy endpoints can be guests, clone groups or switches, so this needs both the network
+    /// and the machines
+    fn validate_policies(&mut self, ovn: &OvnNetworkSchema) {
+        let guests: BTreeSet<_> = self.config.machines.iter().flatten()
+            .map(|machine| machine.name.as_str())
+            .collect();
+        for (idx, policy) in ovn.policies.iter().flatten().enumerate() {
+            let path = format!("network.ovn.policies[{idx}]");
+            if policy.from.is_none() && policy.to.is_none() {
+                self.report.push(&path, "a policy must have at least one of from or to");
+            }
+            for (field, endpoint) in [("from", &policy.from), ("to", &policy.to)] {
+                let Some(endpoint) = endpoint else { continue };
+                match (guests.contains(endpoint.as_str()), self.switches.contains_key(endpoint)) {
+                    (true, true) => self.report.push(format!("{path}.{field}"), format!("'{endpoint}' is ambiguous, it is both a guest and a switch")),
+                    (false, false) => self.report.push(format!("{path}.{field}"), format!("'{endpoint}' is not a guest, clone group or switch")),
+                    _ => {}
+                }
+            }
+            if let Some(ports) = &policy.ports {
+                if !matches!(policy.proto, PolicyProtocol::Tcp | PolicyProtocol::Udp) {
+                    self.report.push(format!("{path}.ports"), "ports can only be given for tcp or udp");
+                } else if ports.is_empty() {
+                    self.report.push(format!("{path}.ports"), "ports must not be empty");
+                }
+            }
+            if policy.priority.is_some_and(|priority| priority < 0) {
+                self.report.push(format!("{path}.priority"), "priority must be between 0 and 32767");
+            }
+        }
+    }
+
+    /// The load balancer backends refer to guests or clone groups, and the vip must not be the ip of
+    /// a guest, so this needs both the network and the machines
+    fn validate_load_balancers(&mut self, ovn: &OvnNetworkSchema) {
+        let guests: BTreeMap<_, _> = self.config.machines.iter().flatten()
+            .map(|machine| (machine.name.as_str(), machine.network.as_ref().map(|network| network.len())))
+            .collect();
+        let empty = HashMap::new();
+        let load_balancers: BTreeMap<_, _> = ovn.load_balancers.as_ref().unwrap_or(&empty).iter().collect();
+        for (name, load_balancer) in load_balancers {
+            let path = format!("network.ovn.load_balancers.{name}");
+            match load_balancer.vip.parse::<SocketAddr>() {
+                Ok(SocketAddr::V4(vip)) => {
+                    if let Some(used_by) = self.ips.get(&IpAddr::V4(*vip.ip())) {
+                        self.report.push(format!("{path}.vip"), format!("ip {} is already used by {used_by}", vip.ip()));
+                    }
+                }
+                _ => self.report.push(format!("{path}.vip"), format!("vip '{}' is not an ipv4 address and port i.e. 10.0.0.100:80", &load_balancer.vip)),
+            }
+            if load_balancer.backends.is_empty() {
+                self.report.push(format!("{path}.backends"), "backends must not be empty");
+            }
+            for (idx, backend) in load_balancer.backends.iter().enumerate() {
+                match guests.get(backend.guest.as_str()) {
+                    None => self.report.push(format!("{path}.backends[{idx}].guest"), format!("'{}' is not a guest or clone group", &backend.guest)),
+                    Some(Some(interfaces)) if backend.interface >= *interfaces => {
+                        self.report.push(format!("{path}.backends[{idx}].interface"), format!("guest '{}' does not have an interface {}", &backend.guest, backend.interface));
+                    }
+                    Some(_) => {}
+                }
+            }
+            if load_balancer.switches.is_none() && load_balancer.routers.is_none() {
+                self.report.push(&path, "a load balancer must be applied on at least one switch or router");
+            }
+            for (idx, switch) in load_balancer.switches.iter().flatten().enumerate() {
+                self.check_switch_exists(switch, &format!("{path}.switches[{idx}]"));
+            }
+            for (idx, router) in load_balancer.routers.iter().flatten().enumerate() {
+                if !ovn.routers.as_ref().is_some_and(|routers| routers.contains_key(router)) {
+                    self.report.push(format!("{path}.routers[{idx}]"), format!("router '{router}' is not defined in the network"));
+                }
+            }
+        }
+    }
+
+    /// The expectations refer to guests or clone groups. The interfaces of clone groups are defined
+    /// in their scaling, so only the interfaces of the other guests are checked.
+    fn validate_expectations(&mut self) {
+        let guests: BTreeMap<_, _> = self.config.machines.iter().flatten()
+            .map(|machine| (machine.name.as_str(), machine.network.as_ref().map(|network| network.len())))
+            .collect();
+        for (idx, expectation) in self.config.expectations.iter().flatten().enumerate() {
+            let path = format!("expectations[{idx}]");
+            for (field, guest) in [("from", &expectation.from), ("to", &expectation.to)] {
+                if !guests.contains_key(guest.as_str()) {
+                    self.report.push(format!("{path}.{field}"), format!("'{guest}' is not a guest or clone group"));
+                }
+            }
+            if let (Some(interface), Some(Some(interfaces))) = (expectation.interface, guests.get(expectation.to.as_str())) {
+                if interface >= *interfaces {
+                    self.report.push(format!("{path}.interface"), format!("guest '{}' does not have an interface {interface}", &expectation.to));
+                }
+            }
+            match (&expectation.proto, &expectation.ports) {
+                (ExpectationProtocol::Tcp, None) => self.report.push(format!("{path}.ports"), "ports must be given for tcp"),
+                (ExpectationProtocol::Tcp, Some(ports)) if ports.is_empty() => self.report.push(format!("{path}.ports"), "ports must not be empty"),
+                (ExpectationProtocol::Icmp, Some(_)) => self.report.push(format!("{path}.ports"), "ports can only be given for tcp"),
+                _ => {}
+            }
+        }
+    }
+
+    /// The bridges are registered as switches, so the guest interfaces are checked the same way as
+    /// for OVN. There is no DHCP on a plain OVS bridge, so dynamic ips are reported by the guest
+    /// checks.
+    fn validate_ovs_network(&mut self, ovs: &OvsNetwork) {
+        let bridges: BTreeMap<_, _> = ovs.bridges.iter().collect();
+        for (name, bridge) in &bridges {
+            let path = format!("network.ovs.bridges.{name}");
+            let subnet = match Subnet::parse(&bridge.subnet) {
+                Ok(subnet) if subnet.ip.is_ipv6() => {
+                    self.report.push(format!("{path}.subnet"), "subnet must be ipv4");
+                    None
+                }
+                Ok(subnet) => Some(subnet),
+                Err(err) => {
+                    self.report.push(format!("{path}.subnet"), err);
+                    None
+                }
+            };
+            self.switches.insert(name.to_string(), subnet);
+            if let Some(host) = &bridge.testbed_host {
+                if !self.is_testbed_host(host) {
+                    self.report.push(format!("{path}.testbed_host"), format!("testbed host '{host}' is not in the testbed cluster"));
+                }
+            }
+        }
+
+        let mut linked = BTreeSet::new();
+        for (idx, link) in ovs.links.iter().flatten().enumerate() {
+            let path = format!("network.ovs.links[{idx}]");
+            for (field, bridge) in [("source", &link.source), ("target", &link.target)] {
+                if !bridges.contains_key(bridge) {
+                    self.report.push(format!("{path}.{field}"), format!("bridge '{bridge}' is not defined in the network"));
+                }
+            }
+            if link.source.eq(&link.target) {
+                self.report.push(&path, "a bridge can't be linked to itself");
+            } else if !linked.insert(BTreeSet::from([&link.source, &link.target])) {
+                self.report.push(&path, format!("bridges '{}' and '{}' are already linked", &link.source, &link.target));
+            }
+        }
+    }
+
+    fn validate_machines(&mut self) {
+        let Some(machines) = &self.config.machines else { return };
+
+        // the names of all guests, including the clones that will be generated from scaling
+        let mut names: HashMap<String, String> = HashMap::new();
+        for (idx, machine) in machines.iter().enumerate() {
+            let path = format!("machines[{idx}]");
+            match names.get(&machine.name) {
+                Some(existing) => self.report.push(format!("{path}.name"), format!("guest name '{}' is already used by {existing}", &machine.name)),
+                None => {
+                    names.insert(machine.name.clone(), path.clone());
+                }
+            }
+        }
+
+        for (idx, machine) in machines.iter().enumerate() {
+            let path = format!("machines[{idx}]");
+            let guest_path = format!("{path}.{}", guest_type_key(&machine.guest_type));
+
+            let (scaling_count, scaling_interfaces) = match &machine.guest_type {
+                GuestType::Libvirt(libvirt) => {
+                    if libvirt.cpus.is_none() {
+                        self.report.push(format!("{guest_path}.cpus"), "libvirt guests must have cpus");
+                    }
+                    if libvirt.memory_mb.is_none() {
+                        self.report.push(format!("{guest_path}.memory_mb"), "libvirt guests must have memory_mb");
+                    }
+                    if let Some(scaling) = &libvirt.scaling {
+                        let scripts = [
+                            ("clone_setup", scaling.clone_setup.as_ref().map(|s| s.iter().map(|s| &s.clones).collect::<Vec<_>>())),
+                            ("clone_run", scaling.clone_run.as_ref().map(|r| r.iter().map(|r| &r.clones).collect::<Vec<_>>())),
+                        ];
+                        for (field, clone_lists) in scripts {
+                            let mut assigned = BTreeSet::new();
+                            for (script_idx, clones) in clone_lists.iter().flatten().enumerate() {
+                                let path = format!("{guest_path}.scaling.{field}[{script_idx}].clones");
+                                for clone in clones.iter() {
+                                    if *clone >= scaling.count {
+                                        self.report.push(&path, format!("clone {clone} is out of range, there are {} clones", scaling.count));
+                                    } else if !assigned.insert(*clone) {
+                                        self.report.push(&path, format!("clone {clone} is assigned more than one {field} script"));
+                                    }
+                                }
+                            }
+                        }
+                        (Some(scaling.count), Some(&scaling.interfaces))
+                    } else {
+                        (None, None)
+                    }
+                }
+                GuestType::Docker(docker) => (docker.scaling.as_ref().map(|s| s.count), docker.scaling.as_ref().map(|s| &s.interfaces)),
+                GuestType::Android(android) => (android.scaling.as_ref().map(|s| s.count), android.scaling.as_ref().map(|s| &s.interfaces)),
+            };
+
+            match (scaling_count, scaling_interfaces) {
+                (Some(count), Some(interfaces)) => {
+                    // clone names must not clash with other guests
+                    for clone_n in 0..count {
+                        let clone_name = format!("{}-{clone_n}", &machine.name);
+                        match names.get(&clone_name) {
+                            Some(existing) => self.report.push(format!("{guest_path}.scaling.count"), format!("clone name '{clone_name}' is already used by {existing}")),
+                            None => {
+                                names.insert(clone_name, format!("{guest_path}.scaling"));
+                            }
+                        }
+                    }
+                    self.validate_scaling(count, interfaces, &format!("{guest_path}.scaling"));
+                }
+                _ => self.validate_machine_network(machine.network.as_ref(), &path),
+            }
+        }
+    }
+
+    fn validate_machine_network(&mut self, network: Option<&Vec<crate::kvm_compose_yaml::MachineNetwork>>, path: &str) {
+        let Some(network) = network else {
+            self.report.push(format!("{path}.network"), "guests must have a network definition");
+            return;
+        };
+        for (idx, interface) in network.iter().enumerate() {
+            let path = format!("{path}.network[{idx}]");
+            let switch_exists = self.check_switch_exists(&interface.switch, &format!("{path}.switch"));
+            // a missing ip or mac is allocated later, from the switch subnet for the ip
+            match &interface.ip {
+                Some(ip) if switch_exists => self.check_switch_ip(ip, &interface.switch, &format!("{path}.ip")),
+                Some(ip) if !ip.eq("dynamic") => {
+                    if let Err(err) = parse_ip(ip) {
+                        self.report.push(format!("{path}.ip"), err);
+                    }
+                }
+                _ => {}
+            }
+            if let Some(mac) = &interface.mac {
+                match parse_mac(mac) {
+                    Ok(parsed) => self.claim_mac(parsed, mac, &format!("{path}.mac")),
+                    Err(err) => self.report.push(format!("{path}.mac"), err),
+                }
+            }
+            if let Some(gateway) = &interface.gateway {
+                if let Err(err) = parse_ip(gateway) {
+                    self.report.push(format!("{path}.gateway"), err);
+                }
+            }
+            match (interface.ipv6.as_deref(), interface.static_ipv6()) {
+                (None, _) => {}
+                (Some("auto"), _) => {
+                    if switch_exists && !self.ra_switches.contains(&interface.switch) {
+                        self.report.push(format!("{path}.ipv6"), format!("ipv6 is auto but there are no router advertisements on switch '{}'", &interface.switch));
+                    }
+                }
+                (Some(_), Ok(Some((ipv6, _)))) => {
+                    // OVN only gives a port a static ipv6 address alongside a static ipv4 address
+                    if interface.ip.as_deref().is_some_and(|ip| ip.eq("dynamic")) {
+                        self.report.push(format!("{path}.ipv6"), "a static ipv6 can't be used with a dynamic ip, use auto instead");
+                    }
+                    if switch_exists {
+                        self.check_ip_in_switch(IpAddr::V6(ipv6), &interface.switch, &format!("{path}.ipv6"));
+                    }
+                }
+                (Some(_), Ok(None)) => {}
+                (Some(_), Err(err)) => self.report.push(format!("{path}.ipv6"), format!("{err:#}")),
+            }
+            if let Some(gateway) = &interface.ipv6_gateway {
+                self.check_ipv6_gateway(gateway, &format!("{path}.ipv6_gateway"));
+            }
+            if let Some(qos) = &interface.qos {
+                self.check_qos(qos, &format!("{path}.qos"));
+            }
+        }
+    }
+
+    /// Check that every clone is given exactly one interface, and that the ip and mac ranges of
+    /// each interface fit the number of clones on the interface
+    fn validate_scaling(&mut self, count: u32, interfaces: &HashMap<String, ConfigScalingInterface>, path: &str) {
+        if count == 0 {
+            self.report.push(format!("{path}.count"), "count must be at least 1");
+        }
+        let interfaces: BTreeMap<_, _> = interfaces.iter().collect();
+        let mut assigned = BTreeSet::new();
+        for (switch, interface) in interfaces {
+            let path = format!("{path}.interfaces.{switch}");
+            let switch_exists = self.check_switch_exists(switch, &path);
+            for clone in &interface.clones {
+                if *clone >= count {
+                    self.report.push(format!("{path}.clones"), format!("clone {clone} is out of range, there are {count} clones"));
+                } else if !assigned.insert(*clone) {
+                    self.report.push(format!("{path}.clones"), format!("clone {clone} is assigned to more than one interface"));
+                }
+            }
+            let clone_count = interface.clones.len() as u128;
+
+            match &interface.ip_type {
+                ConfigScalingIpType::IpRange(range) => {
+                    self.check_scaling_ip_range(range, clone_count, switch, &format!("{path}.ip_type.ip_range"));
+                }
+                ConfigScalingIpType::Dynamic => {
+                    if switch_exists && !self.dhcp_switches.contains(switch) {
+                        self.report.push(format!("{path}.ip_type"), format!("ip is dynamic but there is no DHCP rule for switch '{switch}'"));
+                    }
+                }
+            }
+
+            if let Some(range) = &interface.ipv6_range {
+                let range_path = format!("{path}.ipv6_range");
+                if parse_ip(&range.from).is_ok_and(|ip| ip.is_ipv4()) {
+                    self.report.push(format!("{range_path}.from"), "ipv6_range must be a range of ipv6 addresses");
+                } else {
+                    self.check_scaling_ip_range(range, clone_count, switch, &range_path);
+                }
+            }
+            if let Some(gateway) = &interface.ipv6_gateway {
+                self.check_ipv6_gateway(gateway, &format!("{path}.ipv6_gateway"));
+            }
+            if let Some(qos) = &interface.qos {
+                self.check_qos(qos, &format!("{path}.qos"));
+            }
+
+            let range_path = format!("{path}.mac_range");
+            match (parse_mac(&interface.mac_range.from), parse_mac(&interface.mac_range.to)) {
+                (Ok(from), Ok(to)) => {
+                    if to < from {
+                        self.report.push(format!("{range_path}.to"), format!("{} is less than {}", &interface.mac_range.to, &interface.mac_range.from));
+                    } else if (to - from + 1) as u128 != clone_count {
+                        self.report.push(&range_path, format!("the range has {} macs but there are {clone_count} clones on the interface", to - from + 1));
+                    } else {
+                        for mac in from..=to {
+                            self.claim_mac(mac, &format!("{mac:012x}"), &range_path);
+                        }
+                    }
+                }
+                (from, to) => {
+                    if let Err(err) = from {
+                        self.report.push(format!("{range_path}.from"), err);
+                    }
+                    if let Err(err) = to {
+                        self.report.push(format!("{range_path}.to"), err);
+                    }
+                }
+            }
+        }
+        for clone in 0..count {
+            if !assigned.contains(&clone) {
+                self.report.push(format!("{path}.interfaces"), format!("clone {clone} is not assigned an interface"));
+            }
+        }
+    }
+
+    /// Check that an ip range of a scaling interface fits the number of clones on the interface and
+    /// is in the subnet of the switch
+    fn check_scaling_ip_range(&mut self, range: &ConfigScalingIpRange, clone_count: u128, switch: &str, range_path: &str) {
+        match (parse_ip(&range.from), parse_ip(&range.to)) {
+            (Ok(from), Ok(to)) => {
+                let bounds = match (from, to) {
+                    (IpAddr::V4(from), IpAddr::V4(to)) => Some((u32::from(from) as u128, u32::from(to) as u128)),
+                    (IpAddr::V6(from), IpAddr::V6(to)) => Some((u128::from(from), u128::from(to))),
+                    _ => None,
+                };
+                match bounds {
+                    None => self.report.push(range_path, "from and to must both be ipv4 or both be ipv6"),
+                    Some((from_n, to_n)) if to_n < from_n => self.report.push(format!("{range_path}.to"), format!("{to} is less than {from}")),
+                    Some((from_n, to_n)) => {
+                        if to_n - from_n + 1 != clone_count {
+                            self.report.push(range_path, format!("the range has {} ips but there are {clone_count} clones on the interface", to_n - from_n + 1));
+                        } else {
+                            for offset in 0..clone_count {
+                                let ip = match from {
+                                    IpAddr::V4(_) => IpAddr::V4(((from_n + offset) as u32).into()),
+                                    IpAddr::V6(_) => IpAddr::V6((from_n + offset).into()),
+                                };
+                                self.claim_ip(ip, range_path);
+                            }
+                        }
+                        for (field, ip) in [("from", from), ("to", to)] {
+                            match self.switch_subnet(switch, &ip) {
+                                Some(subnet) if !subnet.contains(&ip) => {
+                                    self.report.push(format!("{range_path}.{field}"), format!("ip {ip} is not in the subnet {subnet} of switch '{switch}'"));
+                                }
+                                None if ip.is_ipv6() && self.switches.contains_key(switch) => {
+                                    self.report.push(format!("{range_path}.{field}"), format!("switch '{switch}' does not have an ipv6_subnet"));
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+            (from, to) => {
+                if let Err(err) = from {
+                    self.report.push(format!("{range_path}.from"), err);
+                }
+                if let Err(err) = to {
+                    self.report.push(format!("{range_path}.to"), err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(yaml: &str) -> Config {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    const NETWORK: &str = r#"
+network:
+  ovn:
+    switches:
+      sw0:
+        subnet: "10.0.0.0/24"
+    routers:
+      lr0:
+        ports:
+          - name: lr0-sw0
+            mac: "00:00:00:00:ff:01"
+            gateway_ip: "10.0.0.1/24"
+            switch: sw0
+"#;
+
+    #[test]
+    fn test_subnet_contains() {
+        let subnet = Subnet::parse("10.0.0.0/24").unwrap();
+        assert!(subnet.contains(&"10.0.0.20".parse().unwrap()));
+        assert!(!subnet.contains(&"10.0.1.20".parse().unwrap()));
+        let all = Subnet::parse("0.0.0.0/0").unwrap();
+        assert!(all.contains(&"192.168.1.1".parse().unwrap()));
+        assert!(Subnet::parse("10.0.0.0").is_err());
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let yaml = format!(r#"
+machines:
+  - name: a
+    network:
+      - switch: sw0
+        mac: "00:00:00:00:00:01"
+        ip: "10.0.0.10"
+    docker:
+      image: nginx
+{NETWORK}"#);
+        let report = config(&yaml).validation_report(None);
+        assert!(report.is_valid(), "{report}");
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let yaml = format!(r#"
+machines:
+  - name: a
+    network:
+      - switch: sw0
+        mac: "00:00:00:00:00:01"
+        ip: "10.0.1.10"
+    docker:
+      image: nginx
+  - name: a
+    network:
+      - switch: sw1
+        mac: "00:00:00:00:00:01"
+        ip: "dynamic"
+    docker:
+      image: nginx
+{NETWORK}"#);
+        let report = config(&yaml).validation_report(None);
+        let paths: Vec<_> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "machines[1].name",
+            "machines[0].network[0].ip",
+            "machines[1].network[0].switch",
+            "machines[1].network[0].mac",
+        ]);
+    }
+
+    #[test]
+    fn test_dual_stack() {
+        let yaml = r#"
+machines:
+  - name: a
+    network:
+      - switch: sw0
+        ip: "10.0.0.10"
+        ipv6: "fd00:10::10"
+    docker:
+      image: nginx
+  - name: b
+    network:
+      - switch: sw0
+        ip: "dynamic"
+        ipv6: "fd00:20::10"
+    docker:
+      image: nginx
+  - name: c
+    network:
+      - switch: sw1
+        ip: "10.0.1.10"
+        ipv6: auto
+    docker:
+      image: nginx
+network:
+  ovn:
+    switches:
+      sw0:
+        subnet: "10.0.0.0/24"
+        ipv6_subnet: "fd00:10::/64"
+      sw1:
+        subnet: "10.0.1.0/24"
+    routers:
+      lr0:
+        ports:
+          - name: lr0-sw0
+            mac: "00:00:00:00:ff:01"
+            gateway_ip: "10.0.0.1/24"
+            ipv6_gateway_ip: "fd00:10::1/64"
+            switch: sw0
+          - name: lr0-sw1
+            mac: "00:00:00:00:ff:02"
+            gateway_ip: "10.0.1.1/24"
+            switch: sw1
+        static_routes:
+          - prefix: "::/0"
+            nexthop: "fd00:10::fe"
+          - prefix: "0.0.0.0/0"
+            nexthop: "fd00:10::fe"
+        dhcp:
+          - switch: sw0
+            exclude_ips:
+              from: "10.0.0.1"
+              to: "10.0.0.9"
+        ipv6_ra:
+          - switch: sw0
+            address_mode: slaac
+          - switch: sw1
+            address_mode: dhcpv6_stateful
+            dns_servers: ["10.0.0.53"]
+"#;
+        let report = config(yaml).validation_report(None);
+        let paths: Vec<_> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "network.ovn.routers.lr0.static_routes[1].nexthop",
+            "network.ovn.routers.lr0.ipv6_ra[1].switch",
+            "network.ovn.routers.lr0.ipv6_ra[1].switch",
+            "network.ovn.routers.lr0.ipv6_ra[1].dns_servers[0]",
+            "machines[1].network[0].ipv6",
+            "machines[1].network[0].ipv6",
+        ], "{report}");
+    }
+
+    #[test]
+    fn test_scaling_ranges() {
+        let yaml = format!(r#"
+machines:
+  - name: a
+    docker:
+      image: nginx
+      scaling:
+        count: 3
+        interfaces:
+          sw0:
+            clones: [0, 1]
+            ip_type:
+              ip_range:
+                from: "10.0.0.10"
+                to: "10.0.0.12"
+            mac_range:
+              from: "00:00:00:00:00:01"
+              to: "00:00:00:00:00:02"
+  - name: a-1
+    network:
+      - switch: sw0
+        mac: "00:00:00:00:00:03"
+        ip: "10.0.0.20"
+    docker:
+      image: nginx
+{NETWORK}"#);
+        let report = config(&yaml).validation_report(None);
+        let paths: Vec<_> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "machines[0].docker.scaling.count",
+            "machines[0].docker.scaling.interfaces.sw0.ip_type.ip_range",
+            "machines[0].docker.scaling.interfaces",
+        ]);
+    }
+
+    #[test]
+    fn test_ovs_network() {
+        let yaml = r#"
+machines:
+  - name: a
+    network:
+      - switch: br0
+        ip: "10.0.0.10"
+    docker:
+      image: nginx
+  - name: b
+    network:
+      - switch: br1
+        ip: "dynamic"
+    docker:
+      image: nginx
+network:
+  ovs:
+    bridges:
+      br0:
+        subnet: "10.0.0.0/24"
+      br1:
+        subnet: "fd00:1::/64"
+        testbed_host: host1
+      br2:
+        subnet: "10.1.0.0/16"
+    links:
+      - source: br0
+        target: br1
+      - source: br1
+        target: br0
+      - source: br0
+        target: br3
+"#;
+        let report = config(yaml).validation_report(None);
+        let paths: Vec<_> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "network.ovs.bridges.br1.subnet",
+            "network.ovs.links[1]",
+            "network.ovs.links[2].target",
+            "machines[1].network[0].ip",
+        ], "{report}");
+    }
+
+    #[test]
+    fn test_policies() {
+        let yaml = format!(r#"
+machines:
+  - name: web
+    network:
+      - switch: sw0
+        ip: "10.0.0.10"
+    docker:
+      image: nginx
+  - name: db
+    docker:
+      image: postgres
+      scaling:
+        count: 2
+        interfaces:
+          sw0:
+            clones: [0, 1]
+            ip_type:
+              ip_range:
+                from: "10.0.0.20"
+                to: "10.0.0.21"
+            mac_range:
+              from: "00:00:00:00:00:20"
+              to: "00:00:00:00:00:21"
+{NETWORK}    policies:
+      - from: web
+        to: db
+        proto: tcp
+        ports: [5432]
+        action: allow
+      - to: sw0
+        proto: icmp
+        ports: [80]
+        action: deny
+      - from: missing
+        action: reject
+        priority: -1
+      - action: deny
+"#);
+        let report = config(&yaml).validation_report(None);
+        let paths: Vec<_> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "network.ovn.policies[1].ports",
+            "network.ovn.policies[2].from",
+            "network.ovn.policies[2].priority",
+            "network.ovn.policies[3]",
+        ], "{report}");
+    }
+
+    #[test]
+    fn test_load_balancers() {
+        let yaml = format!(r#"
+machines:
+  - name: web
+    docker:
+      image: nginx
+      scaling:
+        count: 2
+        interfaces:
+          sw0:
+            clones: [0, 1]
+            ip_type:
+              ip_range:
+                from: "10.0.0.20"
+                to: "10.0.0.21"
+            mac_range:
+              from: "00:00:00:00:00:20"
+              to: "00:00:00:00:00:21"
+  - name: client
+    network:
+      - switch: sw0
+        ip: "10.0.0.11"
+    docker:
+      image: alpine
+{NETWORK}    load_balancers:
+      a_web:
+        vip: "10.0.0.100:80"
+        backends:
+          - guest: web
+            port: 8080
+        switches: [sw0]
+        routers: [lr0]
+      b_bad:
+        vip: "10.0.0.11"
+        backends:
+          - guest: client
+            port: 80
+            interface: 1
+          - guest: missing
+            port: 80
+        switches: [sw1]
+        routers: [lr1]
+      c_empty:
+        vip: "10.0.0.11:80"
+        protocol: udp
+        backends: []
+"#);
+        let report = config(&yaml).validation_report(None);
+        let paths: Vec<_> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "network.ovn.load_balancers.b_bad.vip",
+            "network.ovn.load_balancers.b_bad.backends[0].interface",
+            "network.ovn.load_balancers.b_bad.backends[1].guest",
+            "network.ovn.load_balancers.b_bad.switches[0]",
+            "network.ovn.load_balancers.b_bad.routers[0]",
+            "network.ovn.load_balancers.c_empty.vip",
+            "network.ovn.load_balancers.c_empty.backends",
+            "network.ovn.load_balancers.c_empty",
+        ], "{report}");
+    }
+
+    #[test]
+    fn test_dhcp() {
+        let yaml = r#"
+machines:
+  - name: web
+    network:
+      - switch: sw0
+        ip: dynamic
+    docker:
+      image: nginx
+  - name: db
+    network:
+      - switch: sw0
+        ip: "10.0.0.11"
+    docker:
+      image: postgres
+network:
+  ovn:
+    switches:
+      sw0:
+        subnet: "10.0.0.0/24"
+    routers:
+      lr0:
+        ports:
+          - name: lr0-sw0
+            mac: "00:00:00:00:ff:01"
+            gateway_ip: "10.0.0.1/24"
+            switch: sw0
+        dhcp:
+          - switch: sw0
+            exclude_ips:
+              from: "10.0.0.1"
+              to: "10.0.0.9"
+            lease_time: 0
+            dns_servers: ["10.0.0.53", "fd00::53"]
+            domain_name: testbed.local
+            mtu: 1400
+            static_routes:
+              - prefix: "10.1.0.0/16"
+                via: "10.0.0.254"
+              - prefix: "10.2.0.0"
+                via: "10.0.0.254"
+            options:
+              ntp_server: "{10.0.0.123}"
+              mtu: "1500"
+            reservations:
+              - guest: web
+                ip: "10.0.0.50"
+              - guest: db
+                ip: "10.0.0.11"
+              - guest: missing
+                interface: 1
+                ip: "10.0.1.5"
+"#;
+        let report = config(yaml).validation_report(None);
+        let paths: Vec<_> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "network.ovn.routers.lr0.dhcp[0].dns_servers[1]",
+            "network.ovn.routers.lr0.dhcp[0].lease_time",
+            "network.ovn.routers.lr0.dhcp[0].static_routes[1].prefix",
+            "network.ovn.routers.lr0.dhcp[0].options.mtu",
+            "network.ovn.routers.lr0.dhcp[0].reservations[1].interface",
+            "network.ovn.routers.lr0.dhcp[0].reservations[1].ip",
+            "network.ovn.routers.lr0.dhcp[0].reservations[2].guest",
+            "network.ovn.routers.lr0.dhcp[0].reservations[2].ip",
+        ], "{report}");
+    }
+
+    #[test]
+    fn test_expectations() {
+        let yaml = format!(r#"
+machines:
+  - name: web
+    network:
+      - switch: sw0
+        ip: "10.0.0.10"
+    docker:
+      image: nginx
+  - name: client
+    network:
+      - switch: sw0
+        ip: "10.0.0.11"
+    docker:
+      image: alpine
+{NETWORK}expectations:
+  - from: client
+    to: web
+    proto: tcp
+    ports: [80]
+    reachable: true
+  - from: web
+    to: client
+    interface: 1
+    reachable: false
+  - from: missing
+    to: web
+    proto: tcp
+    reachable: true
+  - from: client
+    to: web
+    ports: [80]
+    reachable: true
+"#);
+        let report = config(&yaml).validation_report(None);
+        let paths: Vec<_> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "expectations[1].interface",
+            "expectations[2].from",
+            "expectations[2].ports",
+            "expectations[3].ports",
+        ], "{report}");
+    }
+
+    #[test]
+    fn test_acl_port_groups_and_address_sets() {
+        let yaml = format!(r#"
+machines:
+  - name: web
+    network:
+      - switch: sw0
+        ip: "10.0.0.10"
+    docker:
+      image: nginx
+  - name: db
+    docker:
+      image: postgres
+      scaling:
+        count: 2
+        interfaces:
+          sw0:
+            clones: [0, 1]
+            ip_type:
+              ip_range:
+                from: "10.0.0.20"
+                to: "10.0.0.21"
+            mac_range:
+              from: "00:00:00:00:00:20"
+              to: "00:00:00:00:00:21"
+{NETWORK}    acl:
+      switches:
+        sw0:
+          - direction: to-lport
+            priority: 10
+            match: "outport == @dbs && ip4.src == $missing"
+            action: drop
+      port_groups:
+        dbs:
+          clones:
+            - guest: db
+              from: 0
+              to: 2
+            - guest: web
+              from: 0
+              to: 0
+          rules:
+            - direction: to-lport
+              priority: 10
+              match: "ip4.src == $web_ips || ip4.src == $dbs_ip4"
+              action: allow-related
+        all-guests:
+          switches: [sw0]
+      address_sets:
+        web_ips:
+          addresses: ["10.0.0.0/24", "not an ip"]
+          guests: [web, missing]
+"#);
+        let report = config(&yaml).validation_report(None);
+        let paths: Vec<_> = report.errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "network.ovn.acl.port_groups.all-guests",
+            "network.ovn.acl.port_groups.dbs.clones[0]",
+            "network.ovn.acl.port_groups.dbs.clones[1].guest",
+            "network.ovn.acl.address_sets.web_ips.addresses[1]",
+            "network.ovn.acl.address_sets.web_ips.guests[1]",
+            "network.ovn.acl.switches.sw0[0].match",
+        ], "{report}");
+    }
+}
+
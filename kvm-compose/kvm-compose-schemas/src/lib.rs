@@ -5,5 +5,11 @@ pub mod settings;
 pub mod exec;
 pub mod gui_models;
 pub mod handlers;
+pub mod image_models;
+pub mod net;
+pub mod capture;
+pub mod trace;
 
 pub const TESTBED_SETTINGS_FOLDER: &str = "/var/lib/testbedos/";
+/// Where cloud images are downloaded and imported to on the testbed host
+pub const TESTBED_IMAGES_FOLDER: &str = "/var/lib/testbedos/images/";
@@ -0,0 +1,92 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use crate::kvm_compose_yaml::network::qos::Qos;
+
+/// Entrypoint to change the network of a running deployment, without redeploying it.
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct NetCmd {
+    #[clap(subcommand)]
+    pub sub_command: NetSubCommand,
+}
+
+impl NetCmd {
+    pub fn name(&self) -> String {
+        match &self.sub_command {
+            NetSubCommand::Qos(qos_cmd) => format!("QoS on {}", qos_cmd.guest_name),
+            NetSubCommand::LinkDown(link_cmd) => format!("Link Down {}", link_cmd.target),
+            NetSubCommand::LinkUp(link_cmd) => format!("Link Up {}", link_cmd.target),
+            NetSubCommand::Partition(partition_cmd) => {
+                format!("Partition {} and {}", partition_cmd.switch_a, partition_cmd.switch_b)
+            }
+            NetSubCommand::Heal(heal_cmd) => {
+                if heal_cmd.all {
+                    "Heal all".to_string()
+                } else {
+                    format!("Heal {} and {}", heal_cmd.switch_a.as_deref().unwrap_or_default(), heal_cmd.switch_b.as_deref().unwrap_or_default())
+                }
+            }
+        }
+    }
+}
+
+/// The changes that can be made to the network of a running deployment
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NetSubCommand {
+    /// Set the traffic shaping and link impairment of a guest interface, replacing what was set
+    Qos(NetQosCmd),
+    /// Disable a guest interface or a router port, so that no traffic passes through it
+    LinkDown(NetLinkCmd),
+    /// Enable a guest interface or router port that was disabled with link-down
+    LinkUp(NetLinkCmd),
+    /// Drop all traffic between two switches
+    Partition(NetPartitionCmd),
+    /// Remove the partition between two switches, or every fault with --all
+    Heal(NetHealCmd),
+}
+
+/// Set the qos of one interface of a guest. Settings that are not given are removed, so giving no
+/// settings clears the qos of the interface.
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct NetQosCmd {
+    #[clap(index = 1)]
+    pub guest_name: String,
+    #[clap(long, default_value_t = 0, help = "Index of the interface in the guest's network definition")]
+    pub interface: usize,
+    #[clap(flatten)]
+    pub qos: Qos,
+}
+
+/// A guest interface or router port to take down or bring up
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct NetLinkCmd {
+    #[clap(index = 1, help = "Guest name, or router port name if there is no guest with the name")]
+    pub target: String,
+    #[clap(long, default_value_t = 0, help = "Index of the interface in the guest's network definition")]
+    pub interface: usize,
+}
+
+/// The two switches to partition from each other
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct NetPartitionCmd {
+    #[clap(index = 1)]
+    pub switch_a: String,
+    #[clap(index = 2)]
+    pub switch_b: String,
+}
+
+/// Either the two switches to heal the partition between, or --all to undo every fault
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct NetHealCmd {
+    #[clap(index = 1, required_unless_present = "all")]
+    pub switch_a: Option<String>,
+    #[clap(index = 2, required_unless_present = "all")]
+    pub switch_b: Option<String>,
+    #[clap(long, conflicts_with_all = &["switch_a", "switch_b"], help = "Undo every fault, including links that are down")]
+    pub all: bool,
+}
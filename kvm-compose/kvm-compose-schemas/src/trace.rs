@@ -0,0 +1,51 @@
+use std::fmt;
+use std::fmt::Formatter;
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+/// Explain the path of a packet from a guest through the OVN network, using ovn-trace on the main
+/// testbed host
+#[derive(Parser, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct TraceCmd {
+    #[clap(index = 1, help = "Guest that sends the packet")]
+    pub source: String,
+    #[clap(index = 2, help = "Guest name or IPv4 address that the packet is sent to")]
+    pub destination: String,
+    #[clap(long, default_value_t = 0, help = "Index of the interface in the source guest's network definition")]
+    pub interface: usize,
+    #[clap(long, value_enum, default_value_t = TraceProtocol::Icmp)]
+    pub proto: TraceProtocol,
+    #[clap(long, help = "Destination port, required for tcp and udp")]
+    pub port: Option<u16>,
+    #[clap(long, help = "Print the full ovn-trace output after the summary")]
+    pub detailed: bool,
+}
+
+impl TraceCmd {
+    pub fn name(&self) -> String {
+        match self.port {
+            Some(port) => format!("{} from {} to {}:{port}", &self.proto, &self.source, &self.destination),
+            None => format!("{} from {} to {}", &self.proto, &self.source, &self.destination),
+        }
+    }
+}
+
+#[derive(ValueEnum, Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceProtocol {
+    Tcp,
+    Udp,
+    Icmp,
+}
+
+impl fmt::Display for TraceProtocol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            TraceProtocol::Tcp => "tcp",
+            TraceProtocol::Udp => "udp",
+            TraceProtocol::Icmp => "icmp",
+        };
+        f.write_str(text)
+    }
+}
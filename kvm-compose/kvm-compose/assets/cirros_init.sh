@@ -1,4 +1,4 @@
-
+#!/bin/sh
 mkdir /home/cirros/.ssh
 cirros-query get public_ssh_key > /home/cirros/.ssh/authorized_keys
 chmod 600 /home/cirros/.ssh/authorized_keys
@@ -0,0 +1,133 @@
+use std::process::Stdio;
+use anyhow::Context;
+use tokio::process::{Child, Command};
+use kvm_compose_schemas::capture::CaptureCmd;
+use crate::net::{get_guest, get_guest_host_interface};
+use crate::orchestration::{is_main_testbed, OrchestrationCommon, run_testbed_orchestration_command_allow_fail};
+use crate::orchestration::ssh::SSHClient;
+use crate::state::State;
+
+/// A running live capture. The process streams the capture as pcapng to its stdout, it is the local
+/// sudo or ssh rather than dumpcap itself, so `stop` must be used to end the capture.
+pub struct LiveCapture {
+    pub child: Child,
+    testbed_host: String,
+    pid_file: String,
+}
+
+impl LiveCapture {
+    /// Stop dumpcap on the testbed host through its pid file, then the local process
+    pub async fn stop(mut self, orchestration_common: &OrchestrationCommon) -> anyhow::Result<()> {
+        run_testbed_orchestration_command_allow_fail(
+            orchestration_common,
+            &self.testbed_host,
+            "sudo",
+            vec!["pkill", "-F", &self.pid_file],
+            false,
+            None,
+        ).await?;
+        self.child.kill().await.context("stopping capture process")?;
+        self.remove_pid_file(orchestration_common).await
+    }
+
+    /// Remove the pid file once dumpcap has exited by itself
+    pub async fn remove_pid_file(&self, orchestration_common: &OrchestrationCommon) -> anyhow::Result<()> {
+        run_testbed_orchestration_command_allow_fail(
+            orchestration_common,
+            &self.testbed_host,
+            "sudo",
+            vec!["rm", "-f", &self.pid_file],
+            false,
+            None,
+        ).await?;
+        Ok(())
+    }
+}
+
+/// Start dumpcap on the interface of the guest, on the testbed host the guest is on. The capture is
+/// written as pcapng to the stdout of the returned process until the capture is stopped.
+pub async fn start_live_capture(
+    capture_cmd: &CaptureCmd,
+    state: &State,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<LiveCapture> {
+    let (guest_name, guest_data) = get_guest(&capture_cmd.guest_name, state)?;
+    let testbed_host = guest_data.testbed_host.as_ref()
+        .context(format!("getting testbed host for guest {guest_name}"))?;
+    let (capture_interface, namespace) = get_guest_host_interface(guest_data, capture_cmd.interface, orchestration_common).await?;
+    let is_main = is_main_testbed(orchestration_common, testbed_host);
+    tracing::info!("starting live capture on interface {capture_interface} on testbed host {testbed_host}");
+
+    let pid_file = format!("/tmp/{}-capture-{}.pid", &orchestration_common.project_name, rand::random::<u32>());
+    let args = dumpcap_args(capture_interface, namespace, capture_cmd.filter.as_ref());
+    let script = dumpcap_script(&args, &pid_file)?;
+
+    let mut command = if is_main {
+        let mut command = Command::new("sudo");
+        command.args(["sh", "-c", &script]);
+        command
+    } else {
+        // remote commands are run through the shell over ssh, so the script needs quoting
+        let script = shlex::try_quote(&script).context("quoting capture command")?;
+        SSHClient::remote_testbed_command(orchestration_common, testbed_host, vec!["sudo", "sh", "-c", &script]).await?
+    };
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("starting dumpcap")?;
+    Ok(LiveCapture {
+        child,
+        testbed_host: testbed_host.clone(),
+        pid_file,
+    })
+}
+
+/// The arguments to run dumpcap on the interface, inside the namespace if given, writing pcapng to
+/// stdout
+fn dumpcap_args(
+    capture_interface: String,
+    namespace: Option<String>,
+    filter: Option<&String>,
+) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(namespace) = namespace {
+        args.extend(["ip".to_string(), "netns".into(), "exec".into(), namespace]);
+    }
+    args.extend(["dumpcap".to_string(), "-q".into(), "-i".into(), capture_interface, "-w".into(), "-".into()]);
+    if let Some(filter) = filter {
+        args.extend(["-f".to_string(), filter.clone()]);
+    }
+    args
+}
+
+/// Shell script that writes its pid to the pid file then execs dumpcap, so that the pid file holds
+/// the pid of dumpcap
+fn dumpcap_script(args: &[String], pid_file: &str) -> anyhow::Result<String> {
+    let command = shlex::try_join(args.iter().map(|s| s.as_str()))
+        .context("quoting dumpcap arguments")?;
+    Ok(format!("echo $$ > {pid_file}; exec {command}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dumpcap_args() {
+        let args = dumpcap_args("vm-test10".into(), None, None);
+        assert_eq!(args.join(" "), "dumpcap -q -i vm-test10 -w -");
+        let args = dumpcap_args("vm-test10".into(), Some("test-phone-nmspc".into()), Some(&"tcp port 80".into()));
+        assert_eq!(
+            dumpcap_script(&args, "/tmp/test.pid").unwrap(),
+            "echo $$ > /tmp/test.pid; exec ip netns exec test-phone-nmspc dumpcap -q -i vm-test10 -w - -f 'tcp port 80'"
+        );
+        let args = dumpcap_args("vm-test10".into(), None, Some(&"host 10.0.0.1' ; reboot '".into()));
+        assert_eq!(
+            dumpcap_script(&args, "/tmp/test.pid").unwrap(),
+            "echo $$ > /tmp/test.pid; exec dumpcap -q -i vm-test10 -w - -f \"host 10.0.0.1' ; reboot '\""
+        );
+    }
+}
@@ -1 +1,2 @@
-pub mod packet_capture;
\ No newline at end of file
+pub mod packet_capture;
+pub mod live_capture;
@@ -1,19 +1,219 @@
-use std::time::Duration;
-use kvm_compose_schemas::cli_models::AnalysisToolsCmd;
+use std::path::{Component, Path, PathBuf};
+use anyhow::{bail, Context};
+use kvm_compose_schemas::cli_models::{AnalysisToolsCmd, AnalysisToolsSubCmd};
+use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
+use crate::components::get_guest_interface_name;
+use crate::net::{get_guest, get_guest_host_interface};
+use crate::orchestration::{is_main_testbed, OrchestrationCommon, run_testbed_orchestration_command, run_testbed_orchestration_command_allow_fail};
+use crate::orchestration::ssh::SSHClient;
+use crate::state::{State, StateNetwork, StateTestbedGuest};
 
-/// Run the packet capture. This function needs to work out which testbed host this capture needs to
-/// run on. This function also needs to work out if the
+/// What the packet capture will run on, resolved from the name given on the command line
+#[derive(Debug)]
+enum CaptureTarget<'a> {
+    /// An interface of a guest, the host side interface is found when the capture is run
+    Guest {
+        guest_data: &'a StateTestbedGuest,
+        idx: usize,
+    },
+    /// An interface on a testbed host, such as an OVS port
+    Interface {
+        name: String,
+        testbed_host: String,
+    },
+}
+
+/// Run tcpdump on the testbed host that the guest or OVS port is on, then pull the capture into the
+/// project folder. The capture stops after the duration or packet count given.
 pub async fn packet_capture(
-    at: &AnalysisToolsCmd
+    at: &AnalysisToolsCmd,
+    state: &State,
+    orchestration_common: &OrchestrationCommon,
 ) -> anyhow::Result<()> {
-    //
-    tracing::info!("tcpdump args: {at:?}");
+    let AnalysisToolsSubCmd::TcpDump { port_or_iface, output_file, interface, filter, duration, count } = &at.tool;
+    if duration.is_none() && count.is_none() {
+        bail!("a duration or packet count must be given to stop the capture");
+    }
+    let output_name = PathBuf::from(output_file);
+    check_output_path(&output_name)?;
+    let output_name = output_name.file_name()
+        .context(format!("getting the file name of output file {output_file}"))?
+        .to_string_lossy()
+        .to_string();
+    let local_dest = orchestration_common.project_working_dir.join(output_file);
 
-    tokio::time::sleep(Duration::from_secs_f32(2.0)).await;
+    let (capture_interface, namespace, testbed_host) = match resolve_capture_target(port_or_iface, *interface, state, orchestration_common)? {
+        CaptureTarget::Guest { guest_data, idx } => {
+            let testbed_host = guest_data.testbed_host.clone()
+                .context(format!("getting testbed host for guest {}", guest_data.guest_type.name))?;
+            let (capture_interface, namespace) = get_guest_host_interface(guest_data, idx, orchestration_common).await?;
+            (capture_interface, namespace, testbed_host)
+        }
+        CaptureTarget::Interface { name, testbed_host } => (name, None, testbed_host),
+    };
+    let is_main = is_main_testbed(orchestration_common, &testbed_host);
+    // on the main testbed host the capture is written straight to the project folder, remote
+    // captures are written to /tmp and then pulled back
+    let capture_path = if is_main {
+        local_dest.to_str().context("converting output file path to string")?.to_string()
+    } else {
+        format!("/tmp/{}-{output_name}", &orchestration_common.project_name)
+    };
+    tracing::info!("capturing packets on interface {capture_interface} on testbed host {testbed_host} to {output_file}");
 
-    // TODO intercept the -i argument
+    let mut cmd = Vec::new();
+    if let Some(namespace) = &namespace {
+        cmd.extend(["ip".to_string(), "netns".into(), "exec".into(), namespace.clone()]);
+    }
+    if let Some(duration) = duration {
+        // tcpdump writes out the capture cleanly when interrupted
+        cmd.extend(["timeout".to_string(), "--preserve-status".into(), "-s".into(), "INT".into(), duration.to_string()]);
+    }
+    // don't drop privileges, so that tcpdump can write to the output folder
+    cmd.extend(["tcpdump".to_string(), "-Z".into(), "root".into(), "-i".into(), capture_interface, "-w".into(), capture_path.clone()]);
+    if let Some(count) = count {
+        cmd.extend(["-c".to_string(), count.to_string()]);
+    }
+    if let Some(filter) = filter {
+        // remote commands are run through the shell over ssh, so the filter needs quoting
+        if is_main {
+            cmd.push(filter.clone());
+        } else {
+            cmd.push(shlex::try_quote(filter).context("quoting capture filter")?.to_string());
+        }
+    }
+    run_testbed_orchestration_command(
+        orchestration_common,
+        &testbed_host,
+        "sudo",
+        cmd.iter().map(|s| s.as_str()).collect(),
+        false,
+        None,
+    ).await.context("running tcpdump")?;
 
-    // TODO - if br-int, disallow due to ambiguity and un-usefulness
-    // TODO - if br-ex, assume is on main host
+    if !is_main {
+        tracing::info!("pulling capture from testbed host {testbed_host}");
+        SSHClient::pull_file_from_remote_testbed(
+            orchestration_common,
+            &testbed_host,
+            local_dest.to_str().context("converting output file path to string")?.to_string(),
+            capture_path.clone(),
+            false,
+        ).await?;
+        run_testbed_orchestration_command_allow_fail(
+            orchestration_common,
+            &testbed_host,
+            "sudo",
+            vec!["rm", "-f", &capture_path],
+            false,
+            None,
+        ).await?;
+    }
+    orchestration_common.apply_user_file_perms(&local_dest)?;
+    tracing::info!("capture saved to {:?}", &local_dest);
     Ok(())
 }
+
+/// The capture is written by root, so the output file must stay inside the project folder
+fn check_output_path(output_file: &Path) -> anyhow::Result<()> {
+    if output_file.is_absolute() {
+        bail!("output file {output_file:?} must be a path relative to the project folder");
+    }
+    if output_file.components().any(|c| c == Component::ParentDir) {
+        bail!("output file {output_file:?} must not contain '..'");
+    }
+    Ok(())
+}
+
+/// Work out what the capture runs on. The name is checked in order against the guests, the
+/// interfaces of libvirt guests, the external bridge and the OVS ports in the state.
+fn resolve_capture_target<'a>(
+    port_or_iface: &String,
+    idx: usize,
+    state: &'a State,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<CaptureTarget<'a>> {
+    // every guest on the testbed host is on the integration bridge, so there is nothing useful
+    // that a capture on it would show
+    if port_or_iface.eq("br-int") {
+        bail!("capturing on br-int is not supported, give a guest or an OVS port instead");
+    }
+    if let Ok((_, guest_data)) = get_guest(port_or_iface, state) {
+        return Ok(CaptureTarget::Guest { guest_data, idx });
+    }
+    for guest_data in state.testbed_guests.0.values() {
+        if let GuestType::Libvirt(_) = guest_data.guest_type.guest_type {
+            let interfaces = guest_data.guest_type.network.iter().flatten().count();
+            if (0..interfaces).any(|i| get_guest_interface_name(&state.project_name, guest_data.guest_id, i).eq(port_or_iface)) {
+                let testbed_host = guest_data.testbed_host.clone()
+                    .context(format!("getting testbed host for guest {}", guest_data.guest_type.name))?;
+                return Ok(CaptureTarget::Interface { name: port_or_iface.clone(), testbed_host });
+            }
+        }
+    }
+    // the external bridge is only on the main testbed host
+    if port_or_iface.eq("br-ex") {
+        return Ok(CaptureTarget::Interface {
+            name: port_or_iface.clone(),
+            testbed_host: orchestration_common.get_main_testbed()?,
+        });
+    }
+    if let StateNetwork::Ovn(ovn) = &state.network {
+        if let Some(ovs_port) = ovn.ovs_ports.get(port_or_iface) {
+            let testbed_host = orchestration_common.kvm_compose_config.testbed_host_ssh_config.iter()
+                .find(|(_, host_config)| host_config.ovn.chassis_name.eq(&ovs_port.chassis))
+                .map(|(host, _)| host.clone())
+                .context(format!("could not find the testbed host for chassis {}", &ovs_port.chassis))?;
+            return Ok(CaptureTarget::Interface { name: port_or_iface.clone(), testbed_host });
+        }
+    }
+    bail!("could not find a guest, guest interface or OVS port called {port_or_iface} in the project state");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ovn::ovn::OvnNetwork;
+    use crate::state::StateTestbedHost;
+    use super::*;
+
+    fn state() -> State {
+        let guest = StateTestbedGuest::test_docker_guest("client", "nginx", "host1", 1, vec![]);
+        State::test_fixture(vec![guest], StateNetwork::Ovn(OvnNetwork::new()))
+    }
+
+    #[test]
+    fn test_resolve_capture_target() {
+        let state = state();
+        let mut common = OrchestrationCommon::default();
+        common.testbed_hosts.insert("main".to_string(), StateTestbedHost {
+            username: "".to_string(),
+            ssh_private_key_location: "".to_string(),
+            ip: "".to_string(),
+            testbed_nic: "".to_string(),
+            is_main_host: true,
+        });
+
+        assert!(resolve_capture_target(&"br-int".into(), 0, &state, &common).is_err());
+        assert!(resolve_capture_target(&"missing".into(), 0, &state, &common).is_err());
+        assert!(matches!(
+            resolve_capture_target(&"test-client".into(), 0, &state, &common).unwrap(),
+            CaptureTarget::Guest { idx: 0, .. }
+        ));
+        match resolve_capture_target(&"br-ex".into(), 0, &state, &common).unwrap() {
+            CaptureTarget::Interface { name, testbed_host } => {
+                assert_eq!(name, "br-ex");
+                assert_eq!(testbed_host, "main");
+            }
+            target => panic!("unexpected capture target {target:?}"),
+        }
+    }
+
+    #[test]
+    fn test_check_output_path() {
+        assert!(check_output_path(Path::new("capture.pcap")).is_ok());
+        assert!(check_output_path(Path::new("captures/capture.pcap")).is_ok());
+        assert!(check_output_path(Path::new("/etc/capture.pcap")).is_err());
+        assert!(check_output_path(Path::new("../capture.pcap")).is_err());
+        assert!(check_output_path(Path::new("captures/../../capture.pcap")).is_err());
+    }
+}
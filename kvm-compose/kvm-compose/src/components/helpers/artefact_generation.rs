@@ -59,3 +59,48 @@ pub async fn resize<T: AsRef<str>>(disk: T, disk_expand: u16) -> anyhow::Result<
     }
     Ok(())
 }
+
+/// Get the libosinfo id of an os variant short id i.e. `ubuntujammy` is
+/// `http://ubuntu.com/ubuntu/22.04`, which is what virt-install records for `--os-variant`
+pub async fn os_variant_id(os_variant: &str) -> anyhow::Result<String> {
+    let output = Command::new("osinfo-query")
+        .arg("--fields=id")
+        .arg("os")
+        .arg(format!("short-id={os_variant}"))
+        .output()
+        .await
+        .context("running osinfo-query, is libosinfo-bin installed?")?;
+    if !output.status.success() {
+        let std_err = std::str::from_utf8(&output.stderr)?;
+        bail!("{}", std_err);
+    }
+    match parse_osinfo_query_id(std::str::from_utf8(&output.stdout)?) {
+        Some(id) => Ok(id),
+        None => bail!("os variant '{os_variant}' is not known to libosinfo"),
+    }
+}
+
+/// The output of `osinfo-query` is a table with a header, so the id is the first row after the
+/// separator line
+fn parse_osinfo_query_id(output: &str) -> Option<String> {
+    output.lines()
+        .skip_while(|line| !line.starts_with('-'))
+        .skip(1)
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_osinfo_query_id() {
+        let output = " ID                             \n\
+            --------------------------------\n \
+            http://ubuntu.com/ubuntu/22.04 \n";
+        assert_eq!(parse_osinfo_query_id(output).unwrap(), "http://ubuntu.com/ubuntu/22.04");
+        assert_eq!(parse_osinfo_query_id(" ID \n----\n"), None);
+    }
+}
@@ -1,10 +1,10 @@
 use std::collections::HashMap;
-use std::net::{IpAddr, Ipv4Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use anyhow::{bail, Context};
 use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt::{
     ConfigLibvirtMachine, LibvirtGuestOptions,
 };
-use kvm_compose_schemas::kvm_compose_yaml::machines::{ConfigScalingInterface, ConfigScalingIpType, ConfigScalingMacRange, GuestType};
+use kvm_compose_schemas::kvm_compose_yaml::machines::{ConfigScalingInterface, ConfigScalingIpRange, ConfigScalingIpType, ConfigScalingMacRange, GuestType};
 use kvm_compose_schemas::kvm_compose_yaml::{Config, Machine, MachineNetwork};
 use std::path::PathBuf;
 use kvm_compose_schemas::kvm_compose_yaml::machines::avd::{AVDGuestOptions, ConfigAVDMachine};
@@ -298,6 +298,11 @@ fn get_clone_interface(clone_n: u32, clone_interfaces: &HashMap<String, ConfigSc
                 &config_scaling_interface.ip_type,
             )?;
 
+            let clone_ipv6 = match &config_scaling_interface.ipv6_range {
+                Some(ipv6_range) => Some(get_clone_ip_in_range(clone_n, &config_scaling_interface.clones, ipv6_range)?),
+                None => None,
+            };
+
             let clone_mac = get_clone_mac_from_range(
                 clone_n,
                 &config_scaling_interface.clones,
@@ -307,9 +312,12 @@ fn get_clone_interface(clone_n: u32, clone_interfaces: &HashMap<String, ConfigSc
             return Ok(MachineNetwork {
                 switch: interface_name.to_string(),
                 gateway: config_scaling_interface.gateway.clone(),
-                mac: clone_mac.address,
-                ip: clone_ip.to_string(),
+                mac: Some(clone_mac.address),
+                ip: Some(clone_ip.to_string()),
+                ipv6: clone_ipv6,
+                ipv6_gateway: config_scaling_interface.ipv6_gateway.clone(),
                 network_name: None,
+                qos: config_scaling_interface.qos.clone(),
             });
 
         }
@@ -325,46 +333,7 @@ fn get_clone_ip_from_range(
     ip_type: &ConfigScalingIpType,
 ) -> anyhow::Result<String> {
     match ip_type {
-        ConfigScalingIpType::IpRange(ip_range) => {
-            // make sure ip values are formatted properly
-            let from = ip_range.from.parse::<IpAddr>()
-                .context(format!("parsing ConfigScalingIpRange (from) into ip address"))?;
-            let to = ip_range.to.parse::<IpAddr>()
-                .context(format!("parsing ConfigScalingIpRange (to) into ip address"))?;
-            // make sure both ips are same type
-            if from.is_ipv4() != to.is_ipv4() || from.is_ipv6() != to.is_ipv6() {
-                bail!("ip types need to match either both ipv4 or ipv6, from = {} and to = {}", from.to_string(), to.to_string())
-            }
-            // get range of possible ips, the range can be 0 if the from and to are the same, meaning there
-            // is only one ip to choose from
-            let range = get_ip_range(from, to)?;
-
-            // we need to get the position of the clone in the list
-            let clone_pos = clone_list.iter().position(|&p| p == clone_n)
-                .context("getting clone position in clone list")? as u32;
-            // make sure the possible ip range suits the number of clones
-            let clone_list_len = clone_list.len() as u32;
-            if clone_list_len != range + 1 {
-                bail!("the number of clones for the interface {clone_list_len} did not match the ip range given {}", range +1);
-            }
-            // create ip from range
-            let mut ip: u32 = match from {
-                IpAddr::V4(v4) => v4.into(),
-                IpAddr::V6(_) => unimplemented!(),
-            };
-            // make sure clone number is within the range
-            if clone_pos > range {
-                bail!("clone id's {} position {} is greater than the number of ips in the range {}", &clone_n, &clone_pos, range + 1);
-            }
-
-            ip += clone_pos;
-            let ip: IpAddr = match from {
-                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::from(ip)),
-                IpAddr::V6(_) => unimplemented!(),
-            };
-
-            Ok(ip.to_string())
-        }
+        ConfigScalingIpType::IpRange(ip_range) => get_clone_ip_in_range(clone_n, clone_list, ip_range),
         ConfigScalingIpType::Dynamic => {
             Ok("dynamic".to_string())
         }
@@ -372,6 +341,48 @@ fn get_clone_ip_from_range(
 
 }
 
+/// Return the ip for the clone from an ipv4 or ipv6 range, based on the position of the clone in
+/// the clone list.
+fn get_clone_ip_in_range(
+    clone_n: u32,
+    clone_list: &[u32],
+    ip_range: &ConfigScalingIpRange,
+) -> anyhow::Result<String> {
+    // make sure ip values are formatted properly
+    let from = ip_range.from.parse::<IpAddr>()
+        .context("parsing ConfigScalingIpRange (from) into ip address".to_string())?;
+    let to = ip_range.to.parse::<IpAddr>()
+        .context("parsing ConfigScalingIpRange (to) into ip address".to_string())?;
+    // make sure both ips are same type
+    if from.is_ipv4() != to.is_ipv4() || from.is_ipv6() != to.is_ipv6() {
+        bail!("ip types need to match either both ipv4 or ipv6, from = {} and to = {}", from.to_string(), to.to_string())
+    }
+    // get range of possible ips, the range can be 0 if the from and to are the same, meaning there
+    // is only one ip to choose from
+    let range = get_ip_range(from, to)?;
+
+    // we need to get the position of the clone in the list
+    let clone_pos = clone_list.iter().position(|&p| p == clone_n)
+        .context("getting clone position in clone list")? as u128;
+    // make sure the possible ip range suits the number of clones
+    let clone_list_len = clone_list.len() as u128;
+    if clone_list_len != range + 1 {
+        bail!("the number of clones for the interface {clone_list_len} did not match the ip range given {}", range +1);
+    }
+    // make sure clone number is within the range
+    if clone_pos > range {
+        bail!("clone id's {} position {} is greater than the number of ips in the range {}", &clone_n, &clone_pos, range + 1);
+    }
+
+    // create ip from range
+    let ip: IpAddr = match from {
+        IpAddr::V4(v4) => IpAddr::V4(Ipv4Addr::from(u32::from(v4) + clone_pos as u32)),
+        IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(u128::from(v6) + clone_pos)),
+    };
+
+    Ok(ip.to_string())
+}
+
 /// Return the `MacAddress` for the clone based on it's clone n, which dictates the mac selected in
 /// the range specified.
 fn get_clone_mac_from_range(
@@ -413,27 +424,17 @@ fn get_clone_mac_from_range(
 fn get_ip_range(
     from: IpAddr,
     to: IpAddr,
-) -> anyhow::Result<u32> {
-    // get range of ip, depends on type, assume we have checked both are same type, we can just work
-    // from one
-    let range = match from {
-        IpAddr::V4(from_v4) => {
-            // let from_octets = v4.octets();
-            let to_v4 = match to {
-                IpAddr::V4(v4) => v4,
-                _ => unreachable!()
-            };
-
-            let from_ip_bytes: u32 = from_v4.into();
-            let to_ip_bytes: u32 = to_v4.into();
-            if to_ip_bytes < from_ip_bytes {
-                bail!("the ip range \"to\" is less than the \"from\" range, from: {}, to: {}", from_v4, to_v4)
-            }
-            Ok(to_ip_bytes - from_ip_bytes)
-        }
-        IpAddr::V6(_) => unimplemented!(),
+) -> anyhow::Result<u128> {
+    // get range of ip, depends on type, assume we have checked both are same type
+    let (from_ip_bytes, to_ip_bytes) = match (from, to) {
+        (IpAddr::V4(from_v4), IpAddr::V4(to_v4)) => (u32::from(from_v4) as u128, u32::from(to_v4) as u128),
+        (IpAddr::V6(from_v6), IpAddr::V6(to_v6)) => (u128::from(from_v6), u128::from(to_v6)),
+        _ => unreachable!(),
     };
-    range
+    if to_ip_bytes < from_ip_bytes {
+        bail!("the ip range \"to\" is less than the \"from\" range, from: {}, to: {}", from, to)
+    }
+    Ok(to_ip_bytes - from_ip_bytes)
 }
 
 /// Since we are working with mac ranges, we want to work out how many consecutive mac addresses in
@@ -597,6 +598,37 @@ mod tests {
         assert!(ip.is_err());
     }
 
+    #[test]
+    fn test_get_ip_range_v6() {
+        let range = get_ip_range("fd00::1".parse().unwrap(), "fd00::ff".parse().unwrap());
+        assert_eq!(range.unwrap(), 254);
+
+        let range = get_ip_range("fd00::ff".parse().unwrap(), "fd00::1".parse().unwrap());
+        assert!(range.is_err());
+    }
+
+    #[test]
+    fn test_get_clone_ip_from_range_v6() {
+        let ip_addr_range = ConfigScalingIpRange {
+            from: "fd00:10::fe".to_string(),
+            to: "fd00:10::100".to_string()
+        };
+        let ip = get_clone_ip_from_range(
+            2,
+            &vec![0,1,2],
+            &ConfigScalingIpType::IpRange(ip_addr_range),
+        );
+        assert_eq!(ip.unwrap(), "fd00:10::100".to_string());
+
+        // ipv4 and ipv6 can't be mixed in a range
+        let ip_addr_range = ConfigScalingIpRange {
+            from: "10.0.0.1".to_string(),
+            to: "fd00:10::1".to_string()
+        };
+        let ip = get_clone_ip_in_range(0, &vec![0], &ip_addr_range);
+        assert!(ip.is_err());
+    }
+
     #[test]
     fn test_get_clone_ip_from_range_where_clones_distributed_across_switches() {
         // if we have 4 clones and want 2 to be on one switch and the other 2 on another
@@ -4,7 +4,10 @@ use serde::Serialize;
 use std::fmt;
 use std::fmt::Formatter;
 use kvm_compose_schemas::kvm_compose_yaml::{MachineNetwork};
+use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt_image_download::CloudInitFlavour;
 use crate::components::helpers::xml::TEMPLATES;
+use crate::ovn::configuration::dns::guest_nameservers;
+use crate::state::StateNetwork;
 
 // The functions in this file simply create a string representation of the cloud-init metadata files
 // to be passed to the "virt-install" command
@@ -46,8 +49,14 @@ pub fn create_meta_data(
     )
 }
 
-pub fn create_user_data() -> Vec<u8> {
-    Assets::get("cloud_init.yaml").unwrap().data.into_owned()
+/// The user data for the type of cloud-init in the image, cirros does not read cloud-config so it
+/// is given a script to run instead
+pub fn create_user_data(cloud_init: &CloudInitFlavour) -> Vec<u8> {
+    let asset = match cloud_init {
+        CloudInitFlavour::CloudInit => "cloud_init.yaml",
+        CloudInitFlavour::CirrosInit => "cirros_init.sh",
+    };
+    Assets::get(asset).unwrap().data.into_owned()
 }
 
 /// This struct represents one ethernet definition in the cloud-init network config yaml
@@ -56,46 +65,64 @@ struct NetworkConfigEthernet {
     name: String,
     mac_address: String,
     dhcp4: bool,
-    addresses: Option<String>,
-    routes: Option<String>,
+    dhcp6: bool,
+    accept_ra: bool,
+    addresses: Vec<String>,
+    routes: Vec<NetworkConfigRoute>,
     nameservers: String,
 }
 
+#[derive(Serialize)]
+struct NetworkConfigRoute {
+    to: String,
+    via: String,
+}
+
 pub fn create_network_config(
     network_definition: &Option<Vec<MachineNetwork>>,
+    network: &StateNetwork,
+    project_name: &str,
 ) -> anyhow::Result<Vec<u8>> {
     let mut tera_context = tera::Context::new();
     let mut interfaces = Vec::new();
     if let Some(network_definition) = network_definition {
         for (idx, interface) in network_definition.iter().enumerate() {
             let interface_name = format!("ens{idx}");
-            let dhcp = if interface.ip.eq(&"dynamic".to_string()) {
-                true
-            } else {
-                false
-            };
-            let ip = if interface.ip.eq(&"dynamic".to_string()) {
-                None
-            } else {
-                Some(interface.ip.clone())
-            };
+            let interface_ip = interface.ip()?;
+            let dhcp = interface_ip.eq("dynamic");
+            let mut addresses = Vec::new();
+            if !dhcp {
+                let prefix = network.subnet_prefix(project_name, &interface.switch)?;
+                addresses.push(format!("{interface_ip}/{prefix}"));
+            }
+            // auto ipv6 addresses come from the router advertisements, with SLAAC or DHCPv6
+            let ipv6_auto = interface.ipv6.as_deref().is_some_and(|ipv6| ipv6.eq("auto"));
+            if let Some((ipv6, prefix)) = interface.static_ipv6()? {
+                addresses.push(format!("{ipv6}/{prefix}"));
+            }
 
             // we can only define the default route once, so only apply on first interface
             // TODO - when interfaces are on different logical switches, the gateway may need to be
             //  different - maybe don't use default?
-            let routes = if idx > 0 {
-                None
-            } else {
-                interface.gateway.clone()
-            };
-            
+            let mut routes = Vec::new();
+            if idx == 0 {
+                if let Some(gateway) = &interface.gateway {
+                    routes.push(NetworkConfigRoute { to: "default".to_string(), via: gateway.clone() });
+                }
+                if let Some(gateway) = &interface.ipv6_gateway {
+                    routes.push(NetworkConfigRoute { to: "::/0".to_string(), via: gateway.clone() });
+                }
+            }
+
             let yaml_def = NetworkConfigEthernet {
                 name: interface_name,
-                mac_address: interface.mac.clone(),
+                mac_address: interface.mac()?.clone(),
                 dhcp4: dhcp,
-                addresses: ip,
+                dhcp6: ipv6_auto,
+                accept_ra: ipv6_auto,
+                addresses,
                 routes,
-                nameservers: "1.1.1.1".to_string(),
+                nameservers: guest_nameservers(interface.gateway.as_ref()).join(", "),
             };
             interfaces.push(yaml_def);
         }
@@ -106,3 +133,49 @@ pub fn create_network_config(
 
     Ok(render.into_bytes())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::state::{StateOvsBridge, StateOvsNetwork};
+    use super::*;
+
+    #[test]
+    fn test_user_data_for_flavour() {
+        let cloud_init = String::from_utf8(create_user_data(&CloudInitFlavour::CloudInit)).unwrap();
+        assert!(cloud_init.contains("#cloud-config"));
+        // cirros only runs user data that starts with a shebang
+        let cirros = String::from_utf8(create_user_data(&CloudInitFlavour::CirrosInit)).unwrap();
+        assert!(cirros.starts_with("#!/bin/sh"));
+    }
+
+    #[test]
+    fn test_network_config_dual_stack() {
+        let network = vec![MachineNetwork {
+            switch: "sw0".to_string(),
+            gateway: Some("10.0.0.1".to_string()),
+            mac: Some("00:00:00:00:00:01".to_string()),
+            ip: Some("10.0.0.10".to_string()),
+            ipv6: Some("fd00:10::10".to_string()),
+            ipv6_gateway: Some("fd00:10::1".to_string()),
+            network_name: None,
+            qos: None,
+        }];
+        // the prefix comes from the subnet of the bridge
+        let state_network = StateNetwork::Ovs(StateOvsNetwork {
+            bridges: BTreeMap::from([("sw0".to_string(), StateOvsBridge {
+                name: "test-sw0".to_string(),
+                subnet: "10.0.0.0/20".to_string(),
+                testbed_host: "host1".to_string(),
+            })]),
+            connections: vec![],
+        });
+        let config = String::from_utf8(create_network_config(&Some(network), &state_network, "test").unwrap()).unwrap();
+        let config: serde_yaml::Value = serde_yaml::from_str(&config).unwrap();
+        let ens0 = &config["network"]["ethernets"]["ens0"];
+        assert_eq!(ens0["addresses"], serde_yaml::from_str::<serde_yaml::Value>("[10.0.0.10/20, fd00:10::10/64]").unwrap());
+        assert_eq!(ens0["routes"][1]["to"].as_str(), Some("::/0"));
+        assert_eq!(ens0["routes"][1]["via"].as_str(), Some("fd00:10::1"));
+        assert_eq!(ens0["accept-ra"].as_bool(), Some(false));
+        assert_eq!(ens0["nameservers"]["addresses"], serde_yaml::from_str::<serde_yaml::Value>("[10.0.0.1, 1.1.1.1]").unwrap());
+    }
+}
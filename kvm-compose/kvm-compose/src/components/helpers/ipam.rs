@@ -0,0 +1,347 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr};
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use kvm_compose_schemas::kvm_compose_yaml::Config;
+use kvm_compose_schemas::kvm_compose_yaml::network::NetworkBackend;
+use kvm_compose_schemas::kvm_compose_yaml::network::switch::SwitchPortType;
+use kvm_compose_schemas::kvm_compose_yaml::validation::Subnet;
+use crate::ovn::components::MacAddress;
+
+/// The addresses allocated to guest interfaces that did not have an ip or mac in the yaml, keyed
+/// by `guest/interface index`
+pub type AddressAllocations = BTreeMap<String, AddressAllocation>;
+
+/// The addresses allocated to an interface, only the addresses that were missing in the yaml are
+/// set
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub struct AddressAllocation {
+    pub switch: String,
+    pub ip: Option<String>,
+    pub mac: Option<String>,
+}
+
+/// Allocate an ip and mac to every guest interface that doesn't have one. Ips are the lowest free
+/// ip in the switch's subnet, skipping router port gateway ips, internal switch port ips, DHCP
+/// excluded ips and ips used by other guests. Macs are generated from the project, guest and
+/// interface index so they stay the same between deployments. Allocations from the previous state
+/// are kept if they are still free, so adding a guest does not move the addresses of other guests.
+/// This must be run after the clones have been generated.
+pub fn allocate_addresses(
+    config: &mut Config,
+    project_name: &str,
+    previous: &AddressAllocations,
+) -> anyhow::Result<AddressAllocations> {
+    let mut ipam = Ipam::new(config)?;
+
+    // interfaces that need an address, in the order they are in the yaml
+    let mut missing = Vec::new();
+    for machine in config.machines.iter().flatten() {
+        for (idx, interface) in machine.network.iter().flatten().enumerate() {
+            if interface.ip.is_none() || interface.mac.is_none() {
+                missing.push((machine.name.clone(), idx, interface.switch.clone(), interface.ip.is_none(), interface.mac.is_none()));
+            }
+        }
+    }
+
+    let mut allocations = AddressAllocations::new();
+    // keep the previous allocations first, so new interfaces can't take them
+    for (guest, idx, switch, needs_ip, needs_mac) in &missing {
+        let key = format!("{guest}/{idx}");
+        let Some(previous) = previous.get(&key).filter(|p| p.switch.eq(switch)) else {
+            continue;
+        };
+        let ip = previous.ip.as_ref()
+            .filter(|_| *needs_ip)
+            .and_then(|ip| ip.parse::<Ipv4Addr>().ok())
+            .filter(|ip| ipam.is_free_in_switch(switch, *ip))
+            .inspect(|ip| ipam.claim_ip(*ip));
+        let mac = previous.mac.as_ref()
+            .filter(|_| *needs_mac)
+            .and_then(|mac| MacAddress::new(mac.clone()).ok())
+            .and_then(|mac| mac.as_bytes)
+            .filter(|mac| !ipam.used_macs.contains(mac))
+            .inspect(|mac| { ipam.used_macs.insert(*mac); });
+        allocations.insert(key, AddressAllocation {
+            switch: switch.clone(),
+            ip: ip.map(|ip| ip.to_string()),
+            mac: mac.map(|mac| MacAddress::from_u64(mac).map(|m| m.address)).transpose()?,
+        });
+    }
+
+    for (guest, idx, switch, needs_ip, needs_mac) in &missing {
+        let key = format!("{guest}/{idx}");
+        let allocation = allocations.entry(key).or_insert(AddressAllocation {
+            switch: switch.clone(),
+            ip: None,
+            mac: None,
+        });
+        if *needs_ip && allocation.ip.is_none() {
+            let ip = ipam.next_free_ip(switch)
+                .with_context(|| format!("allocating an ip for guest {guest} interface {idx}"))?;
+            allocation.ip = Some(ip.to_string());
+        }
+        if *needs_mac && allocation.mac.is_none() {
+            allocation.mac = Some(ipam.generate_mac(project_name, guest, *idx)?);
+        }
+    }
+
+    // fill in the config so the rest of the testbed sees the addresses as if they were in the yaml
+    for machine in config.machines.iter_mut().flatten() {
+        for (idx, interface) in machine.network.iter_mut().flatten().enumerate() {
+            let Some(allocation) = allocations.get(&format!("{}/{idx}", machine.name)) else {
+                continue;
+            };
+            if interface.ip.is_none() {
+                interface.ip = allocation.ip.clone();
+                tracing::info!("allocated ip {} to guest {} interface {idx}", allocation.ip.as_deref().unwrap_or_default(), &machine.name);
+            }
+            if interface.mac.is_none() {
+                interface.mac = allocation.mac.clone();
+            }
+        }
+    }
+    Ok(allocations)
+}
+
+/// The subnets of the switches and the addresses already used in the yaml
+struct Ipam {
+    subnets: HashMap<String, (u32, u8)>,
+    used_ips: HashSet<Ipv4Addr>,
+    used_macs: HashSet<u64>,
+}
+
+impl Ipam {
+    fn new(config: &Config) -> anyhow::Result<Self> {
+        let mut ipam = Self {
+            subnets: HashMap::new(),
+            used_ips: HashSet::new(),
+            used_macs: HashSet::new(),
+        };
+        let network = match &config.network {
+            NetworkBackend::Ovn(network) => network,
+            NetworkBackend::Ovs(network) => {
+                // OVS bridges only have a subnet, there are no router or internal ports
+                for (name, bridge) in network.bridges.iter() {
+                    let subnet = Subnet::parse(&bridge.subnet)
+                        .map_err(anyhow::Error::msg)
+                        .with_context(|| format!("parsing subnet of bridge {name}"))?;
+                    if let IpAddr::V4(ip) = subnet.ip {
+                        ipam.subnets.insert(name.clone(), (u32::from(ip), subnet.mask));
+                    }
+                }
+                ipam.claim_machine_addresses(config);
+                return Ok(ipam);
+            }
+        };
+
+        for (name, switch) in network.switches.iter().flatten() {
+            let subnet = Subnet::parse(&switch.subnet)
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("parsing subnet of switch {name}"))?;
+            if let IpAddr::V4(ip) = subnet.ip {
+                ipam.subnets.insert(name.clone(), (u32::from(ip), subnet.mask));
+            }
+            for port in switch.ports.iter().flatten() {
+                match &port.port_type {
+                    SwitchPortType::Internal { ip, mac, .. } => {
+                        if let Some(ip) = ip {
+                            ipam.claim_ip_string(ip);
+                        }
+                        if let Some(mac) = mac {
+                            ipam.claim_mac_string(mac);
+                        }
+                    }
+                    SwitchPortType::Router { mac: Some(mac), .. } => ipam.claim_mac_string(mac),
+                    _ => {}
+                }
+            }
+        }
+
+        for router in network.routers.iter().flat_map(|r| r.values()) {
+            for port in router.ports.iter().flatten() {
+                ipam.claim_ip_string(&port.gateway_ip);
+                ipam.claim_mac_string(&port.mac);
+            }
+            for dhcp in router.dhcp.iter().flatten() {
+                let from = dhcp.exclude_ips.from.parse::<Ipv4Addr>();
+                let to = dhcp.exclude_ips.to.parse::<Ipv4Addr>();
+                if let (Ok(from), Ok(to)) = (from, to) {
+                    for ip in u32::from(from)..=u32::from(to) {
+                        ipam.claim_ip(Ipv4Addr::from(ip));
+                    }
+                }
+            }
+        }
+
+        ipam.claim_machine_addresses(config);
+        Ok(ipam)
+    }
+
+    /// Claim the ips and macs given to guest interfaces in the yaml
+    fn claim_machine_addresses(&mut self, config: &Config) {
+        for machine in config.machines.iter().flatten() {
+            for interface in machine.network.iter().flatten() {
+                if let Some(ip) = &interface.ip {
+                    self.claim_ip_string(ip);
+                }
+                if let Some(mac) = &interface.mac {
+                    self.claim_mac_string(mac);
+                }
+            }
+        }
+    }
+
+    fn claim_ip(&mut self, ip: Ipv4Addr) {
+        self.used_ips.insert(ip);
+    }
+
+    /// Claim an ip given as `ip` or `ip/mask`, anything else such as `dynamic` is ignored
+    fn claim_ip_string(&mut self, ip: &str) {
+        let ip = ip.split_once('/').map(|(ip, _)| ip).unwrap_or(ip);
+        if let Ok(ip) = ip.parse::<Ipv4Addr>() {
+            self.claim_ip(ip);
+        }
+    }
+
+    fn claim_mac_string(&mut self, mac: &str) {
+        if let Ok(MacAddress { as_bytes: Some(mac), .. }) = MacAddress::new(mac.to_string()) {
+            self.used_macs.insert(mac);
+        }
+    }
+
+    /// The usable ips of the switch's subnet, which excludes the network and broadcast address
+    fn host_range(&self, switch: &str) -> anyhow::Result<std::ops::RangeInclusive<u32>> {
+        let Some((network, mask)) = self.subnets.get(switch) else {
+            bail!("switch {switch} does not have an IPv4 subnet to allocate an ip from, give the interface an ip");
+        };
+        let size = 1u64 << (32 - *mask as u32);
+        if size < 4 {
+            bail!("the subnet of switch {switch} is too small to allocate an ip from");
+        }
+        let network = network & (u32::MAX.checked_shl(32 - *mask as u32).unwrap_or(0));
+        Ok(network + 1..=network + (size - 2) as u32)
+    }
+
+    fn is_free_in_switch(&self, switch: &str, ip: Ipv4Addr) -> bool {
+        match self.host_range(switch) {
+            Ok(range) => range.contains(&u32::from(ip)) && !self.used_ips.contains(&ip),
+            Err(_) => false,
+        }
+    }
+
+    fn next_free_ip(&mut self, switch: &str) -> anyhow::Result<Ipv4Addr> {
+        for ip in self.host_range(switch)? {
+            let ip = Ipv4Addr::from(ip);
+            if !self.used_ips.contains(&ip) {
+                self.claim_ip(ip);
+                return Ok(ip);
+            }
+        }
+        bail!("there are no free ips left in the subnet of switch {switch}");
+    }
+
+    /// Generate a locally administered mac from the hash of the project, guest and interface
+    /// index, hashing again with a counter if the mac is already used
+    fn generate_mac(&mut self, project_name: &str, guest: &str, idx: usize) -> anyhow::Result<String> {
+        for attempt in 0u32.. {
+            let hash = Sha256::digest(format!("{project_name}/{guest}/{idx}/{attempt}").as_bytes());
+            let mut octets = [0u8; 6];
+            octets.copy_from_slice(&hash[..6]);
+            // unicast and locally administered
+            octets[0] = (octets[0] & 0xfc) | 0x02;
+            let mac = octets.iter().fold(0u64, |acc, o| (acc << 8) | u64::from(*o));
+            if self.used_macs.insert(mac) {
+                return Ok(MacAddress::from_u64(mac)?.address);
+            }
+        }
+        unreachable!()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(machines: &str) -> Config {
+        let yaml = format!(r#"
+machines:
+{machines}
+network:
+  ovn:
+    switches:
+      sw0:
+        subnet: "10.0.0.0/24"
+    routers:
+      lr0:
+        ports:
+          - name: lr0-sw0
+            mac: "00:00:00:00:ff:01"
+            gateway_ip: "10.0.0.1/24"
+            switch: sw0
+        dhcp:
+          - switch: sw0
+            exclude_ips:
+              from: "10.0.0.2"
+              to: "10.0.0.9"
+"#);
+        serde_yaml::from_str(&yaml).unwrap()
+    }
+
+    fn guest(name: &str, network: &str) -> String {
+        format!("  - name: {name}\n    network:\n{network}\n    docker:\n      image: nginx\n")
+    }
+
+    fn interface_addresses(config: &Config) -> Vec<(String, String)> {
+        config.machines.iter().flatten()
+            .flat_map(|m| m.network.iter().flatten())
+            .map(|i| (i.ip.clone().unwrap(), i.mac.clone().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_allocate_addresses() {
+        let machines = [
+            guest("a", "      - switch: sw0"),
+            guest("b", "      - switch: sw0\n        ip: \"10.0.0.10\"\n        mac: \"00:00:00:00:00:0b\""),
+            guest("c", "      - switch: sw0\n        ip: dynamic"),
+        ].concat();
+        let mut config = config(&machines);
+        let allocations = allocate_addresses(&mut config, "project", &AddressAllocations::new()).unwrap();
+        let addresses = interface_addresses(&config);
+        // skips the gateway, the DHCP excluded ips and the ip already used by b
+        assert_eq!(addresses[0].0, "10.0.0.11");
+        assert_eq!(addresses[1], ("10.0.0.10".to_string(), "00:00:00:00:00:0b".to_string()));
+        assert_eq!(addresses[2].0, "dynamic");
+        assert_eq!(allocations.len(), 2);
+        assert_eq!(allocations["c/0"].ip, None);
+
+        // the same project and guests always get the same macs
+        let mut again = self::config(&machines);
+        allocate_addresses(&mut again, "project", &AddressAllocations::new()).unwrap();
+        assert_eq!(interface_addresses(&again), addresses);
+    }
+
+    #[test]
+    fn test_previous_allocations_are_kept() {
+        let mut config = config(&guest("a", "      - switch: sw0"));
+        let previous = AddressAllocations::from([("a/0".to_string(), AddressAllocation {
+            switch: "sw0".to_string(),
+            ip: Some("10.0.0.50".to_string()),
+            mac: Some("02:00:00:00:00:50".to_string()),
+        })]);
+        allocate_addresses(&mut config, "project", &previous).unwrap();
+        assert_eq!(interface_addresses(&config)[0], ("10.0.0.50".to_string(), "02:00:00:00:00:50".to_string()));
+
+        // a previous ip that is now used in the yaml is not reused
+        let machines = [
+            guest("b", "      - switch: sw0\n        ip: \"10.0.0.50\""),
+            guest("a", "      - switch: sw0"),
+        ].concat();
+        let mut config = self::config(&machines);
+        allocate_addresses(&mut config, "project", &previous).unwrap();
+        assert_eq!(interface_addresses(&config)[1].0, "10.0.0.10");
+    }
+}
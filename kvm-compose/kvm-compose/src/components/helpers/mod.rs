@@ -3,6 +3,7 @@ use std::path::PathBuf;
 pub mod artefact_generation;
 pub mod cloud_init;
 pub mod clones;
+pub mod ipam;
 pub mod xml;
 pub mod serialisation;
 pub mod android;
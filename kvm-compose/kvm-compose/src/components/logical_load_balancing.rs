@@ -1,5 +1,5 @@
-use std::collections::HashMap;
-use anyhow::Context;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use anyhow::{bail, Context};
 use crate::components::LogicalTestbed;
 use kvm_compose_schemas::kvm_compose_yaml::network::NetworkBackend;
 use kvm_compose_schemas::kvm_compose_yaml::testbed_options::LoadBalancing;
@@ -20,12 +20,15 @@ pub fn load_balance(logical_testbed: &mut LogicalTestbed) -> anyhow::Result<Load
 
 pub struct LoadBalanceTopology {
     pub guest_to_host: HashMap<String, String>,
+    /// Only used by the OVS network, the testbed host of each bridge
+    pub bridge_to_host: HashMap<String, String>,
     // pub interface_to_host: HashMap<String, String>,
 }
 
 /// Simply assign to each testbed host until we run out of resources to assign
 fn naive_round_robin(logical_testbed: &mut LogicalTestbed) -> anyhow::Result<LoadBalanceTopology> {
     let mut guest_to_host = HashMap::new();
+    let mut bridge_to_host = HashMap::new();
     // let mut interface_to_host = HashMap::new();
     match &logical_testbed.common.config.network {
         NetworkBackend::Ovn(_) => {
@@ -49,14 +52,65 @@ fn naive_round_robin(logical_testbed: &mut LogicalTestbed) -> anyhow::Result<Loa
             }
 
         }
-        NetworkBackend::Ovs(_) => {
+        NetworkBackend::Ovs(ovs_network) => {
             // for OVS network, need to assign each bridge to each host which will dictate where
-            // each guest will be
-            unimplemented!()
+            // each guest will be, as a guest's interfaces are plugged directly into the bridges
+            let tb_config = &logical_testbed.common.kvm_compose_config.testbed_host_ssh_config;
+            let mut hosts = tb_config.iter().cycle();
+            // the bridges each guest is connected to
+            let guest_bridges: Vec<BTreeSet<String>> = logical_testbed.logical_guests.iter()
+                .map(|guest| guest.get_machine_definition().network.iter().flatten()
+                    .map(|interface| interface.switch.clone())
+                    .collect())
+                .collect();
+
+            // sort the bridges so that the assignment is the same between runs
+            let bridges: BTreeMap<_, _> = ovs_network.bridges.iter().collect();
+            for (name, bridge) in &bridges {
+                if let Some(host) = &bridge.testbed_host {
+                    if !tb_config.contains_key(host) {
+                        bail!("bridge {name} is pinned to testbed host {host} which is not in the testbed cluster");
+                    }
+                    bridge_to_host.insert(name.to_string(), host.clone());
+                }
+            }
+            for name in bridges.keys() {
+                if bridge_to_host.contains_key(*name) {
+                    continue;
+                }
+                // a bridge that shares a guest with an assigned bridge must be on the same host
+                let shared_host = guest_bridges.iter()
+                    .filter(|connected| connected.contains(*name))
+                    .flatten()
+                    .find_map(|bridge| bridge_to_host.get(bridge).cloned());
+                let host = match shared_host {
+                    Some(host) => host,
+                    None => hosts.next().context("getting next host for naive round robin")?.0.to_string(),
+                };
+                tracing::info!("assigning bridge {} to host {}", name, host);
+                bridge_to_host.insert(name.to_string(), host);
+            }
+
+            for (guest, connected) in logical_testbed.logical_guests.iter_mut().zip(guest_bridges) {
+                let guest_hosts = connected.iter()
+                    .map(|bridge| bridge_to_host.get(bridge)
+                        .with_context(|| format!("getting testbed host of bridge {bridge} for guest {}", guest.get_guest_name())))
+                    .collect::<anyhow::Result<BTreeSet<_>>>()?;
+                let host = match guest_hosts.len() {
+                    // a guest without a network can go anywhere
+                    0 => hosts.next().context("getting next host for naive round robin")?.0.to_string(),
+                    1 => guest_hosts.first().context("getting guest host")?.to_string(),
+                    _ => bail!("guest {} is connected to bridges on different testbed hosts {:?}, pin the bridges to the same testbed host", guest.get_guest_name(), guest_hosts),
+                };
+                tracing::info!("assigning guest {} to host {}", guest.get_guest_name(), host);
+                guest.set_testbed_host(host.clone());
+                guest_to_host.insert(guest.get_guest_name().clone(), host);
+            }
         }
     }
     return Ok(LoadBalanceTopology {
         guest_to_host,
+        bridge_to_host,
         // interface_to_host,
     })
 }
@@ -21,6 +21,7 @@ use crate::components::network::{LogicalNetwork};
 use crate::orchestration::api::{OrchestrationInstruction, OrchestrationProtocol};
 use crate::orchestration::websocket::{send_orchestration_instruction_over_channel};
 use crate::ovn::components::MacAddress;
+use crate::components::helpers::ipam::AddressAllocations;
 
 // The purpose of the Logical testbed struct that holds all of the components is to provide
 // a generic interface for artefact generation and creating state as there are various types
@@ -158,6 +159,8 @@ pub struct LogicalTestbed {
     pub guest_ip_mapping: Option<BTreeMap<String, Option<String>>>,
     // this stored the guest to mac mapping, necessary for OVN
     pub guest_mac_mapping: Option<BTreeMap<String, MacAddress>>,
+    // the ips and macs allocated to interfaces that did not have them in the yaml
+    pub address_allocations: AddressAllocations,
 
     // store a copy of Common
     pub common: Common,
@@ -171,6 +174,7 @@ impl LogicalTestbed {
             network: None,
             guest_ip_mapping: None,
             guest_mac_mapping: None,
+            address_allocations: AddressAllocations::new(),
             common: in_common,
         }
     }
@@ -198,20 +202,19 @@ impl LogicalTestbed {
                     &load_balance_topology,
                     &self.common.kvm_compose_config.testbed_host_ssh_config,
                     &self.logical_guests,
-                    &self.common.project
+                    &self.common.project,
+                    self.common.config.testbed_options.flow_export.as_ref(),
                 )?);
             }
             NetworkBackend::Ovs(ovs_network) => {
-                // TODO - create a mapping of OVS bridge to host
-                //  directly create the state representation here
-                self.network = Some(LogicalNetwork::new_ovs(ovs_network));
-
-                // TODO - work out the connections between OVS bridges to create either local on the
-                //  same host or tunnel connections between hosts
-
-                // TODO - assign guest ips where necessary
-
-                unimplemented!()
+                // the bridges have been assigned to hosts by the load balancing, so work out which
+                // links are local veths and which are tunnels between hosts
+                self.network = Some(LogicalNetwork::new_ovs(
+                    ovs_network,
+                    &load_balance_topology,
+                    &self.common.kvm_compose_config.testbed_host_ssh_config,
+                    &self.common.project,
+                )?);
             }
         }
 
@@ -318,12 +321,30 @@ impl LogicalTestbed {
 /// 7 characters, the interface id 1 character, and the id 4 characters and start with 'vm-',
 /// meaning we can support 9999 guests which should be enough for the foreseeable future..
 pub fn get_guest_interface_name(project_name: &String, unique_id: u32, idx: usize) -> String {
-    let truncated_project_name = if project_name.len() > 7 {
-        let mut temp = project_name.clone();
+    let truncated_project_name = truncate_project_name(project_name);
+    format!("vm-{truncated_project_name}{unique_id}{idx}")
+}
+
+/// The name of an OVS bridge in the OVS network backend, this has the same length limits as the
+/// guest interfaces so follows the same scheme, using the position of the bridge in the network
+pub fn get_bridge_name(project_name: &String, bridge_id: usize) -> String {
+    let truncated_project_name = truncate_project_name(project_name);
+    format!("br-{truncated_project_name}{bridge_id}")
+}
+
+/// The name of the veth or tunnel port at one end of a link between OVS bridges, using the
+/// position of the link in the network
+pub fn get_bridge_link_name(project_name: &String, link_id: usize, end: usize) -> String {
+    let truncated_project_name = truncate_project_name(project_name);
+    format!("ln-{truncated_project_name}{link_id}{end}")
+}
+
+fn truncate_project_name(project_name: &str) -> String {
+    if project_name.len() > 7 {
+        let mut temp = project_name.to_string();
         temp.truncate(7);
         temp
     } else {
-        project_name.clone()
-    };
-    format!("vm-{truncated_project_name}{unique_id}{idx}")
+        project_name.to_string()
+    }
 }
@@ -1,22 +1,39 @@
-use crate::components::{LogicalGuests};
-use std::collections::{HashMap};
-use std::net::{IpAddr};
+use crate::components::{get_bridge_link_name, get_bridge_name, LogicalGuests};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 use anyhow::{bail, Context};
+use sha2::{Digest, Sha256};
 use kvm_compose_schemas::kvm_compose_yaml::{Machine, MachineNetwork};
 use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
 use kvm_compose_schemas::kvm_compose_yaml::network::{OvnNetworkSchema, OvsNetwork};
-use kvm_compose_schemas::kvm_compose_yaml::network::router::{NatType, RouterPort};
+use kvm_compose_schemas::kvm_compose_yaml::network::acl::{map_acl_match_references, ACLDirection, ACLRule, ACL, APPLY_DENY_ALL_NOT_IMPLEMENTED};
+use kvm_compose_schemas::kvm_compose_yaml::network::load_balancer::LoadBalancer;
+use kvm_compose_schemas::kvm_compose_yaml::network::mirror::Mirror;
+use kvm_compose_schemas::kvm_compose_yaml::network::policy::{Policy, PolicyProtocol};
+use kvm_compose_schemas::kvm_compose_yaml::testbed_options::FlowExport;
+use kvm_compose_schemas::kvm_compose_yaml::network::router::{Dhcp, Ipv6AddressMode, NatType, RouterPort};
 use kvm_compose_schemas::kvm_compose_yaml::network::switch::{SwitchPort, SwitchPortType};
 use kvm_compose_schemas::settings::SshConfig;
 use crate::components::logical_load_balancing::LoadBalanceTopology;
 use crate::ovn::components::{MacAddress, OvnIpAddr};
 use crate::ovn::components::acl::ACLRecordType;
+use crate::ovn::components::logical_switch::LogicalSwitch;
+use crate::ovn::components::logical_switch_port::{LogicalSwitchPort, LogicalSwitchPortQos, LogicalSwitchPortType};
+use crate::ovn::components::address_set::LogicalAddressSet;
+use crate::ovn::components::load_balancer::LogicalLoadBalancer;
+use crate::ovn::components::mirror::OvnMirror;
+use crate::ovn::components::port_group::LogicalPortGroup;
+use crate::ovn::components::ovs::OvsFlowExport;
+use crate::ovn::configuration::dhcp::RouterAdvertisementOptions;
+use crate::ovn::configuration::dns::{DnsRecordAddress, OvnDnsRecords};
 use crate::ovn::configuration::nat::OvnNatType;
 use crate::ovn::ovn::OvnNetwork;
+use crate::state::{StateOvsBridge, StateOvsNetwork};
+use crate::state::load_balancing::BridgeConnection;
 
 pub enum LogicalNetwork {
     Ovn(OvnNetwork),
-    Ovs(LogicalOvsNetwork),
+    Ovs(StateOvsNetwork),
 }
 
 impl LogicalNetwork {
@@ -26,16 +43,79 @@ impl LogicalNetwork {
         tb_config: &HashMap<String, SshConfig>,
         guest_list: &LogicalGuests,
         project_name: &String,
+        flow_export: Option<&FlowExport>,
     ) -> anyhow::Result<LogicalNetwork> {
-        Ok(LogicalNetwork::Ovn(parse_ovn_schema(ovn_network_schema, load_balance_topology, tb_config, guest_list, project_name)?))
+        Ok(LogicalNetwork::Ovn(parse_ovn_schema(ovn_network_schema, load_balance_topology, tb_config, guest_list, project_name, flow_export)?))
     }
-    pub fn new_ovs(_ovs_network: &OvsNetwork) -> LogicalNetwork {
-        unimplemented!();
+    pub fn new_ovs(
+        ovs_network: &OvsNetwork,
+        load_balance_topology: &LoadBalanceTopology,
+        tb_config: &HashMap<String, SshConfig>,
+        project_name: &String,
+    ) -> anyhow::Result<LogicalNetwork> {
+        Ok(LogicalNetwork::Ovs(parse_ovs_schema(ovs_network, load_balance_topology, tb_config, project_name)?))
     }
 }
 
-pub struct LogicalOvsNetwork {
+/// Take the ovs schema from the yaml and the testbed host of each bridge from the load balancing,
+/// and work out the bridge names and how each link between the bridges is made
+fn parse_ovs_schema(
+    ovs_network: &OvsNetwork,
+    load_balance_topology: &LoadBalanceTopology,
+    tb_config: &HashMap<String, SshConfig>,
+    project_name: &String,
+) -> anyhow::Result<StateOvsNetwork> {
+    tracing::info!("begin defining internal OVS representation");
+    let mut ovs = StateOvsNetwork::default();
+    // sort the bridges so that the names are the same between runs
+    let bridges: BTreeMap<_, _> = ovs_network.bridges.iter().collect();
+    for (bridge_id, (name, bridge)) in bridges.into_iter().enumerate() {
+        let testbed_host = load_balance_topology.bridge_to_host.get(name)
+            .with_context(|| format!("getting testbed host of bridge {name}"))?;
+        ovs.bridges.insert(name.clone(), StateOvsBridge {
+            name: get_bridge_name(project_name, bridge_id),
+            subnet: bridge.subnet.clone(),
+            testbed_host: testbed_host.clone(),
+        });
+    }
 
+    for (link_id, link) in ovs_network.links.iter().flatten().enumerate() {
+        let source = ovs.bridges.get(&link.source)
+            .with_context(|| format!("link source bridge {} does not exist", &link.source))?;
+        let target = ovs.bridges.get(&link.target)
+            .with_context(|| format!("link target bridge {} does not exist", &link.target))?;
+        let connection = if source.testbed_host.eq(&target.testbed_host) {
+            BridgeConnection::Veth {
+                source_br: source.name.clone(),
+                target_br: target.name.clone(),
+                source_veth: get_bridge_link_name(project_name, link_id, 0),
+                target_veth: get_bridge_link_name(project_name, link_id, 1),
+                testbed_host: source.testbed_host.clone(),
+            }
+        } else {
+            let host_ip = |host: &String| tb_config.get(host)
+                .map(|config| config.ip.clone())
+                .with_context(|| format!("getting ip of testbed host {host} for the tunnel between bridges {} and {}", &link.source, &link.target));
+            // the key is hashed from the project so that links of different projects between
+            // the same hosts don't clash, it is limited to the 24 bits of a VXLAN VNI
+            let hash = Sha256::digest(format!("{project_name}/{link_id}").as_bytes());
+            let key = u32::from_be_bytes([0, hash[0], hash[1], hash[2]]);
+            BridgeConnection::Tunnel {
+                tunnel_type: ovs_network.tunnel_type.clone(),
+                source_br: source.name.clone(),
+                target_br: target.name.clone(),
+                port: get_bridge_link_name(project_name, link_id, 0),
+                source_host_ip: host_ip(&source.testbed_host)?,
+                target_host_ip: host_ip(&target.testbed_host)?,
+                key,
+                testbed_host_source: source.testbed_host.clone(),
+                testbed_host_target: target.testbed_host.clone(),
+            }
+        };
+        tracing::info!("linking bridges {} and {}", &link.source, &link.target);
+        ovs.connections.push(connection);
+    }
+    Ok(ovs)
 }
 
 /// Take the ovn schema from the yaml file and infer all the OVN components as the yaml schema is a
@@ -46,6 +126,7 @@ fn parse_ovn_schema(
     tb_config: &HashMap<String, SshConfig>,
     guest_list: &LogicalGuests,
     project_name: &String,
+    flow_export: Option<&FlowExport>,
 ) -> anyhow::Result<OvnNetwork> {
     tracing::info!("begin defining internal OVN representation");
     let mut ovn = OvnNetwork::new();
@@ -61,6 +142,11 @@ fn parse_ovn_schema(
                 ip_and_mask.0,
                 ip_and_mask.1,
             )?;
+            if let Some(ipv6_subnet) = &switch_data.ipv6_subnet {
+                let ipv6_and_mask = subnet_to_ip_and_mask(ipv6_subnet)?;
+                tracing::info!("setting ipv6 prefix {} on logical switch {}", ipv6_subnet, &switch_name);
+                ovn.switch_set_ipv6_prefix(&switch_name, ipv6_and_mask.0, ipv6_and_mask.1)?;
+            }
             // add any ports on the switch, there are a few types
             if let Some(ports) = &switch_data.ports {
                 for port in ports {
@@ -82,6 +168,15 @@ fn parse_ovn_schema(
                     add_router_port(&mut ovn, port, &router_name, project_name)?;
                 }
             }
+            // add ipv6 router advertisements on the router ports
+            for ra in router_data.ipv6_ra.iter().flatten() {
+                let switch_name = format!("{}-{}", project_name, &ra.switch);
+                tracing::info!("defining ipv6 router advertisements ({}) for switch {} on router {}", ra.address_mode.ovn_name(), &switch_name, &router_name);
+                ovn.lrp_set_ipv6_ra(&router_name, &switch_name, RouterAdvertisementOptions {
+                    address_mode: ra.address_mode.clone(),
+                    send_periodic: ra.send_periodic,
+                })?;
+            }
             // add static routes
             if let Some(static_routes) = &router_data.static_routes {
                 for route in static_routes {
@@ -192,29 +287,59 @@ fn parse_ovn_schema(
                     // port(s) with this rule
                     let router_name = format!("{}-{}", project_name, router_name);
                     let switch_name = format!("{}-{}", project_name, &dhcp.switch);
+                    // the reserved ips are fixed on the switch ports before the rule is linked
+                    let mut reserved_ports = Vec::new();
+                    for reservation in dhcp.reservations.iter().flatten() {
+                        let port_name = guest_switch_port_name(project_name, &dhcp.switch, &reservation.guest, reservation.interface);
+                        let ip = reservation.ip.parse()
+                            .context(format!("parsing the reserved ip {} of guest {}", &reservation.ip, &reservation.guest))?;
+                        ovn.lsp_reserve_ip(&port_name, ip)?;
+                        reserved_ports.push(port_name);
+                    }
                     ovn.add_dhcp_option(
                         &router_name,
                         &switch_name,
                         &format!("{}..{}", dhcp.exclude_ips.from, dhcp.exclude_ips.to),
+                        dhcp.lease_time.unwrap_or(3600),
+                        dhcp_ovn_options(dhcp),
+                        &reserved_ports,
                     )?;
                 }
             }
+            // add DHCPv6 options for switches where the router advertisements use DHCPv6
+            for ra in router_data.ipv6_ra.iter().flatten() {
+                let stateless = match ra.address_mode {
+                    Ipv6AddressMode::Slaac => continue,
+                    Ipv6AddressMode::Dhcpv6Stateful => false,
+                    Ipv6AddressMode::Dhcpv6Stateless => true,
+                };
+                let router_name = format!("{}-{}", project_name, router_name);
+                let switch_name = format!("{}-{}", project_name, &ra.switch);
+                ovn.add_dhcpv6_option(&router_name, &switch_name, stateless, ra.dns_servers.as_ref())?;
+            }
         }
     }
 
-    // add ACL
+    // add ACL, the port groups and address sets use the guest switch ports so must come after all
+    // ports
+    let machines: Vec<Machine> = guest_list.iter()
+        .map(|guest| guest.get_machine_definition())
+        .collect();
     if let Some(acl) = &ovn_network_schema.acl {
         if acl.apply_deny_all {
             // TODO
-            bail!("apply_deny_all not yet implemented")
+            bail!(APPLY_DENY_ALL_NOT_IMPLEMENTED)
         }
 
+        add_acl_sets(&mut ovn, acl, &machines, project_name)?;
+
         // for each rule on a switch, make sure the switch exists
         for (switch, acl_rules) in &acl.switches {
             let switch_name = format!("{}-{}", &project_name, &switch);
             if ovn.switches.contains_key(&switch_name) {
                 for rule in acl_rules {
                     let acl_name = format!("{}-{}-{}-{}-{}", &project_name, &switch, &rule.direction, &rule.action, &rule.priority);
+                    let rule = acl_rule_with_set_names(rule, acl, project_name)?;
                     ovn.add_switch_acl(&acl_name, switch_name.clone(), ACLRecordType::Switch, &rule)?;
                 }
             } else {
@@ -222,7 +347,45 @@ fn parse_ovn_schema(
             }
         }
 
-        // if we implement ACL for port groups, create them here and use ACLRecordType::PortGroup
+        for (group, port_group) in acl.port_groups.iter().flatten() {
+            for rule in port_group.rules.iter().flatten() {
+                let acl_name = format!("{}-pg-{}-{}-{}-{}", &project_name, &group, &rule.direction, &rule.action, &rule.priority);
+                let rule = acl_rule_with_set_names(rule, acl, project_name)?;
+                ovn.add_switch_acl(&acl_name, acl_set_name(project_name, group), ACLRecordType::PortGroup, &rule)?;
+            }
+        }
+    }
+
+    // compile the policies into ACLs, these use the guest switch ports so must come after all ports
+    if let Some(policies) = &ovn_network_schema.policies {
+        for (idx, policy) in policies.iter().enumerate() {
+            add_policy(&mut ovn, idx, policy, &machines, project_name)?;
+        }
+    }
+
+    // add mirrors, the sources and sink are resolved to switch ports so must come after all ports
+    if let Some(mirrors) = &ovn_network_schema.mirrors {
+        for (mirror_name, mirror) in mirrors {
+            add_mirror(&mut ovn, mirror_name, mirror, load_balance_topology, guest_list, project_name)?;
+        }
+    }
+
+    // add load balancers, the backends are resolved to the ips of the guest switch ports so must
+    // come after all ports
+    if let Some(load_balancers) = &ovn_network_schema.load_balancers {
+        for (lb_name, load_balancer) in load_balancers {
+            add_load_balancer(&mut ovn, lb_name, load_balancer, &machines, project_name)?;
+        }
+    }
+
+    // add the DNS records of the guests to every switch, these use the guest switch ports so must
+    // come after all ports
+    add_dns_records(&mut ovn, &machines, project_name)?;
+
+    // add flow export on the integration bridges, this needs the switch ports to know which
+    // testbed hosts the switches are on
+    if let Some(flow_export) = flow_export {
+        add_flow_export(&mut ovn, flow_export, tb_config, guest_list, project_name)?;
     }
 
     tracing::info!("validating the OVN internal representation");
@@ -232,6 +395,30 @@ fn parse_ovn_schema(
     Ok(ovn)
 }
 
+/// The DHCPv4 options of a rule by their OVN name, in the value format of the OVN DHCP_Options
+/// table. The options given by name are passed through as they are.
+fn dhcp_ovn_options(dhcp: &Dhcp) -> BTreeMap<String, String> {
+    let mut options = BTreeMap::new();
+    if let Some(dns_servers) = &dhcp.dns_servers {
+        options.insert("dns_server".to_string(), format!("{{{}}}", dns_servers.join(", ")));
+    }
+    if let Some(domain_name) = &dhcp.domain_name {
+        // string options are quoted inside the value
+        options.insert("domain_name".to_string(), format!("\"{domain_name}\""));
+    }
+    if let Some(mtu) = dhcp.mtu {
+        options.insert("mtu".to_string(), mtu.to_string());
+    }
+    if let Some(static_routes) = &dhcp.static_routes {
+        let routes: Vec<_> = static_routes.iter()
+            .map(|route| format!("{},{}", &route.prefix, &route.via))
+            .collect();
+        options.insert("classless_static_route".to_string(), format!("{{{}}}", routes.join(", ")));
+    }
+    options.extend(dhcp.options.clone().unwrap_or_default());
+    options
+}
+
 pub fn subnet_to_ip_and_mask(string: &String) -> anyhow::Result<(IpAddr, u16)> {
     let split: Vec<_> = string.split("/").collect();
     if split.len() != 2 {
@@ -240,9 +427,23 @@ pub fn subnet_to_ip_and_mask(string: &String) -> anyhow::Result<(IpAddr, u16)> {
     // now we have an ip v4/v6 and the mask
     let mask: u16 = split[1].parse::<u16>()?;
     let ip_res = split[0].parse::<IpAddr>()?;
+    let max_mask = if ip_res.is_ipv4() { 32 } else { 128 };
+    if mask > max_mask {
+        bail!("mask of subnet {string} is larger than {max_mask}");
+    }
     Ok((ip_res, mask))
 }
 
+/// Get the IPv6 address a guest configures for itself with SLAAC, from the /64 prefix of the
+/// switch and the modified EUI-64 interface identifier of its mac.
+pub fn ipv6_eui64(prefix: &Ipv6Addr, mac: u64) -> Ipv6Addr {
+    let mac = mac.to_be_bytes();
+    // the mac is in the lower 6 bytes, flip the universal/local bit and put ff:fe in the middle
+    let interface_id = [mac[2] ^ 0x02, mac[3], mac[4], 0xff, 0xfe, mac[5], mac[6], mac[7]];
+    let prefix = u128::from(*prefix) & !(u64::MAX as u128);
+    Ipv6Addr::from(prefix | u64::from_be_bytes(interface_id) as u128)
+}
+
 /// There are different kinds of switch ports to add, depending on the data given. This function
 /// adds switch ports that are defined in the network section of the yaml, that are not tied to a
 /// guest.
@@ -310,6 +511,9 @@ fn add_switch_port(
             )?;
         }
     }
+    if let Some(qos) = port.qos.as_ref().and_then(LogicalSwitchPortQos::from_qos) {
+        ovn.lsp_set_qos(&port_name, qos)?;
+    }
     Ok(())
 }
 
@@ -325,9 +529,9 @@ fn add_guest_switch_port(
     project_name: &String,
 ) -> anyhow::Result<()> {
     // create a composite name that is unique to this guest and project
-    let port_name = format!("{}-{}-{}-{}", project_name, interface_definition.switch, guest_config.name, idx);
+    let port_name = guest_switch_port_name(project_name, &interface_definition.switch, &guest_config.name, idx);
     tracing::info!("defining guest switch port {}", &port_name);
-    let ip = ip_string_to_ovn_ip(&interface_definition.ip, &port_name)?;
+    let ip = ip_string_to_ovn_ip(interface_definition.ip()?, &port_name)?;
     let host = load_balance_topology.guest_to_host.get(&guest_config.name)
         .context(format!("getting host for guest {} to assign chassis to switch port", &guest_config.name))?;
     let host_config = tb_config.get(host)
@@ -336,17 +540,600 @@ fn add_guest_switch_port(
     // guest ports are always internal
     ovn.add_lsp_internal(
         port_name.clone(),
-        parent_switch,
+        parent_switch.clone(),
         // the OVS port name on the integration bridge is the same as logical port name
         port_name.clone(),
         ip,
         Some(host_config.ovn.chassis_name.clone()),
-        MacAddress::new(interface_definition.mac.clone())?,
+        MacAddress::new(interface_definition.mac()?.clone())?,
         interface_definition.network_name.clone(),
     )?;
+    // dual-stack ports also get the ipv6 address, for auto this is the address the guest will
+    // configure with SLAAC so that OVN knows it. Dynamic ports get this from OVN already.
+    let ipv6 = match interface_definition.ipv6.as_deref() {
+        None => None,
+        Some("auto") if interface_definition.ip()?.eq("dynamic") => None,
+        Some("auto") => {
+            let prefix = match &ovn.switch_get(&parent_switch)?.ipv6_prefix {
+                Some(OvnIpAddr::Subnet { ip: IpAddr::V6(prefix), .. }) => *prefix,
+                _ => bail!("guest {} has an auto ipv6 address but switch {} has no ipv6 subnet", &guest_config.name, &interface_definition.switch),
+            };
+            let mac = MacAddress::new(interface_definition.mac()?.clone())?.as_bytes
+                .context("getting guest mac as bytes")?;
+            Some(ipv6_eui64(&prefix, mac))
+        }
+        Some(_) => interface_definition.static_ipv6()?.map(|(ip, _)| ip),
+    };
+    if let Some(ipv6) = ipv6 {
+        ovn.lsp_set_ipv6(&port_name, IpAddr::V6(ipv6))?;
+    }
+    // the rate limit is done by OVN, the rest of the qos is applied to the guest's interface when
+    // the guest is created
+    if let Some(qos) = interface_definition.qos.as_ref().and_then(LogicalSwitchPortQos::from_qos) {
+        ovn.lsp_set_qos(&port_name, qos)?;
+    }
+    Ok(())
+}
+
+/// Add flow export to the integration bridge of each testbed host. If switches are given, only the
+/// testbed hosts with ports on those switches are used, but the bridge is shared by all switches on
+/// the testbed host so the collector will also get flows from any other switches there.
+fn add_flow_export(
+    ovn: &mut OvnNetwork,
+    flow_export: &FlowExport,
+    tb_config: &HashMap<String, SshConfig>,
+    guest_list: &LogicalGuests,
+    project_name: &String,
+) -> anyhow::Result<()> {
+    let collector_ip = match flow_export.collector.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) => {
+            let guest = guest_list.iter()
+                .map(|guest| guest.get_machine_definition())
+                .find(|machine| machine.name.eq(&flow_export.collector))
+                .context(format!("flow export collector '{}' is not an ip or a guest", &flow_export.collector))?;
+            let interface = guest.network.as_ref()
+                .and_then(|network| network.first())
+                .context(format!("flow export collector guest '{}' has no interfaces", &flow_export.collector))?;
+            interface.ip()?.parse::<IpAddr>()
+                .context(format!("flow export collector guest '{}' must have a static ip on its first interface", &flow_export.collector))?
+        }
+    };
+    let port = flow_export.port.unwrap_or_else(|| flow_export.protocol.default_port());
+    let target = SocketAddr::new(collector_ip, port).to_string();
+
+    let chassis_list: BTreeSet<String> = match &flow_export.switches {
+        None => tb_config.values()
+            .map(|host_config| host_config.ovn.chassis_name.clone())
+            .collect(),
+        Some(switches) => {
+            let mut chassis_list = BTreeSet::new();
+            for switch in switches {
+                let switch_name = format!("{}-{}", project_name, switch);
+                if !ovn.switches.contains_key(&switch_name) {
+                    bail!("switch '{switch}' in flow export was not defined in the main network topology");
+                }
+                chassis_list.extend(ovn.switch_ports.values()
+                    .filter(|lsp| lsp.parent_switch.eq(&switch_name))
+                    .filter_map(|lsp| match &lsp.port_type {
+                        LogicalSwitchPortType::Internal { chassis_name, .. } => chassis_name.clone(),
+                        _ => None,
+                    }));
+            }
+            chassis_list
+        }
+    };
+    if chassis_list.is_empty() {
+        bail!("no testbed hosts have ports on the flow export switches");
+    }
+
+    for chassis in chassis_list {
+        let host_config = tb_config.values()
+            .find(|host_config| host_config.ovn.chassis_name.eq(&chassis))
+            .context(format!("getting host config for chassis {chassis}"))?;
+        tracing::info!("defining {} flow export to {} on chassis {}", &flow_export.protocol, &target, &chassis);
+        ovn.add_flow_export(OvsFlowExport::new(
+            chassis,
+            host_config.ovn.bridge.clone(),
+            flow_export.protocol.clone(),
+            target.clone(),
+            flow_export.sampling,
+        ))?;
+    }
+    Ok(())
+}
+
+/// Resolve the sources and sink of the mirror from the yaml to switch ports. Guests are mirrored on
+/// all of their interfaces, and switches on all of their ports apart from the sink.
+fn add_mirror(
+    ovn: &mut OvnNetwork,
+    mirror_name: &String,
+    mirror: &Mirror,
+    load_balance_topology: &LoadBalanceTopology,
+    guest_list: &LogicalGuests,
+    project_name: &String,
+) -> anyhow::Result<()> {
+    let name = format!("{}-{}", project_name, mirror_name);
+    tracing::info!("defining mirror {}", &name);
+    let get_guest_network = |guest_name: &String| -> anyhow::Result<Vec<MachineNetwork>> {
+        let guest = guest_list.iter()
+            .map(|guest| guest.get_machine_definition())
+            .find(|machine| machine.name.eq(guest_name))
+            .context(format!("guest '{guest_name}' in mirror '{mirror_name}' was not defined in the machines section"))?;
+        Ok(guest.network.unwrap_or_default())
+    };
+
+    let sink_network = get_guest_network(&mirror.sink.guest)?;
+    let sink_interface = sink_network.get(mirror.sink.interface)
+        .context(format!("sink guest '{}' in mirror '{mirror_name}' does not have interface {}", &mirror.sink.guest, mirror.sink.interface))?;
+    let sink_port = guest_switch_port_name(project_name, &sink_interface.switch, &mirror.sink.guest, mirror.sink.interface);
+    let sink_testbed_host = load_balance_topology.guest_to_host.get(&mirror.sink.guest)
+        .context(format!("getting host for mirror sink guest {}", &mirror.sink.guest))?;
+
+    let mut source_ports = BTreeSet::new();
+    for guest_name in mirror.sources.guests.iter().flatten() {
+        for (idx, interface) in get_guest_network(guest_name)?.iter().enumerate() {
+            source_ports.insert(guest_switch_port_name(project_name, &interface.switch, guest_name, idx));
+        }
+    }
+    for port in mirror.sources.switch_ports.iter().flatten() {
+        let port_name = format!("{}-{}", project_name, port);
+        if !ovn.switch_ports.contains_key(&port_name) {
+            bail!("switch port '{port}' in mirror '{mirror_name}' was not defined in the main network topology");
+        }
+        source_ports.insert(port_name);
+    }
+    for switch in mirror.sources.switches.iter().flatten() {
+        let switch_name = format!("{}-{}", project_name, switch);
+        if !ovn.switches.contains_key(&switch_name) {
+            bail!("switch '{switch}' in mirror '{mirror_name}' was not defined in the main network topology");
+        }
+        source_ports.extend(ovn.switch_ports.values()
+            .filter(|lsp| lsp.parent_switch.eq(&switch_name) && lsp.name.ne(&sink_port))
+            .map(|lsp| lsp.name.clone()));
+    }
+    if source_ports.is_empty() {
+        bail!("mirror '{mirror_name}' does not have any sources");
+    }
+
+    ovn.add_mirror(OvnMirror::new(
+        name,
+        mirror.direction.clone(),
+        source_ports.into_iter().collect(),
+        sink_port,
+        mirror.sink.guest.clone(),
+        sink_testbed_host.clone(),
+    ))?;
+    Ok(())
+}
+
+/// Resolve the backends of the load balancer to the static ipv4 addresses of the guests' switch
+/// ports, a clone group adds the same interface of every clone
+fn add_load_balancer(
+    ovn: &mut OvnNetwork,
+    lb_name: &String,
+    load_balancer: &LoadBalancer,
+    machines: &[Machine],
+    project_name: &String,
+) -> anyhow::Result<()> {
+    let name = format!("{}-{}", project_name, lb_name);
+    tracing::info!("defining load balancer {}", &name);
+    let mut backends = Vec::new();
+    for backend in &load_balancer.backends {
+        let machine = machines.iter()
+            .find(|machine| machine.name.eq(&backend.guest))
+            .context(format!("guest '{}' in load balancer '{lb_name}' was not defined in the machines section", &backend.guest))?;
+        let guests: Vec<String> = match scaling_count(machine) {
+            Some(count) => (0..count).map(|clone_n| format!("{}-{clone_n}", &backend.guest)).collect(),
+            None => vec![backend.guest.clone()],
+        };
+        for guest_name in &guests {
+            let interface = machines.iter()
+                .find(|machine| machine.name.eq(guest_name))
+                .and_then(|machine| machine.network.as_ref())
+                .and_then(|network| network.get(backend.interface))
+                .context(format!("guest '{guest_name}' in load balancer '{lb_name}' does not have interface {}", backend.interface))?;
+            let port_name = guest_switch_port_name(project_name, &interface.switch, guest_name, backend.interface);
+            let port = ovn.switch_ports.get(&port_name)
+                .context(format!("getting switch port {port_name} for guest {guest_name}"))?;
+            for ip in port_ipv4_addresses(std::slice::from_ref(port))? {
+                backends.push(format!("{ip}:{}", backend.port));
+            }
+        }
+    }
+    if backends.is_empty() {
+        bail!("load balancer '{lb_name}' does not have any backends");
+    }
+    let switches = load_balancer.switches.iter().flatten()
+        .map(|switch| format!("{}-{}", project_name, switch))
+        .collect();
+    let routers = load_balancer.routers.iter().flatten()
+        .map(|router| format!("{}-{}", project_name, router))
+        .collect();
+
+    ovn.add_load_balancer(LogicalLoadBalancer::new(
+        name,
+        load_balancer.vip.clone(),
+        backends,
+        load_balancer.protocol.clone(),
+        switches,
+        routers,
+    ))?;
     Ok(())
 }
 
+/// Add the DNS records of every guest to every switch. A guest with an interface on the switch is
+/// resolved to the addresses of that interface, otherwise to the addresses of its first interface.
+/// Dynamic addresses are looked up when the records are created.
+fn add_dns_records(
+    ovn: &mut OvnNetwork,
+    machines: &[Machine],
+    project_name: &String,
+) -> anyhow::Result<()> {
+    // the switch ports of each guest, clone groups don't have any as their clones are guests
+    let mut guests: BTreeMap<String, Vec<LogicalSwitchPort>> = BTreeMap::new();
+    for machine in machines.iter().filter(|machine| scaling_count(machine).is_none()) {
+        let ports = guests_ports(ovn, std::slice::from_ref(&machine.name), machines, project_name)?;
+        if !ports.is_empty() {
+            guests.insert(machine.name.to_lowercase(), ports);
+        }
+    }
+    let mut switches: Vec<_> = ovn.switches.keys().cloned().collect();
+    switches.sort();
+    for switch in switches {
+        let mut records = BTreeMap::new();
+        for (hostname, ports) in &guests {
+            let port = ports.iter()
+                .find(|port| port.parent_switch.eq(&switch))
+                .unwrap_or(&ports[0]);
+            let LogicalSwitchPortType::Internal { ip, ipv6, .. } = &port.port_type else { continue };
+            let mut addresses = Vec::new();
+            for address in [Some(ip), ipv6.as_ref()].into_iter().flatten() {
+                match address {
+                    OvnIpAddr::Ip(ip) => addresses.push(DnsRecordAddress::Ip(*ip)),
+                    OvnIpAddr::Dynamic => addresses.push(DnsRecordAddress::Dynamic { switch_port: port.name.clone() }),
+                    OvnIpAddr::Subnet { .. } => {}
+                }
+            }
+            if !addresses.is_empty() {
+                records.insert(hostname.clone(), addresses);
+            }
+        }
+        if !records.is_empty() {
+            tracing::info!("defining DNS records for {} guests on switch {}", records.len(), &switch);
+            ovn.add_dns_records(OvnDnsRecords::new(switch, records))?;
+        }
+    }
+    Ok(())
+}
+
+/// Port groups and address sets are shared by every project in OVN, so their names are prefixed
+/// with the project. These names are used in the matches, so can only contain letters, digits and
+/// underscores.
+pub fn acl_set_name(project_name: &str, name: &str) -> String {
+    let mut project: String = project_name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if project.starts_with(|c: char| c.is_ascii_digit()) {
+        project.insert(0, '_');
+    }
+    format!("{project}_{name}")
+}
+
+/// Rename the port group and address set references in the rule's match to their OVN names. The
+/// `$name_ip4` and `$name_ip6` address sets that OVN makes for a port group are renamed as well.
+fn acl_rule_with_set_names(rule: &ACLRule, acl: &ACL, project_name: &str) -> anyhow::Result<ACLRule> {
+    let is_port_group = |name: &str| acl.port_groups.as_ref().is_some_and(|pgs| pgs.contains_key(name));
+    let is_address_set = |name: &str| acl.address_sets.as_ref().is_some_and(|sets| sets.contains_key(name))
+        || name.strip_suffix("_ip4").or(name.strip_suffix("_ip6")).is_some_and(is_port_group);
+    let _match = map_acl_match_references(&rule._match, |sigil, name| {
+        match sigil {
+            '@' if is_port_group(name) => Ok(acl_set_name(project_name, name)),
+            '$' if is_address_set(name) => Ok(acl_set_name(project_name, name)),
+            _ => bail!("'{sigil}{name}' in ACL match '{}' is not a port group or address set", &rule._match),
+        }
+    })?;
+    Ok(ACLRule {
+        _match,
+        ..rule.clone()
+    })
+}
+
+/// Add the port groups and address sets of the ACL section
+fn add_acl_sets(
+    ovn: &mut OvnNetwork,
+    acl: &ACL,
+    machines: &[Machine],
+    project_name: &String,
+) -> anyhow::Result<()> {
+    for (name, port_group) in acl.port_groups.iter().flatten() {
+        tracing::info!("defining port group {}", name);
+        let mut ports = BTreeSet::new();
+        for guest in port_group.guests.iter().flatten() {
+            let guest_ports = guest_ports(ovn, guest, machines, project_name)?
+                .context(format!("guest '{guest}' in port group '{name}' was not defined in the machines section"))?;
+            ports.extend(guest_ports.into_iter().map(|lsp| lsp.name));
+        }
+        for range in port_group.clones.iter().flatten() {
+            let clones: Vec<String> = (range.from..=range.to)
+                .map(|clone_n| format!("{}-{clone_n}", &range.guest))
+                .collect();
+            ports.extend(guests_ports(ovn, &clones, machines, project_name)?.into_iter().map(|lsp| lsp.name));
+        }
+        for switch in port_group.switches.iter().flatten() {
+            let switch_name = format!("{}-{}", project_name, switch);
+            if !ovn.switches.contains_key(&switch_name) {
+                bail!("switch '{switch}' in port group '{name}' was not defined in the main network topology");
+            }
+            // only the guest ports, not the router or localnet ports
+            ports.extend(ovn.switch_ports.values()
+                .filter(|lsp| lsp.parent_switch.eq(&switch_name) && matches!(lsp.port_type, LogicalSwitchPortType::Internal { .. }))
+                .map(|lsp| lsp.name.clone()));
+        }
+        ovn.add_port_group(LogicalPortGroup::new(acl_set_name(project_name, name), ports.into_iter().collect()))?;
+    }
+
+    for (name, address_set) in acl.address_sets.iter().flatten() {
+        tracing::info!("defining address set {}", name);
+        let mut addresses: BTreeSet<String> = address_set.addresses.iter().flatten().cloned().collect();
+        for guest in address_set.guests.iter().flatten() {
+            let guest_ports = guest_ports(ovn, guest, machines, project_name)?
+                .context(format!("guest '{guest}' in address set '{name}' was not defined in the machines section"))?;
+            addresses.extend(port_ipv4_addresses(&guest_ports)?);
+        }
+        ovn.add_address_set(LogicalAddressSet::new(acl_set_name(project_name, name), addresses.into_iter().collect()))?;
+    }
+    Ok(())
+}
+
+/// Policies without a priority are given one below this in the order they are listed, so that the
+/// earlier policies take precedence
+const POLICY_PRIORITY: i16 = 2000;
+
+/// What a policy `from` or `to` resolves to
+enum PolicyEndpoint {
+    /// The switch ports of a guest or the clones of a clone group
+    Ports(Vec<LogicalSwitchPort>),
+    Switch(LogicalSwitch),
+}
+
+/// Resolve a policy endpoint name, first as a guest, then as a clone group and then as a switch
+fn resolve_policy_endpoint(
+    ovn: &OvnNetwork,
+    name: &String,
+    machines: &[Machine],
+    project_name: &String,
+) -> anyhow::Result<PolicyEndpoint> {
+    if let Some(ports) = guest_ports(ovn, name, machines, project_name)? {
+        return Ok(PolicyEndpoint::Ports(ports));
+    }
+    match ovn.switches.get(&format!("{}-{}", project_name, name)) {
+        Some(switch) => Ok(PolicyEndpoint::Switch(switch.clone())),
+        None => bail!("'{name}' in policy is not a guest, clone group or switch"),
+    }
+}
+
+/// Get the switch ports of every interface of the guest, or of every clone if the guest is a clone
+/// group. Returns None if there is no guest with the name.
+fn guest_ports(
+    ovn: &OvnNetwork,
+    name: &String,
+    machines: &[Machine],
+    project_name: &String,
+) -> anyhow::Result<Option<Vec<LogicalSwitchPort>>> {
+    let Some(machine) = machines.iter().find(|machine| machine.name.eq(name)) else {
+        return Ok(None);
+    };
+    let guests: Vec<String> = match scaling_count(machine) {
+        Some(count) => (0..count).map(|clone_n| format!("{name}-{clone_n}")).collect(),
+        None => vec![name.clone()],
+    };
+    let ports = guests_ports(ovn, &guests, machines, project_name)?;
+    if ports.is_empty() {
+        bail!("guest '{name}' does not have any interfaces");
+    }
+    Ok(Some(ports))
+}
+
+/// Get the switch ports of every interface of the guests
+fn guests_ports(
+    ovn: &OvnNetwork,
+    guests: &[String],
+    machines: &[Machine],
+    project_name: &String,
+) -> anyhow::Result<Vec<LogicalSwitchPort>> {
+    let mut ports = Vec::new();
+    for guest_name in guests {
+        let network = machines.iter()
+            .find(|machine| machine.name.eq(guest_name))
+            .and_then(|machine| machine.network.clone())
+            .unwrap_or_default();
+        for (idx, interface) in network.iter().enumerate() {
+            let port_name = guest_switch_port_name(project_name, &interface.switch, guest_name, idx);
+            let port = ovn.switch_ports.get(&port_name)
+                .context(format!("getting switch port {port_name} for guest {guest_name}"))?;
+            ports.push(port.clone());
+        }
+    }
+    Ok(ports)
+}
+
+/// The priority of the policy's ACLs, the one given or one based on its place in the list
+pub(crate) fn policy_priority(policy: &Policy, idx: usize) -> i16 {
+    match policy.priority {
+        Some(priority) => priority,
+        None => POLICY_PRIORITY.saturating_sub(i16::try_from(idx).unwrap_or(i16::MAX)),
+    }
+}
+
+/// The number of clones of a clone group, or None if the machine is not a clone group
+pub(crate) fn scaling_count(machine: &Machine) -> Option<u32> {
+    match &machine.guest_type {
+        GuestType::Libvirt(libvirt) => libvirt.scaling.as_ref().map(|scaling| scaling.count),
+        GuestType::Docker(docker) => docker.scaling.as_ref().map(|scaling| scaling.count),
+        GuestType::Android(android) => android.scaling.as_ref().map(|scaling| scaling.count),
+    }
+}
+
+/// Format a list as an OVN match set i.e. `{a, b}`
+fn match_set<T: ToString>(items: impl IntoIterator<Item = T>) -> String {
+    format!("{{{}}}", items.into_iter().map(|item| item.to_string()).collect::<Vec<_>>().join(", "))
+}
+
+/// The ipv4 addresses of the guest switch ports, these must be static to be matched
+fn port_ipv4_addresses(ports: &[LogicalSwitchPort]) -> anyhow::Result<BTreeSet<String>> {
+    let mut ips = BTreeSet::new();
+    for port in ports {
+        match &port.port_type {
+            LogicalSwitchPortType::Internal { ip: OvnIpAddr::Ip(ip @ IpAddr::V4(_)), .. } => {
+                ips.insert(ip.to_string());
+            }
+            _ => bail!("switch port {} must have a static ipv4 address to be matched by its address", &port.name),
+        }
+    }
+    Ok(ips)
+}
+
+/// The ipv6 addresses of the guest switch ports. Ports on dual-stack switches must have a static or
+/// auto ipv6 address to be matched, so that their ipv6 traffic can't bypass the match.
+fn port_ipv6_addresses(ovn: &OvnNetwork, ports: &[LogicalSwitchPort]) -> anyhow::Result<BTreeSet<String>> {
+    let mut ips = BTreeSet::new();
+    for port in ports {
+        match &port.port_type {
+            LogicalSwitchPortType::Internal { ipv6: Some(OvnIpAddr::Ip(ip)), .. } => {
+                ips.insert(ip.to_string());
+            }
+            _ if ovn.switch_get(&port.parent_switch)?.ipv6_prefix.is_some() => {
+                bail!("switch port {} is on a dual-stack switch so must have a static or auto ipv6 address to be matched by its address", &port.name)
+            }
+            _ => {}
+        }
+    }
+    Ok(ips)
+}
+
+/// Match the source or destination addresses of the guest switch ports, in both address families
+/// when they have ipv6 addresses
+fn ports_address_match(ovn: &OvnNetwork, ports: &[LogicalSwitchPort], field: &str) -> anyhow::Result<String> {
+    let ipv4_match = format!("ip4.{field} == {}", match_set(port_ipv4_addresses(ports)?));
+    let ipv6 = port_ipv6_addresses(ovn, ports)?;
+    if ipv6.is_empty() {
+        return Ok(ipv4_match);
+    }
+    Ok(format!("({ipv4_match} || ip6.{field} == {})", match_set(ipv6)))
+}
+
+/// Match the source or destination addresses in the subnets of the switch, in both address
+/// families on dual-stack switches
+fn switch_address_match(switch: &LogicalSwitch, field: &str) -> String {
+    let ipv4_match = format!("ip4.{field} == {}", switch.subnet.to_string());
+    match &switch.ipv6_prefix {
+        Some(prefix) => format!("({ipv4_match} || ip6.{field} == {})", prefix.to_string()),
+        None => ipv4_match,
+    }
+}
+
+/// Group the switch ports by their switch, as a match set of the quoted port names
+fn policy_ports_by_switch(ports: &[LogicalSwitchPort]) -> BTreeMap<String, String> {
+    let mut switch_ports: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for port in ports {
+        switch_ports.entry(port.parent_switch.clone()).or_default().push(format!("\"{}\"", &port.name));
+    }
+    switch_ports.into_iter()
+        .map(|(switch, ports)| (switch, match_set(ports)))
+        .collect()
+}
+
+/// Compile a policy into ACLs. When there is a destination, the ACLs are placed on its switches as
+/// `to-lport` rules, otherwise they are placed on the source switches as `from-lport` rules. Each
+/// ACL is named `{project}-policy-{idx}-{switch}`.
+fn add_policy(
+    ovn: &mut OvnNetwork,
+    idx: usize,
+    policy: &Policy,
+    machines: &[Machine],
+    project_name: &String,
+) -> anyhow::Result<()> {
+    tracing::info!("compiling policy {idx} into ACLs");
+    let from = policy.from.as_ref()
+        .map(|name| resolve_policy_endpoint(ovn, name, machines, project_name))
+        .transpose()?;
+    let to = policy.to.as_ref()
+        .map(|name| resolve_policy_endpoint(ovn, name, machines, project_name))
+        .transpose()?;
+
+    let mut proto_match = policy.proto.to_string();
+    if let Some(ports) = &policy.ports {
+        match policy.proto {
+            PolicyProtocol::Tcp | PolicyProtocol::Udp => {
+                proto_match.push_str(&format!(" && {}.dst == {}", &policy.proto, match_set(ports)));
+            }
+            _ => bail!("policy {idx} can only have ports for tcp or udp"),
+        }
+    }
+
+    // switch name to the direction and the match of the ACL on that switch
+    let mut rules: BTreeMap<String, (ACLDirection, Vec<String>)> = BTreeMap::new();
+    match (&from, &to) {
+        (_, Some(to)) => {
+            let source_match = match &from {
+                None => None,
+                Some(PolicyEndpoint::Ports(ports)) => Some(ports_address_match(ovn, ports, "src")?),
+                Some(PolicyEndpoint::Switch(switch)) => Some(switch_address_match(switch, "src")),
+            };
+            match to {
+                PolicyEndpoint::Ports(ports) => {
+                    for (switch, ports) in policy_ports_by_switch(ports) {
+                        rules.insert(switch, (ACLDirection::ToLport, vec![format!("outport == {ports}")]));
+                    }
+                }
+                PolicyEndpoint::Switch(switch) => {
+                    rules.insert(switch.name.clone(), (ACLDirection::ToLport, vec![switch_address_match(switch, "dst")]));
+                }
+            }
+            for (_, matches) in rules.values_mut() {
+                matches.extend(source_match.clone());
+            }
+        }
+        (Some(PolicyEndpoint::Ports(ports)), None) => {
+            for (switch, ports) in policy_ports_by_switch(ports) {
+                rules.insert(switch, (ACLDirection::FromLport, vec![format!("inport == {ports}")]));
+            }
+        }
+        (Some(PolicyEndpoint::Switch(switch)), None) => {
+            rules.insert(switch.name.clone(), (ACLDirection::FromLport, vec![switch_address_match(switch, "src")]));
+        }
+        (None, None) => bail!("policy {idx} must have at least one of from or to"),
+    }
+
+    let priority = policy_priority(policy, idx);
+    for (switch, (direction, mut matches)) in rules {
+        matches.push(proto_match.clone());
+        let switch_suffix = switch.strip_prefix(&format!("{project_name}-")).unwrap_or(&switch);
+        let acl_name = format!("{}-policy-{}-{}", project_name, idx, switch_suffix);
+        let rule = ACLRule {
+            direction,
+            priority,
+            _match: matches.join(" && "),
+            action: policy.action.acl_action(),
+        };
+        ovn.add_switch_acl(&acl_name, switch, ACLRecordType::Switch, &rule)?;
+    }
+    Ok(())
+}
+
+/// Get the name of the logical switch port for the guest's interface at position `idx` in its
+/// network definition. This is also used to find the switch ports that belong to a guest in the
+/// state.
+pub fn guest_switch_port_name(
+    project_name: &String,
+    switch_name: &String,
+    guest_name: &String,
+    idx: usize,
+) -> String {
+    format!("{}-{}-{}-{}", project_name, switch_name, guest_name, idx)
+}
+
 fn add_router_port(
     ovn: &mut OvnNetwork,
     port: &RouterPort,
@@ -366,6 +1153,10 @@ fn add_router_port(
         ip_and_mask.1,
         port.set_gateway_chassis.clone(),
     )?;
+    if let Some(ipv6_gateway_ip) = &port.ipv6_gateway_ip {
+        let ipv6_and_mask = subnet_to_ip_and_mask(ipv6_gateway_ip)?;
+        ovn.lrp_set_ipv6(&port_name, ipv6_and_mask.0, ipv6_and_mask.1)?;
+    }
     // if gateway chassis is set
     if let Some(chassis) = &port.set_gateway_chassis {
         ovn.lrp_add_external_gateway(
@@ -499,4 +1290,304 @@ fn ip_string_to_ovn_ip(
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ipv6_eui64() {
+        let prefix: Ipv6Addr = "fd00:10::".parse().unwrap();
+        let mac = 0x0000_0000_0001u64;
+        assert_eq!(
+            ipv6_eui64(&prefix, mac),
+            "fd00:10::200:ff:fe00:1".parse::<Ipv6Addr>().unwrap(),
+        );
+        let mac = 0x5254_00ab_cdefu64;
+        assert_eq!(
+            ipv6_eui64(&prefix, mac),
+            "fd00:10::5054:ff:feab:cdef".parse::<Ipv6Addr>().unwrap(),
+        );
+    }
+
+    /// guest web on sw0 and the clones of db on sw1, with static ips
+    fn acl_test_network(project: &String) -> (Vec<Machine>, OvnNetwork) {
+        let machines: Vec<Machine> = serde_yaml::from_str(r#"
+- name: web
+  network:
+    - switch: sw0
+  docker:
+    image: nginx
+- name: db
+  docker:
+    image: postgres
+    scaling:
+      count: 2
+      interfaces: {}
+- name: db-0
+  network:
+    - switch: sw1
+  docker:
+    image: postgres
+- name: db-1
+  network:
+    - switch: sw1
+  docker:
+    image: postgres
+"#).unwrap();
+        let mut ovn = OvnNetwork::new();
+        ovn.add_switch(format!("{project}-sw0"), "10.0.0.0".parse().unwrap(), 24).unwrap();
+        ovn.add_switch(format!("{project}-sw1"), "10.0.1.0".parse().unwrap(), 24).unwrap();
+        for (switch, guest, ip) in [("sw0", "web", "10.0.0.10"), ("sw1", "db-0", "10.0.1.10"), ("sw1", "db-1", "10.0.1.11")] {
+            ovn.add_lsp_internal(
+                guest_switch_port_name(project, &switch.into(), &guest.into(), 0),
+                format!("{project}-{switch}"),
+                String::new(),
+                OvnIpAddr::Ip(ip.parse().unwrap()),
+                None,
+                MacAddress::new("00:00:00:00:00:01".into()).unwrap(),
+                None,
+            ).unwrap();
+        }
+        (machines, ovn)
+    }
+
+    #[test]
+    fn test_add_policy() {
+        let project = "test".to_string();
+        let (machines, mut ovn) = acl_test_network(&project);
+        let policies: Vec<Policy> = serde_yaml::from_str(r#"
+- from: web
+  to: db
+  proto: tcp
+  ports: [5432, 5433]
+  action: allow
+- from: db
+  action: deny
+- from: sw1
+  to: sw0
+  proto: icmp
+  action: reject
+  priority: 10
+"#).unwrap();
+        for (idx, policy) in policies.iter().enumerate() {
+            add_policy(&mut ovn, idx, policy, &machines, &project).unwrap();
+        }
+
+        let acl = &ovn.acl["test-policy-0-sw1"];
+        assert_eq!(acl.entity_name, "test-sw1");
+        assert_eq!(acl.direction.to_string(), "to-lport");
+        assert_eq!(acl._match, r#"outport == {"test-sw1-db-0-0", "test-sw1-db-1-0"} && ip4.src == {10.0.0.10} && tcp && tcp.dst == {5432, 5433}"#);
+        assert_eq!(acl.action.to_string(), "allow-related");
+        assert_eq!(acl.priority, POLICY_PRIORITY);
+
+        let acl = &ovn.acl["test-policy-1-sw1"];
+        assert_eq!(acl.direction.to_string(), "from-lport");
+        assert_eq!(acl._match, r#"inport == {"test-sw1-db-0-0", "test-sw1-db-1-0"} && ip"#);
+        assert_eq!(acl.priority, POLICY_PRIORITY - 1);
+
+        let acl = &ovn.acl["test-policy-2-sw0"];
+        assert_eq!(acl._match, "ip4.dst == 10.0.0.0/24 && ip4.src == 10.0.1.0/24 && icmp");
+        assert_eq!(acl.priority, 10);
+
+        let policy: Policy = serde_yaml::from_str("{from: missing, action: deny}").unwrap();
+        assert!(add_policy(&mut ovn, 3, &policy, &machines, &project).is_err());
+    }
+
+    #[test]
+    fn test_add_policy_dual_stack() {
+        let project = "test".to_string();
+        let (machines, mut ovn) = acl_test_network(&project);
+        // sw0 and sw1 are dual-stack, only web has an ipv6 address
+        ovn.switch_set_ipv6_prefix(&format!("{project}-sw0"), "fd00:10::".parse().unwrap(), 64).unwrap();
+        ovn.switch_set_ipv6_prefix(&format!("{project}-sw1"), "fd00:11::".parse().unwrap(), 64).unwrap();
+        ovn.lsp_set_ipv6(&guest_switch_port_name(&project, &"sw0".into(), &"web".into(), 0), "fd00:10::10".parse().unwrap()).unwrap();
+        let policies: Vec<Policy> = serde_yaml::from_str(r#"
+- from: web
+  to: db
+  action: deny
+- from: sw1
+  to: sw0
+  proto: icmp
+  action: reject
+- from: db
+  to: web
+  action: deny
+"#).unwrap();
+        add_policy(&mut ovn, 0, &policies[0], &machines, &project).unwrap();
+        add_policy(&mut ovn, 1, &policies[1], &machines, &project).unwrap();
+
+        let acl = &ovn.acl["test-policy-0-sw1"];
+        assert_eq!(acl._match, r#"outport == {"test-sw1-db-0-0", "test-sw1-db-1-0"} && (ip4.src == {10.0.0.10} || ip6.src == {fd00:10::10}) && ip"#);
+        let acl = &ovn.acl["test-policy-1-sw0"];
+        assert_eq!(acl._match, "(ip4.dst == 10.0.0.0/24 || ip6.dst == fd00:10::/64) && (ip4.src == 10.0.1.0/24 || ip6.src == fd00:11::/64) && icmp");
+        // the db clones don't have ipv6 addresses, so their ipv6 traffic can't be matched
+        assert!(add_policy(&mut ovn, 2, &policies[2], &machines, &project).is_err());
+    }
+
+    #[test]
+    fn test_add_load_balancer() {
+        let project = "test".to_string();
+        let (machines, mut ovn) = acl_test_network(&project);
+        ovn.add_router(format!("{project}-lr0")).unwrap();
+        let load_balancer: LoadBalancer = serde_yaml::from_str(r#"
+vip: "10.0.0.100:80"
+backends:
+  - guest: db
+    port: 5432
+  - guest: web
+    port: 8080
+switches: [sw0]
+routers: [lr0]
+"#).unwrap();
+        add_load_balancer(&mut ovn, &"lb0".into(), &load_balancer, &machines, &project).unwrap();
+        let lb = &ovn.load_balancers["test-lb0"];
+        assert_eq!(lb.backends, vec!["10.0.1.10:5432", "10.0.1.11:5432", "10.0.0.10:8080"]);
+        assert_eq!(lb.switches, vec!["test-sw0"]);
+        assert_eq!(lb.routers, vec!["test-lr0"]);
+
+        let mut load_balancer = load_balancer.clone();
+        load_balancer.backends[0].interface = 1;
+        assert!(add_load_balancer(&mut ovn, &"lb1".into(), &load_balancer, &machines, &project).is_err());
+    }
+
+    #[test]
+    fn test_add_dns_records() {
+        let project = "test".to_string();
+        let (mut machines, mut ovn) = acl_test_network(&project);
+        // web is also on sw1 with a dynamic ip
+        machines[0].network.as_mut().unwrap().push(serde_yaml::from_str("switch: sw1").unwrap());
+        ovn.add_lsp_internal(
+            guest_switch_port_name(&project, &"sw1".into(), &"web".into(), 1),
+            "test-sw1".into(),
+            String::new(),
+            OvnIpAddr::Dynamic,
+            None,
+            MacAddress::new("00:00:00:00:00:02".into()).unwrap(),
+            None,
+        ).unwrap();
+        add_dns_records(&mut ovn, &machines, &project).unwrap();
+
+        let sw0 = &ovn.dns_records["test-sw0"].records;
+        assert_eq!(sw0.keys().collect::<Vec<_>>(), vec!["db-0", "db-1", "web"]);
+        assert_eq!(sw0["web"], vec![DnsRecordAddress::Ip("10.0.0.10".parse().unwrap())]);
+        assert_eq!(sw0["db-1"], vec![DnsRecordAddress::Ip("10.0.1.11".parse().unwrap())]);
+        let sw1 = &ovn.dns_records["test-sw1"];
+        assert_eq!(sw1.records["web"], vec![DnsRecordAddress::Dynamic { switch_port: "test-sw1-web-1".into() }]);
+        assert_eq!(sw1.dynamic_switch_ports().collect::<Vec<_>>(), vec!["test-sw1-web-1"]);
+    }
+
+    #[test]
+    fn test_dhcp_ovn_options() {
+        let dhcp: Dhcp = serde_yaml::from_str(r#"
+switch: sw0
+exclude_ips:
+  from: 10.0.0.1
+  to: 10.0.0.10
+dns_servers: [10.0.0.1, 1.1.1.1]
+domain_name: testbed.local
+mtu: 1400
+static_routes:
+  - prefix: 10.1.0.0/16
+    via: 10.0.0.254
+  - prefix: 0.0.0.0/0
+    via: 10.0.0.1
+options:
+  ntp_server: "{10.0.0.1}"
+"#).unwrap();
+        let options = dhcp_ovn_options(&dhcp);
+        assert_eq!(options["dns_server"], "{10.0.0.1, 1.1.1.1}");
+        assert_eq!(options["domain_name"], "\"testbed.local\"");
+        assert_eq!(options["mtu"], "1400");
+        assert_eq!(options["classless_static_route"], "{10.1.0.0/16,10.0.0.254, 0.0.0.0/0,10.0.0.1}");
+        assert_eq!(options["ntp_server"], "{10.0.0.1}");
+    }
+
+    #[test]
+    fn test_add_acl_sets() {
+        let project = "test-1".to_string();
+        let (machines, mut ovn) = acl_test_network(&project);
+        let acl: ACL = serde_yaml::from_str(r#"
+port_groups:
+  dbs:
+    clones:
+      - guest: db
+        from: 1
+        to: 1
+  everyone:
+    guests: [web, db]
+    rules:
+      - direction: to-lport
+        priority: 10
+        match: "outport == @everyone && ip4.src == $dbs_ip4"
+        action: drop
+address_sets:
+  web:
+    addresses: ["192.168.0.0/24"]
+    guests: [web]
+"#).unwrap();
+        add_acl_sets(&mut ovn, &acl, &machines, &project).unwrap();
+        assert_eq!(ovn.port_groups["test_1_dbs"].ports, vec!["test-1-sw1-db-1-0"]);
+        assert_eq!(ovn.port_groups["test_1_everyone"].ports.len(), 3);
+        assert_eq!(ovn.address_sets["test_1_web"].addresses, vec!["10.0.0.10", "192.168.0.0/24"]);
+
+        let rule = &acl.port_groups.as_ref().unwrap()["everyone"].rules.as_ref().unwrap()[0];
+        let rule = acl_rule_with_set_names(rule, &acl, &project).unwrap();
+        assert_eq!(rule._match, "outport == @test_1_everyone && ip4.src == $test_1_dbs_ip4");
+        let mut rule = rule.clone();
+        rule._match = "ip4.src == $missing".into();
+        assert!(acl_rule_with_set_names(&rule, &acl, &project).is_err());
+    }
+
+    #[test]
+    fn test_parse_ovs_schema() {
+        let ovs_network: OvsNetwork = serde_yaml::from_str(r#"
+bridges:
+  br0:
+    subnet: "10.0.0.0/24"
+  br1:
+    subnet: "10.0.1.0/24"
+  br2:
+    subnet: "10.0.2.0/24"
+links:
+  - source: br0
+    target: br1
+  - source: br1
+    target: br2
+"#).unwrap();
+        let host = |ip: &str| SshConfig {
+            ip: ip.into(),
+            user: "ubuntu".into(),
+            identity_file: String::new(),
+            testbed_nic: "eth0".into(),
+            main_interface: "eth0".into(),
+            is_main_host: None,
+            ovn: Default::default(),
+        };
+        let tb_config = HashMap::from([
+            ("host1".to_string(), host("192.168.0.1")),
+            ("host2".to_string(), host("192.168.0.2")),
+        ]);
+        let load_balance_topology = LoadBalanceTopology {
+            guest_to_host: HashMap::new(),
+            bridge_to_host: HashMap::from([
+                ("br0".to_string(), "host1".to_string()),
+                ("br1".to_string(), "host1".to_string()),
+                ("br2".to_string(), "host2".to_string()),
+            ]),
+        };
+        let ovs = parse_ovs_schema(&ovs_network, &load_balance_topology, &tb_config, &"test".to_string()).unwrap();
+        assert_eq!(ovs.bridges["br2"].name, "br-test2");
+        // bridges on the same host are linked with a veth pair, otherwise a tunnel
+        assert!(matches!(&ovs.connections[0], BridgeConnection::Veth { source_veth, target_veth, .. }
+            if source_veth == "ln-test00" && target_veth == "ln-test01"));
+        match &ovs.connections[1] {
+            BridgeConnection::Tunnel { source_br, target_br, source_host_ip, target_host_ip, key, .. } => {
+                assert_eq!((source_br.as_str(), target_br.as_str()), ("br-test1", "br-test2"));
+                assert_eq!((source_host_ip.as_str(), target_host_ip.as_str()), ("192.168.0.1", "192.168.0.2"));
+                assert!(*key < 1 << 24);
+            }
+            _ => panic!("expected a tunnel between hosts"),
+        }
+    }
+}
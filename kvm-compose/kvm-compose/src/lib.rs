@@ -5,8 +5,11 @@ use anyhow::{Context};
 use kvm_compose_schemas::cli_models::{Common};
 use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
 use kvm_compose_schemas::kvm_compose_yaml::Config;
+use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt_image_download::CloudImageCatalog;
 use components::helpers::clones::generate_clone_guests;
-use std::path::PathBuf;
+use components::helpers::ipam::allocate_addresses;
+use crate::state::State;
+use std::path::{Path, PathBuf};
 use std::string::String;
 use nix::unistd::{Gid, Uid};
 use kvm_compose_schemas::settings::TestbedClusterConfig;
@@ -18,8 +21,10 @@ pub mod state;
 pub mod orchestration;
 pub mod snapshot;
 pub mod exec;
+pub mod net;
 pub mod ovn;
 pub mod analysis_tools;
+pub mod lockfile;
 
 fn format_prj_name(s: &str) -> String {
     // replace awkward characters with "-"
@@ -72,10 +77,12 @@ pub fn assign_tcp_tty_ports(config: &mut Config) -> anyhow::Result<()> {
 /// built/inferred from this.
 pub async fn parse_config(
     path: String,
+    var_file: Option<String>,
     project_name: Option<String>,
     no_ask: bool,
     current_dir: PathBuf,
     force_provisioning: bool,
+    previous_state: Option<&State>,
 ) -> anyhow::Result<LogicalTestbed> {
 
     tracing::trace!("current dir = {:?}", current_dir);
@@ -86,10 +93,20 @@ pub async fn parse_config(
 
     // Project input file will be set to either kvm-compose.yaml as default or a new value will
     // have been supplied - Use and load that file. That will trigger parsing of yaml to return a
-    // Config struct
-    let mut config = Config::load_from_file(path).await?;
+    // Config struct, with variables interpolated and included files merged in
+    let mut config = Config::load_from_file_with_vars(path, var_file.as_deref().map(Path::new)).await?;
+    // make sure the cloud images used are in the catalog before anything is generated
+    CloudImageCatalog::load(Some(&current_dir))
+        .await
+        .context("loading the cloud image catalog")?
+        .check_config(&config)?;
     // expand any machines with scaling parameters to include clones in the machine list
     generate_clone_guests(&mut config)?;
+    // give any interfaces without an ip or mac one, keeping the addresses from the last deployment
+    let previous_allocations = previous_state
+        .map(|state| state.address_allocations.clone())
+        .unwrap_or_default();
+    let address_allocations = allocate_addresses(&mut config, &project_name, &previous_allocations)?;
     // assign tty ports for the guest
     assign_tcp_tty_ports(&mut config)?;
     // end yaml parsing
@@ -111,6 +128,7 @@ pub async fn parse_config(
 
     // now create logical assets for the testbed, that are not yet assigned to any physical infra
     let mut logical_testbed = LogicalTestbed::new(common);
+    logical_testbed.address_allocations = address_allocations;
     logical_testbed.process_config()?;
 
     Ok(logical_testbed)
@@ -0,0 +1,439 @@
+use std::collections::BTreeMap;
+use std::os::linux::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use anyhow::{bail, Context};
+use nix::unistd::{Gid, Uid};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use kvm_compose_schemas::kvm_compose_yaml::Config;
+use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
+use kvm_compose_schemas::kvm_compose_yaml::machines::avd::AVDGuestOptions;
+use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt::LibvirtGuestOptions;
+use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt_image_download::{sha256_file, CloudImageCatalog, CloudImageSource};
+use kvm_compose_schemas::kvm_compose_yaml::resolve::{load_variables, resolve_yaml};
+use kvm_compose_schemas::TESTBED_IMAGES_FOLDER;
+use crate::components::helpers::android::get_sdk_string;
+use crate::components::helpers::clones::generate_clone_guests;
+
+/// The name of the lock file, written next to the kvm-compose.yaml
+pub const LOCK_FILE_NAME: &str = "kvm-compose.lock";
+
+const LOCK_FILE_VERSION: u32 = 1;
+
+/// Records the inputs of a deployment so that a rerun can prove it used the same inputs. This is
+/// the resolved config, the image each guest is created from, the scripts and context folders used
+/// to provision the guests and the clones generated from scaling.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct LockFile {
+    pub version: u32,
+    /// SHA256 of the kvm-compose.yaml after the variables are interpolated and includes are merged
+    pub config_sha256: String,
+    pub guests: BTreeMap<String, LockedGuest>,
+    /// The clones generated for each guest with a scaling section
+    pub clones: BTreeMap<String, Vec<String>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct LockedGuest {
+    pub source: LockedSource,
+    /// The SHA256 of the scripts and the files in context folders, by path as given in the yaml
+    pub files: BTreeMap<String, String>,
+}
+
+/// What the guest is created from
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LockedSource {
+    CloudImage {
+        name: String,
+        /// The url or path of the image from the cloud image catalog
+        source: String,
+        image: Option<LockedFile>,
+    },
+    ExistingDisk(LockedFile),
+    IsoGuest(LockedFile),
+    CloneOf { guest: String },
+    Docker {
+        image: String,
+        /// The repo digest of the image on the testbed host, if the image has been pulled
+        digest: Option<String>,
+    },
+    Avd { system_image: String },
+    ExistingAvd { path: String },
+}
+
+/// A file hashed for the lock. The size and modified time are kept so that large images are only
+/// hashed again if they have changed, they are not compared when checking the lock.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct LockedFile {
+    pub path: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub modified: u64,
+}
+
+impl PartialEq for LockedFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.path.eq(&other.path) && self.sha256.eq(&other.sha256)
+    }
+}
+
+impl LockFile {
+    /// Create the lock for the project's kvm-compose.yaml. If there is a previous lock, files with
+    /// the same size and modified time as in the previous lock are not hashed again.
+    pub async fn generate(
+        yaml_path: &Path,
+        var_file: Option<&Path>,
+        project_location: &Path,
+        previous: Option<&LockFile>,
+    ) -> anyhow::Result<Self> {
+        let variables = load_variables(var_file).await?;
+        let resolved = resolve_yaml(yaml_path, &variables).await?;
+        let config_sha256 = format!("{:x}", Sha256::digest(serde_yaml::to_string(&resolved)?.as_bytes()));
+        let mut config: Config = serde_yaml::from_value(resolved)
+            .context("Parsing Config YAML")?;
+        generate_clone_guests(&mut config)?;
+        let catalog = CloudImageCatalog::load(Some(project_location))
+            .await
+            .context("loading the cloud image catalog")?;
+        let hasher = FileHasher::new(project_location, previous);
+
+        let mut guests = BTreeMap::new();
+        let mut clones: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for machine in config.machines.iter().flatten() {
+            let mut files = BTreeMap::new();
+            let source = match &machine.guest_type {
+                GuestType::Libvirt(libvirt) => {
+                    if let Some(scaling) = &libvirt.scaling {
+                        if let Some(shared_setup) = &scaling.shared_setup {
+                            hasher.hash_into(shared_setup, &mut files).await?;
+                        }
+                    }
+                    if let LibvirtGuestOptions::CloudImage { run_script, setup_script, context, .. } = &libvirt.libvirt_type {
+                        for path in [run_script, setup_script, context].into_iter().flatten() {
+                            hasher.hash_into(path, &mut files).await?;
+                        }
+                    }
+                    match (&libvirt.is_clone_of, &libvirt.libvirt_type) {
+                        (Some(backing_guest), _) => {
+                            clones.entry(backing_guest.clone()).or_default().push(machine.name.clone());
+                            LockedSource::CloneOf { guest: backing_guest.clone() }
+                        }
+                        (None, LibvirtGuestOptions::CloudImage { name, .. }) => {
+                            let cloud_image = catalog.get(name)?;
+                            let source = match &cloud_image.source {
+                                CloudImageSource::Url(url) => url.clone(),
+                                CloudImageSource::Path(path) => path.display().to_string(),
+                            };
+                            // the image is only in the store once it has been downloaded
+                            let store_path = cloud_image.store_path(Path::new(TESTBED_IMAGES_FOLDER));
+                            let image = if store_path.is_file() {
+                                Some(hasher.hash_file(&store_path).await?)
+                            } else {
+                                None
+                            };
+                            if let (Some(image), Some(pinned)) = (&image, &cloud_image.sha256) {
+                                if !image.sha256.eq_ignore_ascii_case(pinned) {
+                                    bail!("cloud image '{name}' at {store_path:?} does not match the catalog SHA256");
+                                }
+                            }
+                            LockedSource::CloudImage { name: name.clone(), source, image }
+                        }
+                        (None, LibvirtGuestOptions::ExistingDisk { path, .. }) => {
+                            LockedSource::ExistingDisk(hasher.hash_file(path).await?)
+                        }
+                        (None, LibvirtGuestOptions::IsoGuest { path, .. }) => {
+                            LockedSource::IsoGuest(hasher.hash_file(path).await?)
+                        }
+                    }
+                }
+                GuestType::Docker(docker) => {
+                    if let Some(env_file) = &docker.env_file {
+                        hasher.hash_into(Path::new(env_file), &mut files).await?;
+                    }
+                    LockedSource::Docker {
+                        image: docker.image.clone(),
+                        digest: docker_image_digest(&docker.image).await,
+                    }
+                }
+                GuestType::Android(avd) => match &avd.avd_type {
+                    AVDGuestOptions::Avd { .. } => LockedSource::Avd {
+                        system_image: get_sdk_string(&avd.avd_type)?,
+                    },
+                    AVDGuestOptions::ExistingAvd { path } => LockedSource::ExistingAvd {
+                        path: path.display().to_string(),
+                    },
+                },
+            };
+            guests.insert(machine.name.clone(), LockedGuest { source, files });
+        }
+
+        Ok(Self {
+            version: LOCK_FILE_VERSION,
+            config_sha256,
+            guests,
+            clones,
+        })
+    }
+
+    /// Read the lock file in the project folder, if there is one
+    pub async fn read(project_location: &Path) -> anyhow::Result<Option<Self>> {
+        let path = project_location.join(LOCK_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let text = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("reading lock file {path:?}"))?;
+        let lock: Self = serde_json::from_str(&text)
+            .with_context(|| format!("parsing lock file {path:?}"))?;
+        if lock.version != LOCK_FILE_VERSION {
+            bail!("lock file {path:?} is version {}, only version {LOCK_FILE_VERSION} is supported", lock.version);
+        }
+        Ok(Some(lock))
+    }
+
+    /// Write the lock file into the project folder, owned by the owner of the project folder
+    pub async fn write(&self, project_location: &Path) -> anyhow::Result<()> {
+        let path = project_location.join(LOCK_FILE_NAME);
+        let mut text = serde_json::to_string_pretty(self)?;
+        text.push('\n');
+        tokio::fs::write(&path, text)
+            .await
+            .with_context(|| format!("writing lock file {path:?}"))?;
+        let metadata = tokio::fs::metadata(project_location).await?;
+        nix::unistd::chown(&path, Some(Uid::from(metadata.st_uid())), Some(Gid::from(metadata.st_gid())))?;
+        Ok(())
+    }
+
+    /// Describe everything in this lock that is different in the other lock
+    pub fn differences(&self, other: &LockFile) -> Vec<String> {
+        let mut differences = Vec::new();
+        if self.config_sha256 != other.config_sha256 {
+            differences.push("the resolved kvm-compose.yaml has changed".to_string());
+        }
+        for (name, guest) in &self.guests {
+            let Some(other_guest) = other.guests.get(name) else {
+                differences.push(format!("guest {name} has been removed"));
+                continue;
+            };
+            if guest.source != other_guest.source {
+                differences.push(format!(
+                    "guest {name} image has changed from {} to {}",
+                    guest.source.describe(),
+                    other_guest.source.describe(),
+                ));
+            }
+            for (path, sha256) in &guest.files {
+                match other_guest.files.get(path) {
+                    None => differences.push(format!("guest {name} no longer uses {path}")),
+                    Some(other_sha256) if other_sha256 != sha256 => {
+                        differences.push(format!("guest {name} file {path} has changed"));
+                    }
+                    Some(_) => {}
+                }
+            }
+            for path in other_guest.files.keys() {
+                if !guest.files.contains_key(path) {
+                    differences.push(format!("guest {name} now uses {path}"));
+                }
+            }
+        }
+        for name in other.guests.keys() {
+            if !self.guests.contains_key(name) {
+                differences.push(format!("guest {name} has been added"));
+            }
+        }
+        if self.clones != other.clones {
+            differences.push("the generated clones have changed".to_string());
+        }
+        differences
+    }
+}
+
+impl LockedSource {
+    fn describe(&self) -> String {
+        match self {
+            LockedSource::CloudImage { name, source, image } => match image {
+                Some(image) => format!("cloud image {name} ({source}, sha256 {})", image.sha256),
+                None => format!("cloud image {name} ({source}, not downloaded)"),
+            },
+            LockedSource::ExistingDisk(file) | LockedSource::IsoGuest(file) => {
+                format!("{} (sha256 {})", file.path, file.sha256)
+            }
+            LockedSource::CloneOf { guest } => format!("clone of {guest}"),
+            LockedSource::Docker { image, digest } => match digest {
+                Some(digest) => format!("{image} ({digest})"),
+                None => format!("{image} (not pulled)"),
+            },
+            LockedSource::Avd { system_image } => system_image.clone(),
+            LockedSource::ExistingAvd { path } => path.clone(),
+        }
+    }
+}
+
+/// Hashes the files for the lock, reusing the hashes of unchanged files from the previous lock
+struct FileHasher<'a> {
+    project_location: &'a Path,
+    previous: BTreeMap<String, &'a LockedFile>,
+}
+
+impl<'a> FileHasher<'a> {
+    fn new(project_location: &'a Path, previous: Option<&'a LockFile>) -> Self {
+        let mut previous_files = BTreeMap::new();
+        for guest in previous.iter().flat_map(|p| p.guests.values()) {
+            let file = match &guest.source {
+                LockedSource::CloudImage { image: Some(file), .. } => file,
+                LockedSource::ExistingDisk(file) | LockedSource::IsoGuest(file) => file,
+                _ => continue,
+            };
+            previous_files.insert(file.path.clone(), file);
+        }
+        Self {
+            project_location,
+            previous: previous_files,
+        }
+    }
+
+    /// Relative paths in the yaml are relative to the project folder
+    fn resolve(&self, path: &Path) -> PathBuf {
+        if path.is_relative() {
+            self.project_location.join(path)
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    async fn hash_file(&self, path: &Path) -> anyhow::Result<LockedFile> {
+        let resolved = self.resolve(path);
+        let metadata = tokio::fs::metadata(&resolved)
+            .await
+            .with_context(|| format!("reading {resolved:?} for the lock file"))?;
+        let modified = metadata.modified()?
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+        let path = path.display().to_string();
+        if let Some(previous) = self.previous.get(&path) {
+            if previous.size_bytes == metadata.len() && previous.modified == modified {
+                return Ok((*previous).clone());
+            }
+        }
+        tracing::info!("hashing {resolved:?} for the lock file");
+        Ok(LockedFile {
+            path,
+            sha256: sha256_file(&resolved).await?,
+            size_bytes: metadata.len(),
+            modified,
+        })
+    }
+
+    /// Hash the script, or every file in the folder, into the guest's files
+    async fn hash_into(&self, path: &Path, files: &mut BTreeMap<String, String>) -> anyhow::Result<()> {
+        let resolved = self.resolve(path);
+        if resolved.is_dir() {
+            for file in files_in_folder(&resolved)? {
+                let relative = file.strip_prefix(&resolved)?;
+                let sha256 = sha256_file(&file).await?;
+                files.insert(path.join(relative).display().to_string(), sha256);
+            }
+        } else {
+            files.insert(path.display().to_string(), sha256_file(&resolved).await?);
+        }
+        Ok(())
+    }
+}
+
+/// Get every file in the folder and its sub folders
+fn files_in_folder(folder: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(folder).with_context(|| format!("reading folder {folder:?}"))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(files_in_folder(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Get the repo digest of the docker image on this host, or `None` if the image has not been
+/// pulled or docker is not available
+async fn docker_image_digest(image: &str) -> Option<String> {
+    let output = tokio::process::Command::new("docker")
+        .args(["image", "inspect", "--format", "{{json .RepoDigests}}", image])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let digests: Vec<String> = serde_json::from_slice(&output.stdout).ok()?;
+    digests.into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock() -> LockFile {
+        LockFile {
+            version: LOCK_FILE_VERSION,
+            config_sha256: "abc".to_string(),
+            guests: BTreeMap::from([
+                ("server".to_string(), LockedGuest {
+                    source: LockedSource::ExistingDisk(LockedFile {
+                        path: "/images/server.img".to_string(),
+                        sha256: "111".to_string(),
+                        size_bytes: 10,
+                        modified: 1,
+                    }),
+                    files: BTreeMap::from([("setup.sh".to_string(), "222".to_string())]),
+                }),
+                ("server-0".to_string(), LockedGuest {
+                    source: LockedSource::CloneOf { guest: "server".to_string() },
+                    files: BTreeMap::new(),
+                }),
+            ]),
+            clones: BTreeMap::from([("server".to_string(), vec!["server-0".to_string()])]),
+        }
+    }
+
+    #[test]
+    fn test_lock_differences() {
+        let locked = lock();
+        let mut current = lock();
+        // a copied image keeps its hash but not its modified time
+        if let LockedSource::ExistingDisk(file) = &mut current.guests.get_mut("server").unwrap().source {
+            file.modified = 2;
+        }
+        assert!(locked.differences(&current).is_empty());
+
+        let server = current.guests.get_mut("server").unwrap();
+        server.files.insert("setup.sh".to_string(), "333".to_string());
+        current.guests.remove("server-0");
+        current.clones.clear();
+        assert_eq!(locked.differences(&current), vec![
+            "guest server file setup.sh has changed".to_string(),
+            "guest server-0 has been removed".to_string(),
+            "the generated clones have changed".to_string(),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_hash_context_folder() {
+        let dir = tempfile::tempdir().unwrap();
+        tokio::fs::create_dir_all(dir.path().join("context/sub")).await.unwrap();
+        tokio::fs::write(dir.path().join("context/a.txt"), b"a").await.unwrap();
+        tokio::fs::write(dir.path().join("context/sub/b.txt"), b"b").await.unwrap();
+        let hasher = FileHasher::new(dir.path(), None);
+        let mut files = BTreeMap::new();
+        hasher.hash_into(Path::new("context"), &mut files).await.unwrap();
+        let paths: Vec<_> = files.keys().cloned().collect();
+        assert_eq!(paths, vec!["context/a.txt".to_string(), "context/sub/b.txt".to_string()]);
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use kvm_compose_schemas::kvm_compose_yaml::network::acl::{ACLAction, ACLDirection};
+use kvm_compose_schemas::net::{NetHealCmd, NetLinkCmd, NetPartitionCmd, NetSubCommand};
+use crate::components::network::guest_switch_port_name;
+use crate::orchestration::api::{OrchestrationInstruction, OrchestrationProtocol};
+use crate::orchestration::OrchestrationCommon;
+use crate::orchestration::websocket::send_orchestration_instruction_over_channel;
+use crate::ovn::components::acl::{ACLRecordType, LogicalACLRecord};
+use crate::ovn::components::logical_switch::LogicalSwitch;
+use crate::ovn::ovn::OvnNetwork;
+use crate::ovn::OvnCommand;
+use crate::state::{State, StateNetwork};
+use crate::state::orchestration_tasks::ovn_network::{ovn_run_cmd, ovn_run_cmd_allow_fail};
+use crate::vec_of_strings;
+
+/// Partition ACL rules use the highest priority so that they override the rules in the yaml
+const PARTITION_ACL_PRIORITY: i16 = i16::MAX;
+
+/// A fault injected into the network of a running deployment with the net commands. Active faults
+/// are recorded in the state so that they can be undone with heal.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkFault {
+    /// A logical switch port that has been disabled
+    SwitchPortDown {
+        port: String,
+    },
+    /// A logical router port that has been disabled
+    RouterPortDown {
+        port: String,
+    },
+    /// Drop rules on both switches for the traffic between them
+    Partition {
+        switches: (String, String),
+        acl: Vec<LogicalACLRecord>,
+    },
+}
+
+impl NetworkFault {
+    /// The name of the fault in the state, this is unique to what the fault is applied to
+    pub fn name(&self) -> String {
+        match self {
+            NetworkFault::SwitchPortDown { port } | NetworkFault::RouterPortDown { port } => {
+                format!("link-down/{port}")
+            }
+            NetworkFault::Partition { switches: (switch_a, switch_b), .. } => {
+                format!("partition/{switch_a}/{switch_b}")
+            }
+        }
+    }
+
+    /// Find the switch port of a guest's interface, or if there is no guest with the name, the
+    /// router port with the name
+    pub fn link_down(
+        link_cmd: &NetLinkCmd,
+        state: &State,
+    ) -> anyhow::Result<Self> {
+        let ovn = get_ovn(state)?;
+        let project_name_hyphen = format!("{}-", &state.project_name);
+        let target = link_cmd.target.strip_prefix(&project_name_hyphen)
+            .unwrap_or(&link_cmd.target)
+            .to_string();
+        if let Some(guest_data) = state.testbed_guests.0.get(&target) {
+            let interface = guest_data.guest_type.network.iter().flatten()
+                .nth(link_cmd.interface)
+                .context(format!("guest {target} does not have an interface {}", link_cmd.interface))?;
+            let port = guest_switch_port_name(&state.project_name, &interface.switch, &target, link_cmd.interface);
+            if !ovn.switch_ports.contains_key(&port) {
+                bail!("could not find switch port {port} for guest {target}");
+            }
+            return Ok(NetworkFault::SwitchPortDown { port });
+        }
+        let port = format!("{}{target}", &project_name_hyphen);
+        if ovn.router_ports.contains_key(&port) {
+            return Ok(NetworkFault::RouterPortDown { port });
+        }
+        bail!("could not find a guest or router port called {target} in the project state");
+    }
+
+    /// Create the drop rules between two switches. The switch names are sorted so that the
+    /// partition has the same name whichever order the switches are given in.
+    pub fn partition(
+        switch_a: &String,
+        switch_b: &String,
+        state: &State,
+    ) -> anyhow::Result<Self> {
+        if switch_a.eq(switch_b) {
+            bail!("cannot partition switch {switch_a} from itself");
+        }
+        let ovn = get_ovn(state)?;
+        let mut switches = [switch_a.clone(), switch_b.clone()];
+        switches.sort();
+        let [switch_a, switch_b] = switches;
+        let logical_switch_a = get_switch(ovn, &state.project_name, &switch_a)?;
+        let logical_switch_b = get_switch(ovn, &state.project_name, &switch_b)?;
+        let ovn_resource_name = format!("{}-partition-{switch_a}-{switch_b}", &state.project_name);
+        // drop the traffic entering each switch that is for the other switch
+        let acl = [(logical_switch_a, logical_switch_b), (logical_switch_b, logical_switch_a)]
+            .into_iter()
+            .map(|(from, to)| LogicalACLRecord::new(
+                from.name.clone(),
+                ACLRecordType::Switch,
+                ACLDirection::FromLport,
+                PARTITION_ACL_PRIORITY,
+                destination_match(to),
+                ACLAction::Drop,
+                ovn_resource_name.clone(),
+            ))
+            .collect();
+        Ok(NetworkFault::Partition {
+            switches: (switch_a, switch_b),
+            acl,
+        })
+    }
+
+    /// Apply the fault to the network
+    pub async fn apply(&self, orchestration_common: &OrchestrationCommon) -> anyhow::Result<()> {
+        tracing::info!("applying network fault {}", self.name());
+        let config = (None, orchestration_common.clone());
+        match self {
+            NetworkFault::SwitchPortDown { port } => {
+                ovn_run_cmd(vec_of_strings!["ovn-nbctl", "lsp-set-enabled", port, "disabled"], config).await?;
+            }
+            NetworkFault::RouterPortDown { port } => {
+                ovn_run_cmd(vec_of_strings!["ovn-nbctl", "lrp-set-enabled", port, "disabled"], config).await?;
+            }
+            NetworkFault::Partition { acl, .. } => {
+                for record in acl {
+                    record.create_command(ovn_run_cmd, config.clone()).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the fault, then record it in the saved state so that it can be healed later
+    pub async fn apply_and_record(&self, orchestration_common: &OrchestrationCommon) -> anyhow::Result<()> {
+        self.apply(orchestration_common).await?;
+        update_saved_faults(orchestration_common, |faults| {
+            faults.insert(self.name(), self.clone());
+        }).await
+    }
+
+    /// Heal the fault, then remove it from the saved state. A fault that could not be healed is
+    /// kept so that healing it can be tried again.
+    pub async fn heal_and_record(&self, orchestration_common: &OrchestrationCommon) -> anyhow::Result<()> {
+        self.heal(orchestration_common).await?;
+        update_saved_faults(orchestration_common, |faults| {
+            faults.remove(&self.name());
+        }).await
+    }
+
+    /// Undo the fault. This is allowed to fail, as the network may have been recreated since the
+    /// fault was applied.
+    pub async fn heal(&self, orchestration_common: &OrchestrationCommon) -> anyhow::Result<()> {
+        tracing::info!("healing network fault {}", self.name());
+        let config = (None, orchestration_common.clone());
+        match self {
+            NetworkFault::SwitchPortDown { port } => {
+                ovn_run_cmd_allow_fail(vec_of_strings!["ovn-nbctl", "lsp-set-enabled", port, "enabled"], config).await?;
+            }
+            NetworkFault::RouterPortDown { port } => {
+                ovn_run_cmd_allow_fail(vec_of_strings!["ovn-nbctl", "lrp-set-enabled", port, "enabled"], config).await?;
+            }
+            NetworkFault::Partition { acl, .. } => {
+                for record in acl {
+                    record.destroy_command(ovn_run_cmd_allow_fail, config.clone()).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Change the active faults in the saved state. The state is read again for every change, as
+/// heal all sends one instruction per fault.
+async fn update_saved_faults(
+    orchestration_common: &OrchestrationCommon,
+    f: impl FnOnce(&mut BTreeMap<String, NetworkFault>),
+) -> anyhow::Result<()> {
+    let project_name = &orchestration_common.project_name;
+    let project_path = &orchestration_common.project_working_dir;
+    let mut state = State::read(project_name, project_path).await?;
+    f(&mut state.faults);
+    state.write(project_name, project_path)
+        .await
+        .context("saving the active faults in the state")
+}
+
+fn get_ovn(state: &State) -> anyhow::Result<&OvnNetwork> {
+    match &state.network {
+        StateNetwork::Ovn(ovn) => Ok(ovn),
+        StateNetwork::Ovs(_) => bail!("network faults are only supported on OVN networks"),
+    }
+}
+
+fn get_switch<'a>(
+    ovn: &'a OvnNetwork,
+    project_name: &String,
+    switch_name: &String,
+) -> anyhow::Result<&'a LogicalSwitch> {
+    ovn.switches.get(&format!("{project_name}-{switch_name}"))
+        .context(format!("could not find switch {switch_name} in the project state"))
+}
+
+/// Match the packets addressed to the subnets of the switch
+fn destination_match(switch: &LogicalSwitch) -> String {
+    let mut rule_match = format!("ip4.dst == {}", switch.subnet.to_string());
+    if let Some(ipv6_prefix) = &switch.ipv6_prefix {
+        rule_match.push_str(&format!(" || ip6.dst == {}", ipv6_prefix.to_string()));
+    }
+    rule_match
+}
+
+/// Work out the faults to apply or heal for the net command and request the changes from the
+/// server. The server records the active faults in the state once each change has been applied.
+pub async fn request_fault_changes(
+    sub_command: &NetSubCommand,
+    state: &State,
+    sender: &mut Sender<OrchestrationProtocol>,
+) -> anyhow::Result<()> {
+    match sub_command {
+        NetSubCommand::LinkDown(link_cmd) => {
+            let fault = NetworkFault::link_down(link_cmd, state)?;
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::ApplyFault(fault),
+            ).await.context("sending Apply Fault request to server")?;
+        }
+        NetSubCommand::LinkUp(link_cmd) => {
+            let fault = NetworkFault::link_down(link_cmd, state)?;
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::HealFault(fault),
+            ).await.context("sending Heal Fault request to server")?;
+        }
+        NetSubCommand::Partition(NetPartitionCmd { switch_a, switch_b }) => {
+            let fault = NetworkFault::partition(switch_a, switch_b, state)?;
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::ApplyFault(fault),
+            ).await.context("sending Apply Fault request to server")?;
+        }
+        NetSubCommand::Heal(NetHealCmd { switch_a, switch_b, all }) => {
+            let faults: Vec<NetworkFault> = if *all {
+                state.faults.values().cloned().collect()
+            } else {
+                let (Some(switch_a), Some(switch_b)) = (switch_a, switch_b) else {
+                    bail!("heal needs two switches or --all");
+                };
+                let name = NetworkFault::partition(switch_a, switch_b, state)?.name();
+                let fault = state.faults.get(&name)
+                    .context(format!("there is no partition between {switch_a} and {switch_b}"))?;
+                vec![fault.clone()]
+            };
+            if faults.is_empty() {
+                tracing::info!("there are no faults to heal");
+            }
+            for fault in faults {
+                send_orchestration_instruction_over_channel(
+                    sender,
+                    OrchestrationInstruction::HealFault(fault),
+                ).await.context("sending Heal Fault request to server")?;
+            }
+        }
+        NetSubCommand::Qos(_) => bail!("qos is not a network fault"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use crate::ovn::components::MacAddress;
+    use super::*;
+
+    fn state() -> State {
+        let mut ovn = OvnNetwork::new();
+        ovn.add_switch("test-sw0".into(), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 24).unwrap();
+        ovn.add_switch("test-sw1".into(), IpAddr::V4(Ipv4Addr::new(10, 0, 1, 0)), 24).unwrap();
+        ovn.add_router("test-lr0".into()).unwrap();
+        ovn.add_lrp(
+            "test-lr0-sw0".into(),
+            "test-lr0".into(),
+            MacAddress::new("00:00:00:00:ff:01".into()).unwrap(),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            24,
+            None,
+        ).unwrap();
+        State::test_fixture(vec![], StateNetwork::Ovn(ovn))
+    }
+
+    #[test]
+    fn test_partition_fault() {
+        let state = state();
+        let fault = NetworkFault::partition(&"sw1".into(), &"sw0".into(), &state).unwrap();
+        assert_eq!(fault.name(), "partition/sw0/sw1");
+        let NetworkFault::Partition { acl, .. } = fault else {
+            panic!("expected a partition");
+        };
+        assert_eq!(acl.len(), 2);
+        assert_eq!(acl[0].entity_name, "test-sw0");
+        assert_eq!(acl[0]._match, "ip4.dst == 10.0.1.0/24");
+        assert_eq!(acl[1].entity_name, "test-sw1");
+        assert_eq!(acl[1]._match, "ip4.dst == 10.0.0.0/24");
+        assert_eq!(acl[0].priority, PARTITION_ACL_PRIORITY);
+
+        assert!(NetworkFault::partition(&"sw0".into(), &"sw0".into(), &state).is_err());
+        assert!(NetworkFault::partition(&"sw0".into(), &"sw2".into(), &state).is_err());
+    }
+
+    #[test]
+    fn test_link_down_router_port() {
+        let state = state();
+        let link_cmd = NetLinkCmd { target: "lr0-sw0".into(), interface: 0 };
+        let fault = NetworkFault::link_down(&link_cmd, &state).unwrap();
+        assert_eq!(fault.name(), "link-down/test-lr0-sw0");
+        assert!(matches!(fault, NetworkFault::RouterPortDown { .. }));
+
+        let link_cmd = NetLinkCmd { target: "missing".into(), interface: 0 };
+        assert!(NetworkFault::link_down(&link_cmd, &state).is_err());
+    }
+}
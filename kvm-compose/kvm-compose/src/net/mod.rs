@@ -0,0 +1,239 @@
+use std::net::{IpAddr, Ipv4Addr};
+use anyhow::{bail, Context};
+use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
+use kvm_compose_schemas::kvm_compose_yaml::network::qos::Qos;
+use kvm_compose_schemas::net::{NetCmd, NetQosCmd, NetSubCommand};
+use crate::components::get_guest_interface_name;
+use crate::components::network::guest_switch_port_name;
+use crate::orchestration::{OrchestrationCommon, run_testbed_orchestration_command, run_testbed_orchestration_command_allow_fail};
+use crate::ovn::components::OvnIpAddr;
+use crate::ovn::components::logical_switch_port::{LogicalSwitchPort, LogicalSwitchPortQos, LogicalSwitchPortType};
+use crate::state::{State, StateNetwork, StateTestbedGuest};
+use crate::state::orchestration_tasks::ovn_network::{ovn_run_cmd, ovn_run_cmd_allow_fail};
+use crate::vec_of_strings;
+
+pub mod faults;
+pub mod trace;
+pub mod verify;
+
+/// Run a net command against the running deployment. The changes are made directly on the testbed
+/// and then recorded in the state, the same as if they had been in the kvm-compose.yaml.
+pub async fn run_net_command(
+    net_cmd: &NetCmd,
+    state: &State,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<()> {
+    match &net_cmd.sub_command {
+        NetSubCommand::Qos(qos_cmd) => set_guest_qos(qos_cmd, state, orchestration_common).await,
+        // faults are sent to the server as ApplyFault and HealFault instructions
+        _ => bail!("net command {} must be sent as network faults", net_cmd.name()),
+    }
+}
+
+/// Get the guest from the state, the name can be given with or without the project prefix
+pub fn get_guest<'a>(
+    guest_name: &str,
+    state: &'a State,
+) -> anyhow::Result<(String, &'a StateTestbedGuest)> {
+    let project_name_hyphen = format!("{}-", &state.project_name);
+    let guest_name = guest_name.strip_prefix(&project_name_hyphen)
+        .unwrap_or(guest_name)
+        .to_string();
+    match state.testbed_guests.0.get(&guest_name) {
+        Some(guest_data) => Ok((guest_name, guest_data)),
+        None => bail!("could not find guest {guest_name} in the project state"),
+    }
+}
+
+/// Get the IPv4 address that OVN has given to a switch port with a dynamic ip, or the ip reserved
+/// for the port in the DHCP configuration
+pub async fn dynamic_ipv4(
+    port_name: &str,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<Ipv4Addr> {
+    if let StateNetwork::Ovn(ovn) = &orchestration_common.network {
+        if let Some(LogicalSwitchPortType::Internal { ip: OvnIpAddr::Ip(IpAddr::V4(ip)), .. }) = ovn.switch_ports.get(port_name)
+            .map(|lsp| &lsp.port_type) {
+            return Ok(*ip);
+        }
+    }
+    let dynamic_addresses = ovn_run_cmd(
+        vec_of_strings!["ovn-nbctl", "--bare", "--columns=dynamic_addresses", "list", "Logical_Switch_Port", port_name],
+        (None, orchestration_common.clone()),
+    ).await?;
+    dynamic_addresses.split_whitespace()
+        .find_map(|address| address.parse::<Ipv4Addr>().ok())
+        .context(format!("switch port {port_name} has not been given a dynamic ip yet"))
+}
+
+/// Replace the qos of a guest's interface on the running deployment
+async fn set_guest_qos(
+    qos_cmd: &NetQosCmd,
+    state: &State,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<()> {
+    qos_cmd.qos.validate()?;
+    let (guest_name, guest_data) = get_guest(&qos_cmd.guest_name, state)?;
+    let interface = guest_data.guest_type.network.iter().flatten()
+        .nth(qos_cmd.interface)
+        .context(format!("guest {guest_name} does not have an interface {}", qos_cmd.interface))?;
+    let testbed_host = guest_data.testbed_host.as_ref()
+        .context(format!("getting testbed host for guest {guest_name}"))?;
+
+    // the rate limit is a QoS rule on the switch port
+    let port_name = guest_switch_port_name(&state.project_name, &interface.switch, &guest_name, qos_cmd.interface);
+    let mut lsp = match &orchestration_common.network {
+        StateNetwork::Ovn(ovn) => ovn.switch_ports.get(&port_name)
+            .context(format!("getting switch port {port_name} for guest {guest_name}"))?
+            .clone(),
+        StateNetwork::Ovs(_) => bail!("qos is only supported on OVN networks"),
+    };
+    tracing::info!("setting qos {:?} on guest {guest_name} interface {}", &qos_cmd.qos, qos_cmd.interface);
+    // the port may not have had a rate limit before, so allow removing the rules to fail
+    let mut cmd = vec_of_strings!["ovn-nbctl"];
+    cmd.extend(lsp.qos_del_args());
+    ovn_run_cmd_allow_fail(cmd, (None, orchestration_common.clone())).await?;
+    lsp.qos = LogicalSwitchPortQos::from_qos(&qos_cmd.qos);
+    if lsp.qos.is_some() {
+        let mut cmd = vec_of_strings!["ovn-nbctl"];
+        cmd.extend(lsp.qos_add_args());
+        ovn_run_cmd(cmd, (None, orchestration_common.clone())).await?;
+    }
+
+    // the rest is tc netem on the guest's interface
+    let (guest_interface, namespace) = get_guest_host_interface(guest_data, qos_cmd.interface, orchestration_common).await?;
+    set_interface_netem(
+        orchestration_common,
+        testbed_host,
+        &guest_interface,
+        namespace.as_deref(),
+        Some(&qos_cmd.qos),
+    ).await?;
+
+    update_saved_qos(orchestration_common, &guest_name, qos_cmd.interface, &qos_cmd.qos, lsp).await
+}
+
+/// Record the new qos of the guest's interface and its switch port in the saved state, so that the
+/// next `up` sees the difference to the kvm-compose.yaml
+async fn update_saved_qos(
+    orchestration_common: &OrchestrationCommon,
+    guest_name: &str,
+    idx: usize,
+    qos: &Qos,
+    lsp: LogicalSwitchPort,
+) -> anyhow::Result<()> {
+    let project_name = &orchestration_common.project_name;
+    let project_path = &orchestration_common.project_working_dir;
+    let mut state = State::read(project_name, project_path).await?;
+    let interface = state.testbed_guests.0.get_mut(guest_name)
+        .and_then(|guest_data| guest_data.guest_type.network.as_mut())
+        .and_then(|network| network.get_mut(idx))
+        .context(format!("getting interface {idx} of guest {guest_name} from the saved state"))?;
+    interface.qos = Some(qos.clone()).filter(|qos| *qos != Qos::default());
+    if let StateNetwork::Ovn(ovn) = &mut state.network {
+        ovn.switch_ports.insert(lsp.name.clone(), lsp);
+    }
+    state.write(project_name, project_path)
+        .await
+        .context("saving the qos in the state")
+}
+
+/// Get the name of the interface on the testbed host that connects the guest's interface to the
+/// integration bridge. Android guest interfaces are moved into a network namespace, so the
+/// namespace is also returned for them.
+pub async fn get_guest_host_interface(
+    guest_data: &StateTestbedGuest,
+    idx: usize,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<(String, Option<String>)> {
+    let project_name = &orchestration_common.project_name;
+    let guest_name = &guest_data.guest_type.name;
+    match &guest_data.guest_type.guest_type {
+        GuestType::Libvirt(_) => {
+            Ok((get_guest_interface_name(project_name, guest_data.guest_id, idx), None))
+        }
+        GuestType::Docker(_) => {
+            if idx != 0 {
+                bail!("docker guests only have one interface");
+            }
+            let testbed_host = guest_data.testbed_host.as_ref()
+                .context(format!("getting testbed host for guest {guest_name}"))?;
+            let interface = get_docker_host_interface(&format!("{project_name}-{guest_name}"), testbed_host, orchestration_common).await?;
+            Ok((interface, None))
+        }
+        GuestType::Android(_) => {
+            if idx != 0 {
+                bail!("android guests only have one interface");
+            }
+            let namespace = format!("{project_name}-{guest_name}-nmspc");
+            Ok((get_guest_interface_name(project_name, guest_data.guest_id, 0), Some(namespace)))
+        }
+    }
+}
+
+/// ovs-docker names the host side of the container's veth pair after the container id, so find it
+/// from the external ids that ovs-docker sets on the OVS interface
+pub async fn get_docker_host_interface(
+    container_name: &String,
+    testbed_host: &String,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<String> {
+    let container_id = format!("external_ids:container_id={container_name}");
+    let interface = run_testbed_orchestration_command(
+        orchestration_common,
+        testbed_host,
+        "sudo",
+        vec!["ovs-vsctl", "--bare", "--columns=name", "find", "interface", &container_id, "external_ids:container_iface=eth0"],
+        false,
+        None,
+    ).await?;
+    let interface = interface.trim();
+    if interface.is_empty() {
+        bail!("could not find the OVS interface of docker guest {container_name}");
+    }
+    Ok(interface.to_string())
+}
+
+/// Apply the delay, jitter, loss and duplication of the qos to an interface on the testbed host
+/// with tc netem, replacing any that was set before. If the qos has none of these, any netem on
+/// the interface is removed. If `namespace` is given, tc is run inside that network namespace.
+pub async fn set_interface_netem(
+    orchestration_common: &OrchestrationCommon,
+    testbed_host: &String,
+    interface: &str,
+    namespace: Option<&str>,
+    qos: Option<&Qos>,
+) -> anyhow::Result<()> {
+    let mut cmd = match namespace {
+        Some(namespace) => vec_of_strings!["ip", "netns", "exec", namespace, "tc", "qdisc"],
+        None => vec_of_strings!["tc", "qdisc"],
+    };
+    match qos.and_then(Qos::netem_args) {
+        Some(netem_args) => {
+            tracing::info!("setting {} on interface {interface}", netem_args.join(" "));
+            cmd.extend(vec_of_strings!["replace", "dev", interface, "root"]);
+            cmd.extend(netem_args);
+            run_testbed_orchestration_command(
+                orchestration_common,
+                testbed_host,
+                "sudo",
+                cmd.iter().map(|s| s.as_str()).collect(),
+                false,
+                None,
+            ).await?;
+        }
+        None => {
+            // there may not be a netem qdisc on the interface
+            cmd.extend(vec_of_strings!["del", "dev", interface, "root"]);
+            run_testbed_orchestration_command_allow_fail(
+                orchestration_common,
+                testbed_host,
+                "sudo",
+                cmd.iter().map(|s| s.as_str()).collect(),
+                false,
+                None,
+            ).await?;
+        }
+    }
+    Ok(())
+}
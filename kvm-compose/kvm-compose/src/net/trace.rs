@@ -0,0 +1,461 @@
+use std::net::{IpAddr, Ipv4Addr};
+use anyhow::{bail, Context};
+use tokio::sync::mpsc::Sender;
+use kvm_compose_schemas::trace::{TraceCmd, TraceProtocol};
+use crate::components::network::guest_switch_port_name;
+use crate::net::{dynamic_ipv4, get_guest};
+use crate::orchestration::api::OrchestrationLogger;
+use crate::orchestration::OrchestrationCommon;
+use crate::ovn::components::logical_switch_port::{LogicalSwitchPort, LogicalSwitchPortType};
+use crate::ovn::components::OvnIpAddr;
+use crate::ovn::ovn::OvnNetwork;
+use crate::state::{State, StateNetwork};
+use crate::state::orchestration_tasks::ovn_network::ovn_run_cmd;
+use crate::vec_of_strings;
+
+/// The source port of traced tcp and udp packets, any ephemeral port would do
+const TRACE_SOURCE_PORT: u16 = 40000;
+/// OVN adds this to the priority of an ACL for its logical flow
+const ACL_FLOW_PRIORITY_OFFSET: u32 = 1000;
+
+/// A guest's switch port that a traced packet is sent from or to
+#[derive(Debug, Clone, PartialEq)]
+struct TraceEndpoint {
+    port: String,
+    switch: String,
+    mac: String,
+    ip: Ipv4Addr,
+}
+
+/// One logical switch or router pipeline in the ovn-trace output
+#[derive(Debug, Clone, PartialEq)]
+struct TracePipeline {
+    /// `ingress` or `egress`
+    direction: String,
+    datapath: String,
+    /// The inport of an ingress pipeline or the outport of an egress pipeline
+    port: String,
+    stages: Vec<TraceStage>,
+}
+
+/// A logical flow table that the packet matched a flow in, or had no match in
+#[derive(Debug, Clone, PartialEq)]
+struct TraceStage {
+    table: u32,
+    name: String,
+    _match: String,
+    priority: Option<u32>,
+    actions: Vec<String>,
+}
+
+/// Trace a packet from a guest with ovn-trace on the main testbed host, then send a summary of the
+/// pipelines, ACLs and NAT rules it went through and where it ended up
+pub async fn run_trace(
+    trace_cmd: &TraceCmd,
+    state: &State,
+    orchestration_common: &OrchestrationCommon,
+    logging_send: &Sender<OrchestrationLogger>,
+) -> anyhow::Result<()> {
+    let StateNetwork::Ovn(ovn) = &orchestration_common.network else {
+        bail!("trace is only supported on OVN networks");
+    };
+    match (&trace_cmd.proto, trace_cmd.port) {
+        (TraceProtocol::Tcp | TraceProtocol::Udp, None) => bail!("a --port must be given to trace {}", &trace_cmd.proto),
+        (TraceProtocol::Icmp, Some(_)) => bail!("--port can only be given for tcp and udp"),
+        _ => {}
+    }
+
+    let (source_name, source_data) = get_guest(&trace_cmd.source, state)?;
+    let interface = source_data.guest_type.network.iter().flatten()
+        .nth(trace_cmd.interface)
+        .context(format!("guest {source_name} does not have an interface {}", trace_cmd.interface))?;
+    let port_name = guest_switch_port_name(&state.project_name, &interface.switch, &source_name, trace_cmd.interface);
+    let source_lsp = ovn.switch_ports.get(&port_name)
+        .context(format!("getting switch port {port_name} for guest {source_name}"))?;
+    let source = trace_endpoint(source_lsp, orchestration_common).await?;
+
+    // packets to another switch are sent to the mac of the router port on the source switch
+    let destination = trace_destination(&trace_cmd.destination, &source, ovn, state, orchestration_common).await?;
+    let (eth_dst, ip_dst) = match destination {
+        TraceDestination::Port(port) if port.switch == source.switch => (port.mac, port.ip),
+        TraceDestination::Port(port) => (router_mac(&source.switch, ovn)?, port.ip),
+        TraceDestination::Ip(ip) => (router_mac(&source.switch, ovn)?, ip),
+    };
+
+    let microflow = trace_microflow(&source, &eth_dst, ip_dst, &trace_cmd.proto, trace_cmd.port);
+    tracing::info!("tracing {microflow} on switch {}", &source.switch);
+    let output = ovn_run_cmd(
+        vec_of_strings!["ovn-trace", &source.switch, &microflow],
+        (None, orchestration_common.clone()),
+    ).await.context("running ovn-trace")?;
+
+    logging_send.send(OrchestrationLogger::info(format!("Trace {}", trace_cmd.name()))).await?;
+    logging_send.send(OrchestrationLogger::info(format!("microflow: {microflow}"))).await?;
+    for line in summarise_trace(&parse_trace(&output), ovn) {
+        logging_send.send(OrchestrationLogger::info(line)).await?;
+    }
+    if trace_cmd.detailed {
+        logging_send.send(OrchestrationLogger::info(output)).await?;
+    }
+    Ok(())
+}
+
+enum TraceDestination {
+    Port(TraceEndpoint),
+    Ip(Ipv4Addr),
+}
+
+/// The destination is either an ip, matched against the ports on the source switch so that the
+/// packet is addressed to the port's mac, or a guest where the interface on the source switch is
+/// preferred over its first interface
+async fn trace_destination(
+    destination: &str,
+    source: &TraceEndpoint,
+    ovn: &OvnNetwork,
+    state: &State,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<TraceDestination> {
+    if let Ok(ip) = destination.parse::<Ipv4Addr>() {
+        for lsp in ovn.switch_ports.values() {
+            if lsp.parent_switch != source.switch || !matches!(lsp.port_type, LogicalSwitchPortType::Internal { .. }) {
+                continue;
+            }
+            let port = trace_endpoint(lsp, orchestration_common).await?;
+            if port.ip == ip {
+                return Ok(TraceDestination::Port(port));
+            }
+        }
+        return Ok(TraceDestination::Ip(ip));
+    }
+
+    let (guest_name, guest_data) = get_guest(destination, state)?;
+    let interfaces: Vec<_> = guest_data.guest_type.network.iter().flatten().enumerate().collect();
+    let (idx, interface) = interfaces.iter()
+        .find(|(_, interface)| format!("{}-{}", &state.project_name, &interface.switch) == source.switch)
+        .or(interfaces.first())
+        .context(format!("guest {guest_name} does not have any interfaces"))?;
+    let port_name = guest_switch_port_name(&state.project_name, &interface.switch, &guest_name, *idx);
+    let lsp = ovn.switch_ports.get(&port_name)
+        .context(format!("getting switch port {port_name} for guest {guest_name}"))?;
+    Ok(TraceDestination::Port(trace_endpoint(lsp, orchestration_common).await?))
+}
+
+/// Get the mac and IPv4 address of a guest's switch port, the address of a port with a dynamic ip
+/// is looked up in the northbound database
+async fn trace_endpoint(
+    lsp: &LogicalSwitchPort,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<TraceEndpoint> {
+    let LogicalSwitchPortType::Internal { ip, mac_address, .. } = &lsp.port_type else {
+        bail!("switch port {} is not a guest port", &lsp.name);
+    };
+    let ip = match ip {
+        OvnIpAddr::Ip(IpAddr::V4(ip)) => *ip,
+        OvnIpAddr::Dynamic => dynamic_ipv4(&lsp.name, orchestration_common).await?,
+        _ => bail!("switch port {} does not have an IPv4 address", &lsp.name),
+    };
+    Ok(TraceEndpoint {
+        port: lsp.name.clone(),
+        switch: lsp.parent_switch.clone(),
+        mac: mac_address.address.clone(),
+        ip,
+    })
+}
+
+/// Get the mac of the router port that is connected to the switch
+fn router_mac(switch: &str, ovn: &OvnNetwork) -> anyhow::Result<String> {
+    ovn.switch_ports.values()
+        .filter(|lsp| lsp.parent_switch == switch)
+        .find_map(|lsp| match &lsp.port_type {
+            LogicalSwitchPortType::Router { router_port_name, .. } => ovn.router_ports.get(router_port_name),
+            _ => None,
+        })
+        .map(|lrp| lrp.mac_address.address.clone())
+        .context(format!("the destination is not on switch {switch} and the switch is not connected to a router"))
+}
+
+/// Build the ovn-trace microflow for a packet from the source port
+fn trace_microflow(
+    source: &TraceEndpoint,
+    eth_dst: &str,
+    ip_dst: Ipv4Addr,
+    proto: &TraceProtocol,
+    port: Option<u16>,
+) -> String {
+    let mut microflow = format!(
+        "inport == \"{}\" && eth.src == {} && eth.dst == {eth_dst} && ip4.src == {} && ip4.dst == {ip_dst} && ip.ttl == 64",
+        &source.port, &source.mac, &source.ip,
+    );
+    match (proto, port) {
+        (TraceProtocol::Icmp, _) | (_, None) => microflow.push_str(" && icmp4 && icmp4.type == 8"),
+        (proto, Some(port)) => microflow.push_str(&format!(
+            " && {proto} && {proto}.src == {TRACE_SOURCE_PORT} && {proto}.dst == {port}"
+        )),
+    }
+    microflow
+}
+
+/// Parse the detailed ovn-trace output into the pipelines the packet went through. Nested headers
+/// such as `ct_next` continue the current pipeline.
+fn parse_trace(output: &str) -> Vec<TracePipeline> {
+    let lines: Vec<&str> = output.lines().map(|line| line.trim()).collect();
+    let mut pipelines: Vec<TracePipeline> = Vec::new();
+    for (idx, line) in lines.iter().enumerate() {
+        let is_header = lines.get(idx + 1)
+            .is_some_and(|next| !next.is_empty() && next.chars().all(|c| c == '-'));
+        if is_header {
+            for direction in ["ingress", "egress"] {
+                if line.starts_with(&format!("{direction}(")) {
+                    pipelines.push(TracePipeline {
+                        direction: direction.to_string(),
+                        datapath: header_value(line, "dp").unwrap_or_default(),
+                        port: header_value(line, "inport")
+                            .or_else(|| header_value(line, "outport"))
+                            .unwrap_or_default(),
+                        stages: Vec::new(),
+                    });
+                }
+            }
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.chars().all(|c| c == '-') {
+            continue;
+        }
+        let Some(pipeline) = pipelines.last_mut() else {
+            continue;
+        };
+        match parse_stage(line) {
+            Some(stage) => pipeline.stages.push(stage),
+            None => {
+                if let Some(stage) = pipeline.stages.last_mut() {
+                    stage.actions.push(line.to_string());
+                }
+            }
+        }
+    }
+    pipelines
+}
+
+/// Get a quoted value from a pipeline header such as `ingress(dp="sw0", inport="sw0-port1")`
+fn header_value(header: &str, key: &str) -> Option<String> {
+    let (_, rest) = header.split_once(&format!("{key}=\""))?;
+    let (value, _) = rest.split_once('"')?;
+    Some(value.to_string())
+}
+
+/// Parse a stage line such as `6. ls_in_acl (northd.c:7023): ip4, priority 1001, uuid 1a2b3c4d`
+fn parse_stage(line: &str) -> Option<TraceStage> {
+    let (table, rest) = line.split_once(". ")?;
+    let table = table.parse::<u32>().ok()?;
+    let (head, flow) = rest.split_once(": ")?;
+    let name = head.split_whitespace().next()?.trim_end_matches(':').to_string();
+    let flow = flow.rsplit_once(", uuid ").map_or(flow, |(flow, _)| flow);
+    let (_match, priority) = match flow.rsplit_once(", priority ") {
+        Some((_match, priority)) => (_match, priority.parse::<u32>().ok()),
+        None => (flow, None),
+    };
+    Some(TraceStage {
+        table,
+        name,
+        _match: _match.to_string(),
+        priority,
+        actions: Vec::new(),
+    })
+}
+
+/// Summarise the traced pipelines, with the testbed ACLs that the packet matched, the NAT actions
+/// and the final outcome of the packet
+fn summarise_trace(pipelines: &[TracePipeline], ovn: &OvnNetwork) -> Vec<String> {
+    let mut summary = Vec::new();
+    let mut outcome = None;
+    for pipeline in pipelines {
+        let datapath_type = if ovn.switches.contains_key(&pipeline.datapath) {
+            "switch"
+        } else if ovn.routers.contains_key(&pipeline.datapath) {
+            "router"
+        } else {
+            "datapath"
+        };
+        let port_direction = if pipeline.direction == "ingress" { "from" } else { "to" };
+        summary.push(format!(
+            "{} pipeline of {datapath_type} {} {port_direction} port {}",
+            &pipeline.direction, &pipeline.datapath, &pipeline.port,
+        ));
+
+        for stage in &pipeline.stages {
+            let dropped = stage._match.contains("implicit drop")
+                || stage.actions.iter().any(|action| action.starts_with("drop;"));
+            let rejected = stage.actions.iter().any(|action| action.starts_with("reject"));
+
+            if is_acl_stage(&stage.name) {
+                let acls = matching_acls(pipeline, stage, ovn);
+                if !acls.is_empty() {
+                    summary.push(format!("  {}: matched ACL {}", &stage.name, acls.join(", ")));
+                } else if dropped || rejected {
+                    summary.push(format!("  {}: matched a default OVN ACL flow", &stage.name));
+                }
+            }
+            for action in &stage.actions {
+                if action.contains("nat(") {
+                    summary.push(format!("  {}: NAT {}", &stage.name, action.trim_end_matches(';')));
+                }
+            }
+
+            if dropped || rejected {
+                let verb = if rejected { "rejected" } else { "dropped" };
+                outcome = Some(format!("{verb} in {} of {datapath_type} {}", &stage.name, &pipeline.datapath));
+            }
+            for action in &stage.actions {
+                if let Some(port) = action.strip_prefix("/* output to \"").and_then(|rest| rest.split_once('"')) {
+                    outcome = Some(format!("delivered to port {}", port.0));
+                }
+            }
+        }
+    }
+    match outcome {
+        Some(outcome) => summary.push(format!("result: {outcome}")),
+        None => summary.push("result: the packet was not output by the logical network, see the trace with --detailed".to_string()),
+    }
+    summary
+}
+
+/// The ACL evaluation stages, excluding the stages before and after them that OVN uses to track
+/// connections
+fn is_acl_stage(name: &str) -> bool {
+    name.contains("_acl") && !name.contains("pre_acl") && !name.contains("acl_hint") && !name.contains("acl_action")
+}
+
+/// Find the testbed ACLs that could have added the flow, from the datapath, direction and priority
+fn matching_acls(
+    pipeline: &TracePipeline,
+    stage: &TraceStage,
+    ovn: &OvnNetwork,
+) -> Vec<String> {
+    let Some(priority) = stage.priority else {
+        return Vec::new();
+    };
+    let direction = if pipeline.direction == "ingress" { "from-lport" } else { "to-lport" };
+    let mut acls: Vec<String> = ovn.acl.values()
+        .filter(|acl| acl.direction.to_string() == direction)
+        .filter(|acl| u32::try_from(acl.priority).is_ok_and(|p| p + ACL_FLOW_PRIORITY_OFFSET == priority))
+        .filter(|acl| {
+            acl.entity_name == pipeline.datapath || ovn.port_groups.get(&acl.entity_name)
+                .is_some_and(|pg| pg.ports.iter().any(|port| {
+                    ovn.switch_ports.get(port).is_some_and(|lsp| lsp.parent_switch == pipeline.datapath)
+                }))
+        })
+        .map(|acl| format!("{} ({})", &acl.ovn_resource_name, &acl.action))
+        .collect();
+    acls.sort();
+    acls
+}
+
+#[cfg(test)]
+mod tests {
+    use kvm_compose_schemas::kvm_compose_yaml::network::acl::{ACLAction, ACLDirection, ACLRule};
+    use crate::ovn::components::acl::ACLRecordType;
+    use super::*;
+
+    const TRACE_OUTPUT: &str = r#"# icmp,reg14=0x1,vlan_tci=0x0000,dl_src=00:00:00:00:00:01,dl_dst=00:00:00:00:ff:01,nw_src=10.0.0.2,nw_dst=10.0.1.2,nw_tos=0,nw_ecn=0,nw_ttl=64,icmp_type=8,icmp_code=0
+
+ingress(dp="test-sw0", inport="test-sw0-guest-0")
+-------------------------------------------------
+ 0. ls_in_check_port_sec (northd.c:8691): 1, priority 50, uuid 4ba9ab5b
+    reg0[15] = check_in_port_sec();
+    next;
+ 8. ls_in_acl_eval (northd.c:7121): reg0[7] == 1 && (ip4), priority 2001, uuid 0aa0c412
+    reg8[16] = 1;
+    next;
+27. ls_in_l2_lkup (northd.c:9586): eth.dst == 00:00:00:00:ff:01, priority 50, uuid 8a2d2a51
+    outport = "test-sw0-lr0";
+    output;
+
+egress(dp="test-sw0", outport="test-sw0-lr0")
+---------------------------------------------
+ 9. ls_out_check_port_sec (northd.c:5843): 1, priority 0, uuid 6e4e9c8e
+    reg0[15] = check_out_port_sec();
+    next;
+10. ls_out_apply_port_sec (northd.c:5848): 1, priority 0, uuid 2e8e5b3b
+    output;
+    /* output to "test-sw0-lr0", type "patch" */
+
+ingress(dp="test-lr0", inport="test-lr0-sw0")
+---------------------------------------------
+ 3. lr_in_ip_input (northd.c:12586): ip4.dst == 10.0.1.2, priority 90, uuid 15c5e1a4
+    next;
+
+egress(dp="test-lr0", outport="test-lr0-sw1")
+---------------------------------------------
+ 1. lr_out_snat (northd.c:13490): ip && ip4.src == 10.0.0.0/24, priority 153, uuid 7f3a2b1c
+    ct_snat(172.16.1.1);
+
+egress(dp="test-sw1", outport="test-sw1-guest2-0")
+--------------------------------------------------
+ 4. ls_out_acl_eval (northd.c:7121): outport == "test-sw1-guest2-0" && ip4, priority 2000, uuid 9c1d0e3f
+    reg8[17] = 1;
+    drop;
+"#;
+
+    #[test]
+    fn test_parse_trace() {
+        let pipelines = parse_trace(TRACE_OUTPUT);
+        assert_eq!(pipelines.len(), 5);
+        assert_eq!(pipelines[0].direction, "ingress");
+        assert_eq!(pipelines[0].datapath, "test-sw0");
+        assert_eq!(pipelines[0].port, "test-sw0-guest-0");
+        assert_eq!(pipelines[0].stages.len(), 3);
+        assert_eq!(pipelines[0].stages[1], TraceStage {
+            table: 8,
+            name: "ls_in_acl_eval".to_string(),
+            _match: "reg0[7] == 1 && (ip4)".to_string(),
+            priority: Some(2001),
+            actions: vec!["reg8[16] = 1;".to_string(), "next;".to_string()],
+        });
+        assert_eq!(pipelines[1].port, "test-sw0-lr0");
+        assert_eq!(pipelines[1].stages[1].actions[1], "/* output to \"test-sw0-lr0\", type \"patch\" */");
+        assert_eq!(pipelines[4].stages[0].actions[1], "drop;");
+    }
+
+    #[test]
+    fn test_summarise_trace() {
+        let mut ovn = OvnNetwork::new();
+        ovn.add_switch("test-sw0".into(), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 24).unwrap();
+        ovn.add_switch("test-sw1".into(), IpAddr::V4(Ipv4Addr::new(10, 0, 1, 0)), 24).unwrap();
+        ovn.add_router("test-lr0".into()).unwrap();
+        let rule = |direction, priority, action| ACLRule {
+            direction,
+            priority,
+            _match: "ip4".to_string(),
+            action,
+        };
+        ovn.add_switch_acl(&"test-allow".to_string(), "test-sw0".into(), ACLRecordType::Switch, &rule(ACLDirection::FromLport, 1001, ACLAction::AllowRelated)).unwrap();
+        ovn.add_switch_acl(&"test-deny".to_string(), "test-sw1".into(), ACLRecordType::Switch, &rule(ACLDirection::ToLport, 1000, ACLAction::Drop)).unwrap();
+
+        let summary = summarise_trace(&parse_trace(TRACE_OUTPUT), &ovn);
+        assert_eq!(summary, vec![
+            "ingress pipeline of switch test-sw0 from port test-sw0-guest-0",
+            "  ls_in_acl_eval: matched ACL test-allow (allow-related)",
+            "egress pipeline of switch test-sw0 to port test-sw0-lr0",
+            "ingress pipeline of router test-lr0 from port test-lr0-sw0",
+            "egress pipeline of router test-lr0 to port test-lr0-sw1",
+            "  lr_out_snat: NAT ct_snat(172.16.1.1)",
+            "egress pipeline of switch test-sw1 to port test-sw1-guest2-0",
+            "  ls_out_acl_eval: matched ACL test-deny (drop)",
+            "result: dropped in ls_out_acl_eval of switch test-sw1",
+        ]);
+    }
+
+    #[test]
+    fn test_trace_microflow() {
+        let source = TraceEndpoint {
+            port: "test-sw0-guest-0".to_string(),
+            switch: "test-sw0".to_string(),
+            mac: "00:00:00:00:00:01".to_string(),
+            ip: Ipv4Addr::new(10, 0, 0, 2),
+        };
+        let microflow = trace_microflow(&source, "00:00:00:00:ff:01", Ipv4Addr::new(10, 0, 1, 2), &TraceProtocol::Tcp, Some(80));
+        assert_eq!(microflow, "inport == \"test-sw0-guest-0\" && eth.src == 00:00:00:00:00:01 && eth.dst == 00:00:00:00:ff:01 && ip4.src == 10.0.0.2 && ip4.dst == 10.0.1.2 && ip.ttl == 64 && tcp && tcp.src == 40000 && tcp.dst == 80");
+        let microflow = trace_microflow(&source, "00:00:00:00:00:02", Ipv4Addr::new(10, 0, 0, 3), &TraceProtocol::Icmp, None);
+        assert!(microflow.ends_with("ip4.dst == 10.0.0.3 && ip.ttl == 64 && icmp4 && icmp4.type == 8"));
+    }
+}
@@ -0,0 +1,561 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::collections::btree_map::Entry;
+use std::net::Ipv4Addr;
+use anyhow::{bail, Context};
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use kvm_compose_schemas::kvm_compose_yaml::Config;
+use kvm_compose_schemas::kvm_compose_yaml::expectations::{Expectation, ExpectationProtocol};
+use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
+use kvm_compose_schemas::kvm_compose_yaml::network::NetworkBackend;
+use kvm_compose_schemas::kvm_compose_yaml::network::policy::{Policy, PolicyAction, PolicyProtocol};
+use crate::components::network::{guest_switch_port_name, policy_priority, scaling_count};
+use crate::net::dynamic_ipv4;
+use crate::orchestration::api::OrchestrationLogger;
+use crate::orchestration::{is_main_testbed, OrchestrationCommon, run_testbed_orchestration_command};
+use crate::orchestration::ssh::SSHClient;
+use crate::state::{State, StateTestbedGuest};
+
+/// How long a probe waits for a reply, in seconds
+const PROBE_TIMEOUT: &str = "2";
+
+/// The probes that `kvm-compose verify` runs against the deployment. This is built on the client
+/// from the kvm-compose.yaml and the state, then the probes are run by the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VerifyPlan {
+    pub probes: Vec<Probe>,
+}
+
+/// A ping, or a tcp connection if there is a port, from a guest to an interface of another guest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub source: String,
+    pub destination: String,
+    pub interface: usize,
+    pub port: Option<u16>,
+    pub expected: Option<ProbeExpectation>,
+}
+
+/// The reachability that the probe should find, and the expectation or policy that it is from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProbeExpectation {
+    pub reachable: bool,
+    pub reason: String,
+}
+
+/// A guest interface that can be probed
+struct VerifyTarget<'a> {
+    guest: &'a str,
+    interface: usize,
+    switch: &'a str,
+}
+
+impl Probe {
+    fn name(&self) -> String {
+        match self.port {
+            Some(port) => format!("{} -> {}/{} tcp {port}", &self.source, &self.destination, self.interface),
+            None => format!("{} -> {}/{} ping", &self.source, &self.destination, self.interface),
+        }
+    }
+}
+
+impl VerifyPlan {
+    /// Ping every guest interface from every other guest, and connect to the tcp ports that are in
+    /// the expectations and tcp policies. Each probe is expected to have the reachability of the
+    /// last expectation that matches it, otherwise that of the highest priority policy that
+    /// matches it. With `expected_only` the pings that neither match are left out.
+    pub fn new(config: &Config, state: &State, expected_only: bool) -> anyhow::Result<Self> {
+        let expectations = config.expectations.as_deref().unwrap_or_default();
+        let policies = match &config.network {
+            NetworkBackend::Ovn(ovn) => ovn.policies.as_deref().unwrap_or_default(),
+            NetworkBackend::Ovs(_) => &[],
+        };
+
+        // clone groups are only templates for their clones, so they are not probed
+        let guests: BTreeMap<&str, &StateTestbedGuest> = state.testbed_guests.0.iter()
+            .filter(|(_, guest_data)| scaling_count(&guest_data.guest_type).is_none())
+            .map(|(name, guest_data)| (name.as_str(), guest_data))
+            .collect();
+        let targets: Vec<VerifyTarget> = guests.iter()
+            .flat_map(|(name, guest_data)| guest_data.guest_type.network.iter().flatten()
+                .enumerate()
+                .map(|(interface, network)| VerifyTarget { guest: name, interface, switch: network.switch.as_str() }))
+            .collect();
+
+        let mut probes = Vec::new();
+        for (source, source_data) in &guests {
+            let source_switches: Vec<&str> = source_data.guest_type.network.iter().flatten()
+                .map(|network| network.switch.as_str())
+                .collect();
+            for target in targets.iter().filter(|target| target.guest != *source) {
+                let expect = |port| expectation_for(expectations, state, source, target, port)
+                    .or_else(|| policy_for(policies, state, source, &source_switches, target, port));
+
+                let expected = expect(None);
+                if !expected_only || expected.is_some() {
+                    probes.push(Probe {
+                        source: source.to_string(),
+                        destination: target.guest.to_string(),
+                        interface: target.interface,
+                        port: None,
+                        expected,
+                    });
+                }
+
+                let mut ports = BTreeSet::new();
+                for expectation in expectations {
+                    if expectation.proto == ExpectationProtocol::Tcp && expectation_matches(expectation, state, source, target) {
+                        ports.extend(expectation.ports.iter().flatten());
+                    }
+                }
+                for policy in policies {
+                    if policy.proto == PolicyProtocol::Tcp && policy_endpoints_match(policy, state, source, &source_switches, target) {
+                        ports.extend(policy.ports.iter().flatten());
+                    }
+                }
+                for port in ports {
+                    probes.push(Probe {
+                        source: source.to_string(),
+                        destination: target.guest.to_string(),
+                        interface: target.interface,
+                        port: Some(port),
+                        expected: expect(Some(port)),
+                    });
+                }
+            }
+        }
+        if probes.is_empty() {
+            bail!("there is nothing to verify, the deployment needs at least two guests with interfaces");
+        }
+        Ok(Self { probes })
+    }
+}
+
+/// Whether the guest is the named guest, or a clone in the named clone group
+fn is_guest_or_clone(name: &str, guest: &str, state: &State) -> bool {
+    if name == guest {
+        return true;
+    }
+    let count = state.testbed_guests.0.get(name)
+        .and_then(|guest_data| scaling_count(&guest_data.guest_type))
+        .unwrap_or(0);
+    (0..count).any(|clone_n| guest == format!("{name}-{clone_n}"))
+}
+
+fn expectation_matches(expectation: &Expectation, state: &State, source: &str, target: &VerifyTarget) -> bool {
+    is_guest_or_clone(&expectation.from, source, state)
+        && is_guest_or_clone(&expectation.to, target.guest, state)
+        && expectation.interface.is_none_or(|interface| interface == target.interface)
+}
+
+/// The last expectation that matches the probe
+fn expectation_for(
+    expectations: &[Expectation],
+    state: &State,
+    source: &str,
+    target: &VerifyTarget,
+    port: Option<u16>,
+) -> Option<ProbeExpectation> {
+    expectations.iter().enumerate().rev()
+        .filter(|(_, expectation)| expectation_matches(expectation, state, source, target))
+        .find(|(_, expectation)| match (&expectation.proto, port) {
+            (ExpectationProtocol::Icmp, None) => true,
+            (ExpectationProtocol::Tcp, Some(port)) => expectation.ports.iter().flatten().any(|p| *p == port),
+            _ => false,
+        })
+        .map(|(idx, expectation)| ProbeExpectation {
+            reachable: expectation.reachable,
+            reason: format!("expectations[{idx}]"),
+        })
+}
+
+/// A policy endpoint is a guest, a clone group or a switch, and matches anything if not given
+fn policy_endpoints_match(
+    policy: &Policy,
+    state: &State,
+    source: &str,
+    source_switches: &[&str],
+    target: &VerifyTarget,
+) -> bool {
+    let from = policy.from.as_ref().is_none_or(|from| {
+        is_guest_or_clone(from, source, state) || source_switches.contains(&from.as_str())
+    });
+    let to = policy.to.as_ref().is_none_or(|to| {
+        is_guest_or_clone(to, target.guest, state) || target.switch == to
+    });
+    from && to
+}
+
+/// The highest priority policy that matches the probe, the earlier policy wins a tie as OVN does
+/// not define which of two ACLs with the same priority is used
+fn policy_for(
+    policies: &[Policy],
+    state: &State,
+    source: &str,
+    source_switches: &[&str],
+    target: &VerifyTarget,
+    port: Option<u16>,
+) -> Option<ProbeExpectation> {
+    policies.iter().enumerate()
+        .filter(|(_, policy)| policy_endpoints_match(policy, state, source, source_switches, target))
+        .filter(|(_, policy)| match (&policy.proto, port) {
+            (PolicyProtocol::Any, _) | (PolicyProtocol::Icmp, None) => true,
+            (PolicyProtocol::Tcp, Some(port)) => policy.ports.as_ref().is_none_or(|ports| ports.contains(&port)),
+            _ => false,
+        })
+        .max_by_key(|(idx, policy)| (policy_priority(policy, *idx), std::cmp::Reverse(*idx)))
+        .map(|(idx, policy)| ProbeExpectation {
+            reachable: policy.action == PolicyAction::Allow,
+            reason: format!("policies[{idx}]"),
+        })
+}
+
+/// Run the probes of the plan from inside the guests, then send the reachability matrix and the
+/// probes that did not match their expectation. Fails if any probe did not match.
+pub async fn run_verify(
+    plan: &VerifyPlan,
+    state: &State,
+    orchestration_common: &OrchestrationCommon,
+    logging_send: &Sender<OrchestrationLogger>,
+) -> anyhow::Result<()> {
+    // the ip of every probed interface, dynamic ips are looked up in OVN
+    let mut ips = BTreeMap::new();
+    for probe in &plan.probes {
+        if let Entry::Vacant(entry) = ips.entry((probe.destination.clone(), probe.interface)) {
+            entry.insert(interface_ipv4(&probe.destination, probe.interface, state, orchestration_common).await?);
+        }
+    }
+
+    // the probes from each guest run one at a time, while the guests probe at the same time
+    let mut by_source: BTreeMap<&str, Vec<&Probe>> = BTreeMap::new();
+    for probe in &plan.probes {
+        by_source.entry(probe.source.as_str()).or_default().push(probe);
+    }
+    let results: Vec<(Probe, anyhow::Result<bool>)> = join_all(by_source.into_iter().map(|(source, probes)| {
+        let ips = &ips;
+        async move {
+            let mut results = Vec::new();
+            for probe in probes {
+                let ip = ips[&(probe.destination.clone(), probe.interface)];
+                let reachable = run_probe(source, ip, probe.port, state, orchestration_common).await;
+                results.push((probe.clone(), reachable));
+            }
+            results
+        }
+    })).await.into_iter().flatten().collect();
+
+    // a probe that could not run has not tested anything, so it fails the verify rather than
+    // counting as unreachable
+    let mut errors = Vec::new();
+    let results: Vec<(Probe, bool)> = results.into_iter()
+        .filter_map(|(probe, reachable)| match reachable {
+            Ok(reachable) => Some((probe, reachable)),
+            Err(err) => {
+                errors.push(format!("{err:#}"));
+                None
+            }
+        })
+        .collect();
+
+    for line in format_results(&results) {
+        logging_send.send(OrchestrationLogger::info(line)).await?;
+    }
+    for error in &errors {
+        logging_send.send(OrchestrationLogger::error(error.clone())).await?;
+    }
+    check_probe_results(&results, errors.len())
+}
+
+/// Fail if any probe could not be run or did not match its expectation, so that the verify command
+/// exits with an error
+pub fn check_probe_results(results: &[(Probe, bool)], errors: usize) -> anyhow::Result<()> {
+    if errors > 0 {
+        bail!("{errors} probes could not be run");
+    }
+    let mismatches = results.iter()
+        .filter(|(probe, reachable)| probe.expected.as_ref().is_some_and(|expected| expected.reachable != *reachable))
+        .count();
+    if mismatches > 0 {
+        bail!("{mismatches} of {} probes did not match the expected reachability", results.len());
+    }
+    Ok(())
+}
+
+/// Get the IPv4 address of a guest's interface from the state
+async fn interface_ipv4(
+    guest_name: &str,
+    idx: usize,
+    state: &State,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<Ipv4Addr> {
+    let guest_data = state.testbed_guests.0.get(guest_name)
+        .context(format!("could not find guest {guest_name} in the project state"))?;
+    let interface = guest_data.guest_type.network.iter().flatten()
+        .nth(idx)
+        .context(format!("guest {guest_name} does not have an interface {idx}"))?;
+    let ip = interface.ip()?;
+    if ip == "dynamic" {
+        let port_name = guest_switch_port_name(&state.project_name, &interface.switch, &guest_name.to_string(), idx);
+        return dynamic_ipv4(&port_name, orchestration_common).await;
+    }
+    ip.parse::<Ipv4Addr>()
+        .context(format!("interface {idx} of guest {guest_name} does not have an IPv4 address"))
+}
+
+/// Ping the ip, or connect to the tcp port, from inside the guest. Libvirt guests are reached over
+/// SSH, docker guests with docker exec and android guests through their network namespace. Returns
+/// an error if the probe could not be run, so that it is not mistaken for an unreachable guest.
+async fn run_probe(
+    guest_name: &str,
+    ip: Ipv4Addr,
+    port: Option<u16>,
+    state: &State,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<bool> {
+    let script = probe_script(&ip, port);
+    let guest_data = state.testbed_guests.0.get(guest_name)
+        .context(format!("could not find guest {guest_name} in the project state"))?;
+    let testbed_host = guest_data.testbed_host.as_ref();
+    // the arguments are joined by ssh on remote testbed hosts, so the script must be quoted there,
+    // the script doesn't contain any single quotes
+    let quoted_script = match testbed_host {
+        Some(testbed_host) if !is_main_testbed(orchestration_common, testbed_host) => format!("'{script}'"),
+        _ => script.clone(),
+    };
+    let output = match (&guest_data.guest_type.guest_type, testbed_host) {
+        (GuestType::Libvirt(_), _) => {
+            // the login shell of the guest runs the script
+            SSHClient::run_guest_command(orchestration_common, vec![&script], guest_data, false).await
+        }
+        (GuestType::Docker(_), Some(testbed_host)) => {
+            let container = format!("{}-{guest_name}", &state.project_name);
+            let args = vec!["docker", "exec", &container, "sh", "-c", &quoted_script];
+            run_testbed_orchestration_command(orchestration_common, testbed_host, "sudo", args, false, None).await
+        }
+        (GuestType::Android(_), Some(testbed_host)) => {
+            let namespace = format!("{}-{guest_name}-nmspc", &state.project_name);
+            let args = vec!["ip", "netns", "exec", &namespace, "sh", "-c", &quoted_script];
+            run_testbed_orchestration_command(orchestration_common, testbed_host, "sudo", args, false, None).await
+        }
+        (_, None) => bail!("guest {guest_name} does not have a testbed host"),
+    }.context(format!("running the probe from {guest_name} to {ip}"))?;
+    parse_probe_output(&output, port)
+        .context(format!("probe from {guest_name} to {ip}"))
+}
+
+/// The probe's own output is discarded and its exit code printed after this marker, as the exit
+/// code is what tells an unreachable guest apart from a probe that could not run
+const PROBE_EXIT_MARKER: &str = "probe-exit=";
+
+fn probe_script(ip: &Ipv4Addr, port: Option<u16>) -> String {
+    let probe = match port {
+        Some(port) => format!("nc -z -w {PROBE_TIMEOUT} {ip} {port}"),
+        None => format!("ping -c 1 -W {PROBE_TIMEOUT} {ip}"),
+    };
+    format!("{probe} >/dev/null 2>&1; echo {PROBE_EXIT_MARKER}$?")
+}
+
+/// Get the reachability from the exit code of the probe. nc exits with 1 when the connection is
+/// refused or times out, ping with 1 when there is no reply and 2 when there is no route. A shell
+/// exits with 126 or 127 when the probe command is missing.
+fn parse_probe_output(output: &str, port: Option<u16>) -> anyhow::Result<bool> {
+    let code = output.lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix(PROBE_EXIT_MARKER))
+        .context(format!("the probe did not report its exit code, the output was {output:?}"))?;
+    match (code, port) {
+        ("0", _) => Ok(true),
+        ("1", _) | ("2", None) => Ok(false),
+        ("126" | "127", Some(_)) => bail!("nc could not be run in the guest, is it installed?"),
+        ("126" | "127", None) => bail!("ping could not be run in the guest, is it installed?"),
+        (code, _) => bail!("the probe exited with {code}"),
+    }
+}
+
+/// Format the ping results as a matrix of source guests by destination interfaces, followed by
+/// the tcp results and the probes that did not match their expectation. A `!` marks a mismatch.
+fn format_results(results: &[(Probe, bool)]) -> Vec<String> {
+    let cell = |probe: &Probe, reachable: bool| {
+        let mismatch = probe.expected.as_ref().is_some_and(|expected| expected.reachable != reachable);
+        format!("{}{}", if reachable { "yes" } else { "no" }, if mismatch { "!" } else { "" })
+    };
+
+    let mut lines = Vec::new();
+    let pings: Vec<_> = results.iter().filter(|(probe, _)| probe.port.is_none()).collect();
+    if !pings.is_empty() {
+        let sources: BTreeSet<&str> = pings.iter().map(|(probe, _)| probe.source.as_str()).collect();
+        let columns: BTreeSet<String> = pings.iter()
+            .map(|(probe, _)| format!("{}/{}", &probe.destination, probe.interface))
+            .collect();
+        let width = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+        lines.push("ping reachability, rows are the source guests and columns the destination interfaces:".to_string());
+        let mut header = format!("{:width$}", "");
+        for column in &columns {
+            header.push_str(&format!("  {column}"));
+        }
+        lines.push(header);
+        for source in sources {
+            let mut row = format!("{source:width$}");
+            for column in &columns {
+                let value = pings.iter()
+                    .find(|(probe, _)| probe.source == source && format!("{}/{}", &probe.destination, probe.interface) == *column)
+                    .map(|(probe, reachable)| cell(probe, *reachable))
+                    .unwrap_or_else(|| "-".to_string());
+                row.push_str(&format!("  {value:<len$}", len = column.len()));
+            }
+            lines.push(row);
+        }
+    }
+
+    for (probe, reachable) in results.iter().filter(|(probe, _)| probe.port.is_some()) {
+        lines.push(format!("{}: {}", probe.name(), cell(probe, *reachable)));
+    }
+
+    for (probe, reachable) in results {
+        if let Some(expected) = probe.expected.as_ref().filter(|expected| expected.reachable != *reachable) {
+            let (found, wanted) = if *reachable { ("reachable", "unreachable") } else { ("unreachable", "reachable") };
+            lines.push(format!("mismatch: {} was {found}, {} expects {wanted}", probe.name(), &expected.reason));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use kvm_compose_schemas::kvm_compose_yaml::Machine;
+    use crate::state::{StateNetwork, StateTestbedGuestExtraInfo};
+    use super::*;
+
+    const YAML: &str = r#"
+machines:
+  - name: web
+    network:
+      - switch: sw0
+        ip: "10.0.0.10"
+    docker:
+      image: nginx
+  - name: client
+    network:
+      - switch: sw0
+        ip: "10.0.0.11"
+    docker:
+      image: alpine
+  - name: db
+    network:
+      - switch: sw1
+        ip: "10.0.1.10"
+    docker:
+      image: postgres
+network:
+  ovn:
+    switches:
+      sw0:
+        subnet: "10.0.0.0/24"
+      sw1:
+        subnet: "10.0.1.0/24"
+    routers:
+      lr0:
+        ports:
+          - name: lr0-sw0
+            mac: "00:00:00:00:ff:01"
+            gateway_ip: "10.0.0.1/24"
+            switch: sw0
+          - name: lr0-sw1
+            mac: "00:00:00:00:ff:02"
+            gateway_ip: "10.0.1.1/24"
+            switch: sw1
+    policies:
+      - to: sw1
+        action: deny
+      - from: web
+        to: db
+        proto: tcp
+        ports: [5432]
+        action: allow
+expectations:
+  - from: client
+    to: web
+    proto: tcp
+    ports: [80]
+    reachable: true
+"#;
+
+    fn config_and_state() -> (Config, State) {
+        let config: Config = serde_yaml::from_str(YAML).unwrap();
+        let guests = config.machines.iter().flatten()
+            .enumerate()
+            .map(|(idx, machine): (usize, &Machine)| StateTestbedGuest {
+                guest_type: machine.clone(),
+                testbed_host: Some("host".to_string()),
+                is_golden_image: false,
+                guest_id: idx as u32,
+                extra_info: StateTestbedGuestExtraInfo { reference_image: None },
+            })
+            .collect();
+        let state = State::test_fixture(guests, StateNetwork::default());
+        (config, state)
+    }
+
+    fn expected(plan: &VerifyPlan, source: &str, destination: &str, port: Option<u16>) -> Option<ProbeExpectation> {
+        plan.probes.iter()
+            .find(|probe| probe.source == source && probe.destination == destination && probe.port == port)
+            .unwrap_or_else(|| panic!("no probe from {source} to {destination} port {port:?}"))
+            .expected
+            .clone()
+    }
+
+    #[test]
+    fn test_verify_plan() {
+        let (config, state) = config_and_state();
+        let plan = VerifyPlan::new(&config, &state, false).unwrap();
+        // 6 pings between the 3 guests, the tcp expectation and the tcp policy
+        assert_eq!(plan.probes.len(), 8);
+        assert_eq!(expected(&plan, "client", "web", None), None);
+        assert_eq!(expected(&plan, "client", "db", None), Some(ProbeExpectation { reachable: false, reason: "policies[0]".into() }));
+        assert_eq!(expected(&plan, "client", "web", Some(80)), Some(ProbeExpectation { reachable: true, reason: "expectations[0]".into() }));
+        // the earlier policy has the higher priority
+        assert_eq!(expected(&plan, "web", "db", Some(5432)), Some(ProbeExpectation { reachable: false, reason: "policies[0]".into() }));
+
+        let plan = VerifyPlan::new(&config, &state, true).unwrap();
+        assert_eq!(plan.probes.len(), 4);
+        assert!(plan.probes.iter().all(|probe| probe.expected.is_some()));
+    }
+
+    #[test]
+    fn test_format_results() {
+        let probe = |source: &str, destination: &str, port, expected: Option<bool>| Probe {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            interface: 0,
+            port,
+            expected: expected.map(|reachable| ProbeExpectation { reachable, reason: "expectations[0]".into() }),
+        };
+        let results = vec![
+            (probe("client", "web", None, None), true),
+            (probe("web", "client", None, Some(false)), true),
+            (probe("client", "web", Some(80), Some(true)), true),
+        ];
+        assert_eq!(format_results(&results), vec![
+            "ping reachability, rows are the source guests and columns the destination interfaces:",
+            "        client/0  web/0",
+            "client  -         yes  ",
+            "web     yes!      -    ",
+            "client -> web/0 tcp 80: yes",
+            "mismatch: web -> client/0 ping was reachable, expectations[0] expects unreachable",
+        ]);
+    }
+
+    #[test]
+    fn test_parse_probe_output() {
+        let ip = Ipv4Addr::new(10, 0, 0, 10);
+        assert_eq!(probe_script(&ip, Some(80)), "nc -z -w 2 10.0.0.10 80 >/dev/null 2>&1; echo probe-exit=$?");
+        assert!(parse_probe_output("probe-exit=0\n", None).unwrap());
+        assert!(!parse_probe_output("probe-exit=1\n", Some(80)).unwrap());
+        assert!(!parse_probe_output("probe-exit=2\n", None).unwrap());
+        // a missing probe command is not an unreachable guest
+        assert!(parse_probe_output("probe-exit=127\n", Some(80)).is_err());
+        assert!(parse_probe_output("probe-exit=2\n", Some(80)).is_err());
+        assert!(parse_probe_output("", None).is_err());
+    }
+}
@@ -7,18 +7,29 @@ use tokio::sync::mpsc::Sender;
 use kvm_compose_schemas::cli_models::{AnalysisToolsCmd, AnalysisToolsSubCmd, SnapshotSubCommand};
 use kvm_compose_schemas::deployment_models::{Deployment, DeploymentCommand};
 use kvm_compose_schemas::exec::ExecCmd;
+use kvm_compose_schemas::net::NetCmd;
+use kvm_compose_schemas::trace::TraceCmd;
 use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
-use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt_image_download::OnlineCloudImage;
+use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt_image_download::CloudImageCatalog;
 use crate::analysis_tools::packet_capture::packet_capture;
 use crate::exec::prepare_guest_exec_command;
+use crate::net::faults::NetworkFault;
+use crate::net::run_net_command;
+use crate::net::trace::run_trace;
+use crate::net::verify::{run_verify, VerifyPlan};
 use crate::orchestration::{create_remote_project_folders, OrchestrationCommon, OrchestrationGuestTask};
 use crate::orchestration::ssh::SSHClient;
 use crate::ovn::components::acl::LogicalACLRecord;
+use crate::ovn::components::mirror::OvnMirror;
+use crate::ovn::components::port_group::LogicalPortGroup;
+use crate::ovn::components::load_balancer::LogicalLoadBalancer;
+use crate::ovn::configuration::dns::OvnDnsRecords;
+use crate::ovn::components::address_set::LogicalAddressSet;
 use crate::ovn::components::logical_router::LogicalRouter;
 use crate::ovn::components::logical_router_port::LogicalRouterPort;
 use crate::ovn::components::logical_switch::LogicalSwitch;
 use crate::ovn::components::logical_switch_port::LogicalSwitchPort;
-use crate::ovn::components::ovs::OvsPort;
+use crate::ovn::components::ovs::{OvsFlowExport, OvsPort};
 use crate::ovn::configuration::dhcp::DhcpDatabaseEntry;
 use crate::ovn::configuration::external_gateway::OvnExternalGateway;
 use crate::ovn::configuration::nat::OvnNat;
@@ -30,7 +41,8 @@ use crate::snapshot::TestbedSnapshots;
 use crate::state::orchestration_tasks::*;
 use crate::state::orchestration_tasks::guests::*;
 use crate::state::orchestration_tasks::ovn_network::*;
-use crate::state::{State, StateTestbedGuest};
+use crate::state::{State, StateOvsBridge, StateTestbedGuest};
+use crate::state::load_balancing::BridgeConnection;
 use crate::state::orchestration_tasks::generate_artefacts::generate_artefacts;
 
 // here we define the different atomic things we can send to the testbed server to trigger an
@@ -117,6 +129,16 @@ pub enum OrchestrationInstruction {
     ListCloudImages,
     /// Run an exec command
     Exec(ExecCmd),
+    /// Change the network of the running deployment
+    Net(NetCmd),
+    /// Explain the path of a packet through the network with ovn-trace
+    Trace(TraceCmd),
+    /// Probe the reachability between guests and compare it against the expectations
+    Verify(VerifyPlan),
+    /// Apply a network fault to the running deployment
+    ApplyFault(NetworkFault),
+    /// Undo a network fault on the running deployment
+    HealFault(NetworkFault),
     /// Instruct the orchestration to cancel
     Cancel,
     /// Internal use to show that the commands have finished generating
@@ -203,6 +225,21 @@ impl OrchestrationInstruction {
             OrchestrationInstruction::Exec(e) => {
                 instruction.push_str(&format!("Exec {}", e.name()))
             }
+            OrchestrationInstruction::Net(n) => {
+                instruction.push_str(&format!("Net {}", n.name()))
+            }
+            OrchestrationInstruction::Trace(t) => {
+                instruction.push_str(&format!("Trace {}", t.name()))
+            }
+            OrchestrationInstruction::Verify(plan) => {
+                instruction.push_str(&format!("Verify with {} probes", plan.probes.len()))
+            }
+            OrchestrationInstruction::ApplyFault(f) => {
+                instruction.push_str(&format!("Apply Fault {}", f.name()))
+            }
+            OrchestrationInstruction::HealFault(f) => {
+                instruction.push_str(&format!("Heal Fault {}", f.name()))
+            }
             OrchestrationInstruction::ListCloudImages => instruction.push_str(&"List Cloud Images".to_string()),
             OrchestrationInstruction::Cancel => {
                 instruction.push_str(&"Cancel".to_string())
@@ -546,7 +583,7 @@ impl OrchestrationInstruction {
             OrchestrationInstruction::AnalysisTool(at) => {
                 let analysis_tool_res = match at.tool {
                     AnalysisToolsSubCmd::TcpDump { .. } => {
-                        packet_capture(at).await
+                        packet_capture(at, state, orchestration_common).await
                     }
                 };
                 match analysis_tool_res {
@@ -575,8 +612,70 @@ impl OrchestrationInstruction {
                 }
 
             }
+            OrchestrationInstruction::Net(net_cmd) => {
+                match run_net_command(net_cmd, state, orchestration_common).await {
+                    Ok(_) => OrchestrationProtocolResponse::Generic {
+                        is_success: true,
+                        message: format!("Net command {} succeeded", net_cmd.name()),
+                    },
+                    Err(err) => OrchestrationProtocolResponse::Generic {
+                        is_success: false,
+                        message: format!("Net command {} error: {err:#}", net_cmd.name()),
+                    }
+                }
+            }
+            OrchestrationInstruction::Trace(trace_cmd) => {
+                match run_trace(trace_cmd, state, orchestration_common, logging_send).await {
+                    Ok(_) => OrchestrationProtocolResponse::Generic {
+                        is_success: true,
+                        message: format!("Trace {} succeeded", trace_cmd.name()),
+                    },
+                    Err(err) => OrchestrationProtocolResponse::Generic {
+                        is_success: false,
+                        message: format!("Trace {} error: {err:#}", trace_cmd.name()),
+                    }
+                }
+            }
+            OrchestrationInstruction::Verify(plan) => {
+                match run_verify(plan, state, orchestration_common, logging_send).await {
+                    Ok(_) => OrchestrationProtocolResponse::Generic {
+                        is_success: true,
+                        message: format!("Verified {} probes", plan.probes.len()),
+                    },
+                    Err(err) => OrchestrationProtocolResponse::Generic {
+                        is_success: false,
+                        message: format!("Verify error: {err:#}"),
+                    }
+                }
+            }
+            OrchestrationInstruction::ApplyFault(fault) => {
+                match fault.apply_and_record(orchestration_common).await {
+                    Ok(_) => OrchestrationProtocolResponse::Generic {
+                        is_success: true,
+                        message: format!("Applied fault {}", fault.name()),
+                    },
+                    Err(err) => OrchestrationProtocolResponse::Generic {
+                        is_success: false,
+                        message: format!("Apply fault {} error: {err:#}", fault.name()),
+                    }
+                }
+            }
+            OrchestrationInstruction::HealFault(fault) => {
+                match fault.heal_and_record(orchestration_common).await {
+                    Ok(_) => OrchestrationProtocolResponse::Generic {
+                        is_success: true,
+                        message: format!("Healed fault {}", fault.name()),
+                    },
+                    Err(err) => OrchestrationProtocolResponse::Generic {
+                        is_success: false,
+                        message: format!("Heal fault {} error: {err:#}", fault.name()),
+                    }
+                }
+            }
             OrchestrationInstruction::ListCloudImages => {
-                let images = OnlineCloudImage::pretty_to_string()?;
+                let images = CloudImageCatalog::load(Some(&orchestration_common.project_working_dir))
+                    .await?
+                    .pretty_to_string();
                 logging_send.send(OrchestrationLogger::info("Available cloud images:".to_string())).await?;
                 for img in images {
                     logging_send.send(OrchestrationLogger::info(img)).await?;
@@ -685,6 +784,40 @@ impl OrchestrationResource {
                             OrchestrationResourceNetwork::ACL(acl) => {
                                 name.push_str(&format!("ACL (type: {}, action: {}, match: {}, priority: {}) on {}", &acl.direction, &acl.action, &acl._match, &acl.priority, &acl.entity_name))
                             }
+                            OrchestrationResourceNetwork::FlowExport(fe) => {
+                                name.push_str(&format!("Bridge {} {} Flow Export to {}", &fe.integration_bridge_name, &fe.protocol, &fe.target))
+                            }
+                            OrchestrationResourceNetwork::Mirror(mirror) => {
+                                name.push_str(&format!("Mirror {} to LSP {}", &mirror.name, &mirror.sink_port))
+                            }
+                            OrchestrationResourceNetwork::PortGroup(pg) => {
+                                name.push_str(&format!("Port Group {} with {} ports", &pg.name, pg.ports.len()))
+                            }
+                            OrchestrationResourceNetwork::AddressSet(set) => {
+                                name.push_str(&format!("Address Set {} with {} addresses", &set.name, set.addresses.len()))
+                            }
+                            OrchestrationResourceNetwork::LoadBalancer(lb) => {
+                                name.push_str(&format!("Load Balancer {} for {} with {} backends", &lb.name, &lb.vip, lb.backends.len()))
+                            }
+                            OrchestrationResourceNetwork::DnsRecords(dns) => {
+                                name.push_str(&format!("DNS Records for {} guests on LS {}", dns.records.len(), &dns.switch))
+                            }
+                        }
+                    }
+                    OrchestrationResourceNetworkType::Ovs(ovs) => {
+                        name.push_str("Ovs ");
+                        match ovs {
+                            OrchestrationResourceOvsNetwork::Bridge(bridge) => {
+                                name.push_str(&format!("Bridge {} on {}", &bridge.name, &bridge.testbed_host))
+                            }
+                            OrchestrationResourceOvsNetwork::Connection(connection) => {
+                                let (source, target) = connection.bridges();
+                                let link_type = match connection {
+                                    BridgeConnection::Veth { .. } => "Veth".to_string(),
+                                    BridgeConnection::Tunnel { tunnel_type, .. } => tunnel_type.to_string().to_uppercase(),
+                                };
+                                name.push_str(&format!("{link_type} Link between Bridges {source} and {target}"))
+                            }
                         }
                     }
                 }
@@ -693,6 +826,58 @@ impl OrchestrationResource {
         name
     }
 
+    /// Get the identity of the resource, this is the kind of resource and the fields that OVN or
+    /// the testbed host identify it by, so unlike `name()` it does not change when the resource is
+    /// modified
+    pub fn id(&self) -> String {
+        match self {
+            OrchestrationResource::Guest(guest) => format!("guest {}", &guest.guest_type.name),
+            OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(ovn)) => match ovn {
+                OrchestrationResourceNetwork::Switch(switch) => format!("ovn switch {}", &switch.name),
+                OrchestrationResourceNetwork::SwitchPort(lsp) => format!("ovn switch port {}", &lsp.name),
+                OrchestrationResourceNetwork::Router(router) => format!("ovn router {}", &router.name),
+                OrchestrationResourceNetwork::RouterPort(lrp) => format!("ovn router port {}", &lrp.name),
+                OrchestrationResourceNetwork::OvsPort(ovs) => format!("ovs port {} {}", &ovs.chassis, &ovs.name),
+                OrchestrationResourceNetwork::DhcpOption(dhcp) => format!("ovn dhcp option {}", dhcp.cidr.to_string()),
+                OrchestrationResourceNetwork::ExternalGateway(gateway) => {
+                    format!("ovn external gateway {} {}", &gateway.router_port_name, &gateway.chassis_name)
+                }
+                OrchestrationResourceNetwork::Nat(nat) => format!(
+                    "ovn nat {} {} {}",
+                    &nat.logical_router_name,
+                    nat.nat_type.to_string(),
+                    nat.external_ip.to_string(),
+                ),
+                OrchestrationResourceNetwork::Route(route) => format!(
+                    "ovn route {} {} {}",
+                    &route.router_name,
+                    route.prefix.to_string(),
+                    route.next_hop.to_string(),
+                ),
+                OrchestrationResourceNetwork::ACL(acl) => format!(
+                    "ovn acl {} {} {} {}",
+                    &acl.entity_name,
+                    &acl.direction,
+                    &acl.priority,
+                    &acl._match,
+                ),
+                OrchestrationResourceNetwork::FlowExport(fe) => format!("ovs flow export {}", &fe.chassis),
+                OrchestrationResourceNetwork::Mirror(mirror) => format!("ovn mirror {}", &mirror.name),
+                OrchestrationResourceNetwork::PortGroup(pg) => format!("ovn port group {}", &pg.name),
+                OrchestrationResourceNetwork::AddressSet(set) => format!("ovn address set {}", &set.name),
+                OrchestrationResourceNetwork::LoadBalancer(lb) => format!("ovn load balancer {}", &lb.name),
+                OrchestrationResourceNetwork::DnsRecords(dns) => format!("ovn dns records {}", &dns.switch),
+            },
+            OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovs(ovs)) => match ovs {
+                OrchestrationResourceOvsNetwork::Bridge(bridge) => format!("ovs bridge {}", &bridge.name),
+                OrchestrationResourceOvsNetwork::Connection(connection) => {
+                    let (source, target) = connection.bridges();
+                    format!("ovs link {source} {target}")
+                }
+            },
+        }
+    }
+
     /// Get the future for the create action for the resource
     pub async fn get_create_future(&self, orchestration_common: OrchestrationCommon) -> anyhow::Result<()> {
         match self {
@@ -761,6 +946,53 @@ impl OrchestrationResource {
                                 r.create_command(&ovn_run_cmd, (None, orchestration_common.clone())).await?;
                                 Ok(())
                             }
+                            OrchestrationResourceNetwork::FlowExport(r) => {
+                                r.create_command(
+                                    &ovn_run_cmd,
+                                    (
+                                        Some(chassis_to_tb_host(&r.chassis, &orchestration_common)?),
+                                        orchestration_common.clone()
+                                    )
+                                ).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::Mirror(r) => {
+                                // the sink interface is on the sink guest's testbed host
+                                r.create_command(
+                                    &ovn_run_cmd,
+                                    (Some(r.sink_testbed_host.clone()), orchestration_common.clone())
+                                ).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::PortGroup(r) => {
+                                r.create_command(&ovn_run_cmd, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::AddressSet(r) => {
+                                r.create_command(&ovn_run_cmd, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::LoadBalancer(r) => {
+                                r.create_command(&ovn_run_cmd, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::DnsRecords(r) => {
+                                r.create_command(&ovn_run_cmd, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                        }
+                    }
+                    OrchestrationResourceNetworkType::Ovs(ovs) => {
+                        // the OVS resources run their commands on their own testbed hosts
+                        match ovs {
+                            OrchestrationResourceOvsNetwork::Bridge(r) => {
+                                r.create_command(&ovn_run_cmd, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceOvsNetwork::Connection(r) => {
+                                r.create_command(&ovn_run_cmd, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
                         }
                     }
                 }
@@ -836,6 +1068,53 @@ impl OrchestrationResource {
                                 r.destroy_command(&ovn_run_cmd_allow_fail, (None, orchestration_common.clone())).await?;
                                 Ok(())
                             }
+                            OrchestrationResourceNetwork::FlowExport(r) => {
+                                r.destroy_command(
+                                    &ovn_run_cmd_allow_fail,
+                                    (
+                                        Some(chassis_to_tb_host(&r.chassis, &orchestration_common)?),
+                                        orchestration_common.clone()
+                                    )
+                                ).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::Mirror(r) => {
+                                // the sink interface is on the sink guest's testbed host
+                                r.destroy_command(
+                                    &ovn_run_cmd_allow_fail,
+                                    (Some(r.sink_testbed_host.clone()), orchestration_common.clone())
+                                ).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::PortGroup(r) => {
+                                r.destroy_command(&ovn_run_cmd_allow_fail, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::AddressSet(r) => {
+                                r.destroy_command(&ovn_run_cmd_allow_fail, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::LoadBalancer(r) => {
+                                r.destroy_command(&ovn_run_cmd_allow_fail, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceNetwork::DnsRecords(r) => {
+                                r.destroy_command(&ovn_run_cmd_allow_fail, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                        }
+                    }
+                    OrchestrationResourceNetworkType::Ovs(ovs) => {
+                        // the OVS resources run their commands on their own testbed hosts
+                        match ovs {
+                            OrchestrationResourceOvsNetwork::Bridge(r) => {
+                                r.destroy_command(&ovn_run_cmd_allow_fail, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
+                            OrchestrationResourceOvsNetwork::Connection(r) => {
+                                r.destroy_command(&ovn_run_cmd_allow_fail, (None, orchestration_common.clone())).await?;
+                                Ok(())
+                            }
                         }
                     }
                 }
@@ -910,7 +1189,7 @@ impl OrchestrationResource {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum OrchestrationResourceNetworkType {
     Ovn(OrchestrationResourceNetwork),
-    // Ovs,
+    Ovs(OrchestrationResourceOvsNetwork),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -925,6 +1204,18 @@ pub enum OrchestrationResourceNetwork {
     Nat(OvnNat),
     Route(OvnRoute),
     ACL(LogicalACLRecord),
+    FlowExport(OvsFlowExport),
+    Mirror(OvnMirror),
+    PortGroup(LogicalPortGroup),
+    AddressSet(LogicalAddressSet),
+    LoadBalancer(LogicalLoadBalancer),
+    DnsRecords(OvnDnsRecords),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum OrchestrationResourceOvsNetwork {
+    Bridge(StateOvsBridge),
+    Connection(BridgeConnection),
 }
 
 /// This enum is to be sent from the server back to the client as a response to the result of `OrchestrationProtocol`,
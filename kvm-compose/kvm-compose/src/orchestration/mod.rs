@@ -345,16 +345,20 @@ pub async fn write_state_request(
 
 pub async fn create_logical_testbed(
     yaml_path: &String,
+    var_file: &Option<String>,
     deployment: &Deployment,
     project_location: &PathBuf,
     force_provisioning: bool,
+    previous_state: Option<&State>,
 ) -> anyhow::Result<LogicalTestbed> {
     let logical_testbed = parse_config(
         yaml_path.clone(),
+        var_file.clone(),
         Some(deployment.name.clone()),
         true,
         project_location.clone(),
         force_provisioning,
+        previous_state,
     ).await.context("Failed to parse the yaml config and create a logical testbed")?;
     Ok(logical_testbed)
 }
@@ -1,17 +1,23 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use anyhow::{bail, Context};
 use reqwest::Client;
 use tokio::sync::mpsc::{Sender};
 use kvm_compose_schemas::cli_models::Opts;
 use crate::orchestration::{create_logical_testbed, OrchestrationTask, read_previous_state_request, write_state_request};
 use crate::parse_config;
+use crate::lockfile::{LockFile, LOCK_FILE_NAME};
 use crate::state::orchestration_tasks::{check_if_guest_images_exist, get_orchestration_common};
 use crate::state::State;
+use crate::state::delta::StateDelta;
 use kvm_compose_schemas::deployment_models::{Deployment, DeploymentCommand, DeploymentState};
 use kvm_compose_schemas::settings::TestbedClusterConfig;
 use crate::orchestration::api::{OrchestrationInstruction, OrchestrationProtocol};
 use crate::orchestration::websocket::{send_orchestration_instruction_over_channel};
 use crate::state::orchestration_tasks::ovn_network::reapply_acl_action;
+use crate::net::faults::request_fault_changes;
+use kvm_compose_schemas::net::NetSubCommand;
+use kvm_compose_schemas::kvm_compose_yaml::Config;
+use crate::net::verify::VerifyPlan;
 
 
 pub async fn run_orchestration(
@@ -40,18 +46,67 @@ pub async fn run_orchestration(
         }
     };
 
+    let yaml = PathBuf::from(&deployment.project_location).join("kvm-compose.yaml");
+    let project_location = PathBuf::from(&deployment.project_location);
+    let var_file = opts.var_file.as_deref().map(PathBuf::from);
+    let locked = matches!(&action, DeploymentCommand::Up { up_cmd } if up_cmd.locked);
+    if locked {
+        check_lock_file(&yaml, var_file.as_deref(), &project_location)
+            .await
+            .context("checking the deployment against the lock file")?;
+    }
+
     let command_result = orchestration_parse_command(
-        action,
+        action.clone(),
         deployment,
         kvm_compose_config,
         sender,
         client,
         opts.server_connection,
+        opts.var_file,
     ).await.context("running the chosen orchestration command")?;
 
+    // record the inputs of a successful deployment, a locked deployment already matches the lock
+    let update_lock = match &action {
+        DeploymentCommand::Up { .. } => !locked && command_result.state == DeploymentState::Up,
+        DeploymentCommand::GenerateArtefacts => !matches!(command_result.state, DeploymentState::Failed(_)),
+        _ => false,
+    };
+    if update_lock {
+        if let Err(err) = update_lock_file(&yaml, var_file.as_deref(), &project_location).await {
+            tracing::warn!("could not write {LOCK_FILE_NAME}: {err:#}");
+        }
+    }
+
     Ok(command_result)
 }
 
+/// Make sure the inputs of the deployment are the same as recorded in the lock file, listing
+/// everything that is different
+async fn check_lock_file(yaml: &Path, var_file: Option<&Path>, project_location: &Path) -> anyhow::Result<()> {
+    let Some(lock) = LockFile::read(project_location).await? else {
+        bail!("--locked was given but there is no {LOCK_FILE_NAME}, run up without --locked to create it");
+    };
+    let current = LockFile::generate(yaml, var_file, project_location, Some(&lock)).await?;
+    let differences = lock.differences(&current);
+    if !differences.is_empty() {
+        for difference in &differences {
+            tracing::error!("{difference}");
+        }
+        bail!("{} difference(s) from {LOCK_FILE_NAME}, refusing to deploy", differences.len());
+    }
+    tracing::info!("deployment matches {LOCK_FILE_NAME}");
+    Ok(())
+}
+
+async fn update_lock_file(yaml: &Path, var_file: Option<&Path>, project_location: &Path) -> anyhow::Result<()> {
+    let previous = LockFile::read(project_location).await.ok().flatten();
+    let lock = LockFile::generate(yaml, var_file, project_location, previous.as_ref()).await?;
+    lock.write(project_location).await?;
+    tracing::info!("written {LOCK_FILE_NAME}");
+    Ok(())
+}
+
 pub async fn orchestration_parse_command(
     command: DeploymentCommand,
     mut deployment: Deployment,
@@ -59,6 +114,7 @@ pub async fn orchestration_parse_command(
     sender: &mut Sender<OrchestrationProtocol>,
     http_client: Client,
     server_conn: String,
+    var_file: Option<String>,
 ) -> anyhow::Result<Deployment> {
     let yaml = format!("{}/kvm-compose.yaml", deployment.project_location.clone());
     let project_location = PathBuf::from(&deployment.project_location);
@@ -72,7 +128,7 @@ pub async fn orchestration_parse_command(
             let reapply_acl = up_cmd.reapply_acl.clone();
             if reapply_acl {
                 let previous_state = read_previous_state_request(&http_client, &server_conn, &project_name).await?;
-                let logical_testbed = create_logical_testbed(&yaml, &deployment, &project_location, false)
+                let logical_testbed = create_logical_testbed(&yaml, &var_file, &deployment, &project_location, false, Some(&previous_state))
                     .await
                     .context("Creating logical testbed")?;
                 reapply_acl_action(
@@ -116,7 +172,7 @@ pub async fn orchestration_parse_command(
 
                         // either return a new state or the old state based on force_provision
                         if force_provision {
-                            let logical_testbed = create_logical_testbed(&yaml, &deployment, &project_location, force_provision)
+                            let logical_testbed = create_logical_testbed(&yaml, &var_file, &deployment, &project_location, force_provision, Some(&state))
                                 .await
                                 .context("Creating logical testbed")?;
                             tracing::info!("parsed {project_name} kvm-compose.yaml");
@@ -140,9 +196,23 @@ pub async fn orchestration_parse_command(
                                 .await
                                 .context("Generating artefacts from logical testbed")?;
                             state
-                        } else {
-                            // nothing to do, just init before continue
-                            // send the deployment and the command and receive OK
+                        } else if matches!(deployment.state, DeploymentState::Down | DeploymentState::Failed(DeploymentCommand::Down)) {
+                            // the state is kept after down, but none of its guests or network
+                            // exist, so the whole testbed is created from the kvm-compose.yaml.
+                            // After any other command the guests may still be running, so the
+                            // stored state is diffed below
+                            tracing::info!("deployment is down, creating the whole testbed");
+                            let logical_testbed = create_logical_testbed(&yaml, &var_file, &deployment, &project_location, force_provision, Some(&state))
+                                .await
+                                .context("Creating logical testbed")?;
+                            tracing::info!("parsed {project_name} kvm-compose.yaml");
+                            let mut new_state = State::new(&logical_testbed)
+                                .context("Creating state from logical testbed")?;
+                            new_state.state_provisioning.guests_provisioned = state.state_provisioning.guests_provisioned;
+                            write_state_request(&http_client, &server_conn, project_name, &new_state)
+                                .await
+                                .context("Sending the state json file to server to save to disk.")?;
+                            tracing::info!("written state for {project_name}");
                             send_orchestration_instruction_over_channel(
                                 sender,
                                 OrchestrationInstruction::Init {
@@ -150,13 +220,74 @@ pub async fn orchestration_parse_command(
                                     deployment_command: command.clone(),
                                 },
                             ).await.context("sending Init request to server")?;
-                            // return the previous state
-                            state
+                            logical_testbed.request_generate_artefacts(sender)
+                                .await
+                                .context("Generating artefacts from logical testbed")?;
+                            new_state
+                        } else {
+                            // work out what has changed in the kvm-compose.yaml since the previous
+                            // state so that only the changes are applied
+                            let logical_testbed = create_logical_testbed(&yaml, &var_file, &deployment, &project_location, force_provision, Some(&state))
+                                .await
+                                .context("Creating logical testbed")?;
+                            tracing::info!("parsed {project_name} kvm-compose.yaml");
+                            let mut new_state = State::new(&logical_testbed)
+                                .context("Creating state from logical testbed")?;
+                            let delta = StateDelta::new(&state, &new_state)
+                                .context("Comparing the previous state to the new state")?;
+                            if delta.is_empty() {
+                                tracing::info!("no changes found since the previous state");
+                                // nothing to do, just init before continue
+                                // send the deployment and the command and receive OK
+                                send_orchestration_instruction_over_channel(
+                                    sender,
+                                    OrchestrationInstruction::Init {
+                                        deployment: deployment.clone(),
+                                        deployment_command: command.clone(),
+                                    },
+                                ).await.context("sending Init request to server")?;
+                                // return the previous state
+                                state
+                            } else {
+                                delta.log_summary();
+                                // the provisioning status carries over from the previous state
+                                new_state.state_provisioning.guests_provisioned = state.state_provisioning.guests_provisioned;
+                                // so do any faults that have not been healed, the resources they
+                                // were applied to still exist
+                                new_state.faults = state.faults.clone();
+                                send_orchestration_instruction_over_channel(
+                                    sender,
+                                    OrchestrationInstruction::Init {
+                                        deployment: deployment.clone(),
+                                        deployment_command: command.clone(),
+                                    },
+                                ).await.context("sending Init request to server")?;
+                                // generate artefacts for any new or changed guests, existing guest
+                                // images are not overwritten
+                                logical_testbed.request_generate_artefacts(sender)
+                                    .await
+                                    .context("Generating artefacts from logical testbed")?;
+                                // apply only the delta rather than the full create action, the new
+                                // state is only written once the changes exist
+                                let common = get_orchestration_common(&new_state, force_provision, force_rerun_scripts, reapply_acl, kvm_compose_config).await?;
+                                match delta.request_apply_action(&common, sender).await.context("requesting apply action") {
+                                    Ok(_) => deployment.state = DeploymentState::Up,
+                                    Err(err) => {
+                                        deployment.state = DeploymentState::Failed(command.clone());
+                                        bail!("{err:#}");
+                                    },
+                                }
+                                write_state_request(&http_client, &server_conn, project_name, &new_state)
+                                    .await
+                                    .context("Sending the state json file to server to save to disk.")?;
+                                tracing::info!("written state for {project_name}");
+                                return Ok(deployment);
+                            }
                         }
                     }
                     Err(_) => {
                         tracing::info!("There was no state file found, starting a fresh testbed deployment");
-                        let logical_testbed = create_logical_testbed(&yaml, &deployment, &project_location, force_provision)
+                        let logical_testbed = create_logical_testbed(&yaml, &var_file, &deployment, &project_location, force_provision, None)
                             .await
                             .context("Creating logical testbed")?;
                         tracing::info!("parsed {project_name} kvm-compose.yaml");
@@ -245,12 +376,16 @@ pub async fn orchestration_parse_command(
             // TODO - disallow this if a deployment is already up as we dont want to overwrite the
             //  state as this could be different
             // create logical testbed to get a state
+            // keep the addresses allocated by any previous deployment
+            let previous_state = read_previous_state_request(&http_client, &server_conn, project_name).await.ok();
             let logical_testbed = parse_config(
                 yaml.clone(),
+                var_file.clone(),
                 Some(deployment.name.clone()),
                 true,
                 project_location.clone(),
                 false,
+                previous_state.as_ref(),
             )
                 .await
                 .context("Failed to parse the yaml config and create a logical testbed")?;
@@ -368,7 +503,7 @@ pub async fn orchestration_parse_command(
                 send_orchestration_instruction_over_channel(
                     sender,
                     OrchestrationInstruction::AnalysisTool(tool.clone()),
-                ).await.context("sending snapshot request to server")?;
+                ).await.context("sending analysis tool request to server")?;
 
 
             } else {
@@ -397,6 +532,83 @@ pub async fn orchestration_parse_command(
             }
             Ok(deployment)
         }
+        DeploymentCommand::Net(ref net_cmd) => {
+            if let Ok(state) = read_previous_state_request(&http_client, &server_conn, project_name).await {
+
+                send_orchestration_instruction_over_channel(
+                    sender,
+                    OrchestrationInstruction::Init {
+                        deployment: deployment.clone(),
+                        deployment_command: command.clone(),
+                    },
+                ).await.context("sending Init request to server")?;
+
+                match net_cmd.sub_command {
+                    NetSubCommand::Qos(_) => {
+                        send_orchestration_instruction_over_channel(
+                            sender,
+                            OrchestrationInstruction::Net(net_cmd.clone()),
+                        ).await.context("sending Net request to server")?;
+                    }
+                    _ => {
+                        // the server records the faults in the state so that they can be healed later
+                        request_fault_changes(&net_cmd.sub_command, &state, sender).await?;
+                    }
+                }
+
+            } else {
+                tracing::error!("could not run net command, no state file, is the deployment up?");
+            }
+            Ok(deployment)
+        }
+        DeploymentCommand::Trace(ref trace_cmd) => {
+            if read_previous_state_request(&http_client, &server_conn, project_name).await.is_ok() {
+
+                send_orchestration_instruction_over_channel(
+                    sender,
+                    OrchestrationInstruction::Init {
+                        deployment: deployment.clone(),
+                        deployment_command: command.clone(),
+                    },
+                ).await.context("sending Init request to server")?;
+
+                send_orchestration_instruction_over_channel(
+                    sender,
+                    OrchestrationInstruction::Trace(trace_cmd.clone()),
+                ).await.context("sending Trace request to server")?;
+
+            } else {
+                tracing::error!("could not run trace command, no state file, is the deployment up?");
+            }
+            Ok(deployment)
+        }
+        DeploymentCommand::Verify(ref verify_cmd) => {
+            if let Ok(state) = read_previous_state_request(&http_client, &server_conn, project_name).await {
+                // the expectations and policies come from the yaml, the guests from the state
+                let config = Config::load_from_file_with_vars(&yaml, var_file.as_deref().map(Path::new))
+                    .await
+                    .context("loading the kvm-compose.yaml for the expectations")?;
+                let plan = VerifyPlan::new(&config, &state, verify_cmd.expected_only)?;
+                tracing::info!("verifying the deployment with {} probes", plan.probes.len());
+
+                send_orchestration_instruction_over_channel(
+                    sender,
+                    OrchestrationInstruction::Init {
+                        deployment: deployment.clone(),
+                        deployment_command: command.clone(),
+                    },
+                ).await.context("sending Init request to server")?;
+
+                send_orchestration_instruction_over_channel(
+                    sender,
+                    OrchestrationInstruction::Verify(plan),
+                ).await.context("sending Verify request to server")?;
+
+            } else {
+                bail!("could not run verify command, no state file, is the deployment up?");
+            }
+            Ok(deployment)
+        }
         DeploymentCommand::ListCloudImages => {
             send_orchestration_instruction_over_channel(
                 sender,
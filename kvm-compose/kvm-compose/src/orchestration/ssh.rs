@@ -171,6 +171,23 @@ impl SSHClient {
         todo!()
     }
 
+    /// Build the ssh command that runs a command on a remote testbed host, for when the output of
+    /// the command needs to be streamed rather than waited for
+    pub async fn remote_testbed_command(
+        common: &OrchestrationCommon,
+        testbed_host: &String,
+        remote_cmd: Vec<&str>,
+    ) -> anyhow::Result<Command> {
+        let testbed_host_ssh_config = _get_conn_testbed_host(common, testbed_host).await?;
+        let ssh_address = format!("{}@{}", testbed_host_ssh_config.username, testbed_host_ssh_config.ip);
+        let mut command = Command::new("ssh");
+        command
+            .args(["-i", &testbed_host_ssh_config.ssh_private_key_location, &ssh_address])
+            .args(_get_ssh_opts())
+            .args(remote_cmd);
+        Ok(command)
+    }
+
     /// Pull a file from a remote testbed host
     pub async fn pull_file_from_remote_testbed(
         common: &OrchestrationCommon,
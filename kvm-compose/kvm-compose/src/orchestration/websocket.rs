@@ -3,6 +3,7 @@ use std::sync::Arc;
 use anyhow::{bail, Context, Error};
 use futures_util::stream::{SplitSink, SplitStream};
 use futures_util::{SinkExt, StreamExt};
+use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 use tokio::sync::{mpsc, Mutex};
 use tokio::sync::mpsc::{Sender};
@@ -11,6 +12,7 @@ use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use tokio_tungstenite::tungstenite::{Message};
 use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use kvm_compose_schemas::capture::LiveCaptureRequest;
 use kvm_compose_schemas::cli_models::Opts;
 use kvm_compose_schemas::deployment_models::{Deployment, DeploymentCommand};
 use crate::orchestration::api::{OrchestrationInstruction, OrchestrationLogger, OrchestrationLoggerLevel, OrchestrationProtocol, OrchestrationProtocolResponse};
@@ -338,3 +340,55 @@ async fn send_orchestration_instruction(
 
     Ok(())
 }
+
+/// Stream a live capture from the server to stdout. The capture is stopped by the server when this
+/// ends, which is when the server closes the capture, the reader of stdout goes away or the user
+/// presses ctrl + C.
+pub async fn ws_live_capture_client(
+    runner_url: String,
+    request: LiveCaptureRequest,
+) -> anyhow::Result<()> {
+    let (ws_stream, _) = connect_async(runner_url)
+        .await
+        .context("live capture websocket handshake")?;
+    let (mut sender, mut receiver) = ws_stream.split();
+    sender.send(Message::Binary(serde_json::to_vec(&request)?))
+        .await
+        .context("sending live capture request")?;
+
+    let mut stdout = tokio::io::stdout();
+    loop {
+        tokio::select! {
+            msg = receiver.next() => {
+                match msg {
+                    Some(Ok(Message::Binary(b))) => {
+                        if stdout.write_all(&b).await.is_err() || stdout.flush().await.is_err() {
+                            tracing::info!("output closed, stopping live capture");
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(close))) => {
+                        if let Some(close) = close {
+                            if close.code != CloseCode::Normal {
+                                bail!("live capture closed by server, reason: {}", close.reason);
+                            }
+                        }
+                        return Ok(());
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => bail!("live capture connection error: {err:#}"),
+                    None => return Ok(()),
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                tracing::info!("captured ctrl + C, stopping live capture");
+                break;
+            }
+        }
+    }
+    let _ = sender.send(Message::Close(Some(CloseFrame {
+        code: CloseCode::Normal,
+        reason: Cow::from("End of live capture"),
+    }))).await;
+    Ok(())
+}
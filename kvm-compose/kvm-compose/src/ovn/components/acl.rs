@@ -12,13 +12,14 @@ use crate::vec_of_strings;
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum ACLRecordType {
     Switch,
-    // PortGroup,
+    PortGroup,
 }
 
 impl fmt::Display for ACLRecordType {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let text = match self {
             ACLRecordType::Switch => "switch".to_string(),
+            ACLRecordType::PortGroup => "port-group".to_string(),
         };
         f.write_str(&text)
             .expect("Pretty printing ACLRecordType failed");
@@ -75,6 +76,14 @@ impl LogicalACLRecord {
     ) -> OrchestrationResource {
         OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(OrchestrationResourceNetwork::ACL(self.clone())))
     }
+
+    /// A port group can have the same name as a switch, so ovn-nbctl needs to be told the type
+    fn type_option(&self) -> Option<String> {
+        match self._type {
+            ACLRecordType::Switch => None,
+            ACLRecordType::PortGroup => Some(format!("--type={}", &self._type)),
+        }
+    }
 }
 
 #[async_trait]
@@ -86,7 +95,9 @@ impl OvnCommand for LogicalACLRecord {
         tracing::info!("creating ACL on {:?}", &self.entity_name);
 
         let name = format!("--name={}", &self.ovn_resource_name);
-        let cmd = vec_of_strings!["ovn-nbctl", "--may-exist", &name, "acl-add", &self.entity_name, &self.direction, &self.priority, &self._match, &self.action];
+        let mut cmd = vec_of_strings!["ovn-nbctl", "--may-exist", &name];
+        cmd.extend(self.type_option());
+        cmd.extend(vec_of_strings!["acl-add", &self.entity_name, &self.direction, &self.priority, &self._match, &self.action]);
 
         f(cmd, config).await
     }
@@ -97,7 +108,9 @@ impl OvnCommand for LogicalACLRecord {
     {
         tracing::info!("destroying ACL {:?}", &self);
 
-        let cmd = vec_of_strings!["ovn-nbctl", "acl-del",  &self.entity_name, &self.direction, &self.priority, &self._match];
+        let mut cmd = vec_of_strings!["ovn-nbctl"];
+        cmd.extend(self.type_option());
+        cmd.extend(vec_of_strings!["acl-del",  &self.entity_name, &self.direction, &self.priority, &self._match]);
 
         f(cmd, config).await
     }
@@ -124,4 +137,21 @@ mod tests {
         let expected_del = vec_of_strings!["ovn-nbctl", "acl-del", "ovn-sw0", "to-lport", "10", "match", "drop"].join(" ");
         assert_eq!(expected_del, record.destroy_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_port_group_acl_record() {
+        let record = LogicalACLRecord::new(
+            "test_dbs".to_string(),
+            ACLRecordType::PortGroup,
+            ACLDirection::ToLport,
+            10,
+            "ip4.src == $test_web".to_string(),
+            ACLAction::AllowRelated,
+            "test-dbs-0".to_string(),
+        );
+        let expected_add = vec_of_strings!["ovn-nbctl", "--may-exist", "--name=test-dbs-0", "--type=port-group", "acl-add", "test_dbs", "to-lport", "10", "ip4.src == $test_web", "allow-related"].join(" ");
+        assert_eq!(expected_add, record.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap());
+        let expected_del = vec_of_strings!["ovn-nbctl", "--type=port-group", "acl-del", "test_dbs", "to-lport", "10", "ip4.src == $test_web"].join(" ");
+        assert_eq!(expected_del, record.destroy_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap());
+    }
 }
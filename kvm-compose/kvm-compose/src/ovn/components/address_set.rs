@@ -0,0 +1,81 @@
+use std::future::Future;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType};
+use crate::orchestration::OrchestrationCommon;
+use crate::ovn::OvnCommand;
+use crate::vec_of_strings;
+
+/// This represents an OVN address set, a named set of ip addresses or subnets that can be
+/// referenced as `$name` in ACL matches
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LogicalAddressSet {
+    pub name: String,
+    pub addresses: Vec<String>,
+}
+
+impl LogicalAddressSet {
+    pub fn new(
+        name: String,
+        addresses: Vec<String>,
+    ) -> Self {
+        Self {
+            name,
+            addresses,
+        }
+    }
+
+    pub fn to_orchestration_resource(
+        &self,
+    ) -> OrchestrationResource {
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(OrchestrationResourceNetwork::AddressSet(self.clone())))
+    }
+}
+
+#[async_trait]
+impl OvnCommand for LogicalAddressSet {
+    async fn create_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("creating address set {} with {} addresses", &self.name, self.addresses.len());
+        // the addresses are a set of strings in the database, which must be quoted
+        let addresses = if self.addresses.is_empty() {
+            "[]".to_string()
+        } else {
+            self.addresses.iter()
+                .map(|address| format!("\"{address}\""))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let cmd = vec_of_strings!["ovn-nbctl", "create", "Address_Set", format!("name={}", &self.name), format!("addresses={addresses}")];
+        f(cmd, config).await
+    }
+
+    async fn destroy_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("destroying address set {}", &self.name);
+        let cmd = vec_of_strings!["ovn-nbctl", "destroy", "Address_Set", &self.name];
+        f(cmd, config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ovn::test_ovn_run_cmd;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_logical_address_set() {
+        let address_set = LogicalAddressSet::new(
+            "test_web".into(),
+            vec!["10.0.0.10".into(), "10.0.1.0/24".into()],
+        );
+        let create_cmd = address_set.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(create_cmd, r#"ovn-nbctl create Address_Set name=test_web addresses="10.0.0.10","10.0.1.0/24""#);
+        let destroy_cmd = address_set.destroy_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(destroy_cmd, "ovn-nbctl destroy Address_Set test_web");
+    }
+}
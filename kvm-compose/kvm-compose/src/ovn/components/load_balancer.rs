@@ -0,0 +1,101 @@
+use std::future::Future;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use kvm_compose_schemas::kvm_compose_yaml::network::load_balancer::LoadBalancerProtocol;
+use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType};
+use crate::orchestration::OrchestrationCommon;
+use crate::ovn::OvnCommand;
+use crate::vec_of_strings;
+
+/// This represents an OVN load balancer, traffic to the vip is sent to one of the backends. The load
+/// balancer is applied on the logical switches and routers it is attached to, removing one of them
+/// only removes the attachment so the load balancer itself must be removed separately.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LogicalLoadBalancer {
+    pub name: String,
+    /// ip and port i.e. 10.0.0.100:80
+    pub vip: String,
+    /// ip and port of each backend
+    pub backends: Vec<String>,
+    pub protocol: LoadBalancerProtocol,
+    pub switches: Vec<String>,
+    pub routers: Vec<String>,
+}
+
+impl LogicalLoadBalancer {
+    pub fn new(
+        name: String,
+        vip: String,
+        backends: Vec<String>,
+        protocol: LoadBalancerProtocol,
+        switches: Vec<String>,
+        routers: Vec<String>,
+    ) -> Self {
+        Self {
+            name,
+            vip,
+            backends,
+            protocol,
+            switches,
+            routers,
+        }
+    }
+
+    pub fn to_orchestration_resource(
+        &self,
+    ) -> OrchestrationResource {
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(OrchestrationResourceNetwork::LoadBalancer(self.clone())))
+    }
+}
+
+#[async_trait]
+impl OvnCommand for LogicalLoadBalancer {
+    async fn create_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("creating load balancer {} for {} with {} backends", &self.name, &self.vip, self.backends.len());
+        // create and attach in one transaction, so a failed attachment doesn't leave the load
+        // balancer behind
+        let mut cmd = vec_of_strings!["ovn-nbctl", "lb-add", &self.name, &self.vip, self.backends.join(","), &self.protocol];
+        for switch in &self.switches {
+            cmd.extend(vec_of_strings!["--", "ls-lb-add", switch, &self.name]);
+        }
+        for router in &self.routers {
+            cmd.extend(vec_of_strings!["--", "lr-lb-add", router, &self.name]);
+        }
+        f(cmd, config).await
+    }
+
+    async fn destroy_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("destroying load balancer {}", &self.name);
+        // this also removes it from the switches and routers it is attached to
+        let cmd = vec_of_strings!["ovn-nbctl", "lb-del", &self.name];
+        f(cmd, config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ovn::test_ovn_run_cmd;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_logical_load_balancer() {
+        let load_balancer = LogicalLoadBalancer::new(
+            "test_web".into(),
+            "10.0.0.100:80".into(),
+            vec!["10.0.0.20:8080".into(), "10.0.0.21:8080".into()],
+            LoadBalancerProtocol::Tcp,
+            vec!["test-sw0".into()],
+            vec!["test-lr0".into()],
+        );
+        let create_cmd = load_balancer.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(create_cmd, "ovn-nbctl lb-add test_web 10.0.0.100:80 10.0.0.20:8080,10.0.0.21:8080 tcp -- ls-lb-add test-sw0 test_web -- lr-lb-add test-lr0 test_web");
+        let destroy_cmd = load_balancer.destroy_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(destroy_cmd, "ovn-nbctl lb-del test_web");
+    }
+}
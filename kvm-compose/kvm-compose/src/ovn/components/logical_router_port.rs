@@ -6,6 +6,7 @@ use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetw
 use crate::orchestration::OrchestrationCommon;
 use crate::ovn::components::{MacAddress, OvnIpAddr};
 use crate::ovn::{OvnCommand};
+use crate::ovn::configuration::dhcp::RouterAdvertisementOptions;
 use crate::vec_of_strings;
 
 /// This represents an OVN logical router port
@@ -16,6 +17,10 @@ pub struct LogicalRouterPort {
     pub mac_address: MacAddress,
     pub ip: OvnIpAddr, // mut be ip with mask
     pub chassis_name: Option<String>, // set chassis to make this a gateway router
+    #[serde(default)]
+    pub ipv6: Option<OvnIpAddr>, // must be ip with mask, for dual-stack ports
+    #[serde(default)]
+    pub ipv6_ra: Option<RouterAdvertisementOptions>,
 }
 
 impl LogicalRouterPort {
@@ -36,6 +41,8 @@ impl LogicalRouterPort {
                 mask,
             },
             chassis_name,
+            ipv6: None,
+            ipv6_ra: None,
         }
     }
 
@@ -76,9 +83,17 @@ impl OvnCommand for LogicalRouterPort {
             F: Future<Output=anyhow::Result<String>> + Send
     {
         tracing::info!("creating LRP {}", &self.name);
-        f(vec_of_strings![
+        let mut cmd = vec_of_strings![
             "ovn-nbctl", "--may-exist", "lrp-add", &self.parent_router, &self.name, self.mac_address.get_string(), self.ip.to_string()
-        ], config).await
+        ];
+        if let Some(ipv6) = &self.ipv6 {
+            cmd.push(ipv6.to_string());
+        }
+        if let Some(ra) = &self.ipv6_ra {
+            cmd.extend(vec_of_strings!["--", "set", "Logical_Router_Port", &self.name]);
+            cmd.extend(ra.to_ovn_options());
+        }
+        f(cmd, config).await
     }
 
     async fn destroy_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
@@ -93,6 +108,7 @@ impl OvnCommand for LogicalRouterPort {
 #[cfg(test)]
 mod tests {
     use std::net::Ipv4Addr;
+    use kvm_compose_schemas::kvm_compose_yaml::network::router::Ipv6AddressMode;
     use crate::ovn::test_ovn_run_cmd;
     use super::*;
 
@@ -114,4 +130,28 @@ mod tests {
 
     }
 
+    #[tokio::test]
+    async fn test_logical_router_port_dual_stack() {
+        let mut lrp0 = LogicalRouterPort::new(
+            "lr0-port0".into(),
+            "lr0".into(),
+            MacAddress::new("00:00:00:00:ff:01".into()).unwrap(),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            24,
+            None,
+        );
+        lrp0.ipv6 = Some(OvnIpAddr::Subnet { ip: "fd00:10::1".parse().unwrap(), mask: 64 });
+        lrp0.ipv6_ra = Some(RouterAdvertisementOptions {
+            address_mode: Ipv6AddressMode::Slaac,
+            send_periodic: true,
+        });
+        let create_cmd = lrp0.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        let expected_cmd = vec_of_strings![
+            "ovn-nbctl", "--may-exist", "lrp-add", "lr0", "lr0-port0", "00:00:00:00:ff:01", "10.0.0.1/24", "fd00:10::1/64",
+            "--", "set", "Logical_Router_Port", "lr0-port0", "ipv6_ra_configs:address_mode=slaac",
+            "ipv6_ra_configs:send_periodic=true"
+        ].join(" ");
+        assert_eq!(create_cmd, expected_cmd);
+    }
+
 }
\ No newline at end of file
@@ -15,6 +15,8 @@ pub struct LogicalSwitch {
     pub name: String,
     pub subnet: OvnIpAddr, // must be subnet
     pub dhcp: Option<SwitchDhcpOptions>,
+    #[serde(default)]
+    pub ipv6_prefix: Option<OvnIpAddr>, // must be a /64 subnet, for dual-stack switches
 }
 
 impl LogicalSwitch {
@@ -30,6 +32,7 @@ impl LogicalSwitch {
                 mask,
             },
             dhcp: None,
+            ipv6_prefix: None,
         }
     }
 
@@ -53,6 +56,10 @@ impl OvnCommand for LogicalSwitch {
             tracing::info!("adding exclude ips option on LS {} as there is a switch port with a dynamic ip address", &self.name);
             cmd.push(format!("other_config:exclude_ips={}", &dhcp.exclude_ips))
         }
+        if let Some(OvnIpAddr::Subnet { ip, .. }) = &self.ipv6_prefix {
+            // OVN only takes the prefix, the length is always 64
+            cmd.push(format!("other_config:ipv6_prefix={ip}"))
+        }
         f(cmd, config).await
     }
 
@@ -85,6 +92,18 @@ mod tests {
 
     }
 
+    #[tokio::test]
+    async fn test_logical_switch_ipv6_prefix() {
+        let mut ls = LogicalSwitch::new(
+            "sw0".into(),
+            IpAddr::V4(Ipv4Addr::new(10,0,0,0)),
+            24,
+        );
+        ls.ipv6_prefix = Some(OvnIpAddr::Subnet { ip: "fd00:10::".parse().unwrap(), mask: 64 });
+        let expected_add = vec_of_strings!["ovn-nbctl", "--may-exist", "ls-add", "sw0", "--", "set", "Logical_Switch", "sw0", "other_config:subnet=10.0.0.0/24", "other_config:ipv6_prefix=fd00:10::"].join(" ");
+        assert_eq!(expected_add, ls.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap());
+    }
+
     #[tokio::test]
     async fn test_logical_switch_dhcp() {
         // test if it has DHCP option
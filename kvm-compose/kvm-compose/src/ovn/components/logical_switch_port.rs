@@ -1,12 +1,16 @@
 use std::future::Future;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use kvm_compose_schemas::kvm_compose_yaml::network::qos::Qos;
 use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType};
 use crate::orchestration::OrchestrationCommon;
 use crate::ovn::components::{MacAddress, OvnIpAddr};
 use crate::ovn::{OvnCommand};
 use crate::vec_of_strings;
 
+/// The priority of the QoS rules for switch port rate limits
+const QOS_PRIORITY: u16 = 100;
+
 /// This represents a logical switch port that has different variants, depending on what kind of
 /// port it is providing in the network
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -15,6 +19,28 @@ pub struct LogicalSwitchPort {
     pub parent_switch: String,
     pub port_type: LogicalSwitchPortType,
     pub dhcp_options_uuid: Option<u64>, // this is the hash of DhcpDatabaseEntry
+    #[serde(default)]
+    pub dhcpv6_options_uuid: Option<u64>, // this is the hash of the DHCPv6 DhcpDatabaseEntry
+    #[serde(default)]
+    pub qos: Option<LogicalSwitchPortQos>,
+}
+
+/// A rate limit on a switch port, this is added as a QoS rule on the parent switch for traffic in
+/// both directions through the port
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct LogicalSwitchPortQos {
+    pub rate_kbps: u64,
+    pub burst_kbits: Option<u64>,
+}
+
+impl LogicalSwitchPortQos {
+    /// Get the rate limit from the qos in the yaml, if it has one
+    pub fn from_qos(qos: &Qos) -> Option<Self> {
+        Some(Self {
+            rate_kbps: qos.rate_kbps?,
+            burst_kbits: qos.burst_kbits,
+        })
+    }
 }
 
 impl LogicalSwitchPort {
@@ -28,7 +54,46 @@ impl LogicalSwitchPort {
             parent_switch,
             port_type,
             dhcp_options_uuid: None,
+            dhcpv6_options_uuid: None,
+            qos: None,
+        }
+    }
+
+    /// The direction and match of the QoS rules for this port
+    fn qos_rules(&self) -> [(&'static str, String); 2] {
+        [
+            ("from-lport", format!("inport == \"{}\"", &self.name)),
+            ("to-lport", format!("outport == \"{}\"", &self.name)),
+        ]
+    }
+
+    /// The ovn-nbctl arguments to add the QoS rules for the port's rate limit, to be chained onto
+    /// another ovn-nbctl command. This is empty if the port has no rate limit.
+    pub fn qos_add_args(&self) -> Vec<String> {
+        let Some(qos) = &self.qos else {
+            return Vec::new();
+        };
+        let mut args = Vec::new();
+        for (direction, rule_match) in self.qos_rules() {
+            args.extend(vec_of_strings![
+                "--", "--may-exist", "qos-add", &self.parent_switch, direction, QOS_PRIORITY, rule_match,
+                format!("rate={}", qos.rate_kbps)
+            ]);
+            if let Some(burst) = qos.burst_kbits {
+                args.push(format!("burst={burst}"));
+            }
         }
+        args
+    }
+
+    /// The ovn-nbctl arguments to remove the QoS rules for the port's rate limit, to be chained
+    /// onto another ovn-nbctl command
+    pub fn qos_del_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (direction, rule_match) in self.qos_rules() {
+            args.extend(vec_of_strings!["--", "qos-del", &self.parent_switch, direction, QOS_PRIORITY, rule_match]);
+        }
+        args
     }
 
     pub fn to_orchestration_resource(
@@ -48,6 +113,8 @@ pub enum LogicalSwitchPortType {
         chassis_name: Option<String>,
         mac_address: MacAddress,
         provider_network_name: Option<String>, // TODO is this option or mandatory?
+        #[serde(default)]
+        ipv6: Option<OvnIpAddr>, // must be ip, for dual-stack ports with a static ip
     },
     Router {
         router_port_name: String,
@@ -73,6 +140,7 @@ impl LogicalSwitchPortType {
             chassis_name,
             mac_address,
             provider_network_name,
+            ipv6: None,
         }
     }
 
@@ -107,10 +175,14 @@ impl OvnCommand for LogicalSwitchPort {
                 ip,
                 chassis_name,
                 mac_address,
-                provider_network_name
+                provider_network_name,
+                ipv6,
             } => {
                 tracing::info!("creating LSP type internal {} on LS {}", &self.name, &self.parent_switch);
-                let ip = ip.to_string();
+                let ip = match ipv6 {
+                    Some(ipv6) => format!("{} {}", ip.to_string(), ipv6.to_string()),
+                    None => ip.to_string(),
+                };
                 let mac_address = mac_address.get_string();
                 let mut cmd = vec_of_strings![
                     "ovn-nbctl", "--may-exist", "lsp-add", &self.parent_switch, &self.name,
@@ -125,6 +197,7 @@ impl OvnCommand for LogicalSwitchPort {
                     options.push_str(&format!("chassis={chassis_name}"));
                 }
                 cmd.push(options);
+                cmd.extend(self.qos_add_args());
                 // run command
                 f(cmd, config).await
             }
@@ -134,25 +207,29 @@ impl OvnCommand for LogicalSwitchPort {
             } => {
                 tracing::info!("creating LSP type router {} on LS {}", &self.name, &self.parent_switch);
                 let mac_address = mac_address.get_string();
-                // run command
-                f(vec_of_strings![
+                let mut cmd = vec_of_strings![
                     "ovn-nbctl", "--may-exist", "lsp-add", &self.parent_switch, &self.name,
                     "--", "set", "Logical_Switch_Port", &self.name, "type=router",
                     format!("options:router-port={router_port_name}"),
                     format!("addresses=\"{mac_address}\"")
-                ], config).await
+                ];
+                cmd.extend(self.qos_add_args());
+                // run command
+                f(cmd, config).await
             }
             LogicalSwitchPortType::LocalNet {
                 provider_network_name
             } => {
                 tracing::info!("creating LSP type localnet {} on LS {}", &self.name, &self.parent_switch);
-                // run command
-                f(vec_of_strings![
+                let mut cmd = vec_of_strings![
                     "ovn-nbctl", "--may-exist", "lsp-add", &self.parent_switch, &self.name,
                     "--", "set", "Logical_Switch_Port", &self.name, "type=localnet",
                     format!("options:network_name={provider_network_name}"),
                     format!("addresses=\"unknown\"")
-                ], config).await
+                ];
+                cmd.extend(self.qos_add_args());
+                // run command
+                f(cmd, config).await
             }
         }
     }
@@ -162,7 +239,12 @@ impl OvnCommand for LogicalSwitchPort {
             F: Future<Output=anyhow::Result<String>> + Send
     {
         tracing::info!("destroying LSP type {:?} {} on LS {}", self.port_type, &self.name, &self.parent_switch);
-        f(vec_of_strings!["ovn-nbctl", "lsp-del", &self.name], config).await
+        let mut cmd = vec_of_strings!["ovn-nbctl", "lsp-del", &self.name];
+        // the QoS rules belong to the switch, so are not removed with the port
+        if self.qos.is_some() {
+            cmd.extend(self.qos_del_args());
+        }
+        f(cmd, config).await
     }
 }
 
@@ -230,6 +312,59 @@ mod tests {
         assert_eq!(delete_cmd, expected_cmd);
     }
 
+    #[tokio::test]
+    async fn test_logical_switch_port_internal_dual_stack() {
+        let mut internal = LogicalSwitchPortType::new_internal(
+            "ovs-sw0-port0".to_string(),
+            OvnIpAddr::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))),
+            None,
+            MacAddress::new("00:00:00:00:00:01".into()).unwrap(),
+            None
+        );
+        if let LogicalSwitchPortType::Internal { ipv6, .. } = &mut internal {
+            *ipv6 = Some(OvnIpAddr::Ip("fd00:10::2".parse().unwrap()));
+        }
+        let lsp = LogicalSwitchPort::new(
+            "sw0-port0".into(),
+            "sw0".into(),
+            internal,
+        );
+        let expected_cmd = vec_of_strings![
+            "ovn-nbctl", "--may-exist", "lsp-add", "sw0", "sw0-port0",
+            "--", "set", "Logical_Switch_Port", "sw0-port0",
+            "addresses=\"00:00:00:00:00:01 10.0.0.2 fd00:10::2\"", "options:"
+        ].join(" ");
+        let create_cmd = lsp.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(create_cmd, expected_cmd);
+    }
+
+    #[tokio::test]
+    async fn test_logical_switch_port_qos() {
+        let localnet = LogicalSwitchPortType::new_localnet("public".into());
+        let mut lsp = LogicalSwitchPort::new(
+            "public-port0".into(),
+            "public".into(),
+            localnet,
+        );
+        lsp.qos = Some(LogicalSwitchPortQos { rate_kbps: 1000, burst_kbits: Some(100) });
+        let expected_cmd = vec_of_strings![
+            "ovn-nbctl", "--may-exist", "lsp-add", "public", "public-port0",
+            "--", "set", "Logical_Switch_Port", "public-port0", "type=localnet",
+            "options:network_name=public", "addresses=\"unknown\"",
+            "--", "--may-exist", "qos-add", "public", "from-lport", "100", "inport == \"public-port0\"", "rate=1000", "burst=100",
+            "--", "--may-exist", "qos-add", "public", "to-lport", "100", "outport == \"public-port0\"", "rate=1000", "burst=100"
+        ].join(" ");
+        let create_cmd = lsp.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(create_cmd, expected_cmd);
+        let expected_cmd = vec_of_strings![
+            "ovn-nbctl", "lsp-del", "public-port0",
+            "--", "qos-del", "public", "from-lport", "100", "inport == \"public-port0\"",
+            "--", "qos-del", "public", "to-lport", "100", "outport == \"public-port0\""
+        ].join(" ");
+        let delete_cmd = lsp.destroy_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(delete_cmd, expected_cmd);
+    }
+
     #[tokio::test]
     async fn test_logical_switch_port_router() {
         // router type
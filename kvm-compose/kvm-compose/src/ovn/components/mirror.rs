@@ -0,0 +1,131 @@
+use std::future::Future;
+use anyhow::bail;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use kvm_compose_schemas::kvm_compose_yaml::network::mirror::MirrorDirection;
+use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType};
+use crate::orchestration::OrchestrationCommon;
+use crate::ovn::OvnCommand;
+use crate::vec_of_strings;
+
+/// This represents an OVN local mirror, the traffic of the source switch ports is copied to the
+/// sink guest's OVS interface. The sink interface is found by the `mirror-id` external id, which is
+/// set on the interface when the mirror is created, so the sink guest must be running.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OvnMirror {
+    pub name: String,
+    pub direction: MirrorDirection,
+    pub source_ports: Vec<String>,
+    /// the logical switch port of the sink guest's interface
+    pub sink_port: String,
+    pub sink_guest: String,
+    pub sink_testbed_host: String,
+}
+
+impl OvnMirror {
+    pub fn new(
+        name: String,
+        direction: MirrorDirection,
+        source_ports: Vec<String>,
+        sink_port: String,
+        sink_guest: String,
+        sink_testbed_host: String,
+    ) -> Self {
+        Self {
+            name,
+            direction,
+            source_ports,
+            sink_port,
+            sink_guest,
+            sink_testbed_host,
+        }
+    }
+
+    pub fn to_orchestration_resource(
+        &self,
+    ) -> OrchestrationResource {
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(OrchestrationResourceNetwork::Mirror(self.clone())))
+    }
+
+    /// The command to find the name of the OVS interface bound to the sink switch port
+    fn find_sink_interface_cmd(&self) -> Vec<String> {
+        vec_of_strings!["ovs-vsctl", "--bare", "--columns=name", "find", "Interface", format!("external_ids:iface-id={}", &self.sink_port)]
+    }
+}
+
+#[async_trait]
+impl OvnCommand for OvnMirror {
+    /// The config must have the sink's testbed host, the OVN commands are always run on the main
+    /// testbed host
+    async fn create_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("creating mirror {} to switch port {}", &self.name, &self.sink_port);
+        let sink_interface = f(self.find_sink_interface_cmd(), config.clone()).await?;
+        let sink_interface = sink_interface.trim();
+        if sink_interface.is_empty() {
+            bail!("could not find the OVS interface for mirror sink {}, is guest {} running?", &self.sink_port, &self.sink_guest);
+        }
+        f(vec_of_strings!["ovs-vsctl", "set", "Interface", sink_interface, format!("external_ids:mirror-id={}", &self.name)], config.clone()).await?;
+
+        let mut cmd = vec_of_strings!["ovn-nbctl", "mirror-add", &self.name, "local", &self.direction, &self.name];
+        for port in &self.source_ports {
+            cmd.extend(vec_of_strings!["--", "lsp-attach-mirror", port, &self.name]);
+        }
+        f(cmd, (None, config.1)).await
+    }
+
+    async fn destroy_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("destroying mirror {}", &self.name);
+        let mut cmd = vec_of_strings!["ovn-nbctl"];
+        for port in &self.source_ports {
+            cmd.extend(vec_of_strings!["lsp-detach-mirror", port, &self.name, "--"]);
+        }
+        cmd.extend(vec_of_strings!["mirror-del", &self.name]);
+        let res = f(cmd, (None, config.1.clone())).await?;
+
+        // the sink interface is gone if the sink guest has already been destroyed
+        let sink_interface = f(self.find_sink_interface_cmd(), config.clone()).await?;
+        let sink_interface = sink_interface.trim();
+        if !sink_interface.is_empty() {
+            f(vec_of_strings!["ovs-vsctl", "remove", "Interface", sink_interface, "external_ids", "mirror-id"], config).await?;
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ovn::test_ovn_run_cmd;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ovn_mirror() {
+        let mirror = OvnMirror::new(
+            "test-ids".into(),
+            MirrorDirection::Both,
+            vec!["test-sw0-client-0".into(), "test-sw0-server-0".into()],
+            "test-sw0-ids-0".into(),
+            "ids".into(),
+            "host1".into(),
+        );
+        let create_cmd = mirror.create_command(&test_ovn_run_cmd, (Some("host1".into()), OrchestrationCommon::default())).await.unwrap();
+        let expected_cmd = vec_of_strings![
+            "ovn-nbctl", "mirror-add", "test-ids", "local", "both", "test-ids",
+            "--", "lsp-attach-mirror", "test-sw0-client-0", "test-ids",
+            "--", "lsp-attach-mirror", "test-sw0-server-0", "test-ids"
+        ].join(" ");
+        assert_eq!(create_cmd, expected_cmd);
+        let destroy_cmd = mirror.destroy_command(&test_ovn_run_cmd, (Some("host1".into()), OrchestrationCommon::default())).await.unwrap();
+        let expected_cmd = vec_of_strings![
+            "ovn-nbctl", "lsp-detach-mirror", "test-sw0-client-0", "test-ids",
+            "--", "lsp-detach-mirror", "test-sw0-server-0", "test-ids",
+            "--", "mirror-del", "test-ids"
+        ].join(" ");
+        assert_eq!(destroy_cmd, expected_cmd);
+    }
+}
@@ -9,6 +9,10 @@ pub mod logical_router;
 pub mod logical_router_port;
 pub mod logical_switch_port;
 pub mod acl;
+pub mod mirror;
+pub mod port_group;
+pub mod address_set;
+pub mod load_balancer;
 
 /// Helper macro to convert Vec<&str> to Vec<String> to avoid having to keep writing `.to_string()`
 #[macro_export]
@@ -1,8 +1,10 @@
 use std::future::Future;
+use anyhow::bail;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType};
-use crate::orchestration::OrchestrationCommon;
+use kvm_compose_schemas::kvm_compose_yaml::testbed_options::FlowExportProtocol;
+use crate::orchestration::{is_main_testbed, OrchestrationCommon};
 use crate::ovn::components::OvnIpAddr;
 use crate::ovn::{OvnCommand};
 use crate::vec_of_strings;
@@ -89,6 +91,130 @@ impl OvnCommand for OvsPort {
     }
 }
 
+/// Flow export on the integration bridge of an OVN chassis, the bridge can only have one of each
+/// kind of flow export
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OvsFlowExport {
+    pub chassis: String,
+    pub integration_bridge_name: String,
+    pub protocol: FlowExportProtocol,
+    /// the ip and port of the collector
+    pub target: String,
+    pub sampling: u32,
+}
+
+impl OvsFlowExport {
+    pub fn new(
+        chassis: String,
+        integration_bridge_name: String,
+        protocol: FlowExportProtocol,
+        target: String,
+        sampling: u32,
+    ) -> Self {
+        Self {
+            chassis,
+            integration_bridge_name,
+            protocol,
+            target,
+            sampling,
+        }
+    }
+
+    pub fn to_orchestration_resource(
+        &self,
+    ) -> OrchestrationResource {
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(OrchestrationResourceNetwork::FlowExport(self.clone())))
+    }
+
+    /// The name of the column on the bridge and the table of the flow export record
+    fn bridge_column_and_table(&self) -> (&'static str, &'static str) {
+        match self.protocol {
+            FlowExportProtocol::Netflow => ("netflow", "NetFlow"),
+            FlowExportProtocol::Ipfix => ("ipfix", "IPFIX"),
+            FlowExportProtocol::Sflow => ("sflow", "sFlow"),
+        }
+    }
+
+    /// The uuid and owning project of the flow export of this kind on the bridge, if there is one.
+    /// The owner is empty if the flow export was not created by a testbed project.
+    async fn current_export<F>(&self, f: &(impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync), config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<Option<(String, String)>>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        let (column, table) = self.bridge_column_and_table();
+        let uuid = f(vec_of_strings!["ovs-vsctl", "get", "Bridge", &self.integration_bridge_name, column], config.clone()).await?;
+        // an empty column is shown as an empty set
+        let uuid = uuid.trim();
+        if uuid.is_empty() || uuid.eq("[]") {
+            return Ok(None);
+        }
+        let owner = f(vec_of_strings!["ovs-vsctl", "--if-exists", "get", table, uuid, "external_ids:testbedos-project"], config).await?;
+        Ok(Some((uuid.to_string(), owner.trim().trim_matches('"').to_string())))
+    }
+}
+
+#[async_trait]
+impl OvnCommand for OvsFlowExport {
+    async fn create_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("creating {} flow export to {} on chassis {}", &self.protocol, &self.target, &self.chassis);
+        let project_name = &config.1.project_name;
+        // the integration bridge is shared by every project on the testbed host
+        if let Some((_, owner)) = self.current_export(&f, config.clone()).await? {
+            if !owner.eq(project_name) {
+                bail!(
+                    "bridge {} on chassis {} already has a {} flow export owned by {}",
+                    &self.integration_bridge_name,
+                    &self.chassis,
+                    &self.protocol,
+                    if owner.is_empty() { "something other than a testbed project" } else { &owner },
+                );
+            }
+        }
+        let (column, table) = self.bridge_column_and_table();
+        // the target must be quoted for ovs-vsctl, and remote commands are run through the shell
+        // over ssh so need quoting again
+        let is_main = config.0.as_ref()
+            .map(|host| is_main_testbed(&config.1, host))
+            .unwrap_or(true);
+        let target = if is_main {
+            format!("targets=\"{}\"", &self.target)
+        } else {
+            format!("'targets=\"{}\"'", &self.target)
+        };
+        let mut cmd = vec_of_strings![
+            "ovs-vsctl", "--", "set", "Bridge", &self.integration_bridge_name, format!("{column}=@fe"),
+            "--", "--id=@fe", "create", table, target, format!("external_ids:testbedos-project={project_name}")
+        ];
+        // NetFlow exports every flow rather than sampling packets
+        if self.protocol != FlowExportProtocol::Netflow {
+            cmd.push(format!("sampling={}", self.sampling));
+        }
+        f(cmd, config).await
+    }
+
+    async fn destroy_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("destroying {} flow export on chassis {}", &self.protocol, &self.chassis);
+        let (column, _) = self.bridge_column_and_table();
+        match self.current_export(&f, config.clone()).await? {
+            Some((uuid, owner)) if owner.eq(&config.1.project_name) => {
+                // the flow export record is removed by ovsdb once the bridge no longer refers to
+                // it, and removing by uuid leaves an export that has since replaced it
+                f(vec_of_strings!["ovs-vsctl", "remove", "Bridge", &self.integration_bridge_name, column, uuid], config).await
+            }
+            _ => {
+                tracing::warn!("{} flow export on chassis {} is not owned by this project, leaving it", &self.protocol, &self.chassis);
+                Ok(String::new())
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::ovn::test_ovn_run_cmd;
@@ -113,4 +239,63 @@ mod tests {
         let expected_cmd = vec_of_strings!["ovs-vsctl", "del-port", "br-int", "ovs-port"].join(" ");
         assert_eq!(destroy_cmd, expected_cmd);
     }
-}
\ No newline at end of file
+
+    /// Runs the flow export commands against a bridge whose current export is owned by the given
+    /// project, passing any other command through
+    fn flow_export_run_cmd(
+        owner: Option<&'static str>,
+    ) -> impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> std::future::Ready<anyhow::Result<String>> + Send + Sync {
+        move |cmd, _| {
+            let res = match (cmd.get(1).map(|s| s.as_str()), owner) {
+                (Some("get"), None) => "[]\n".to_string(),
+                (Some("get"), Some(_)) => "a2bc1c9e-b5b6-4a0b-9e4d-0d4c5a2f8c11\n".to_string(),
+                (Some("--if-exists"), Some(owner)) => format!("\"{owner}\"\n"),
+                _ => cmd.join(" "),
+            };
+            std::future::ready(Ok(res))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ovs_flow_export() {
+        let common = OrchestrationCommon {
+            project_name: "test".into(),
+            ..Default::default()
+        };
+        let export = OvsFlowExport::new(
+            "ovn".into(),
+            "br-int".into(),
+            FlowExportProtocol::Ipfix,
+            "10.0.0.5:4739".into(),
+            64,
+        );
+        let create_cmd = export.create_command(flow_export_run_cmd(None), (None, common.clone())).await.unwrap();
+        let expected_cmd = vec_of_strings![
+            "ovs-vsctl", "--", "set", "Bridge", "br-int", "ipfix=@fe",
+            "--", "--id=@fe", "create", "IPFIX", "targets=\"10.0.0.5:4739\"",
+            "external_ids:testbedos-project=test", "sampling=64"
+        ].join(" ");
+        assert_eq!(create_cmd, expected_cmd);
+        let destroy_cmd = export.destroy_command(flow_export_run_cmd(Some("test")), (None, common.clone())).await.unwrap();
+        assert_eq!(destroy_cmd, "ovs-vsctl remove Bridge br-int ipfix a2bc1c9e-b5b6-4a0b-9e4d-0d4c5a2f8c11");
+
+        // the flow export of another project is neither replaced nor removed
+        assert!(export.create_command(flow_export_run_cmd(Some("other")), (None, common.clone())).await.is_err());
+        assert!(export.create_command(flow_export_run_cmd(Some("")), (None, common.clone())).await.is_err());
+        let destroy_cmd = export.destroy_command(flow_export_run_cmd(Some("other")), (None, common.clone())).await.unwrap();
+        assert_eq!(destroy_cmd, "");
+        let destroy_cmd = export.destroy_command(flow_export_run_cmd(None), (None, common.clone())).await.unwrap();
+        assert_eq!(destroy_cmd, "");
+
+        // a remote sFlow target is quoted for the shell
+        let export = OvsFlowExport::new(
+            "ovn".into(),
+            "br-int".into(),
+            FlowExportProtocol::Sflow,
+            "10.0.0.5:6343".into(),
+            128,
+        );
+        let create_cmd = export.create_command(flow_export_run_cmd(Some("test")), (Some("host2".into()), common)).await.unwrap();
+        assert!(create_cmd.ends_with("create sFlow 'targets=\"10.0.0.5:6343\"' external_ids:testbedos-project=test sampling=128"));
+    }
+}
@@ -0,0 +1,75 @@
+use std::future::Future;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType};
+use crate::orchestration::OrchestrationCommon;
+use crate::ovn::OvnCommand;
+use crate::vec_of_strings;
+
+/// This represents an OVN port group, a named set of logical switch ports that can be referenced
+/// as `@name` in ACL matches and have ACLs applied to it. OVN also creates the `$name_ip4` and
+/// `$name_ip6` address sets with the addresses of the ports. Removing the port group also removes
+/// its ACLs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LogicalPortGroup {
+    pub name: String,
+    pub ports: Vec<String>,
+}
+
+impl LogicalPortGroup {
+    pub fn new(
+        name: String,
+        ports: Vec<String>,
+    ) -> Self {
+        Self {
+            name,
+            ports,
+        }
+    }
+
+    pub fn to_orchestration_resource(
+        &self,
+    ) -> OrchestrationResource {
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(OrchestrationResourceNetwork::PortGroup(self.clone())))
+    }
+}
+
+#[async_trait]
+impl OvnCommand for LogicalPortGroup {
+    async fn create_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("creating port group {} with {} ports", &self.name, self.ports.len());
+        let mut cmd = vec_of_strings!["ovn-nbctl", "pg-add", &self.name];
+        cmd.extend(self.ports.iter().cloned());
+        f(cmd, config).await
+    }
+
+    async fn destroy_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("destroying port group {}", &self.name);
+        let cmd = vec_of_strings!["ovn-nbctl", "pg-del", &self.name];
+        f(cmd, config).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ovn::test_ovn_run_cmd;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_logical_port_group() {
+        let port_group = LogicalPortGroup::new(
+            "test_dbs".into(),
+            vec!["test-sw0-db-0-0".into(), "test-sw0-db-1-0".into()],
+        );
+        let create_cmd = port_group.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(create_cmd, "ovn-nbctl pg-add test_dbs test-sw0-db-0-0 test-sw0-db-1-0");
+        let destroy_cmd = port_group.destroy_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(destroy_cmd, "ovn-nbctl pg-del test_dbs");
+    }
+}
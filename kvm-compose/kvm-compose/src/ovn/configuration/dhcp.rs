@@ -1,9 +1,11 @@
+use std::collections::BTreeMap;
 use std::collections::hash_map::DefaultHasher;
 use std::future::Future;
 use std::hash::{Hash, Hasher};
 use anyhow::{bail, Context};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use kvm_compose_schemas::kvm_compose_yaml::network::router::Ipv6AddressMode;
 use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType};
 use crate::orchestration::OrchestrationCommon;
 use crate::ovn::components::{MacAddress, OvnIpAddr};
@@ -35,18 +37,70 @@ pub enum SwitchPortDhcpOptions {
     DhcpV4,
 }
 
+/// This represents the IPv6 router advertisement configuration of a logical router port, the
+/// address mode tells the guests on the switch whether to use SLAAC or DHCPv6.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RouterAdvertisementOptions {
+    pub address_mode: Ipv6AddressMode,
+    pub send_periodic: bool,
+}
+
+impl RouterAdvertisementOptions {
+    pub fn to_ovn_options(&self) -> Vec<String> {
+        let mut options = vec![format!("ipv6_ra_configs:address_mode={}", self.address_mode.ovn_name())];
+        if self.send_periodic {
+            options.push("ipv6_ra_configs:send_periodic=true".to_string());
+        }
+        options
+    }
+}
+
+/// The DHCP version of a `DhcpDatabaseEntry`, DHCPv6 entries only use the cidr and server mac.
+#[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq, Hash, Default)]
+pub enum DhcpVersion {
+    #[default]
+    V4,
+    V6 {
+        stateless: bool,
+    },
+}
+
 /// This represents the DHCP table entry in OVN. The UUID of this table entry is to be assigned to
 /// a logical switch port to activate the DHCP configuration for that port. The entry is based on
 /// a router's configuration, as it will be processing the DHCP requests in addition to being the
 /// default gateway for the guest on the port. This will be saved in the `OvnNetwork` as a
 /// `HashSet`.
-#[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq)]
 pub struct DhcpDatabaseEntry {
     pub cidr: OvnIpAddr, // must be a subnet
     pub lease_time: String,
     pub router: String, // ip of the router to be set as the default gateway
     pub server_id: String, // ip of the virtual dhcp server
     pub server_mac: MacAddress, // mac of the virtual dhcp server
+    #[serde(default)]
+    pub version: DhcpVersion,
+    /// further DHCPv4 options by their OVN name, with the value as OVN expects it i.e.
+    /// "dns_server" = "{10.0.0.1, 1.1.1.1}"
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+}
+
+// the switch ports in existing states store the hash of their DHCPv4 entry, so the version and
+// options are only hashed when they are set to keep the existing DHCPv4 hashes the same
+impl Hash for DhcpDatabaseEntry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.cidr.hash(state);
+        self.lease_time.hash(state);
+        self.router.hash(state);
+        self.server_id.hash(state);
+        self.server_mac.hash(state);
+        if let DhcpVersion::V6 { .. } = &self.version {
+            self.version.hash(state);
+        }
+        if !self.options.is_empty() {
+            self.options.hash(state);
+        }
+    }
 }
 
 impl DhcpDatabaseEntry {
@@ -64,6 +118,63 @@ impl DhcpDatabaseEntry {
             router,
             server_id,
             server_mac,
+            version: DhcpVersion::V4,
+            options: BTreeMap::new(),
+        }
+    }
+
+    /// Create a DHCPv6 entry for the ipv6 prefix of a switch, the server mac is the mac of the
+    /// router port on the switch
+    pub fn new_v6(
+        cidr: OvnIpAddr,
+        server_mac: MacAddress,
+        stateless: bool,
+        dns_servers: Option<&Vec<String>>,
+    ) -> Self {
+        let mut options = BTreeMap::new();
+        if let Some(dns_servers) = dns_servers {
+            options.insert("dns_server".to_string(), format!("{{{}}}", dns_servers.join(", ")));
+        }
+        Self {
+            cidr,
+            lease_time: String::new(),
+            router: String::new(),
+            server_id: String::new(),
+            server_mac,
+            version: DhcpVersion::V6 { stateless },
+            options,
+        }
+    }
+
+    /// The options column of the entry in the DHCP_Options table
+    fn ovn_options(&self) -> String {
+        match &self.version {
+            DhcpVersion::V4 => {
+                let mut options = format!(
+                    "\"lease_time\"=\"{}\" \"router\"=\"{}\" \"server_id\"=\"{}\" \"server_mac\"=\"{}\"",
+                    &self.lease_time, &self.router, &self.server_id, &self.server_mac.address,
+                );
+                // guests keep using 8.8.8.8 unless other DNS servers are given
+                if !self.options.contains_key("dns_server") {
+                    options.push_str(" \"dns_server\"=\"{8.8.8.8}\"");
+                }
+                for (name, value) in &self.options {
+                    options.push_str(&format!(" \"{name}\"=\"{}\"", value.replace('"', "\\\"")));
+                }
+                options
+            }
+            DhcpVersion::V6 { stateless } => {
+                let mut options = format!("\"server_id\"=\"{}\"", &self.server_mac.address);
+                // guests use 2001:4860:4860::8888 unless the switch gives other DNS servers
+                let dns_server = self.options.get("dns_server")
+                    .map(String::as_str)
+                    .unwrap_or("{2001:4860:4860::8888}");
+                options.push_str(&format!(" \"dns_server\"=\"{dns_server}\""));
+                if *stateless {
+                    options.push_str(" \"dhcpv6_stateless\"=\"true\"");
+                }
+                options
+            }
         }
     }
 
@@ -76,17 +187,13 @@ impl DhcpDatabaseEntry {
 
 fn get_rule_uuid_cmd(
     cidr: &String,
-    lease: &String,
-    router: &String,
-    server_id: &String,
-    mac: &String,
+    options: &String,
     project: &String,
-    dns: &String,
 ) -> Vec<String> {
     vec_of_strings![
         "ovn-nbctl", "--bare", "--columns=_uuid", "find", "dhcp_options",
         format!("cidr=\"{cidr}\""),
-        format!("options=\"lease_time\"=\"{lease}\" \"router\"=\"{router}\" \"server_id\"=\"{server_id}\" \"server_mac\"=\"{mac}\" \"dns_server\"=\"{dns}\""),
+        format!("options={options}"),
         format!("external_ids:testbedos-project={project}")
     ]
 }
@@ -121,10 +228,12 @@ impl OvnCommand for DhcpDatabaseEntry {
         let dhcp_self_hash = s.finish();
 
         // get the switch ports that have this rule
+        let is_v6 = matches!(self.version, DhcpVersion::V6 { .. });
         let switch_ports = {
             let mut dynamic_lsp = Vec::new();
             for (_, lsp) in switch_ports_hashmap {
-                if let Some(dhcp_uuid) = lsp.dhcp_options_uuid {
+                let lsp_dhcp_uuid = if is_v6 { lsp.dhcpv6_options_uuid } else { lsp.dhcp_options_uuid };
+                if let Some(dhcp_uuid) = lsp_dhcp_uuid {
                     if dhcp_uuid == dhcp_self_hash {
                         // this switch port matches the internal UUID of the database rule
                         dynamic_lsp.push(lsp);
@@ -140,8 +249,7 @@ impl OvnCommand for DhcpDatabaseEntry {
         tracing::info!("creating DHCP Options database rule cidr: {} router: {}", &self.cidr.to_string(), &self.router);
         let rule_create_res = f(vec_of_strings![
             "ovn-nbctl", "create", "dhcp_options", format!("cidr={}", self.cidr.to_string()),
-            format!("options=\"lease_time\"=\"{}\" \"router\"=\"{}\" \"server_id\"=\"{}\" \"server_mac\"=\"{}\" \"dns_server\"=\"{}\"",
-                &self.lease_time, &self.router, &self.server_id, &self.server_mac.address.to_string(), "{8.8.8.8}"),
+            format!("options={}", self.ovn_options()),
             &external_ids
 
         ], config.clone()).await;
@@ -159,7 +267,8 @@ impl OvnCommand for DhcpDatabaseEntry {
         };
         // take the rule uuid and add to every switch port
         for lsp in switch_ports {
-            let cmd = vec_of_strings!["ovn-nbctl", "lsp-set-dhcpv4-options", lsp.name, rule_uuid.clone()];
+            let set_options = if is_v6 { "lsp-set-dhcpv6-options" } else { "lsp-set-dhcpv4-options" };
+            let cmd = vec_of_strings!["ovn-nbctl", set_options, lsp.name, rule_uuid.clone()];
             f(cmd, config.clone()).await?;
         }
 
@@ -175,12 +284,8 @@ impl OvnCommand for DhcpDatabaseEntry {
         // lookup the dhcp_options table
         let uuid_lookup_cmd = f(get_rule_uuid_cmd(
             &self.cidr.to_string(),
-            &self.lease_time,
-            &self.router,
-            &self.server_id,
-            &self.server_mac.address.to_string(),
+            &self.ovn_options(),
             &config.1.project_name,
-            &"{8.8.8.8}".to_string(),
         ), config.clone()).await;
         let rule_uuid = match uuid_lookup_cmd {
             Ok(ok) => ok,
@@ -218,4 +323,22 @@ impl OvnCommand for DhcpDatabaseEntry {
         // remove rule
         Ok("done".into())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dhcpv6_dns_servers() {
+        let cidr = OvnIpAddr::Subnet { ip: "fd00:10::".parse().unwrap(), mask: 64 };
+        let mac = MacAddress::new("00:00:00:00:ff:01".into()).unwrap();
+        let dhcp = DhcpDatabaseEntry::new_v6(cidr.clone(), mac.clone(), false, None);
+        assert_eq!(dhcp.ovn_options(), r#""server_id"="00:00:00:00:ff:01" "dns_server"="{2001:4860:4860::8888}""#);
+        let dns_servers = vec!["fd00:10::53".to_string(), "2606:4700:4700::1111".to_string()];
+        let dhcp = DhcpDatabaseEntry::new_v6(cidr, mac, true, Some(&dns_servers));
+        assert_eq!(
+            dhcp.ovn_options(),
+            r#""server_id"="00:00:00:00:ff:01" "dns_server"="{fd00:10::53, 2606:4700:4700::1111}" "dhcpv6_stateless"="true""#
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::net::IpAddr;
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType};
+use crate::orchestration::OrchestrationCommon;
+use crate::ovn::OvnCommand;
+use crate::vec_of_strings;
+
+/// The resolver guests fall back to for names that are not guests
+pub const UPSTREAM_NAMESERVER: &str = "1.1.1.1";
+
+/// The nameservers given to a guest interface. OVN answers the DNS queries for guest names sent
+/// to any address, so the gateway is only first so that the queries leave the guest, other names
+/// are resolved by the upstream resolver.
+pub fn guest_nameservers(gateway: Option<&String>) -> Vec<String> {
+    gateway.into_iter()
+        .cloned()
+        .chain([UPSTREAM_NAMESERVER.to_string()])
+        .collect()
+}
+
+/// An address of a DNS record, dynamic addresses are only known once OVN has assigned them to the
+/// switch port so they are looked up when the records are created
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub enum DnsRecordAddress {
+    Ip(IpAddr),
+    Dynamic {
+        switch_port: String,
+    },
+}
+
+/// This represents the entry in the OVN DNS table for a logical switch, OVN replies to the DNS
+/// queries from guests on the switch for these names. The entry is not removed with the switch, so
+/// it is found by its external ids to remove it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OvnDnsRecords {
+    pub switch: String,
+    /// hostname to its addresses
+    pub records: BTreeMap<String, Vec<DnsRecordAddress>>,
+}
+
+impl OvnDnsRecords {
+    pub fn new(
+        switch: String,
+        records: BTreeMap<String, Vec<DnsRecordAddress>>,
+    ) -> Self {
+        Self {
+            switch,
+            records,
+        }
+    }
+
+    /// The switch ports with dynamic addresses that the records use
+    pub fn dynamic_switch_ports(&self) -> impl Iterator<Item = &String> {
+        self.records.values()
+            .flatten()
+            .filter_map(|address| match address {
+                DnsRecordAddress::Ip(_) => None,
+                DnsRecordAddress::Dynamic { switch_port } => Some(switch_port),
+            })
+    }
+
+    pub fn to_orchestration_resource(
+        &self,
+    ) -> OrchestrationResource {
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(OrchestrationResourceNetwork::DnsRecords(self.clone())))
+    }
+}
+
+/// Get the ipv4 address from the `dynamic_addresses` of a switch port, which is "mac ip" or
+/// "mac ip ipv6" if the switch has an ipv6 prefix
+fn parse_dynamic_ipv4(dynamic_addresses: &str) -> Option<IpAddr> {
+    dynamic_addresses.split_whitespace()
+        .nth(1)
+        .and_then(|ip| ip.parse().ok())
+}
+
+#[async_trait]
+impl OvnCommand for OvnDnsRecords {
+    async fn create_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("creating DNS records for {} guests on switch {}", self.records.len(), &self.switch);
+        // the dynamic addresses are assigned by northd, so wait for it to catch up before reading
+        // them
+        if self.dynamic_switch_ports().next().is_some() {
+            f(vec_of_strings!["ovn-nbctl", "--wait=sb", "sync"], config.clone()).await?;
+        }
+        let mut records = Vec::new();
+        for (hostname, addresses) in &self.records {
+            let mut ips = Vec::new();
+            for address in addresses {
+                match address {
+                    DnsRecordAddress::Ip(ip) => ips.push(ip.to_string()),
+                    DnsRecordAddress::Dynamic { switch_port } => {
+                        let res = f(vec_of_strings![
+                            "ovn-nbctl", "--bare", "--columns=dynamic_addresses", "find", "Logical_Switch_Port", format!("name={switch_port}")
+                        ], config.clone()).await?;
+                        let ip = parse_dynamic_ipv4(&res)
+                            .context(format!("could not get the dynamic ip for lsp {switch_port} as result from NB DB was {res}"))?;
+                        ips.push(ip.to_string());
+                    }
+                }
+            }
+            records.push(format!("\"{hostname}\"=\"{}\"", ips.join(" ")));
+        }
+        if records.is_empty() {
+            bail!("there are no DNS records for switch {}", &self.switch);
+        }
+
+        let cmd = vec_of_strings![
+            "ovn-nbctl", "--id=@dns", "create", "DNS",
+            format!("records={{{}}}", records.join(", ")),
+            format!("external_ids:testbedos-project={}", &config.1.project_name),
+            format!("external_ids:testbedos-switch={}", &self.switch),
+            "--", "add", "Logical_Switch", &self.switch, "dns_records", "@dns"
+        ];
+        f(cmd, config).await
+    }
+
+    async fn destroy_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("destroying DNS records on switch {}", &self.switch);
+        let uuids = f(vec_of_strings![
+            "ovn-nbctl", "--bare", "--columns=_uuid", "find", "DNS", format!("external_ids:testbedos-switch={}", &self.switch)
+        ], config.clone()).await?;
+        // removing the entry also removes it from the switch
+        for uuid in uuids.split_whitespace() {
+            f(vec_of_strings!["ovn-nbctl", "destroy", "DNS", uuid], config.clone()).await?;
+        }
+        Ok("done".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ovn::test_ovn_run_cmd;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dns_records() {
+        let dns = OvnDnsRecords::new(
+            "test-sw0".into(),
+            BTreeMap::from([
+                ("db-0".to_string(), vec![DnsRecordAddress::Ip("10.0.1.10".parse().unwrap())]),
+                ("web".to_string(), vec![DnsRecordAddress::Ip("10.0.0.10".parse().unwrap()), DnsRecordAddress::Ip("fd00::10".parse().unwrap())]),
+            ]),
+        );
+        let common = OrchestrationCommon { project_name: "test".into(), ..Default::default() };
+        let create_cmd = dns.create_command(&test_ovn_run_cmd, (None, common)).await.unwrap();
+        assert_eq!(create_cmd, r#"ovn-nbctl --id=@dns create DNS records={"db-0"="10.0.1.10", "web"="10.0.0.10 fd00::10"} external_ids:testbedos-project=test external_ids:testbedos-switch=test-sw0 -- add Logical_Switch test-sw0 dns_records @dns"#);
+    }
+
+    #[test]
+    fn test_parse_dynamic_ipv4() {
+        assert_eq!(parse_dynamic_ipv4("0a:00:00:00:00:01 10.0.0.5\n"), Some("10.0.0.5".parse().unwrap()));
+        assert_eq!(parse_dynamic_ipv4("0a:00:00:00:00:01 10.0.0.5 fd00::5"), Some("10.0.0.5".parse().unwrap()));
+        assert_eq!(parse_dynamic_ipv4(""), None);
+    }
+}
@@ -2,3 +2,4 @@ pub mod nat;
 pub mod route;
 pub mod external_gateway;
 pub mod dhcp;
+pub mod dns;
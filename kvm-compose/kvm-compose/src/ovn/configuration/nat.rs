@@ -41,16 +41,20 @@ impl OvnNat {
         logical_ip: OvnIpAddr,
         nat_type: OvnNatType,
     ) -> anyhow::Result<Self> {
-        let external_ip = match external_ip {
-            OvnIpAddr::Ip(_) => external_ip,
+        let external = match &external_ip {
+            OvnIpAddr::Ip(ip) => *ip,
             OvnIpAddr::Dynamic => bail!("cannot be dynamic ip"),
             OvnIpAddr::Subnet { .. } => bail!("cannot be subnet ip"),
         };
-        let logical_ip = match logical_ip {
-            OvnIpAddr::Ip(_) => logical_ip,
+        let logical = match &logical_ip {
+            OvnIpAddr::Ip(ip) => *ip,
             OvnIpAddr::Dynamic => bail!("cannot be dynamic ip"),
-            OvnIpAddr::Subnet { .. } => logical_ip,
+            OvnIpAddr::Subnet { ip, .. } => *ip,
         };
+        // OVN supports NAT44 and NAT66, but not translating between ipv4 and ipv6
+        if external.is_ipv4() != logical.is_ipv4() {
+            bail!("the external ip {external} and logical ip {} must both be ipv4 or both be ipv6", logical_ip.to_string());
+        }
         Ok(Self {
             logical_router_name,
             external_ip,
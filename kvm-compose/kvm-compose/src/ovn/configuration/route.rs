@@ -24,11 +24,14 @@ impl OvnRoute {
         prefix: OvnIpAddr,
         next_hop: IpAddr,
     ) -> anyhow::Result<Self> {
-        let prefix = match prefix {
-            OvnIpAddr::Ip(_) => prefix,
+        let prefix_ip = match &prefix {
+            OvnIpAddr::Ip(ip) => *ip,
             OvnIpAddr::Dynamic => bail!("cannot be dynamic ip"),
-            OvnIpAddr::Subnet { .. } => prefix,
+            OvnIpAddr::Subnet { ip, .. } => *ip,
         };
+        if prefix_ip.is_ipv4() != next_hop.is_ipv4() {
+            bail!("the prefix {} and next hop {next_hop} must both be ipv4 or both be ipv6", prefix.to_string());
+        }
         Ok(Self {
             router_name,
             prefix,
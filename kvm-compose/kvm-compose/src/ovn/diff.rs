@@ -0,0 +1,568 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use anyhow::Context;
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+use crate::orchestration::api::{OrchestrationInstruction, OrchestrationProtocol, OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType};
+use crate::orchestration::websocket::send_orchestration_instruction_over_channel;
+use crate::ovn::components::acl::ACLRecordType;
+use crate::ovn::components::logical_switch_port::LogicalSwitchPort;
+use crate::ovn::components::mirror::OvnMirror;
+use crate::ovn::configuration::dhcp::DhcpDatabaseEntry;
+use crate::ovn::configuration::external_gateway::OvnExternalGateway;
+use crate::ovn::configuration::nat::OvnNat;
+use crate::ovn::configuration::route::OvnRoute;
+use crate::ovn::ovn::OvnNetwork;
+
+/// This is the difference between two `OvnNetwork`s, as the list of resources that need to be
+/// destroyed and the list of resources that need to be created to turn the old network into the
+/// new network. A resource that has changed is destroyed using the old definition and created
+/// using the new definition.
+///
+/// Removing a switch or router in OVN also removes everything that belongs to it, so when a parent
+/// is recreated so are its children i.e. a changed switch also recreates its ports and ACL rules.
+/// DHCP rules are only linked to the switch ports that exist when the rule is created, so any rule
+/// used by a switch port that is created will also be recreated, and the same goes for mirrors and
+/// port groups. A changed rule is recreated on its own, as it links itself to the ports again.
+#[derive(Debug, Default, Clone)]
+pub struct OvnNetworkDiff {
+    /// resources to destroy, in the order they must be destroyed
+    pub destroy: Vec<OrchestrationResourceNetwork>,
+    /// resources to create, in the order they must be created
+    pub create: Vec<OrchestrationResourceNetwork>,
+}
+
+impl OvnNetworkDiff {
+    pub fn new(old: &OvnNetwork, new: &OvnNetwork) -> Self {
+        // components
+        let switches = diff_resources(
+            old.switches.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.switches.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |_| false,
+        );
+        // the DHCP rules are linked to the switch ports when the rules are created, so a switch
+        // port is not recreated when only the rule it uses has changed
+        let old_switch_ports = switch_ports_without_dhcp(old);
+        let new_switch_ports = switch_ports_without_dhcp(new);
+        let mut switch_ports = diff_resources(
+            old_switch_ports.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new_switch_ports.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |lsp| switches.destroyed.contains(&lsp.parent_switch),
+        );
+        for lsp in switch_ports.destroy.iter_mut() {
+            *lsp = &old.switch_ports[&lsp.name];
+        }
+        for lsp in switch_ports.create.iter_mut() {
+            *lsp = &new.switch_ports[&lsp.name];
+        }
+        // the router's own configuration is compared separately below
+        let routers = diff_resources(
+            old.routers.iter().map(|(k, v)| (k.clone(), &v.name)).collect(),
+            new.routers.iter().map(|(k, v)| (k.clone(), &v.name)).collect(),
+            |_| false,
+        );
+        let router_ports = diff_resources(
+            old.router_ports.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.router_ports.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |lrp| routers.destroyed.contains(&lrp.parent_router),
+        );
+        let ovs_ports = diff_resources(
+            old.ovs_ports.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.ovs_ports.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |_| false,
+        );
+        let flow_exports = diff_resources(
+            old.flow_exports.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.flow_exports.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |_| false,
+        );
+        // removing a switch port also removes it from its port groups
+        let port_groups = diff_resources(
+            old.port_groups.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.port_groups.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |pg| pg.ports.iter().any(|port| switch_ports.destroyed.contains(port)),
+        );
+        let address_sets = diff_resources(
+            old.address_sets.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.address_sets.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |_| false,
+        );
+        let acl = diff_resources(
+            old.acl.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.acl.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |acl| match acl._type {
+                ACLRecordType::Switch => switches.destroyed.contains(&acl.entity_name),
+                ACLRecordType::PortGroup => port_groups.destroyed.contains(&acl.entity_name),
+            },
+        );
+        let mirrors = diff_resources(
+            old.mirrors.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.mirrors.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |mirror| mirror.source_ports.iter()
+                .chain([&mirror.sink_port])
+                .any(|port| switch_ports.destroyed.contains(port)),
+        );
+        // removing a switch or router only detaches the load balancer, so it is recreated to
+        // attach it again
+        let load_balancers = diff_resources(
+            old.load_balancers.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.load_balancers.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |lb| lb.switches.iter().any(|switch| switches.destroyed.contains(switch))
+                || lb.routers.iter().any(|router| routers.destroyed.contains(router)),
+        );
+        // the DNS records are not removed with their switch, and dynamic addresses may change when
+        // their switch port is recreated
+        let dns_records = diff_resources(
+            old.dns_records.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            new.dns_records.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            |dns| switches.destroyed.contains(&dns.switch)
+                || dns.dynamic_switch_ports().any(|port| switch_ports.destroyed.contains(port)),
+        );
+
+        // router configuration
+        let routes = diff_resources(
+            get_routes(old),
+            get_routes(new),
+            |route| routers.destroyed.contains(&route.router_name),
+        );
+        let external_gateways = diff_resources(
+            get_external_gateways(old),
+            get_external_gateways(new),
+            |ext| router_ports.destroyed.contains(&ext.router_port_name),
+        );
+        let nat = diff_resources(
+            get_nat(old),
+            get_nat(new),
+            |nat| routers.destroyed.contains(&nat.logical_router_name),
+        );
+
+        // database entries, these are compared by their hash as that is how switch ports refer to
+        // them, a changed rule will have a new hash
+        let created_dhcp_hashes: HashSet<u64> = switch_ports.create.iter()
+            .flat_map(|lsp| [lsp.dhcp_options_uuid, lsp.dhcpv6_options_uuid])
+            .flatten()
+            .collect();
+        let dhcp = diff_resources(
+            old.dhcp_options.iter().map(|d| (dhcp_entry_hash(d).to_string(), d)).collect(),
+            new.dhcp_options.iter().map(|d| (dhcp_entry_hash(d).to_string(), d)).collect(),
+            |d| created_dhcp_hashes.contains(&dhcp_entry_hash(d)),
+        );
+
+        // this is the same order as the destroy and create actions for the whole network
+        let mut destroy = Vec::new();
+        destroy.extend(dns_records.destroy.into_iter().map(|r| OrchestrationResourceNetwork::DnsRecords(r.clone())));
+        destroy.extend(load_balancers.destroy.into_iter().map(|r| OrchestrationResourceNetwork::LoadBalancer(r.clone())));
+        destroy.extend(mirrors.destroy.into_iter().map(|r| OrchestrationResourceNetwork::Mirror(r.clone())));
+        destroy.extend(acl.destroy.into_iter().map(|r| OrchestrationResourceNetwork::ACL(r.clone())));
+        destroy.extend(port_groups.destroy.into_iter().map(|r| OrchestrationResourceNetwork::PortGroup(r.clone())));
+        destroy.extend(address_sets.destroy.into_iter().map(|r| OrchestrationResourceNetwork::AddressSet(r.clone())));
+        destroy.extend(dhcp.destroy.into_iter().map(|r| OrchestrationResourceNetwork::DhcpOption(r.clone())));
+        destroy.extend(routes.destroy.into_iter().map(|r| OrchestrationResourceNetwork::Route(r.clone())));
+        destroy.extend(external_gateways.destroy.into_iter().map(|r| OrchestrationResourceNetwork::ExternalGateway(r.clone())));
+        destroy.extend(nat.destroy.into_iter().map(|r| OrchestrationResourceNetwork::Nat(r.clone())));
+        destroy.extend(switch_ports.destroy.into_iter().map(|r| OrchestrationResourceNetwork::SwitchPort(r.clone())));
+        destroy.extend(router_ports.destroy.into_iter().map(|r| OrchestrationResourceNetwork::RouterPort(r.clone())));
+        destroy.extend(switches.destroy.into_iter().map(|r| OrchestrationResourceNetwork::Switch(r.clone())));
+        destroy.extend(routers.destroyed.iter().map(|name| OrchestrationResourceNetwork::Router(old.routers[name].clone())));
+        destroy.extend(ovs_ports.destroy.into_iter().map(|r| OrchestrationResourceNetwork::OvsPort(r.clone())));
+        destroy.extend(flow_exports.destroy.into_iter().map(|r| OrchestrationResourceNetwork::FlowExport(r.clone())));
+
+        let mut create = Vec::new();
+        create.extend(switches.create.into_iter().map(|r| OrchestrationResourceNetwork::Switch(r.clone())));
+        create.extend(switch_ports.create.into_iter().map(|r| OrchestrationResourceNetwork::SwitchPort(r.clone())));
+        create.extend(routers.created.iter().map(|name| OrchestrationResourceNetwork::Router(new.routers[name].clone())));
+        create.extend(router_ports.create.into_iter().map(|r| OrchestrationResourceNetwork::RouterPort(r.clone())));
+        create.extend(ovs_ports.create.into_iter().map(|r| OrchestrationResourceNetwork::OvsPort(r.clone())));
+        create.extend(flow_exports.create.into_iter().map(|r| OrchestrationResourceNetwork::FlowExport(r.clone())));
+        create.extend(routes.create.into_iter().map(|r| OrchestrationResourceNetwork::Route(r.clone())));
+        create.extend(external_gateways.create.into_iter().map(|r| OrchestrationResourceNetwork::ExternalGateway(r.clone())));
+        create.extend(nat.create.into_iter().map(|r| OrchestrationResourceNetwork::Nat(r.clone())));
+        create.extend(dhcp.create.into_iter().map(|r| OrchestrationResourceNetwork::DhcpOption(r.clone())));
+        create.extend(address_sets.create.into_iter().map(|r| OrchestrationResourceNetwork::AddressSet(r.clone())));
+        create.extend(port_groups.create.into_iter().map(|r| OrchestrationResourceNetwork::PortGroup(r.clone())));
+        create.extend(acl.create.into_iter().map(|r| OrchestrationResourceNetwork::ACL(r.clone())));
+        create.extend(mirrors.create.into_iter().map(|r| OrchestrationResourceNetwork::Mirror(r.clone())));
+        create.extend(load_balancers.create.into_iter().map(|r| OrchestrationResourceNetwork::LoadBalancer(r.clone())));
+        create.extend(dns_records.create.into_iter().map(|r| OrchestrationResourceNetwork::DnsRecords(r.clone())));
+
+        Self {
+            destroy,
+            create,
+        }
+    }
+
+    /// Recreate the mirrors that are in both networks and match the predicate, if they are not
+    /// already being recreated. This is needed when the sink guest is recreated, as the mirror is
+    /// bound to the sink guest's interface rather than to its switch port.
+    pub fn recreate_mirrors(&mut self, old: &OvnNetwork, new: &OvnNetwork, f: impl Fn(&OvnMirror) -> bool) {
+        for (name, new_mirror) in new.mirrors.iter() {
+            let Some(old_mirror) = old.mirrors.get(name) else { continue };
+            let already_created = self.create.iter()
+                .any(|r| matches!(r, OrchestrationResourceNetwork::Mirror(m) if m.name.eq(name)));
+            if f(new_mirror) && !already_created {
+                self.destroy.insert(0, OrchestrationResourceNetwork::Mirror(old_mirror.clone()));
+                self.create.push(OrchestrationResourceNetwork::Mirror(new_mirror.clone()));
+            }
+        }
+    }
+
+    /// Return true if the two networks are the same
+    pub fn is_empty(&self) -> bool {
+        self.destroy.is_empty() && self.create.is_empty()
+    }
+
+    /// Keep only the resources that match the predicate in both lists
+    pub fn retain(&mut self, f: impl Fn(&OrchestrationResourceNetwork) -> bool) {
+        self.destroy.retain(&f);
+        self.create.retain(&f);
+    }
+
+    /// Get the destroy list as orchestration resources
+    pub fn destroy_resources(&self) -> Vec<OrchestrationResource> {
+        self.destroy.iter()
+            .map(|r| OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(r.clone())))
+            .collect()
+    }
+
+    /// Get the create list as orchestration resources
+    pub fn create_resources(&self) -> Vec<OrchestrationResource> {
+        self.create.iter()
+            .map(|r| OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(r.clone())))
+            .collect()
+    }
+
+    /// Request the server to destroy the resources in the destroy list. Each resource is sent on
+    /// its own, as batched instructions are run concurrently and the order matters here.
+    pub async fn request_destroy_action(&self, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
+        for resource in self.destroy_resources() {
+            let name = resource.name();
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::Destroy(vec![resource]),
+            ).await.with_context(|| format!("requesting the destruction of {name}"))?;
+        }
+        Ok(())
+    }
+
+    /// Request the server to create the resources in the create list. Each resource is sent on its
+    /// own, as batched instructions are run concurrently and the order matters here.
+    pub async fn request_create_action(&self, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
+        for resource in self.create_resources() {
+            let name = resource.name();
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::Deploy(vec![resource]),
+            ).await.with_context(|| format!("requesting the creation of {name}"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of comparing one kind of resource, keyed by the resource's name
+struct ResourceDiff<'a, T> {
+    destroy: Vec<&'a T>,
+    create: Vec<&'a T>,
+    destroyed: BTreeSet<String>,
+    created: BTreeSet<String>,
+}
+
+/// Compare the old and new resources by name. A resource is recreated if its definition has
+/// changed, or if `parent_recreated` returns true for either definition. The definitions are
+/// compared in their serialised form as the OVN structs do not implement `PartialEq`.
+fn diff_resources<'a, T: Serialize>(
+    old: BTreeMap<String, &'a T>,
+    new: BTreeMap<String, &'a T>,
+    parent_recreated: impl Fn(&T) -> bool,
+) -> ResourceDiff<'a, T> {
+    let mut diff = ResourceDiff {
+        destroy: Vec::new(),
+        create: Vec::new(),
+        destroyed: BTreeSet::new(),
+        created: BTreeSet::new(),
+    };
+    for (name, old_resource) in old.iter() {
+        let recreate = match new.get(name) {
+            None => {
+                diff.destroy.push(*old_resource);
+                diff.destroyed.insert(name.clone());
+                continue;
+            }
+            Some(new_resource) => {
+                serde_json::to_value(old_resource).ok() != serde_json::to_value(new_resource).ok()
+                    || parent_recreated(old_resource)
+                    || parent_recreated(new_resource)
+            }
+        };
+        if recreate {
+            diff.destroy.push(*old_resource);
+            diff.destroyed.insert(name.clone());
+            diff.create.push(new[name]);
+            diff.created.insert(name.clone());
+        }
+    }
+    for (name, new_resource) in new.iter() {
+        if !old.contains_key(name) {
+            diff.create.push(*new_resource);
+            diff.created.insert(name.clone());
+        }
+    }
+    diff
+}
+
+fn get_routes(ovn: &OvnNetwork) -> BTreeMap<String, &OvnRoute> {
+    ovn.routers.values()
+        .flat_map(|router| router.routing.0.iter())
+        .map(|((router, prefix, next_hop), route)| (format!("{router}/{prefix}/{next_hop}"), route))
+        .collect()
+}
+
+fn get_external_gateways(ovn: &OvnNetwork) -> BTreeMap<String, &OvnExternalGateway> {
+    ovn.routers.values()
+        .flat_map(|router| router.external_gateway.0.iter()
+            .map(move |((router_port, chassis), ext)| (format!("{}/{router_port}/{chassis}", &router.name), ext)))
+        .collect()
+}
+
+fn get_nat(ovn: &OvnNetwork) -> BTreeMap<String, &OvnNat> {
+    ovn.routers.values()
+        .flat_map(|router| router.nat.0.iter())
+        .map(|((router, nat_type, external_ip), nat)| (format!("{router}/{nat_type}/{external_ip}"), nat))
+        .collect()
+}
+
+/// The switch ports with the links to their DHCP rules removed
+fn switch_ports_without_dhcp(ovn: &OvnNetwork) -> BTreeMap<String, LogicalSwitchPort> {
+    ovn.switch_ports.iter()
+        .map(|(name, lsp)| {
+            let mut lsp = lsp.clone();
+            lsp.dhcp_options_uuid = None;
+            lsp.dhcpv6_options_uuid = None;
+            (name.clone(), lsp)
+        })
+        .collect()
+}
+
+/// The switch ports store the hash of the `DhcpDatabaseEntry` they are linked to
+pub fn dhcp_entry_hash(dhcp: &DhcpDatabaseEntry) -> u64 {
+    let mut s = DefaultHasher::new();
+    dhcp.hash(&mut s);
+    s.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use kvm_compose_schemas::kvm_compose_yaml::network::acl::{ACLAction, ACLDirection, ACLRule};
+    use crate::ovn::components::{MacAddress, OvnIpAddr};
+    use crate::ovn::components::address_set::LogicalAddressSet;
+    use crate::ovn::components::logical_switch_port::LogicalSwitchPortQos;
+    use kvm_compose_schemas::kvm_compose_yaml::network::load_balancer::LoadBalancerProtocol;
+    use crate::ovn::components::load_balancer::LogicalLoadBalancer;
+    use crate::ovn::components::port_group::LogicalPortGroup;
+    use super::*;
+
+    fn network() -> OvnNetwork {
+        let mut ovn = OvnNetwork::new();
+        ovn.add_switch("sw0".into(), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 24).unwrap();
+        ovn.add_lsp_internal(
+            "sw0-port0".into(),
+            "sw0".into(),
+            "ovs-sw0-port0".into(),
+            OvnIpAddr::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))),
+            Some("ovn".into()),
+            MacAddress::new("00:00:00:00:00:01".into()).unwrap(),
+            None,
+        ).unwrap();
+        ovn.add_router("lr0".into()).unwrap();
+        ovn.add_lrp(
+            "lr0-sw0".into(),
+            "lr0".into(),
+            MacAddress::new("00:00:00:00:ff:01".into()).unwrap(),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            24,
+            None,
+        ).unwrap();
+        ovn
+    }
+
+    fn names(resources: Vec<OrchestrationResource>) -> Vec<String> {
+        resources.iter().map(|r| r.name()).collect()
+    }
+
+    #[test]
+    fn test_diff_no_change() {
+        let diff = OvnNetworkDiff::new(&network(), &network());
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_diff_route_added() {
+        let old = network();
+        let mut new = network();
+        new.lr_route_add(
+            "lr0".into(),
+            OvnIpAddr::Subnet { ip: IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), mask: 0 },
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 254)),
+        ).unwrap();
+        let diff = OvnNetworkDiff::new(&old, &new);
+        // only the route is created, the router is left alone
+        assert!(diff.destroy.is_empty());
+        assert_eq!(diff.create.len(), 1);
+        assert!(matches!(diff.create[0], OrchestrationResourceNetwork::Route(_)));
+    }
+
+    #[test]
+    fn test_diff_switch_changed_recreates_children() {
+        let old = network();
+        let mut new = network();
+        new.switches.get_mut("sw0").unwrap().subnet = OvnIpAddr::Subnet { ip: IpAddr::V4(Ipv4Addr::new(10, 0, 5, 0)), mask: 24 };
+        let diff = OvnNetworkDiff::new(&old, &new);
+        // children are destroyed before parents, and parents are created before children
+        assert_eq!(names(diff.destroy_resources()), vec![
+            "Ovn Logical Switch Port sw0-port0".to_string(),
+            "Ovn Logical Switch sw0".to_string(),
+        ]);
+        assert_eq!(names(diff.create_resources()), vec![
+            "Ovn Logical Switch sw0".to_string(),
+            "Ovn Logical Switch Port sw0-port0".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_diff_switch_changed_recreates_load_balancer() {
+        let balanced = |subnet| {
+            let mut ovn = network();
+            ovn.switches.get_mut("sw0").unwrap().subnet = OvnIpAddr::Subnet { ip: IpAddr::V4(subnet), mask: 24 };
+            ovn.add_load_balancer(LogicalLoadBalancer::new(
+                "web".into(),
+                "10.0.0.100:80".into(),
+                vec!["10.0.0.2:8080".into()],
+                LoadBalancerProtocol::Tcp,
+                vec!["sw0".into()],
+                vec![],
+            )).unwrap();
+            ovn
+        };
+        let old = balanced(Ipv4Addr::new(10, 0, 0, 0));
+        let new = balanced(Ipv4Addr::new(10, 0, 5, 0));
+        let diff = OvnNetworkDiff::new(&old, &new);
+        // the load balancer is only detached from the removed switch, so it is recreated to attach
+        // it to the new switch
+        assert_eq!(names(diff.destroy_resources())[0], "Ovn Load Balancer web for 10.0.0.100:80 with 1 backends");
+        assert_eq!(names(diff.create_resources()).last().unwrap(), "Ovn Load Balancer web for 10.0.0.100:80 with 1 backends");
+    }
+
+    #[test]
+    fn test_diff_router_port_removed() {
+        let old = network();
+        let mut new = network();
+        new.router_ports.remove("lr0-sw0");
+        let diff = OvnNetworkDiff::new(&old, &new);
+        assert!(diff.create.is_empty());
+        assert_eq!(names(diff.destroy_resources()), vec!["Ovn Logical Router Port lr0-sw0".to_string()]);
+    }
+
+    #[test]
+    fn test_diff_port_changed_recreates_mirror() {
+        let mirrored = || {
+            let mut ovn = network();
+            ovn.add_lsp_internal(
+                "sw0-port1".into(),
+                "sw0".into(),
+                "ovs-sw0-port1".into(),
+                OvnIpAddr::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3))),
+                Some("ovn".into()),
+                MacAddress::new("00:00:00:00:00:02".into()).unwrap(),
+                None,
+            ).unwrap();
+            ovn.add_mirror(OvnMirror::new(
+                "ids".into(),
+                Default::default(),
+                vec!["sw0-port0".into()],
+                "sw0-port1".into(),
+                "ids".into(),
+                "host1".into(),
+            )).unwrap();
+            ovn
+        };
+        let old = mirrored();
+        let mut new = mirrored();
+        new.switch_port_get_mut(&"sw0-port0".into()).unwrap().qos = Some(LogicalSwitchPortQos { rate_kbps: 1000, burst_kbits: None });
+        let mut diff = OvnNetworkDiff::new(&old, &new);
+        // the mirror is destroyed first and created last
+        assert_eq!(names(diff.destroy_resources()), vec![
+            "Ovn Mirror ids to LSP sw0-port1".to_string(),
+            "Ovn Logical Switch Port sw0-port0".to_string(),
+        ]);
+        assert_eq!(names(diff.create_resources()), vec![
+            "Ovn Logical Switch Port sw0-port0".to_string(),
+            "Ovn Mirror ids to LSP sw0-port1".to_string(),
+        ]);
+        // a mirror that is already recreated is not added twice
+        diff.recreate_mirrors(&old, &new, |_| true);
+        assert_eq!(diff.create.len(), 2);
+    }
+
+    #[test]
+    fn test_diff_dhcp_changed_keeps_switch_ports() {
+        let with_dhcp = |lease_time| {
+            let mut ovn = network();
+            ovn.add_lsp_internal(
+                "sw0-port1".into(),
+                "sw0".into(),
+                "ovs-sw0-port1".into(),
+                OvnIpAddr::Dynamic,
+                Some("ovn".into()),
+                MacAddress::new("00:00:00:00:00:02".into()).unwrap(),
+                None,
+            ).unwrap();
+            ovn.add_lsp_router(
+                "sw0-lr0".into(),
+                "sw0".into(),
+                MacAddress::new("router".into()).unwrap(),
+                "lr0-sw0".into(),
+            ).unwrap();
+            ovn.add_dhcp_option(&"lr0".into(), &"sw0".into(), &"10.0.0.1..10.0.0.10".into(), lease_time, BTreeMap::new(), &[]).unwrap();
+            ovn
+        };
+        let diff = OvnNetworkDiff::new(&with_dhcp(3600), &with_dhcp(600));
+        // only the rule is recreated, which links itself to the switch port again
+        assert_eq!(diff.destroy.len(), 1);
+        assert!(matches!(&diff.destroy[0], OrchestrationResourceNetwork::DhcpOption(d) if d.lease_time.eq("3600")));
+        assert_eq!(diff.create.len(), 1);
+        assert!(matches!(&diff.create[0], OrchestrationResourceNetwork::DhcpOption(d) if d.lease_time.eq("600")));
+    }
+
+    #[test]
+    fn test_diff_port_changed_recreates_port_group_acl() {
+        let grouped = || {
+            let mut ovn = network();
+            ovn.add_port_group(LogicalPortGroup::new("pg0".into(), vec!["sw0-port0".into()])).unwrap();
+            ovn.add_address_set(LogicalAddressSet::new("as0".into(), vec!["10.0.0.0/24".into()])).unwrap();
+            ovn.add_switch_acl(&"pg0-acl".into(), "pg0".into(), ACLRecordType::PortGroup, &ACLRule {
+                direction: ACLDirection::ToLport,
+                priority: 10,
+                _match: "ip4.src == $as0".into(),
+                action: ACLAction::Drop,
+            }).unwrap();
+            ovn
+        };
+        let old = grouped();
+        let mut new = grouped();
+        new.switch_port_get_mut(&"sw0-port0".into()).unwrap().qos = Some(LogicalSwitchPortQos { rate_kbps: 1000, burst_kbits: None });
+        let diff = OvnNetworkDiff::new(&old, &new);
+        // the address set is not linked to the ports so is left alone
+        assert_eq!(names(diff.destroy_resources()), vec![
+            "Ovn ACL (type: to-lport, action: drop, match: ip4.src == $as0, priority: 10) on pg0".to_string(),
+            "Ovn Port Group pg0 with 1 ports".to_string(),
+            "Ovn Logical Switch Port sw0-port0".to_string(),
+        ]);
+        assert_eq!(names(diff.create_resources()), vec![
+            "Ovn Logical Switch Port sw0-port0".to_string(),
+            "Ovn Port Group pg0 with 1 ports".to_string(),
+            "Ovn ACL (type: to-lport, action: drop, match: ip4.src == $as0, priority: 10) on pg0".to_string(),
+        ]);
+    }
+}
+
@@ -9,6 +9,7 @@ pub mod components;
 pub mod ovn;
 pub mod configuration;
 pub mod ovn_serde;
+pub mod diff;
 
 /// This enum represents the different kinds of results in adding or removing different OVN logical
 /// components to OvnNetwork.
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use std::net::IpAddr;
@@ -8,16 +8,22 @@ use kvm_compose_schemas::kvm_compose_yaml::network::acl::ACLRule;
 use crate::ovn::components::logical_switch_port::{LogicalSwitchPortType};
 use crate::ovn::components::{MacAddress, OvnIpAddr};
 use crate::ovn::LogicalOperationResult;
+use crate::orchestration::api::OrchestrationResource;
 use crate::ovn::configuration::external_gateway::OvnExternalGateway;
 use crate::ovn::configuration::nat::{OvnNat, OvnNatType};
 use crate::ovn::configuration::route::OvnRoute;
 use crate::ovn::components::logical_router::LogicalRouter;
 use crate::ovn::components::logical_router_port::LogicalRouterPort;
 use crate::ovn::components::logical_switch::LogicalSwitch;
-use crate::ovn::components::logical_switch_port::LogicalSwitchPort;
-use crate::ovn::components::ovs::OvsPort;
+use crate::ovn::components::logical_switch_port::{LogicalSwitchPort, LogicalSwitchPortQos};
+use crate::ovn::components::ovs::{OvsFlowExport, OvsPort};
 use crate::ovn::components::acl::{ACLRecordType, LogicalACLRecord};
-use crate::ovn::configuration::dhcp::{DhcpDatabaseEntry, SwitchDhcpOptions};
+use crate::ovn::components::mirror::OvnMirror;
+use crate::ovn::components::port_group::LogicalPortGroup;
+use crate::ovn::components::load_balancer::LogicalLoadBalancer;
+use crate::ovn::configuration::dns::OvnDnsRecords;
+use crate::ovn::components::address_set::LogicalAddressSet;
+use crate::ovn::configuration::dhcp::{DhcpDatabaseEntry, DhcpVersion, RouterAdvertisementOptions, SwitchDhcpOptions};
 
 
 /// This represents the OVN logical network components and configuration. Components are the main
@@ -31,7 +37,21 @@ pub struct OvnNetwork {
     pub routers: HashMap<String, LogicalRouter>,
     pub router_ports: HashMap<String, LogicalRouterPort>,
     pub ovs_ports: HashMap<String, OvsPort>,
+    /// flow export on the integration bridge, by chassis
+    #[serde(default)]
+    pub flow_exports: HashMap<String, OvsFlowExport>,
     pub acl: HashMap<String, LogicalACLRecord>,
+    #[serde(default)]
+    pub mirrors: HashMap<String, OvnMirror>,
+    #[serde(default)]
+    pub port_groups: HashMap<String, LogicalPortGroup>,
+    #[serde(default)]
+    pub address_sets: HashMap<String, LogicalAddressSet>,
+    #[serde(default)]
+    pub load_balancers: HashMap<String, LogicalLoadBalancer>,
+    /// DNS records of the guests, by switch
+    #[serde(default)]
+    pub dns_records: HashMap<String, OvnDnsRecords>,
     // TODO - track the OVN chassis as well?
     // database entries
     pub dhcp_options: HashSet<DhcpDatabaseEntry>,
@@ -45,11 +65,46 @@ impl OvnNetwork {
             routers: Default::default(),
             router_ports: Default::default(),
             ovs_ports: Default::default(),
+            flow_exports: Default::default(),
             acl: Default::default(),
+            mirrors: Default::default(),
+            port_groups: Default::default(),
+            address_sets: Default::default(),
+            load_balancers: Default::default(),
+            dns_records: Default::default(),
             dhcp_options: Default::default(),
         }
     }
 
+    /// Get every component and configuration in the network as an `OrchestrationResource`, in the
+    /// order they would be created.
+    pub fn to_orchestration_resources(&self) -> Vec<OrchestrationResource> {
+        let mut resources = Vec::new();
+        resources.extend(self.switches.values().map(|ls| ls.to_orchestration_resource()));
+        resources.extend(self.switch_ports.values().map(|lsp| lsp.to_orchestration_resource()));
+        resources.extend(self.routers.values().map(|lr| lr.to_orchestration_resource()));
+        resources.extend(self.router_ports.values().map(|lrp| lrp.to_orchestration_resource()));
+        resources.extend(self.ovs_ports.values().map(|ovs| ovs.to_orchestration_resource()));
+        resources.extend(self.flow_exports.values().map(|fe| fe.to_orchestration_resource()));
+        for router in self.routers.values() {
+            resources.extend(router.routing.0.values().map(|route| route.to_orchestration_resource()));
+        }
+        for router in self.routers.values() {
+            resources.extend(router.external_gateway.0.values().map(|ext| ext.to_orchestration_resource()));
+        }
+        for router in self.routers.values() {
+            resources.extend(router.nat.0.values().map(|nat| nat.to_orchestration_resource()));
+        }
+        resources.extend(self.dhcp_options.iter().map(|dhcp| dhcp.to_orchestration_resource()));
+        resources.extend(self.address_sets.values().map(|set| set.to_orchestration_resource()));
+        resources.extend(self.port_groups.values().map(|pg| pg.to_orchestration_resource()));
+        resources.extend(self.acl.values().map(|acl| acl.to_orchestration_resource()));
+        resources.extend(self.mirrors.values().map(|mirror| mirror.to_orchestration_resource()));
+        resources.extend(self.load_balancers.values().map(|lb| lb.to_orchestration_resource()));
+        resources.extend(self.dns_records.values().map(|dns| dns.to_orchestration_resource()));
+        resources
+    }
+
     /// Return true if the logical switch already exists, otherwise return false
     fn switch_exists(
         &self,
@@ -521,6 +576,118 @@ impl OvnNetwork {
         todo!()
     }
 
+    /// Give a logical switch an IPv6 prefix, making it dual-stack. OVN only supports /64 prefixes.
+    pub fn switch_set_ipv6_prefix(
+        &mut self,
+        name: &String,
+        prefix: IpAddr,
+        mask: u16,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        if !prefix.is_ipv6() || mask != 64 {
+            return Err(LogicalOperationResult::Error {
+                msg: format!("the ipv6 prefix {prefix}/{mask} of switch {name} must be an ipv6 /64 prefix"),
+            });
+        }
+        let switch = self.switches.get_mut(name)
+            .ok_or(LogicalOperationResult::DoesNotExist { name: name.clone() })?;
+        switch.ipv6_prefix = Some(OvnIpAddr::Subnet { ip: prefix, mask });
+        Ok(())
+    }
+
+    /// Give a logical switch port of type internal an IPv6 address as well as its IPv4 address.
+    pub fn lsp_set_ipv6(
+        &mut self,
+        name: &String,
+        ip: IpAddr,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        if !ip.is_ipv6() {
+            return Err(LogicalOperationResult::Error {
+                msg: format!("the ipv6 address {ip} of switch port {name} is not an ipv6 address"),
+            });
+        }
+        let lsp = self.switch_ports.get_mut(name)
+            .ok_or(LogicalOperationResult::DoesNotExist { name: name.clone() })?;
+        match &mut lsp.port_type {
+            LogicalSwitchPortType::Internal { ipv6, .. } => {
+                *ipv6 = Some(OvnIpAddr::Ip(ip));
+                Ok(())
+            }
+            _ => Err(LogicalOperationResult::Error {
+                msg: format!("only internal switch ports can be given an ipv6 address, {name} is not internal"),
+            }),
+        }
+    }
+
+    /// Reserve an ip for a logical switch port of type internal with a dynamic ip, the port keeps
+    /// the ip and is still linked to the DHCP rule of its switch.
+    pub fn lsp_reserve_ip(
+        &mut self,
+        name: &String,
+        ip: IpAddr,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        let lsp = self.switch_ports.get_mut(name)
+            .ok_or(LogicalOperationResult::DoesNotExist { name: name.clone() })?;
+        match &mut lsp.port_type {
+            LogicalSwitchPortType::Internal { ip: lsp_ip @ OvnIpAddr::Dynamic, .. } => {
+                *lsp_ip = OvnIpAddr::Ip(ip);
+                Ok(())
+            }
+            _ => Err(LogicalOperationResult::Error {
+                msg: format!("only internal switch ports with a dynamic ip can have a reserved ip, {name} does not"),
+            }),
+        }
+    }
+
+    /// Set the rate limit of a logical switch port.
+    pub fn lsp_set_qos(
+        &mut self,
+        name: &String,
+        qos: LogicalSwitchPortQos,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        let lsp = self.switch_ports.get_mut(name)
+            .ok_or(LogicalOperationResult::DoesNotExist { name: name.clone() })?;
+        lsp.qos = Some(qos);
+        Ok(())
+    }
+
+    /// Give a logical router port an IPv6 address and prefix as well as its IPv4 network.
+    pub fn lrp_set_ipv6(
+        &mut self,
+        name: &String,
+        ip: IpAddr,
+        mask: u16,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        if !ip.is_ipv6() || mask > 128 {
+            return Err(LogicalOperationResult::Error {
+                msg: format!("the ipv6 network {ip}/{mask} of router port {name} is not an ipv6 network"),
+            });
+        }
+        let lrp = self.router_ports.get_mut(name)
+            .ok_or(LogicalOperationResult::DoesNotExist { name: name.clone() })?;
+        lrp.ipv6 = Some(OvnIpAddr::Subnet { ip, mask });
+        Ok(())
+    }
+
+    /// Send IPv6 router advertisements from the router port that links the router to the switch.
+    /// The router port must have an IPv6 network.
+    pub fn lrp_set_ipv6_ra(
+        &mut self,
+        router_name: &String,
+        switch_name: &String,
+        ra: RouterAdvertisementOptions,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        let lrp_name = self.get_lsp_lrp_pair(switch_name, router_name)?.1.name.clone();
+        let lrp = self.router_ports.get_mut(&lrp_name)
+            .ok_or(LogicalOperationResult::DoesNotExist { name: lrp_name.clone() })?;
+        if lrp.ipv6.is_none() {
+            return Err(LogicalOperationResult::Error {
+                msg: format!("router port {lrp_name} needs an ipv6 network to send router advertisements"),
+            });
+        }
+        lrp.ipv6_ra = Some(ra);
+        Ok(())
+    }
+
     pub fn switch_get(
         &self,
         name: &String,
@@ -627,11 +794,17 @@ impl OvnNetwork {
             .context(format!("Getting Route rule name tuple {name_tuple:?}"))
     }
 
+    /// Add a DHCPv4 rule for the switch, served by the router. Every internal switch port on the
+    /// switch with a dynamic ip is linked to the rule, as well as the reserved ports which have a
+    /// fixed ip but still get their configuration from DHCP.
     pub fn add_dhcp_option(
         &mut self,
         router_name: &String,
         switch_name: &String,
         exclude_ips: &String,
+        lease_time: u32,
+        options: BTreeMap<String, String>,
+        reserved_ports: &[String],
     ) -> anyhow::Result<(), LogicalOperationResult> {
 
         // borrow checker avoidance - we will work on their names up here then below once we have
@@ -669,6 +842,17 @@ impl OvnNetwork {
             }
         }
 
+        for lsp_name in reserved_ports {
+            let lsp = self.switch_ports.get(lsp_name)
+                .ok_or(LogicalOperationResult::DoesNotExist { name: lsp_name.clone() })?;
+            if !lsp.parent_switch.eq(&sw) {
+                return Err(LogicalOperationResult::Error {
+                    msg: format!("the reserved switch port {lsp_name} is not on switch {}", &sw)
+                })
+            }
+            lsp_dynamic.push(lsp_name.clone());
+        }
+
         if lsp_dynamic.len() == 0 {
             return Err(LogicalOperationResult::Error {
                 msg: format!("the switch {} did not have any internal switch ports with a dynamic ip", &sw)
@@ -683,10 +867,12 @@ impl OvnNetwork {
         // create dhcp options database rule
         let dhcp = DhcpDatabaseEntry {
             cidr: switch.subnet.clone(),
-            lease_time: "3600".to_string(),
+            lease_time: lease_time.to_string(),
             router: lrp_ip_no_mask.to_string(),
             server_id: lrp_ip_no_mask.to_string(),
             server_mac: lsp_lrp_port_pair.1.mac_address.clone(),
+            version: DhcpVersion::V4,
+            options,
         };
 
         // create a hash of entry
@@ -710,6 +896,44 @@ impl OvnNetwork {
         Ok(())
     }
 
+    /// Add a DHCPv6 rule for the IPv6 prefix of the switch, served by the router. Every internal
+    /// switch port on the switch that has an IPv6 address, or a dynamic ip which OVN gives an IPv6
+    /// address from the prefix, is linked to the rule.
+    pub fn add_dhcpv6_option(
+        &mut self,
+        router_name: &String,
+        switch_name: &String,
+        stateless: bool,
+        dns_servers: Option<&Vec<String>>,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        let switch = self.switch_get(switch_name)
+            .or(Err(LogicalOperationResult::DoesNotExist { name: switch_name.clone() }))?;
+        let Some(prefix) = switch.ipv6_prefix.clone() else {
+            return Err(LogicalOperationResult::Error {
+                msg: format!("the switch {switch_name} needs an ipv6 prefix for DHCPv6"),
+            });
+        };
+        let server_mac = self.get_lsp_lrp_pair(switch_name, router_name)?.1.mac_address.clone();
+
+        let dhcp = DhcpDatabaseEntry::new_v6(prefix, server_mac, stateless, dns_servers);
+        let mut s = DefaultHasher::new();
+        dhcp.hash(&mut s);
+        let dhcp_hash = s.finish();
+        self.dhcp_options.insert(dhcp);
+
+        for lsp in self.switch_ports.values_mut() {
+            if !lsp.parent_switch.eq(switch_name) {
+                continue;
+            }
+            if let LogicalSwitchPortType::Internal { ip, ipv6, .. } = &lsp.port_type {
+                if ipv6.is_some() || matches!(ip, OvnIpAddr::Dynamic) {
+                    lsp.dhcpv6_options_uuid = Some(dhcp_hash);
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_lsp_lrp_pair(
         &self,
         switch_name: &String,
@@ -773,6 +997,109 @@ impl OvnNetwork {
         Ok(())
     }
 
+    /// Adds flow export to the integration bridge of the chassis, a chassis can only have one flow
+    /// export
+    pub fn add_flow_export(
+        &mut self,
+        flow_export: OvsFlowExport,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        if self.flow_exports.contains_key(&flow_export.chassis) {
+            return Err(LogicalOperationResult::AlreadyExists { name: format!("flow export on chassis {}", &flow_export.chassis) });
+        }
+        self.flow_exports.insert(flow_export.chassis.clone(), flow_export);
+        Ok(())
+    }
+
+    /// Adds a mirror of the source switch ports to the sink switch port. All the switch ports must
+    /// already exist.
+    pub fn add_mirror(
+        &mut self,
+        mirror: OvnMirror,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        if self.mirrors.contains_key(&mirror.name) {
+            return Err(LogicalOperationResult::AlreadyExists { name: mirror.name.clone() });
+        }
+        for port in mirror.source_ports.iter().chain([&mirror.sink_port]) {
+            if !self.switch_ports.contains_key(port) {
+                return Err(LogicalOperationResult::ParentDoesNotExist { name: mirror.name.clone(), parent: port.clone() });
+            }
+        }
+        if mirror.source_ports.contains(&mirror.sink_port) {
+            return Err(LogicalOperationResult::Error { msg: format!("mirror {} has its sink port {} as a source", &mirror.name, &mirror.sink_port) });
+        }
+        self.mirrors.insert(mirror.name.clone(), mirror);
+        Ok(())
+    }
+
+    /// Adds a port group, the switch ports must already exist
+    pub fn add_port_group(
+        &mut self,
+        port_group: LogicalPortGroup,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        if self.port_groups.contains_key(&port_group.name) {
+            return Err(LogicalOperationResult::AlreadyExists { name: port_group.name.clone() });
+        }
+        for port in &port_group.ports {
+            if !self.switch_ports.contains_key(port) {
+                return Err(LogicalOperationResult::ParentDoesNotExist { name: port_group.name.clone(), parent: port.clone() });
+            }
+        }
+        self.port_groups.insert(port_group.name.clone(), port_group);
+        Ok(())
+    }
+
+    /// Adds a load balancer, the switches and routers it is attached to must already exist
+    pub fn add_load_balancer(
+        &mut self,
+        load_balancer: LogicalLoadBalancer,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        if self.load_balancers.contains_key(&load_balancer.name) {
+            return Err(LogicalOperationResult::AlreadyExists { name: load_balancer.name.clone() });
+        }
+        for switch in &load_balancer.switches {
+            if !self.switches.contains_key(switch) {
+                return Err(LogicalOperationResult::ParentDoesNotExist { name: load_balancer.name.clone(), parent: switch.clone() });
+            }
+        }
+        for router in &load_balancer.routers {
+            if !self.routers.contains_key(router) {
+                return Err(LogicalOperationResult::ParentDoesNotExist { name: load_balancer.name.clone(), parent: router.clone() });
+            }
+        }
+        self.load_balancers.insert(load_balancer.name.clone(), load_balancer);
+        Ok(())
+    }
+
+    /// Adds the DNS records for a switch, the switch and the switch ports of any dynamic addresses
+    /// must already exist
+    pub fn add_dns_records(
+        &mut self,
+        dns_records: OvnDnsRecords,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        if self.dns_records.contains_key(&dns_records.switch) {
+            return Err(LogicalOperationResult::AlreadyExists { name: format!("DNS records on switch {}", &dns_records.switch) });
+        }
+        if !self.switches.contains_key(&dns_records.switch) {
+            return Err(LogicalOperationResult::ParentDoesNotExist { name: "DNS records".into(), parent: dns_records.switch.clone() });
+        }
+        if let Some(port) = dns_records.dynamic_switch_ports().find(|port| !self.switch_ports.contains_key(*port)) {
+            return Err(LogicalOperationResult::ParentDoesNotExist { name: format!("DNS records on switch {}", &dns_records.switch), parent: port.clone() });
+        }
+        self.dns_records.insert(dns_records.switch.clone(), dns_records);
+        Ok(())
+    }
+
+    pub fn add_address_set(
+        &mut self,
+        address_set: LogicalAddressSet,
+    ) -> anyhow::Result<(), LogicalOperationResult> {
+        if self.address_sets.contains_key(&address_set.name) {
+            return Err(LogicalOperationResult::AlreadyExists { name: address_set.name.clone() });
+        }
+        self.address_sets.insert(address_set.name.clone(), address_set);
+        Ok(())
+    }
+
     /// Validate the OvnNetwork to make sure all relations are valid. While the logical switch
     /// and logical switch port, and logical router and logical router port do have a mechanism
     /// to prevent parent-less ports, we must validate everything else.
@@ -794,7 +1121,13 @@ impl OvnNetwork {
         let mut ip_set = HashMap::new();
         for (name, lsp) in &self.switch_ports {
             match &lsp.port_type {
-                LogicalSwitchPortType::Internal { ip, .. } => {
+                LogicalSwitchPortType::Internal { ip, ipv6, .. } => {
+                    if let Some(ipv6) = ipv6 {
+                        if let Some(existing) = ip_set.get(ipv6) {
+                            bail!("ports {} and {} both have the same ip {}", existing, name, ipv6.to_string());
+                        }
+                        ip_set.insert(ipv6.clone(), name);
+                    }
                     let option = ip_set.get(ip);
                     if option.is_some() {
                         bail!("ports {} and {} both have the same ip {}", option.unwrap(), name, ip.to_string());
@@ -810,6 +1143,13 @@ impl OvnNetwork {
         }
         // now also do router ports
         for (name, lrp) in &self.router_ports {
+            if let Some(OvnIpAddr::Subnet { ip, .. }) = &lrp.ipv6 {
+                let ip = OvnIpAddr::Ip(*ip);
+                if let Some(existing) = ip_set.get(&ip) {
+                    bail!("ports {} and {} both have the same ip {}", existing, name, ip.to_string());
+                }
+                ip_set.insert(ip, name);
+            }
             let get = match &lrp.ip {
                 OvnIpAddr::Ip(ip) => {
                     // need to convert IpAddr to OvnIpAddr
@@ -836,12 +1176,9 @@ impl OvnNetwork {
         }
 
 
+        // gateway chassis, static route next hops, NAT rules, routers linked to a switch twice and
+        // dynamic ips without a DHCP rule are checked on the yaml in `Config::validation_report`
         // TODO - check if external gateway ips match external bridge ips
-        // TODO - check LRP gateway chassis matches kvm-compose-config
-        // TODO - check static routes have valid next hops?
-        // TODO - check NAT rules
-        // TODO - check if a router and switch havent been linked twice
-        // TODO - check if ip is dynamic, there is a corresponding DHCP rule (the opposite exists)
 
         Ok(())
     }
@@ -1103,7 +1440,10 @@ mod tests {
         ovn.add_dhcp_option(
             &"lr0".into(),
             &"sw0".into(),
-            &"10.0.0.1..10.0.0.10".into()
+            &"10.0.0.1..10.0.0.10".into(),
+            3600,
+            BTreeMap::new(),
+            &[],
         )?;
         // check all components have the correct information, unwrap as we know they exist
         let switch = ovn.switches.get("sw0").unwrap();
@@ -1132,6 +1472,8 @@ mod tests {
             router: "10.0.0.1".into(),
             server_id: "10.0.0.1".into(),
             server_mac: MacAddress::new("00:00:00:00:00:04".into()).unwrap(),
+            version: DhcpVersion::V4,
+            options: BTreeMap::new(),
         };
         let mut s = DefaultHasher::new();
         dhcp.hash(&mut s);
@@ -1143,16 +1485,38 @@ mod tests {
         let res = ovn.add_dhcp_option(
             &"lr1".into(), // this router doesnt exist
             &"sw0".into(),
-            &"10.0.0.1..10.0.0.10".into()
+            &"10.0.0.1..10.0.0.10".into(),
+            3600,
+            BTreeMap::new(),
+            &[],
         );
         assert!(res.is_err());
         let res = ovn.add_dhcp_option(
             &"lr0".into(),
             &"sw1".into(), // this switch doesnt exist
-            &"10.0.0.1..10.0.0.10".into()
+            &"10.0.0.1..10.0.0.10".into(),
+            3600,
+            BTreeMap::new(),
+            &[],
         );
         assert!(res.is_err());
 
+        // reserve an ip for one of the dynamic ports, it is still linked to the new rule
+        assert!(ovn.lsp_reserve_ip(&"sw0-port1".into(), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5))).is_err());
+        ovn.lsp_reserve_ip(&"sw0-port0".into(), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)))?;
+        ovn.add_dhcp_option(
+            &"lr0".into(),
+            &"sw0".into(),
+            &"10.0.0.1..10.0.0.10".into(),
+            600,
+            BTreeMap::from([("mtu".to_string(), "1400".to_string())]),
+            &["sw0-port0".into()],
+        )?;
+        let switch_port_1 = ovn.switch_ports.get("sw0-port0").unwrap();
+        let switch_port_3 = ovn.switch_ports.get("sw0-port2").unwrap();
+        assert_ne!(switch_port_1.dhcp_options_uuid.unwrap(), dhcp_hash);
+        assert_eq!(switch_port_1.dhcp_options_uuid, switch_port_3.dhcp_options_uuid);
+
         Ok(())
     }
 
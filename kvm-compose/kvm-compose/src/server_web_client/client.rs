@@ -1,11 +1,18 @@
 use crate::server_web_client::http_actions;
-use crate::get_project_name;
+use crate::{get_project_name, parse_config};
 use anyhow::{bail, Context};
-use kvm_compose_schemas::cli_models::{AnalysisToolsSubCmd, DeploymentCmd, DeploymentSubCommand, Opts, SubCommand};
+use kvm_compose_schemas::cli_models::{DeploymentCmd, DeploymentSubCommand, ImagesCmd, ImagesSubCommand, Opts, PlanCmd, PlanOutputFormat, SubCommand};
 use kvm_compose_schemas::deployment_models::{Deployment, DeploymentCommand, DeploymentState};
+use kvm_compose_schemas::capture::{CaptureCmd, LiveCaptureRequest};
 use reqwest::Client;
-use crate::orchestration::websocket::ws_orchestration_client;
+use std::path::Path;
+use kvm_compose_schemas::kvm_compose_yaml::resolve::{load_variables, resolve_yaml};
+use kvm_compose_schemas::image_models::{human_readable_size, ImportImage, PruneImages};
+use crate::orchestration::websocket::{ws_live_capture_client, ws_orchestration_client};
 use crate::server_web_client::deployment::reset_state;
+use crate::orchestration::read_previous_state_request;
+use crate::state::plan::Plan;
+use crate::state::State;
 
 pub async fn orchestration_action(
     client: &Client,
@@ -57,14 +64,15 @@ pub async fn orchestration_action(
         .await
         .context("connecting websocket to testbed server for orchestration");
 
-    // handle result from `orchestration_result`
+    // handle result from `orchestration_result`, a failed read only command does not change the
+    // deployment state so the failure must be returned from here
     match orchestration_result {
         Ok(_) => {
             tracing::debug!("websocket closed Ok");
         }
         Err(err) => {
             tracing::error!("there was a problem in the orchestration, will stop");
-            err.chain().for_each(|cause| tracing::error!("because: {}", cause));
+            return Err(err);
         }
     }
 
@@ -110,6 +118,15 @@ pub fn get_deployment_action(
         SubCommand::Exec(exec_cmd) => {
             DeploymentCommand::Exec(exec_cmd.clone())
         }
+        SubCommand::Net(net_cmd) => {
+            DeploymentCommand::Net(net_cmd.clone())
+        }
+        SubCommand::Trace(trace_cmd) => {
+            DeploymentCommand::Trace(trace_cmd.clone())
+        }
+        SubCommand::Verify(verify_cmd) => {
+            DeploymentCommand::Verify(verify_cmd.clone())
+        }
         SubCommand::CloudImages => {
             DeploymentCommand::ListCloudImages
         }
@@ -117,6 +134,32 @@ pub fn get_deployment_action(
     })
 }
 
+/// Stream a live capture of a guest's traffic from the server to stdout
+pub async fn capture_action(client: &Client, opts: &Opts, capture_cmd: &CaptureCmd) -> anyhow::Result<()> {
+    if !capture_cmd.live {
+        bail!("only live captures are supported, use analysis-tools tcp-dump to write a capture to a file");
+    }
+    let project_name = get_project_name(opts.project_name.clone())
+        .context("getting project name")?;
+    let deployment = http_actions::check_deployment(client, &project_name, &opts.server_connection).await
+        .context(format!("getting deployment {project_name}"))?;
+    ensure_current_folder_matches_deployment(&deployment)?;
+    match &deployment.state {
+        DeploymentState::Up => {}
+        state => bail!("deployment must be up to capture, it is {state:?}"),
+    }
+
+    let runner_url = format!(
+        "{}api/orchestration/capture",
+        &opts.server_connection,
+    ).replace("http://", "ws://");
+    tracing::debug!("live capture url = {runner_url}");
+    ws_live_capture_client(
+        runner_url,
+        LiveCaptureRequest { project_name, capture: capture_cmd.clone() },
+    ).await
+}
+
 /// This set of commands will control the deployments on the server
 pub async fn deployment_action(client: &Client, opts: &Opts, dep_cmd: &DeploymentCmd) -> anyhow::Result<()> {
     match &dep_cmd.sub_command {
@@ -129,13 +172,112 @@ pub async fn deployment_action(client: &Client, opts: &Opts, dep_cmd: &Deploymen
     }
 }
 
-/// This will run the analysis tooling that is provided through the testbed python code, without
-/// using the testbed server.
-#[allow(dead_code)]
-async fn analysis_tools_action(
-    _tool: &AnalysisToolsSubCmd,
-) -> anyhow::Result<()> {
+/// Compare the kvm-compose.yaml against the stored state of the deployment on the server and print
+/// what `up` would create, destroy or modify. Nothing is changed on the testbed.
+pub async fn plan_action(client: &Client, opts: &Opts, plan_cmd: &PlanCmd) -> anyhow::Result<()> {
+    let project_name = get_project_name(opts.project_name.clone())
+        .context("getting project name")?;
+    // the deployment may not exist yet, in which case there is no stored state to compare with
+    let previous_state = if let Ok(deployment) = http_actions::check_deployment(client, &project_name, &opts.server_connection).await {
+        ensure_current_folder_matches_deployment(&deployment)?;
+        read_previous_state_request(client, &opts.server_connection, &project_name).await.ok()
+    } else {
+        None
+    };
+    if previous_state.is_none() {
+        tracing::info!("there is no stored state for {project_name}, all resources will be created");
+    }
 
+    let logical_testbed = parse_config(
+        opts.input.clone(),
+        opts.var_file.clone(),
+        Some(project_name.clone()),
+        true,
+        std::env::current_dir()?,
+        false,
+        previous_state.as_ref(),
+    )
+        .await
+        .context("Failed to parse the yaml config and create a logical testbed")?;
+    let new_state = State::new(&logical_testbed)
+        .context("creating state from logical testbed")?;
+
+    let plan = Plan::new(previous_state.as_ref(), &new_state);
+    match plan_cmd.output {
+        PlanOutputFormat::Text => {
+            for line in plan.to_string().lines() {
+                tracing::info!("{line}");
+            }
+        }
+        // print directly to stdout so that the output can be piped into other tools
+        PlanOutputFormat::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+    }
+    Ok(())
+}
+
+/// Validate the kvm-compose.yaml on the testbed server, logging every error found
+pub async fn validate_action(client: &Client, opts: &Opts) -> anyhow::Result<()> {
+    // the server is sent the fully resolved yaml, as included files are relative to this folder
+    let variables = load_variables(opts.var_file.as_deref().map(Path::new)).await?;
+    let resolved = resolve_yaml(Path::new(&opts.input), &variables).await
+        .with_context(|| format!("resolving {}", &opts.input))?;
+    let yaml = serde_yaml::to_string(&resolved)?;
+    let report = http_actions::validate_yaml(client, yaml, &opts.server_connection).await?;
+    if report.is_valid() {
+        tracing::info!("{} is valid", &opts.input);
+        return Ok(());
+    }
+    for error in &report.errors {
+        if error.path.is_empty() {
+            tracing::error!("{}", error.message);
+        } else {
+            tracing::error!("{}: {}", error.path, error.message);
+        }
+    }
+    bail!("{} has {} error(s)", &opts.input, report.errors.len());
+}
+
+/// Manage the images on the testbed host. Images are only removed if no deployment references
+/// them, the server works out the references from the deployment states.
+pub async fn images_action(client: &Client, opts: &Opts, images_cmd: &ImagesCmd) -> anyhow::Result<()> {
+    let server_url = &opts.server_connection;
+    match &images_cmd.sub_command {
+        ImagesSubCommand::List => {
+            let list = http_actions::list_images(client, server_url).await?;
+            for line in list.pretty_to_string() {
+                tracing::info!("{line}");
+            }
+            tracing::info!("{} images using {}", list.images.len(), human_readable_size(list.total_bytes()));
+        }
+        ImagesSubCommand::Import { file, name, os_variant } => {
+            // the server runs in a different folder, so send the absolute path
+            let path = std::fs::canonicalize(file)
+                .with_context(|| format!("finding image file {file}"))?;
+            let request = ImportImage {
+                path: path.to_string_lossy().to_string(),
+                name: name.clone(),
+                os_variant: os_variant.clone(),
+            };
+            let image = http_actions::import_image(client, &request, server_url).await?;
+            tracing::info!("imported {file} to {}", image.path);
+        }
+        ImagesSubCommand::Prune { dry_run, include_artefacts } => {
+            let request = PruneImages {
+                dry_run: *dry_run,
+                include_artefacts: *include_artefacts,
+            };
+            let result = http_actions::prune_images(client, &request, server_url).await?;
+            let action = if *dry_run { "would remove" } else { "removed" };
+            for image in &result.removed {
+                tracing::info!("{action} {}", image.path);
+            }
+            tracing::info!("{action} {} images, {}", result.removed.len(), human_readable_size(result.freed_bytes));
+        }
+        ImagesSubCommand::Rm { name } => {
+            http_actions::delete_image(client, name, server_url).await?;
+            tracing::info!("removed image {name}");
+        }
+    }
     Ok(())
 }
 
@@ -1,8 +1,10 @@
-use anyhow::bail;
+use anyhow::{bail, Context};
 use kvm_compose_schemas::deployment_models::{
     Deployment, NewDeployment,
 };
 use reqwest::Response;
+use kvm_compose_schemas::kvm_compose_yaml::validation::ValidationReport;
+use kvm_compose_schemas::image_models::{ImageList, ImportImage, PruneImages, PruneResult, StoredImage};
 
 /// Reusable helper method for parsing the response from the server for command results
 async fn parse_response(resp: Response, command_name: String) -> anyhow::Result<String> {
@@ -85,3 +87,75 @@ pub async fn update_deployment_state(
     Ok(())
 }
 
+/// Send the yaml to the server to be validated, the server also checks the network against the
+/// testbed cluster config
+pub async fn validate_yaml(
+    client: &reqwest::Client,
+    yaml: String,
+    server_url: &String,
+) -> anyhow::Result<ValidationReport> {
+    tracing::debug!("validating yaml");
+    let server_api = format!("{}api/validate/yaml", server_url);
+    tracing::trace!("api url used = {:?}", &server_api);
+    let resp = client.post(server_api).body(yaml).send().await?;
+    let report: ValidationReport = serde_json::from_str(&resp.text().await?)
+        .context("parsing validation report from server")?;
+    Ok(report)
+}
+
+/// Get the images on the testbed host and what references them
+pub async fn list_images(
+    client: &reqwest::Client,
+    server_url: &String,
+) -> anyhow::Result<ImageList> {
+    let server_api = format!("{}api/images", server_url);
+    tracing::trace!("api url used = {:?}", &server_api);
+    let resp = client.get(server_api).send().await?;
+    let text = parse_response(resp, "list images".into()).await?;
+    let list: ImageList = serde_json::from_str(&text)
+        .context("parsing image list from server")?;
+    Ok(list)
+}
+
+/// Copy an image file on the testbed host into the image store
+pub async fn import_image(
+    client: &reqwest::Client,
+    request: &ImportImage,
+    server_url: &String,
+) -> anyhow::Result<StoredImage> {
+    let server_api = format!("{}api/images/import", server_url);
+    tracing::trace!("api url used = {:?}", &server_api);
+    let resp = client.post(server_api).json(request).send().await?;
+    let text = parse_response(resp, "import image".into()).await?;
+    let image: StoredImage = serde_json::from_str(&text)
+        .context("parsing imported image from server")?;
+    Ok(image)
+}
+
+/// Remove the images that are not referenced by any deployment
+pub async fn prune_images(
+    client: &reqwest::Client,
+    request: &PruneImages,
+    server_url: &String,
+) -> anyhow::Result<PruneResult> {
+    let server_api = format!("{}api/images/prune", server_url);
+    tracing::trace!("api url used = {:?}", &server_api);
+    let resp = client.post(server_api).json(request).send().await?;
+    let text = parse_response(resp, "prune images".into()).await?;
+    let result: PruneResult = serde_json::from_str(&text)
+        .context("parsing prune result from server")?;
+    Ok(result)
+}
+
+/// Remove an unreferenced image from the image store
+pub async fn delete_image(
+    client: &reqwest::Client,
+    name: &String,
+    server_url: &String,
+) -> anyhow::Result<()> {
+    let server_api = format!("{}api/images/{}", server_url, name);
+    tracing::trace!("api url used = {:?}", &server_api);
+    let resp = client.delete(server_api).send().await?;
+    parse_response(resp, "remove image".into()).await?;
+    Ok(())
+}
@@ -1,20 +1,412 @@
-use crate::state::State;
+use std::collections::BTreeMap;
+use anyhow::{bail, Context};
+use tokio::sync::mpsc::Sender;
+use kvm_compose_schemas::kvm_compose_yaml::Machine;
+use kvm_compose_schemas::net::{NetCmd, NetQosCmd, NetSubCommand};
+use crate::components::network::guest_switch_port_name;
+use crate::orchestration::api::{OrchestrationInstruction, OrchestrationProtocol, OrchestrationResourceNetwork};
+use crate::orchestration::OrchestrationCommon;
+use crate::orchestration::websocket::send_orchestration_instruction_over_channel;
+use crate::ovn::diff::OvnNetworkDiff;
+use crate::state::orchestration_tasks::check_provision_temporary_network;
+use crate::state::orchestration_tasks::stages::*;
+use crate::state::{State, StateNetwork, StateTestbedGuest, StateTestbedGuestList};
 
 /// This struct and it's implementation will figure out the different between two `State` structs.
-/// This is an important step in preventing state drift
+/// This is an important step in preventing state drift, as we can apply only what has changed in
+/// the kvm-compose.yaml to a running deployment rather than tearing down the whole testbed.
+///
+/// For guests, we work out if they have been added, removed, moved testbed hosts or if their
+/// machine definition has changed. A guest that has moved or changed is destroyed using the old
+/// definition and deployed using the new definition, the guest's disk image is kept. On OVN
+/// networks a guest where only the qos of its interfaces has changed is kept, and the qos is set
+/// in place the same way as `net qos`. The network is compared with `OvnNetworkDiff`, so only the
+/// network resources that have changed are destroyed and created, without touching the guests.
 pub struct StateDelta {
-
+    pub guests_added: Vec<String>,
+    pub guests_removed: Vec<String>,
+    pub guests_moved: Vec<String>,
+    pub guests_changed: Vec<String>,
+    /// the guest interfaces that only need their qos set
+    pub qos_changed: Vec<NetQosCmd>,
+    /// the network resources to destroy and create
+    pub network: OvnNetworkDiff,
+    // old definitions of the guests that need to be torn down i.e. removed, moved and changed
+    teardown_guests: StateTestbedGuestList,
+    // new definitions of the guests that need to be brought up i.e. added, moved and changed
+    bringup_guests: StateTestbedGuestList,
 }
 
 impl StateDelta {
-    pub fn new(_old_state: &State, _new_state: &State) -> Self {
-        todo!()
+    pub fn new(old_state: &State, new_state: &State) -> anyhow::Result<Self> {
+        let old_guests = &old_state.testbed_guests.0;
+        let new_guests = &new_state.testbed_guests.0;
+
+        let mut guests_added = Vec::new();
+        let mut guests_removed = Vec::new();
+        let mut guests_moved = Vec::new();
+        let mut guests_changed = Vec::new();
+        let mut qos_changed = Vec::new();
+        let mut teardown_guests = BTreeMap::new();
+        let mut bringup_guests = BTreeMap::new();
+
+        // the qos can only be set in place on OVN networks
+        let in_place_qos = matches!(new_state.network, StateNetwork::Ovn(_));
+        for (guest_name, old_guest) in old_guests.iter() {
+            match new_guests.get(guest_name) {
+                None => {
+                    guests_removed.push(guest_name.clone());
+                    teardown_guests.insert(guest_name.clone(), old_guest.clone());
+                }
+                Some(new_guest) => {
+                    let is_moved = old_guest.testbed_host != new_guest.testbed_host;
+                    if is_moved {
+                        guests_moved.push(guest_name.clone());
+                    } else if guest_definition_changed(old_guest, new_guest, in_place_qos) {
+                        guests_changed.push(guest_name.clone());
+                    } else {
+                        if in_place_qos {
+                            qos_changed.extend(qos_changes(guest_name, old_guest, new_guest));
+                        }
+                        continue;
+                    }
+                    teardown_guests.insert(guest_name.clone(), old_guest.clone());
+                    bringup_guests.insert(guest_name.clone(), new_guest.clone());
+                }
+            }
+        }
+        for (guest_name, new_guest) in new_guests.iter() {
+            if !old_guests.contains_key(guest_name) {
+                guests_added.push(guest_name.clone());
+                bringup_guests.insert(guest_name.clone(), new_guest.clone());
+            }
+        }
+
+        // the network is compared as a whole, this includes the switch ports of the guests
+        let network = match (&old_state.network, &new_state.network) {
+            (StateNetwork::Ovn(old_ovn), StateNetwork::Ovn(new_ovn)) => {
+                // the rate limits of the switch ports with a qos change are set with the rest of
+                // the qos, so they do not cause the switch port to be recreated
+                let mut new_ovn = new_ovn.clone();
+                for qos_cmd in &qos_changed {
+                    let interface = &new_guests[&qos_cmd.guest_name].guest_type.network.as_ref()
+                        .context("getting the network of a guest with a qos change")?[qos_cmd.interface];
+                    let port_name = guest_switch_port_name(&new_state.project_name, &interface.switch, &qos_cmd.guest_name, qos_cmd.interface);
+                    if let (Some(old_lsp), Some(new_lsp)) = (old_ovn.switch_ports.get(&port_name), new_ovn.switch_ports.get_mut(&port_name)) {
+                        new_lsp.qos = old_lsp.qos.clone();
+                    }
+                }
+                let mut diff = OvnNetworkDiff::new(old_ovn, &new_ovn);
+                // a recreated sink guest has a new interface, so its mirrors need to be recreated
+                diff.recreate_mirrors(old_ovn, &new_ovn, |mirror| teardown_guests.contains_key(&mirror.sink_guest));
+                diff
+            }
+            // the guests of an OVS network can still be changed, as long as the bridges are not
+            (StateNetwork::Ovs(old_ovs), StateNetwork::Ovs(new_ovs)) => {
+                if old_ovs != new_ovs {
+                    bail!("the OVS bridges or links have changed, these can't be applied to a running deployment, run down and then up");
+                }
+                OvnNetworkDiff::default()
+            }
+            _ => bail!("the network backend has changed, run down and then up"),
+        };
+
+        Ok(Self {
+            guests_added,
+            guests_removed,
+            guests_moved,
+            guests_changed,
+            qos_changed,
+            network,
+            teardown_guests: StateTestbedGuestList(teardown_guests),
+            bringup_guests: StateTestbedGuestList(bringup_guests),
+        })
+    }
+
+    /// Return true if there are no differences between the two states
+    pub fn is_empty(&self) -> bool {
+        self.guests_added.is_empty()
+            && self.guests_removed.is_empty()
+            && self.guests_moved.is_empty()
+            && self.guests_changed.is_empty()
+            && self.qos_changed.is_empty()
+            && self.network.is_empty()
+    }
+
+    /// Log a summary of the delta
+    pub fn log_summary(&self) {
+        for guest in &self.guests_added {
+            tracing::info!("guest {guest} has been added");
+        }
+        for guest in &self.guests_removed {
+            tracing::info!("guest {guest} has been removed");
+        }
+        for guest in &self.guests_moved {
+            tracing::info!("guest {guest} has moved testbed host");
+        }
+        for guest in &self.guests_changed {
+            tracing::info!("guest {guest} has a changed machine definition");
+        }
+        for qos_cmd in &self.qos_changed {
+            tracing::info!("guest {} interface {} has a changed qos", qos_cmd.guest_name, qos_cmd.interface);
+        }
+        for resource in self.network.destroy_resources() {
+            tracing::info!("{} will be destroyed", resource.name());
+        }
+        for resource in self.network.create_resources() {
+            tracing::info!("{} will be created", resource.name());
+        }
     }
 
-    // TODO - start simple, work out if guests have been added/removed and if they have moved
-    //  testbed hosts
-    // TODO - we can look at the configuration of each guest to see if changed, including
-    //  any scripts and mounts etc
-    // TODO - network, bridges may or may not have changed etc which could impact guests
+    /// Request the server to apply only the changes in this delta to the running deployment. The
+    /// orchestration common must be created from the new state, as the server will use the new
+    /// state for the guests that are brought up.
+    pub async fn request_apply_action(
+        &self,
+        common: &OrchestrationCommon,
+        sender: &mut Sender<OrchestrationProtocol>,
+    ) -> anyhow::Result<()> {
+        tracing::info!("running request apply action for state delta");
+
+        // changing a backing image would mean all of its clones need to be recreated
+        for guest in self.bringup_guests.0.values() {
+            if guest.is_golden_image && !self.guests_added.contains(&guest.guest_type.name) {
+                bail!("backing image guest {} has changed, rerun up with the --provision flag to recreate it and its clones", &guest.guest_type.name);
+            }
+        }
+
+        send_orchestration_instruction_over_channel(
+            sender,
+            OrchestrationInstruction::TestbedHostCheck,
+        ).await.context("requesting if testbed hosts are up")?;
+
+        send_orchestration_instruction_over_channel(
+            sender,
+            OrchestrationInstruction::Setup,
+        ).await.context("requesting setup orchestration instruction")?;
+
+        // mirrors are bound to the sink guest's interface, so they are destroyed before the guests
+        // and created after the guests
+        let is_mirror = |r: &OrchestrationResourceNetwork| matches!(r, OrchestrationResourceNetwork::Mirror(_));
+        let mut mirrors = self.network.clone();
+        mirrors.retain(is_mirror);
+        let mut network = self.network.clone();
+        network.retain(|r| !is_mirror(r));
+        mirrors.request_destroy_action(sender).await?;
+
+        // tear down first, in case a guest is being brought back up with the same name
+        destroy_guest_stage(&self.teardown_guests, sender).await?;
+
+        // then apply the network changes, removed resources first so that anything being
+        // recreated can take the same name
+        network.request_destroy_action(sender).await?;
+        network.request_create_action(sender).await?;
+
+        // the guests that are kept only need their qos set
+        for qos_cmd in &self.qos_changed {
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::Net(NetCmd { sub_command: NetSubCommand::Qos(qos_cmd.clone()) }),
+            ).await.context("requesting net qos instruction")?;
+        }
+
+        // only guests that are new need their images to be set up, moved and changed guests
+        // keep their existing image
+        let added_guests = StateTestbedGuestList(self.bringup_guests.0.iter()
+            .filter(|(name, _)| self.guests_added.contains(name))
+            .map(|(name, guest)| (name.clone(), guest.clone()))
+            .collect());
+
+        tracing::info!("Stage: setting up any new libvirt backing image guests");
+        let net_provision = check_provision_temporary_network(&added_guests);
+        if net_provision {
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::CreateTempNetwork(common.clone()),
+            ).await.context("requesting create temporary network instruction")?;
+        }
+        setup_backing_image_stage(&added_guests, sender).await?;
+        if net_provision {
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::DestroyTempNetwork(common.clone()),
+            ).await.context("requesting destroy temporary network instruction")?;
+        }
+
+        tracing::info!("Stage: creating any new libvirt clones of backing image guests");
+        setup_linked_clones_stage(&added_guests, sender).await?;
+
+        tracing::info!("Stage: pushing guest images to remote testbed hosts");
+        push_guest_images_stage(&self.bringup_guests, sender).await?;
+        push_backing_guest_images_stage(&self.bringup_guests, common, sender).await?;
+
+        tracing::info!("Stage: rebasing clones on remote testbed hosts");
+        rebase_clone_images_stage(&self.bringup_guests, common, sender).await?;
+
+        tracing::info!("Stage: deploying guests");
+        deploy_guest_stage(&self.bringup_guests, sender).await?;
+
+        tracing::info!("Stage: creating any new or changed mirrors");
+        mirrors.request_create_action(sender).await?;
 
+        tracing::info!("Stage: running any guest setup scripts");
+        if common.force_rerun_scripts {
+            run_guest_setup_scripts_stage(&self.bringup_guests, sender).await?;
+        } else {
+            run_guest_setup_scripts_stage(&added_guests, sender).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compare the machine definitions of a guest in both states, leaving out the qos of the interfaces
+/// if it can be set in place. We compare the serialised form as the yaml schema structs do not
+/// implement `PartialEq`.
+fn guest_definition_changed(old_guest: &StateTestbedGuest, new_guest: &StateTestbedGuest, ignore_qos: bool) -> bool {
+    let definition = |guest: &StateTestbedGuest| {
+        let mut machine: Machine = guest.guest_type.clone();
+        if ignore_qos {
+            machine.network.iter_mut().flatten().for_each(|interface| interface.qos = None);
+        }
+        serde_json::to_value(&machine).ok()
+    };
+    let old_def = definition(old_guest);
+    let new_def = definition(new_guest);
+    old_def != new_def
+        || old_guest.is_golden_image != new_guest.is_golden_image
+        || old_guest.extra_info.reference_image != new_guest.extra_info.reference_image
+}
+
+/// The interfaces of a guest with a different qos in the new state, as the `net qos` command that
+/// sets the new qos. No qos clears the qos of the interface.
+fn qos_changes(guest_name: &str, old_guest: &StateTestbedGuest, new_guest: &StateTestbedGuest) -> Vec<NetQosCmd> {
+    old_guest.guest_type.network.iter().flatten()
+        .zip(new_guest.guest_type.network.iter().flatten())
+        .enumerate()
+        .filter(|(_, (old, new))| old.qos != new.qos)
+        .map(|(idx, (_, new))| NetQosCmd {
+            guest_name: guest_name.to_string(),
+            interface: idx,
+            qos: new.qos.clone().unwrap_or_default(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use kvm_compose_schemas::kvm_compose_yaml::MachineNetwork;
+    use kvm_compose_schemas::kvm_compose_yaml::network::qos::Qos;
+    use crate::ovn::components::{MacAddress, OvnIpAddr};
+    use crate::ovn::components::logical_switch_port::LogicalSwitchPortQos;
+    use crate::ovn::ovn::OvnNetwork;
+    use super::*;
+
+    fn docker_guest(name: &str, image: &str, host: &str, guest_id: u32) -> StateTestbedGuest {
+        StateTestbedGuest::test_docker_guest(name, image, host, guest_id, vec![MachineNetwork {
+            switch: "sw0".to_string(),
+            gateway: None,
+            mac: Some(format!("00:00:00:00:00:0{guest_id}")),
+            ip: Some(format!("10.0.0.{}", guest_id + 10)),
+            ipv6: None,
+            ipv6_gateway: None,
+            qos: None,
+            network_name: None,
+        }])
+    }
+
+    fn state(guests: Vec<StateTestbedGuest>) -> State {
+        let mut ovn = OvnNetwork::new();
+        ovn.add_switch("test-sw0".into(), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 24).unwrap();
+        for guest in &guests {
+            let interface = &guest.guest_type.network.as_ref().unwrap()[0];
+            ovn.add_lsp_internal(
+                guest_switch_port_name(&"test".into(), &interface.switch, &guest.guest_type.name, 0),
+                "test-sw0".into(),
+                "ovs-port".into(),
+                OvnIpAddr::Ip(interface.ip().unwrap().parse().unwrap()),
+                guest.testbed_host.clone(),
+                MacAddress::new(interface.mac().unwrap().clone()).unwrap(),
+                None,
+            ).unwrap();
+        }
+        State::test_fixture(guests, StateNetwork::Ovn(ovn))
+    }
+
+    #[test]
+    fn test_delta_no_change() {
+        let old = state(vec![docker_guest("a", "nginx", "host1", 1)]);
+        let new = state(vec![docker_guest("a", "nginx", "host1", 1)]);
+        let delta = StateDelta::new(&old, &new).unwrap();
+        assert!(delta.is_empty());
+    }
+
+    #[test]
+    fn test_delta_guest_added_and_removed() {
+        let old = state(vec![docker_guest("a", "nginx", "host1", 1), docker_guest("b", "nginx", "host1", 2)]);
+        let new = state(vec![docker_guest("a", "nginx", "host1", 1), docker_guest("c", "nginx", "host1", 3)]);
+        let delta = StateDelta::new(&old, &new).unwrap();
+        assert_eq!(delta.guests_added, vec!["c".to_string()]);
+        assert_eq!(delta.guests_removed, vec!["b".to_string()]);
+        assert!(delta.guests_moved.is_empty());
+        assert!(delta.guests_changed.is_empty());
+        // only the switch ports of the removed and added guests change in the network
+        let destroyed: Vec<_> = delta.network.destroy_resources().iter().map(|r| r.name()).collect();
+        let created: Vec<_> = delta.network.create_resources().iter().map(|r| r.name()).collect();
+        assert_eq!(destroyed, vec!["Ovn Logical Switch Port test-sw0-b-0".to_string()]);
+        assert_eq!(created, vec!["Ovn Logical Switch Port test-sw0-c-0".to_string()]);
+    }
+
+    #[test]
+    fn test_delta_guest_moved_and_changed() {
+        let old = state(vec![docker_guest("a", "nginx", "host1", 1), docker_guest("b", "nginx", "host1", 2)]);
+        let new = state(vec![docker_guest("a", "nginx", "host2", 1), docker_guest("b", "httpd", "host1", 2)]);
+        let delta = StateDelta::new(&old, &new).unwrap();
+        assert!(delta.guests_added.is_empty());
+        assert!(delta.guests_removed.is_empty());
+        assert_eq!(delta.guests_moved, vec!["a".to_string()]);
+        assert_eq!(delta.guests_changed, vec!["b".to_string()]);
+        // moved and changed guests are torn down with the old definition and brought up with new
+        assert_eq!(delta.teardown_guests.0["a"].testbed_host, Some("host1".to_string()));
+        assert_eq!(delta.bringup_guests.0["a"].testbed_host, Some("host2".to_string()));
+    }
+
+    #[test]
+    fn test_delta_qos_changed() {
+        let old = state(vec![docker_guest("a", "nginx", "host1", 1)]);
+        let mut new = state(vec![docker_guest("a", "nginx", "host1", 1)]);
+        let qos = Qos { rate_kbps: Some(1000), delay_ms: Some(50), ..Default::default() };
+        new.testbed_guests.0.get_mut("a").unwrap().guest_type.network.as_mut().unwrap()[0].qos = Some(qos.clone());
+        match &mut new.network {
+            StateNetwork::Ovn(ovn) => {
+                ovn.switch_port_get_mut(&"test-sw0-a-0".into()).unwrap().qos = LogicalSwitchPortQos::from_qos(&qos);
+            }
+            StateNetwork::Ovs(_) => unreachable!(),
+        }
+        let delta = StateDelta::new(&old, &new).unwrap();
+        // the guest and its switch port are kept, the qos is set in place
+        assert!(delta.guests_changed.is_empty());
+        assert!(delta.network.is_empty());
+        assert_eq!(delta.qos_changed, vec![NetQosCmd { guest_name: "a".to_string(), interface: 0, qos }]);
+        assert!(!delta.is_empty());
+    }
+
+    #[test]
+    fn test_delta_network_changed() {
+        let old = state(vec![docker_guest("a", "nginx", "host1", 1)]);
+        let mut new = state(vec![docker_guest("a", "nginx", "host1", 1)]);
+        match &mut new.network {
+            StateNetwork::Ovn(ovn) => {
+                ovn.add_switch("test-sw1".into(), IpAddr::V4(Ipv4Addr::new(10, 0, 1, 0)), 24).unwrap();
+            }
+            StateNetwork::Ovs(_) => unreachable!(),
+        }
+        let delta = StateDelta::new(&old, &new).unwrap();
+        // the guests are left alone, only the new switch is created
+        assert!(delta.guests_changed.is_empty());
+        assert!(delta.network.destroy.is_empty());
+        assert_eq!(delta.network.create.len(), 1);
+        assert!(!delta.is_empty());
+    }
 }
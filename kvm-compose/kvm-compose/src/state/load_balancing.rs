@@ -1,26 +1,30 @@
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use kvm_compose_schemas::kvm_compose_yaml::network::bridge::TunnelType;
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+/// A link between two bridges of the OVS network. Bridges that were load balanced onto the same
+/// testbed host are connected with a veth pair, otherwise with a tunnel between the hosts.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum BridgeConnection {
-    Ovs {
-        name: String, // TODO - redundant?, can remove for ovs only
+    Veth {
         source_br: String,
         target_br: String,
         source_veth: String,
         target_veth: String,
-        ip: Option<String>,
         testbed_host: String,
     },
     Tunnel {
+        tunnel_type: TunnelType,
         source_br: String,
         target_br: String,
-        source_remote_ip: Option<String>,
-        target_remote_ip: Option<String>,
-        key: Option<String>,
-        source_br_ip: Option<String>,
-        target_br_ip: Option<String>,
+        /// the name of the tunnel port, which is the same on both testbed hosts
+        port: String,
+        /// the ip of the source testbed host, which is the remote ip of the target's tunnel port
+        source_host_ip: String,
+        target_host_ip: String,
+        /// keeps the traffic of each link separate when there are many links between two hosts
+        key: u32,
         testbed_host_source: String,
         testbed_host_target: String,
     },
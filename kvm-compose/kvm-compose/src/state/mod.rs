@@ -1,24 +1,30 @@
 pub mod load_balancing;
 pub mod orchestration_tasks;
 pub mod delta;
+pub mod plan;
 
 use crate::components::LogicalTestbed;
+use crate::components::helpers::ipam::AddressAllocations;
 use chrono;
 use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
 use kvm_compose_schemas::kvm_compose_yaml::Machine;
+use kvm_compose_schemas::kvm_compose_yaml::validation::Subnet;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::{fmt, fs};
 use std::fmt::Formatter;
 use tokio::fs::File;
 use std::os::linux::fs::MetadataExt;
-use std::path::PathBuf;
-use anyhow::Context;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Context};
 use nix::unistd::{Gid, Uid};
 use tokio::io::AsyncWriteExt;
 
 use crate::components::network::LogicalNetwork;
+use crate::ovn::components::OvnIpAddr;
 use crate::ovn::ovn::OvnNetwork;
+use crate::net::faults::NetworkFault;
+use crate::state::load_balancing::BridgeConnection;
 
 // the data structures in this file represent the state, they are generated from the Config and Common
 // data structures used to parse the kvm-compose.yaml
@@ -40,6 +46,12 @@ pub struct State {
     pub testbed_guest_shared_config: StateTestbedGuestSharedConfig,
     pub network: StateNetwork,
     pub state_provisioning: StateProvisioning,
+    /// Ips and macs allocated to interfaces without one in the yaml, reused on the next deployment
+    #[serde(default)]
+    pub address_allocations: AddressAllocations,
+    /// Network faults applied with the net commands that have not been healed
+    #[serde(default)]
+    pub faults: BTreeMap<String, NetworkFault>,
 }
 
 impl State {
@@ -80,6 +92,8 @@ impl State {
             state_provisioning: StateProvisioning {
                 guests_provisioned: false
             },
+            address_allocations: logical_testbed.address_allocations.clone(),
+            faults: BTreeMap::new(),
         })
     }
 
@@ -159,15 +173,26 @@ impl State {
                 // in the logical testbed as it is the same schema used in the state
                 Ok(StateNetwork::Ovn(ovn_network.clone()))
             }
-            LogicalNetwork::Ovs(_) => {
-                // since OVS requires some extra information, we need to do further work here
-                unimplemented!()
+            LogicalNetwork::Ovs(ovs_network) => {
+                // the bridges and their connections have already been load balanced onto the
+                // testbed hosts, so like OVN this is the same schema used in the state
+                Ok(StateNetwork::Ovs(ovs_network.clone()))
             }
         }
     }
 
-    pub async fn write(&self, project_name: &String, project_path: &PathBuf) -> anyhow::Result<()> {
-        let path_str = &project_path.clone()
+    /// Read the saved state of the project, the server uses this while running an instruction to
+    /// record changes that are only known once they have been applied
+    pub async fn read(project_name: &str, project_path: &Path) -> anyhow::Result<Self> {
+        let file_name = project_path.join(format!("{project_name}-state.json"));
+        let state_json = tokio::fs::read_to_string(&file_name)
+            .await
+            .context(format!("reading state from {file_name:?}"))?;
+        serde_json::from_str(&state_json).context("parsing state with serde")
+    }
+
+    pub async fn write(&self, project_name: &str, project_path: &Path) -> anyhow::Result<()> {
+        let path_str = &project_path
             .to_string_lossy()
             .to_string();
         let file_name = format!("{}/{}-state.json", &path_str, &project_name);
@@ -262,21 +287,47 @@ impl Default for StateNetwork {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+impl StateNetwork {
+    /// The prefix length of the IPv4 subnet of the switch or bridge, by its name in the yaml, that
+    /// the static ips of guest interfaces are configured with
+    pub fn subnet_prefix(&self, project_name: &str, switch: &str) -> anyhow::Result<u16> {
+        match self {
+            StateNetwork::Ovn(ovn) => {
+                let logical_switch = ovn.switches.get(&format!("{project_name}-{switch}"))
+                    .context(format!("getting switch {switch} from the state"))?;
+                match &logical_switch.subnet {
+                    OvnIpAddr::Subnet { mask, .. } => Ok(*mask),
+                    _ => bail!("switch {switch} does not have a subnet"),
+                }
+            }
+            StateNetwork::Ovs(ovs) => {
+                let bridge = ovs.bridges.get(switch)
+                    .context(format!("getting bridge {switch} from the state"))?;
+                let subnet = Subnet::parse(&bridge.subnet)
+                    .map_err(|err| anyhow!("parsing subnet of bridge {switch}: {err}"))?;
+                Ok(subnet.mask as u16)
+            }
+        }
+    }
+}
+
+/// The plain OVS network, this is created from the yaml and the load balancing in the logical
+/// testbed so is passed straight through to the state
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
 #[serde(rename_all = "snake_case")]
-#[derive(Clone)]
 pub struct StateOvsNetwork {
-    // libvirt_network_name: String,
-    // livbirt_network_bridge_name: String,
-    // libvirt_network_xml: String,
-    // veth_peer_left: String,
-    // veth_peer_right: String,
-    // pub libvirt_network_subnet: String,
-    // pub bridges: Vec<StateTestbedInterface>,
-    // logical_bridge_connections: LogicalBridgeConnections,
-    // pub physical_bridge_connections: PhysicalBridgeConnections,
-    // // TODO place the list of network interfaces from config, after being processed into state
-    // // network_interfaces: Vec<NetworkInterface>,
+    /// the bridges keyed by their name in the yaml
+    pub bridges: BTreeMap<String, StateOvsBridge>,
+    pub connections: Vec<BridgeConnection>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct StateOvsBridge {
+    /// the name of the bridge on the testbed host, this is limited in length like an interface
+    pub name: String,
+    pub subnet: String,
+    pub testbed_host: String,
 }
 
 /// Struct to keep tract of provisioning changes and actions. Used to make sure deployments are not
@@ -285,4 +336,63 @@ pub struct StateOvsNetwork {
 #[serde(rename_all = "snake_case")]
 pub struct StateProvisioning {
     pub guests_provisioned: bool,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+impl State {
+    /// A provisioned state of the `test` project with just the given guests and network, for tests
+    pub fn test_fixture(guests: Vec<StateTestbedGuest>, network: StateNetwork) -> Self {
+        Self {
+            project_name: "test".to_string(),
+            creation_date: "".to_string(),
+            project_working_dir: Default::default(),
+            testbed_hosts: StateTestbedHostList(BTreeMap::new()),
+            testbed_guests: StateTestbedGuestList(guests.into_iter()
+                .map(|guest| (guest.guest_type.name.clone(), guest))
+                .collect()),
+            testbed_host_shared_config: StateTestbedHostSharedConfig {},
+            testbed_guest_shared_config: StateTestbedGuestSharedConfig::default(),
+            network,
+            state_provisioning: StateProvisioning { guests_provisioned: true },
+            address_allocations: Default::default(),
+            faults: Default::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl StateTestbedGuest {
+    /// A docker guest with the given interfaces, for tests
+    pub fn test_docker_guest(
+        name: &str,
+        image: &str,
+        host: &str,
+        guest_id: u32,
+        network: Vec<kvm_compose_schemas::kvm_compose_yaml::MachineNetwork>,
+    ) -> Self {
+        use kvm_compose_schemas::kvm_compose_yaml::machines::docker::ConfigDockerMachine;
+        Self {
+            guest_type: Machine {
+                name: name.to_string(),
+                network: Some(network),
+                guest_type: GuestType::Docker(ConfigDockerMachine {
+                    image: image.to_string(),
+                    command: None,
+                    entrypoint: None,
+                    environment: None,
+                    env_file: None,
+                    volumes: None,
+                    privileged: None,
+                    scaling: None,
+                    user: None,
+                    device: None,
+                    hostname: name.to_string(),
+                    static_ip: None,
+                }),
+            },
+            testbed_host: Some(host.to_string()),
+            is_golden_image: false,
+            guest_id,
+            extra_info: StateTestbedGuestExtraInfo { reference_image: None },
+        }
+    }
+}
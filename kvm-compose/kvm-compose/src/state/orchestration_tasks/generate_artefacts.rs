@@ -1,4 +1,4 @@
-use kvm_compose_schemas::TESTBED_SETTINGS_FOLDER;
+use kvm_compose_schemas::TESTBED_IMAGES_FOLDER;
 use std::path::PathBuf;
 use anyhow::{bail, Context};
 use nix::unistd::{Gid, Uid};
@@ -7,10 +7,11 @@ use kvm_compose_schemas::kvm_compose_yaml::machines::avd::ConfigAVDMachine;
 use kvm_compose_schemas::kvm_compose_yaml::machines::docker::ConfigDockerMachine;
 use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
 use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt::{ConfigLibvirtMachine, LibvirtGuestOptions};
+use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt_image_download::{CloudImage, CloudImageCatalog};
 use crate::components::get_guest_interface_name;
 use crate::components::helpers::{check_file_exists, serialisation};
 use crate::components::helpers::android::{create_avd, download_system_image, get_sdk_string};
-use crate::components::helpers::artefact_generation::{copy_and_set_permissions_orchestration, resize};
+use crate::components::helpers::artefact_generation::{copy_and_set_permissions_orchestration, os_variant_id, resize};
 use crate::components::helpers::cloud_init::{create_meta_data, create_network_config, create_user_data};
 use crate::components::helpers::xml::render_libvirt_domain_xml;
 use crate::orchestration::{OrchestrationCommon, run_testbed_orchestration_command};
@@ -63,10 +64,27 @@ async fn libvirt(
         format!("/home/{remote_host_username}/testbed-projects/{project_name}/artefacts/").to_owned()
     };
 
+    // the catalog entry of a cloud image guest, which decides the os variant and cloud-init used
+    let cloud_image = match &libvirt_config.libvirt_type {
+        LibvirtGuestOptions::CloudImage { name, .. } => {
+            let catalog = CloudImageCatalog::load(Some(&common.project_working_dir))
+                .await
+                .context("loading the cloud image catalog")?;
+            Some(catalog.get(name)?.clone())
+        }
+        _ => None,
+    };
+
     // set up the XML template
 
     let mut tera_context = tera::Context::new();
     tera_context.insert("guest_name", &client_name);
+    if let Some(os_variant) = cloud_image.as_ref().and_then(|image| image.os_variant.as_ref()) {
+        let id = os_variant_id(os_variant)
+            .await
+            .with_context(|| format!("getting the libosinfo id of os variant {os_variant}"))?;
+        tera_context.insert("os_variant_id", &id);
+    }
     tera_context.insert("vcpu", &libvirt_config.cpus
         .context("getting n cpus for libvirt guest")?.to_string());
     tera_context.insert("memory", &libvirt_config.memory_mb
@@ -132,7 +150,7 @@ async fn libvirt(
                     bail!("currently don't support a guest with more than 10 interfaces");
                 }
                 let interface = get_guest_interface_name(&common.project_name, guest_config.guest_id, idx);
-                let mac = yaml_interface.mac.clone();
+                let mac = yaml_interface.mac()?.clone();
                 let mut interface_id = format!("{idx}");
                 if interface_id.len() == 1 {
                     interface_id = format!("0{interface_id}");
@@ -209,14 +227,15 @@ async fn libvirt(
     // it is a non clone or is a backing image guest
     // implementation for each libvirt type
     match &libvirt_config.libvirt_type {
-        LibvirtGuestOptions::CloudImage { name, .. } => {
+        LibvirtGuestOptions::CloudImage { .. } => {
             if libvirt_config.is_clone_of.is_some() {
                 // is a clone, dont need to copy image as orchestration will create the linked
                 // clone image once the backing image is deployed and setup
             } else {
                 // not a clone, get copy of cloud init from testbed folder
-                let cloud_init_image_path = name
-                    .resolve_path(format!("{TESTBED_SETTINGS_FOLDER}/images/").into()).await?;
+                let cloud_init_image_path = cloud_image.as_ref()
+                    .context("getting the cloud image catalog entry")?
+                    .resolve_path(TESTBED_IMAGES_FOLDER.into()).await?;
                 // if disk already exists, leave it unless force provisioning is true
                 if !check_file_exists(&disk_path_on_main) || common.force_provisioning {
                     // create a copy of the cloud image into the artefacts folder
@@ -321,6 +340,7 @@ async fn libvirt(
             &common,
             network_def,
             libvirt_config,
+            cloud_image.as_ref().context("getting the cloud image catalog entry")?,
             client_name.clone(),
             project_artefacts_folder.clone(),
             guest_config,
@@ -403,6 +423,7 @@ async fn cloud_init_setup(
     common: &OrchestrationCommon,
     network_def: &Option<Vec<MachineNetwork>>,
     libvirt_config: &ConfigLibvirtMachine,
+    cloud_image: &CloudImage,
     client_name: String,
     project_artefacts_folder: String,
     guest_config: &StateTestbedGuest,
@@ -446,7 +467,7 @@ async fn cloud_init_setup(
     };
 
 
-    let user_data = create_user_data();
+    let user_data = create_user_data(&cloud_image.cloud_init);
     let user_data_str = format!("{}/user-data", &project_artefacts_folder);
     let user_data_dest = if !check_file_exists(&user_data_str) || common.force_provisioning {
         let user_data_dest = serialisation::write_file_vecu8_with_permissions_orchestration(
@@ -461,7 +482,7 @@ async fn cloud_init_setup(
     };
 
 
-    let network_config = create_network_config(network_def)?;
+    let network_config = create_network_config(network_def, &common.network, &common.project_name)?;
     let network_config_str = format!("{}/network-config", &project_artefacts_folder);
     let network_config_dest = if !check_file_exists(&network_config_str) || common.force_provisioning {
         let network_config_dest = serialisation::write_file_vecu8_with_permissions_orchestration(
@@ -9,11 +9,15 @@ use nix::unistd::{Gid, Uid};
 use kvm_compose_schemas::kvm_compose_yaml::machines::avd::ConfigAVDMachine;
 use kvm_compose_schemas::kvm_compose_yaml::machines::docker::ConfigDockerMachine;
 use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
+use kvm_compose_schemas::kvm_compose_yaml::MachineNetwork;
 use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt::{ConfigLibvirtMachine, LibvirtGuestOptions};
 use crate::components::get_guest_interface_name;
+use crate::net::{get_docker_host_interface, set_interface_netem};
 use crate::orchestration::{is_main_testbed, OrchestrationCommon, OrchestrationGuestTask, run_testbed_orchestration_command, run_testbed_orchestration_command_allow_fail};
 use crate::orchestration::ssh::SSHClient;
+use crate::ovn::components::OvnIpAddr;
 use crate::ovn::components::logical_switch_port::LogicalSwitchPortType;
+use crate::ovn::configuration::dns::guest_nameservers;
 use crate::state::{State, StateNetwork, StateTestbedGuest, StateTestbedGuestList};
 
 
@@ -324,22 +328,20 @@ impl OrchestrationGuestTask for ConfigLibvirtMachine {
         // only provision an OVN port of is not a backing image guest (linked clones)
         if self.scaling.is_none() {
             // add guest's interface to network
-            // TODO - need to get OVN integration bridge name from config rather than hardcode
             let net = &machine_config.guest_type.network
                 .context("getting guest network in libvirt create action")?;
 
             for (idx, machine_network) in net.iter().enumerate() {
                 let interface = get_guest_interface_name(&common.project_name, machine_config.guest_id, idx);
-                tracing::info!("creating guest {} OVS port {} to bind to OVN network", &machine_config.guest_type.name, &interface);
+                tracing::info!("creating guest {} OVS port {} to bind to network", &machine_config.guest_type.name, &interface);
 
                 let lsp = format!("{}-{}-{}-{}", &common.project_name, machine_network.switch, &machine_config.guest_type.name, idx);
                 let ext_id = format!("external_ids:iface-id={}", &lsp);
 
-                let integration_bridge = &common.kvm_compose_config.testbed_host_ssh_config.get(testbed_host)
-                    .unwrap().ovn.bridge;
+                let guest_bridge = get_guest_bridge(&common, testbed_host, Some(machine_network))?;
 
                 let cmd = vec![
-                    "ovs-vsctl", "add-port", integration_bridge, &interface,
+                    "ovs-vsctl", "add-port", &guest_bridge, &interface,
                     "--", "set", "Interface", &interface,
                     &ext_id,
                 ];
@@ -364,6 +366,9 @@ impl OrchestrationGuestTask for ConfigLibvirtMachine {
                         }
                     }
                 }
+                if let Some(qos) = machine_network.qos.as_ref().filter(|qos| qos.has_netem()) {
+                    set_interface_netem(&common, testbed_host, &interface, None, Some(qos)).await?;
+                }
             }
         }
 
@@ -598,12 +603,12 @@ impl OrchestrationGuestTask for ConfigDockerMachine {
         let net = &machine_config.guest_type.network
             .context("getting guest network in docker create action")?;
 
-        // add dns servers to prevent the use of the host's dns. add cloudflare and the libvirt dns
+        // add dns servers to prevent the use of the host's dns, OVN answers for the other guests'
+        // names and the rest go to the upstream resolver
         // assume there is only one interface for docker
-        if !net.is_empty() {
-            // for now assume only one interface
-            if let Some(gateway) = &net[0].gateway {
-                cmd_string.push(format!("--dns={}", gateway));
+        if let Some(interface) = net.first() {
+            for nameserver in guest_nameservers(interface.gateway.as_ref()) {
+                cmd_string.push(format!("--dns={nameserver}"));
             }
         }
 
@@ -616,6 +621,13 @@ impl OrchestrationGuestTask for ConfigDockerMachine {
             cmd_string.push(user.clone());
         }
 
+        // docker disables ipv6 on the container interfaces unless the docker network has ipv6, so
+        // enable it for dual-stack guests
+        if net.first().is_some_and(|interface| interface.ipv6.is_some()) {
+            cmd_string.push("--sysctl".to_string());
+            cmd_string.push("net.ipv6.conf.all.disable_ipv6=0".to_string());
+        }
+
         // add hostname to container
         cmd_string.push("-h".to_string());
         cmd_string.push(format!("{guest_name}"));
@@ -630,10 +642,9 @@ impl OrchestrationGuestTask for ConfigDockerMachine {
 
         let mut ovs_cmd = vec!["sudo".to_string(), "ovs-docker".to_string(), "add-port".to_string()];
 
-        let integration_bridge = &common.kvm_compose_config.testbed_host_ssh_config.get(testbed_host)
-            .unwrap().ovn.bridge;
+        let guest_bridge = get_guest_bridge(&common, testbed_host, net.first())?;
 
-        ovs_cmd.push(integration_bridge.clone());
+        ovs_cmd.push(guest_bridge.clone());
         ovs_cmd.push("eth0".to_string());
         ovs_cmd.push(guest_name.clone());
 
@@ -648,12 +659,13 @@ impl OrchestrationGuestTask for ConfigDockerMachine {
                         .context(format!("Getting LSP for docker guest {}", &guest_name))?;
                     // do ip address
                     // if guest has been given a dynamic ip, need to check on OVN for the assigned ip
-                    let ip = &net[0].ip;
+                    let ip = net[0].ip()?;
+                    let prefix = common.network.subnet_prefix(&common.project_name, &net[0].switch)?;
                     if ip.eq("dynamic") {
                         let dynamic_ip = get_lsp_dynamic_ip(&lsp_name, testbed_host, &common).await?;
-                        ovs_cmd.push(format!("--ipaddress={dynamic_ip}/24"));
+                        ovs_cmd.push(format!("--ipaddress={dynamic_ip}/{prefix}"));
                     } else {
-                        ovs_cmd.push(format!("--ipaddress={ip}/24"));
+                        ovs_cmd.push(format!("--ipaddress={ip}/{prefix}"));
                     }
 
                     // do mac address
@@ -664,7 +676,14 @@ impl OrchestrationGuestTask for ConfigDockerMachine {
                     ovs_cmd.push(format!("--macaddress={mac}"));
                 }
             }
-            StateNetwork::Ovs(_) => unimplemented!(),
+            StateNetwork::Ovs(_) => {
+                if let Some(interface) = net.first() {
+                    // there is no DHCP on an OVS bridge, so the ip is always static
+                    let prefix = common.network.subnet_prefix(&common.project_name, &interface.switch)?;
+                    ovs_cmd.push(format!("--ipaddress={}/{prefix}", interface.ip()?));
+                    ovs_cmd.push(format!("--macaddress={}", interface.mac()?));
+                }
+            }
         };
 
 
@@ -718,9 +737,8 @@ impl OrchestrationGuestTask for ConfigDockerMachine {
                         ).await?;
                     }
                     if !net.is_empty() {
-                        // and remove the ovs bridge - assume one interface
-                        let port = format!("{}-{}-{}-0", common.project_name, &net[0].switch, &machine_config.guest_type.name);
-                        let cmd = vec!["ovs-docker", "del-port", &port, "eth0", &guest_name];
+                        // and remove the port from the bridge - assume one interface
+                        let cmd = vec!["ovs-docker", "del-port", &guest_bridge, "eth0", &guest_name];
                         run_testbed_orchestration_command(
                             &common,
                             testbed_host,
@@ -780,6 +798,14 @@ impl OrchestrationGuestTask for ConfigDockerMachine {
                         false,
                         None,
                     ).await?;
+                    // ovs-docker only sets the ipv4 address
+                    if !net.is_empty() {
+                        configure_docker_ipv6(&net[0], &guest_name, testbed_host, &common).await?;
+                        if let Some(qos) = net[0].qos.as_ref().filter(|qos| qos.has_netem()) {
+                            let interface = get_docker_host_interface(&guest_name, testbed_host, &common).await?;
+                            set_interface_netem(&common, testbed_host, &interface, None, Some(qos)).await?;
+                        }
+                    }
                 }
                 Err(err) => {
                     let expected_err = format!("Conflict. The container name \"/{guest_name}\" is already in use by con");
@@ -847,9 +873,8 @@ impl OrchestrationGuestTask for ConfigDockerMachine {
         let guest_name = format!("{}-{}", &common.project_name, &machine_config.guest_type.name);
 
         let mut cmd_ovs = vec!["sudo".to_string(), "ovs-docker".to_string(), "del-port".to_string()];
-        let integration_bridge = &common.kvm_compose_config.testbed_host_ssh_config.get(testbed_host)
-            .unwrap().ovn.bridge;
-        cmd_ovs.push(integration_bridge.clone());
+        let guest_bridge = get_guest_bridge(&common, testbed_host, machine_config.guest_type.network.iter().flatten().next())?;
+        cmd_ovs.push(guest_bridge);
         cmd_ovs.push("eth0".to_string());
         cmd_ovs.push(guest_name.clone());
         let cmd: Vec<&str> = cmd_ovs.iter()
@@ -952,17 +977,16 @@ impl OrchestrationGuestTask for ConfigAVDMachine {
                         _ => unreachable!(),
                     }
                 }
-                StateNetwork::Ovs(_) => unimplemented!(),
+                StateNetwork::Ovs(_) => net[0].mac()?.clone(),
             };
             let gateway = &net[0].gateway.as_ref()
                 .context("android guest was not given a gateway")?;
 
             // create port for android guest
             let iface_id = format!("external_ids:iface-id={}", &lsp_name);
-            let integration_bridge = &common.kvm_compose_config.testbed_host_ssh_config.get(testbed_host)
-                .unwrap().ovn.bridge;
+            let guest_bridge = get_guest_bridge(&common, testbed_host, net.first())?;
             let cmd = vec![
-                "ovs-vsctl", "--may-exist", "add-port", integration_bridge, &guest_interface,
+                "ovs-vsctl", "--may-exist", "add-port", &guest_bridge, &guest_interface,
                 "--", "set", "Interface", &guest_interface, "type=internal",
                 "--", "set", "Interface", &guest_interface, &iface_id,
             ];
@@ -1021,12 +1045,12 @@ impl OrchestrationGuestTask for ConfigAVDMachine {
                 None,
             ).await?;
             // set ip of ovs port
-            let ip = if net[0].ip.eq("dynamic") {
+            let ip = if net[0].ip()?.eq("dynamic") {
                 let lsp_name = format!("{}-{}-{}-0", &common.project_name, &net[0].switch, &machine_config.guest_type.name);
                 let dynamic_ip = get_lsp_dynamic_ip(&lsp_name, testbed_host, &common).await?;
                 dynamic_ip
             } else {
-                net[0].ip.clone()
+                net[0].ip()?.clone()
             };
             // TODO - get mask for this ip
             let namespace_ip = format!("{ip}/24");
@@ -1041,6 +1065,22 @@ impl OrchestrationGuestTask for ConfigAVDMachine {
                 false,
                 None,
             ).await?;
+            // set the static ipv6 of the ovs port, auto ipv6 addresses are configured from the
+            // router advertisements once the port is up
+            if let Some((ipv6, prefix)) = net[0].static_ipv6()? {
+                let namespace_ipv6 = format!("{ipv6}/{prefix}");
+                let cmd = vec![
+                    "ip", "netns", "exec", &namespace, "ip", "-6", "addr", "add", &namespace_ipv6, "dev", &guest_interface,
+                ];
+                run_testbed_orchestration_command_allow_fail(
+                    &common,
+                    testbed_host,
+                    "sudo",
+                    cmd,
+                    false,
+                    None,
+                ).await?;
+            }
 
             // set ovs port up
             let cmd = vec![
@@ -1066,16 +1106,39 @@ impl OrchestrationGuestTask for ConfigAVDMachine {
                 false,
                 None,
             ).await?;
+            if let Some(ipv6_gateway) = &net[0].ipv6_gateway {
+                let cmd = vec![
+                    "ip", "netns", "exec", &namespace, "ip", "-6", "route", "add", "default", "via", ipv6_gateway, "dev", &guest_interface,
+                ];
+                run_testbed_orchestration_command_allow_fail(
+                    &common,
+                    testbed_host,
+                    "sudo",
+                    cmd,
+                    false,
+                    None,
+                ).await?;
+            }
+            // the interface is in the namespace, so this shapes the traffic sent by the guest
+            if let Some(qos) = net[0].qos.as_ref().filter(|qos| qos.has_netem()) {
+                set_interface_netem(&common, testbed_host, &guest_interface, Some(&namespace), Some(qos)).await?;
+            }
         }
 
 
-        // finally, deploy avd in background
-        let cmd = vec![
+        // finally, deploy avd in background, the emulator forwards the guest's dns queries to the
+        // same nameservers as the other guests
+        let nameservers = net.first()
+            .map(|interface| guest_nameservers(interface.gateway.as_ref()).join(","));
+        let mut cmd = vec![
             "ip", "netns", "exec", &namespace, "/opt/android-sdk/emulator/emulator",
             "-avd", &guest_project_name,
             // qemu options
 
         ];
+        if let Some(nameservers) = &nameservers {
+            cmd.extend(["-dns-server", nameservers]);
+        }
         run_testbed_orchestration_command(
             &common,
             testbed_host,
@@ -1116,10 +1179,8 @@ impl OrchestrationGuestTask for ConfigAVDMachine {
             false,
             None,
         ).await?;
-        // destroy the ovs port for the namespace
-        let integration_bridge = &common.kvm_compose_config.testbed_host_ssh_config.get(testbed_host)
-            .unwrap().ovn.bridge;
-        let cmd = vec!["ovs-vsctl", "del-port", integration_bridge, &guest_interface];
+        // destroy the ovs port for the namespace, ovs finds the bridge the port is on
+        let cmd = vec!["ovs-vsctl", "del-port", &guest_interface];
         run_testbed_orchestration_command_allow_fail(
             &common,
             testbed_host,
@@ -1147,6 +1208,28 @@ impl OrchestrationGuestTask for ConfigAVDMachine {
     }
 }
 
+/// Get the OVS bridge that a guest's interface is plugged into. For OVN this is the integration
+/// bridge of the testbed host, for OVS it is the bridge set as the interface's switch.
+fn get_guest_bridge(
+    common: &OrchestrationCommon,
+    testbed_host: &String,
+    interface: Option<&MachineNetwork>,
+) -> anyhow::Result<String> {
+    match &common.network {
+        StateNetwork::Ovn(_) => {
+            let host_config = common.kvm_compose_config.testbed_host_ssh_config.get(testbed_host)
+                .with_context(|| format!("getting testbed host {testbed_host} config for the integration bridge"))?;
+            Ok(host_config.ovn.bridge.clone())
+        }
+        StateNetwork::Ovs(ovs) => {
+            let interface = interface.context("guest has no interface to plug into an OVS bridge")?;
+            let bridge = ovs.bridges.get(&interface.switch)
+                .with_context(|| format!("getting OVS bridge {} for guest interface", &interface.switch))?;
+            Ok(bridge.name.clone())
+        }
+    }
+}
+
 fn get_local_image_folder_path(
     project_working_dir: &String,
 ) -> String {
@@ -1304,12 +1387,18 @@ fn get_remote_project_folder(
 }
 
 /// Get the ip address assigned by OVN to a logical switch port that has been given a dynamic IP
-/// address.
+/// address. A port with an ip reserved in the DHCP configuration already has its ip.
 async fn get_lsp_dynamic_ip(
     lsp_name: &String,
     testbed_host: &String,
     orchestration_common: &OrchestrationCommon,
 ) -> anyhow::Result<String> {
+    if let StateNetwork::Ovn(ovn) = &orchestration_common.network {
+        if let Some(LogicalSwitchPortType::Internal { ip: OvnIpAddr::Ip(ip), .. }) = ovn.switch_ports.get(lsp_name)
+            .map(|lsp| &lsp.port_type) {
+            return Ok(ip.to_string());
+        }
+    }
     tracing::info!("getting dynamic ip address assigned to logical switch port {lsp_name}");
     let name = format!("name={lsp_name}");
     let res = run_testbed_orchestration_command(
@@ -1320,15 +1409,60 @@ async fn get_lsp_dynamic_ip(
         false,
         None,
     ).await?;
-    // result will be "mac ip" so we need to make sure there are two results, get the second, and
-    // then also remove the trailing newline
-    let split: Vec<_> = res.split(" ").collect();
-    if split.len() != 2 {
+    // result will be "mac ip", or "mac ip ipv6" if the switch has an ipv6 prefix, so we need to
+    // make sure there are at least two results and get the second
+    let split: Vec<_> = res.trim().split(" ").collect();
+    if split.len() < 2 {
         bail!("could not get the dynamic ip for lsp {lsp_name} as result from NB DB was {}", &res);
     }
     let ip = split[1];
-    let ip = ip.strip_suffix("\n")
-        .context("stripping newline from dynamic ip")?;
 
     Ok(ip.to_string())
 }
+
+/// Give a docker guest its static ipv6 address and default route. The commands are run in the
+/// network namespace of the container, as the image may not have the ip command. Auto addresses
+/// are configured by the container kernel from the router advertisements.
+async fn configure_docker_ipv6(
+    interface: &MachineNetwork,
+    guest_name: &String,
+    testbed_host: &String,
+    orchestration_common: &OrchestrationCommon,
+) -> anyhow::Result<()> {
+    let ipv6 = interface.static_ipv6()?;
+    if ipv6.is_none() && interface.ipv6_gateway.is_none() {
+        return Ok(());
+    }
+    let pid = run_testbed_orchestration_command(
+        orchestration_common,
+        testbed_host,
+        "sudo",
+        vec!["docker", "inspect", "-f", "{{.State.Pid}}", guest_name],
+        false,
+        None,
+    ).await?;
+    let pid = pid.trim();
+    if let Some((ipv6, prefix)) = ipv6 {
+        tracing::info!("setting ipv6 address {ipv6}/{prefix} on docker guest {guest_name}");
+        let address = format!("{ipv6}/{prefix}");
+        run_testbed_orchestration_command(
+            orchestration_common,
+            testbed_host,
+            "sudo",
+            vec!["nsenter", "-t", pid, "-n", "ip", "-6", "addr", "add", &address, "dev", "eth0"],
+            false,
+            None,
+        ).await?;
+    }
+    if let Some(gateway) = &interface.ipv6_gateway {
+        run_testbed_orchestration_command(
+            orchestration_common,
+            testbed_host,
+            "sudo",
+            vec!["nsenter", "-t", pid, "-n", "ip", "-6", "route", "add", "default", "via", gateway, "dev", "eth0"],
+            false,
+            None,
+        ).await?;
+    }
+    Ok(())
+}
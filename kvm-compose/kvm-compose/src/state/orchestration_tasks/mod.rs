@@ -22,7 +22,7 @@ use crate::state::orchestration_tasks::stages::*;
 pub mod ovs_network;
 pub mod ovn_network;
 pub mod guests;
-mod stages;
+pub(crate) mod stages;
 pub mod generate_artefacts;
 
 
@@ -240,6 +240,9 @@ impl OrchestrationTask for State {
         }
         try_join_all(guest_deploy_futures).await?;
 
+        tracing::info!("Stage: creating any mirrors");
+        self.network.create_mirrors_action(common).await?;
+
         // if the guest has a setup script, execute it
         if !self.state_provisioning.guests_provisioned || common.force_rerun_scripts {
             // only run setup scripts if either forcing provisioning or state never been provisioned
@@ -333,7 +336,7 @@ impl OrchestrationTask for State {
                 ).await.context("requesting create temporary network instruction")?;
             }
 
-            setup_backing_image_stage(&self, sender).await?;
+            setup_backing_image_stage(&self.testbed_guests, sender).await?;
 
             if net_provision {
                 tracing::info!("turning on temporary network for backing images with shared setup scripts");
@@ -345,7 +348,7 @@ impl OrchestrationTask for State {
             }
 
             tracing::info!("Stage: creating any libvirt clones of backing image guests");
-            setup_linked_clones_stage(&self, sender).await?;
+            setup_linked_clones_stage(&self.testbed_guests, sender).await?;
         } else {
             tracing::info!("skipping setting up backing image and creating clones as they have already been provisioned");
         }
@@ -353,18 +356,23 @@ impl OrchestrationTask for State {
         // if already been provisioned previously, this will only check if the images exist on the
         // remote
         tracing::info!("Stage: pushing guest images to remote testbed hosts");
-        push_guest_images_stage(&self, sender).await?;
-        push_backing_guest_images_stage(&self, common, sender).await?;
+        push_guest_images_stage(&self.testbed_guests, sender).await?;
+        push_backing_guest_images_stage(&self.testbed_guests, common, sender).await?;
 
         tracing::info!("Stage: rebasing clones on remote testbed hosts");
-        rebase_clone_images_stage(&self, common, sender).await?;
+        rebase_clone_images_stage(&self.testbed_guests, common, sender).await?;
 
         tracing::info!("Stage: deploying guests");
-        deploy_guest_stage(&self, sender).await?;
+        deploy_guest_stage(&self.testbed_guests, sender).await?;
+
+        tracing::info!("Stage: creating any mirrors");
+        self.network.request_create_mirrors_action(sender)
+            .await
+            .context("requesting mirrors to be created")?;
 
         if !self.state_provisioning.guests_provisioned || common.force_rerun_scripts {
             tracing::info!("Stage: running any guest setup scripts");
-            run_guest_setup_scripts_stage(&self, sender).await?;
+            run_guest_setup_scripts_stage(&self.testbed_guests, sender).await?;
         } else {
             tracing::info!("Skipping guest setup scripts as guest have already been provisioned");
         }
@@ -381,8 +389,13 @@ impl OrchestrationTask for State {
             OrchestrationInstruction::TestbedHostCheck,
         ).await.context("requesting if testbed hosts are up")?;
 
+        // destroy mirrors while their sink guests still exist
+        self.network.request_destroy_mirrors_action(sender)
+            .await
+            .context("requesting mirrors to be destroyed")?;
+
         // destroy guests
-        destroy_guest_stage(&self, sender).await?;
+        destroy_guest_stage(&self.testbed_guests, sender).await?;
 
         // make sure temporary network is down
         send_orchestration_instruction_over_channel(
@@ -515,7 +528,7 @@ pub async fn check_if_testbed_hosts_up(
 /// have a shared setup script. If there is a shared setup script then we count this guest. If there
 /// are any guests with a shared setup script then we will want to provision a temporary network
 /// for them.
-fn check_provision_temporary_network(state_testbed_guest_list: &StateTestbedGuestList) -> bool {
+pub(crate) fn check_provision_temporary_network(state_testbed_guest_list: &StateTestbedGuestList) -> bool {
     // if there are backing images with a setup script, we need to provision a temporary
     // network
     let provision_temporary_network = state_testbed_guest_list.0
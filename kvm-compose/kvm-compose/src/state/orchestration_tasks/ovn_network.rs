@@ -9,6 +9,8 @@ use crate::components::network::LogicalNetwork;
 use crate::orchestration::{OrchestrationCommon, OrchestrationTask, read_previous_state_request, run_subprocess_command, run_subprocess_command_allow_fail, run_testbed_orchestration_command, run_testbed_orchestration_command_allow_fail, write_state_request};
 use crate::orchestration::api::*;
 use crate::orchestration::websocket::{send_orchestration_instruction_over_channel};
+use crate::ovn::components::acl::ACLRecordType;
+use crate::ovn::diff::OvnNetworkDiff;
 use crate::ovn::OvnCommand;
 use crate::state::{State, StateNetwork};
 
@@ -109,6 +111,15 @@ impl OrchestrationTask for StateNetwork {
                         )
                     ).await?;
                 }
+                for flow_export in ovn_state.flow_exports.values() {
+                    flow_export.create_command(
+                        &ovn_run_cmd,
+                        (
+                            Some(chassis_to_tb_host(&flow_export.chassis, common)?),
+                            common.clone(),
+                        )
+                    ).await?;
+                }
                 for (_, router) in &ovn_state.routers {
                     for (_, route) in &router.routing.0 {
                         route.create_command(&ovn_run_cmd, (None, common.clone())).await?;
@@ -129,9 +140,15 @@ impl OrchestrationTask for StateNetwork {
                     // "remote_config" for this - should probably consider renaming it
                     dhcp.create_command(&ovn_run_cmd, (None, common.clone())).await?;
                 }
+                for load_balancer in ovn_state.load_balancers.values() {
+                    load_balancer.create_command(&ovn_run_cmd, (None, common.clone())).await?;
+                }
+                for dns_records in ovn_state.dns_records.values() {
+                    dns_records.create_command(&ovn_run_cmd, (None, common.clone())).await?;
+                }
 
             }
-            StateNetwork::Ovs(_) => unimplemented!(),
+            StateNetwork::Ovs(ovs_state) => ovs_state.create_action(common).await?,
         }
 
         Ok(())
@@ -143,6 +160,17 @@ impl OrchestrationTask for StateNetwork {
         // destroy all OVN resources
         match &self {
             StateNetwork::Ovn(ovn_state) => {
+                for mirror in ovn_state.mirrors.values() {
+                    mirror.destroy_command(&ovn_run_cmd_allow_fail, (Some(mirror.sink_testbed_host.clone()), common.clone())).await?;
+                }
+                // load balancers and DNS records are not removed with the switches and routers they
+                // are attached to
+                for load_balancer in ovn_state.load_balancers.values() {
+                    load_balancer.destroy_command(&ovn_run_cmd_allow_fail, (None, common.clone())).await?;
+                }
+                for dns_records in ovn_state.dns_records.values() {
+                    dns_records.destroy_command(&ovn_run_cmd_allow_fail, (None, common.clone())).await?;
+                }
                 for dhcp in &ovn_state.dhcp_options {
                     dhcp.destroy_command(&ovn_run_cmd_allow_fail, (None, common.clone())).await?;
                 }
@@ -173,6 +201,14 @@ impl OrchestrationTask for StateNetwork {
                 for (_, lr_data) in &ovn_state.routers {
                     lr_data.destroy_command(&ovn_run_cmd_allow_fail, (None, common.clone())).await?;
                 }
+                for flow_export in ovn_state.flow_exports.values() {
+                    flow_export.destroy_command(
+                        &ovn_run_cmd_allow_fail,
+                        (
+                            Some(chassis_to_tb_host(&flow_export.chassis, common)?),
+                            common.clone(),
+                        )).await?;
+                }
                 for (_, lrp_data) in &ovn_state.ovs_ports {
                     // OVS ports may need to be destroyed on remote testbed hosts
                     lrp_data.destroy_command(
@@ -183,13 +219,13 @@ impl OrchestrationTask for StateNetwork {
                         )).await?;
                 }
             }
-            StateNetwork::Ovs(_) => unimplemented!(),
+            StateNetwork::Ovs(ovs_state) => ovs_state.destroy_action(common).await?,
         }
 
         Ok(())
     }
 
-    async fn request_create_action(&self, _common: &OrchestrationCommon, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
+    async fn request_create_action(&self, common: &OrchestrationCommon, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
         tracing::info!("requesting to deploy OVN components");
         // no need to batch these as OVN is quick to create resources
 
@@ -232,6 +268,12 @@ impl OrchestrationTask for StateNetwork {
                         OrchestrationInstruction::Deploy(vec![ovs_data.to_orchestration_resource()]),
                     ).await.context("requesting the creation of ovs port")?;
                 }
+                for flow_export in ovn_state.flow_exports.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Deploy(vec![flow_export.to_orchestration_resource()]),
+                    ).await.context("requesting the creation of flow export")?;
+                }
                 for (_, router) in &ovn_state.routers {
                     for (_, route) in &router.routing.0 {
                         send_orchestration_instruction_over_channel(
@@ -266,30 +308,79 @@ impl OrchestrationTask for StateNetwork {
                         OrchestrationInstruction::Deploy(vec![dhcp.to_orchestration_resource()]),
                     ).await.context("requesting the creation of dhcp rule")?;
                 }
+                // the address sets and port groups are used by the ACLs
+                for address_set in ovn_state.address_sets.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Deploy(vec![address_set.to_orchestration_resource()]),
+                    ).await.context("requesting the creation of address set")?;
+                }
+                for port_group in ovn_state.port_groups.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Deploy(vec![port_group.to_orchestration_resource()]),
+                    ).await.context("requesting the creation of port group")?;
+                }
                 for (_, acl_record) in &ovn_state.acl {
                     send_orchestration_instruction_over_channel(
                         sender,
                         OrchestrationInstruction::Deploy(vec![acl_record.to_orchestration_resource()]),
                     ).await.context("requesting the creation of ACL")?;
                 }
+                for load_balancer in ovn_state.load_balancers.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Deploy(vec![load_balancer.to_orchestration_resource()]),
+                    ).await.context("requesting the creation of load balancer")?;
+                }
+                for dns_records in ovn_state.dns_records.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Deploy(vec![dns_records.to_orchestration_resource()]),
+                    ).await.context("requesting the creation of DNS records")?;
+                }
             }
-            StateNetwork::Ovs(_) => unimplemented!(),
+            StateNetwork::Ovs(ovs_state) => ovs_state.request_create_action(common, sender).await?,
         }
 
         Ok(())
     }
 
-    async fn request_destroy_action(&self, _common: &OrchestrationCommon, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
+    async fn request_destroy_action(&self, common: &OrchestrationCommon, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
         tracing::info!("requesting to destroy OVN components");
         // no need to batch these as OVN is quick to create resources
         match &self {
             StateNetwork::Ovn(ovn_state) => {
+                for dns_records in ovn_state.dns_records.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Destroy(vec![dns_records.to_orchestration_resource()]),
+                    ).await.context("requesting the destruction of DNS records")?;
+                }
+                for load_balancer in ovn_state.load_balancers.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Destroy(vec![load_balancer.to_orchestration_resource()]),
+                    ).await.context("requesting the destruction of load balancer")?;
+                }
                 for (_, acl_record) in &ovn_state.acl {
                     send_orchestration_instruction_over_channel(
                         sender,
                         OrchestrationInstruction::Destroy(vec![acl_record.to_orchestration_resource()]),
                     ).await.context("requesting the destruction of ACL")?;
                 }
+                for port_group in ovn_state.port_groups.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Destroy(vec![port_group.to_orchestration_resource()]),
+                    ).await.context("requesting the destruction of port group")?;
+                }
+                for address_set in ovn_state.address_sets.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Destroy(vec![address_set.to_orchestration_resource()]),
+                    ).await.context("requesting the destruction of address set")?;
+                }
                 for (_, ls_data) in &ovn_state.switches {
                     send_orchestration_instruction_over_channel(
                         sender,
@@ -353,6 +444,12 @@ impl OrchestrationTask for StateNetwork {
                         OrchestrationInstruction::Destroy(vec![dhcp.to_orchestration_resource()]),
                     ).await.context("requesting the destruction of dhcp rule")?;
                 }
+                for flow_export in ovn_state.flow_exports.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Destroy(vec![flow_export.to_orchestration_resource()]),
+                    ).await.context("requesting the destruction of flow export")?;
+                }
                 for (_, ovs_data) in &ovn_state.ovs_ports {
                     send_orchestration_instruction_over_channel(
                         sender,
@@ -362,9 +459,62 @@ impl OrchestrationTask for StateNetwork {
                 }
 
             }
-            StateNetwork::Ovs(_) => unimplemented!(),
+            StateNetwork::Ovs(ovs_state) => ovs_state.request_destroy_action(common, sender).await?,
+        }
+
+        Ok(())
+    }
+}
+
+impl StateNetwork {
+    /// Create the mirrors in the network. Mirrors send traffic to a guest's interface, so unlike the
+    /// rest of the network they can only be created once the guests are running.
+    pub async fn create_mirrors_action(&self, common: &OrchestrationCommon) -> anyhow::Result<()> {
+        match &self {
+            StateNetwork::Ovn(ovn_state) => {
+                for mirror in ovn_state.mirrors.values() {
+                    mirror.create_command(&ovn_run_cmd, (Some(mirror.sink_testbed_host.clone()), common.clone())).await?;
+                }
+            }
+            // mirrors are only in the OVN network schema
+            StateNetwork::Ovs(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Request the server to create the mirrors in the network, this must be done after the guests
+    /// have been deployed
+    pub async fn request_create_mirrors_action(&self, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
+        match &self {
+            StateNetwork::Ovn(ovn_state) => {
+                for mirror in ovn_state.mirrors.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Deploy(vec![mirror.to_orchestration_resource()]),
+                    ).await.context("requesting the creation of mirror")?;
+                }
+            }
+            // mirrors are only in the OVN network schema
+            StateNetwork::Ovs(_) => {}
         }
+        Ok(())
+    }
 
+    /// Request the server to destroy the mirrors in the network, this is done before the guests
+    /// are destroyed so that the sink interfaces can be cleaned up
+    pub async fn request_destroy_mirrors_action(&self, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
+        match &self {
+            StateNetwork::Ovn(ovn_state) => {
+                for mirror in ovn_state.mirrors.values() {
+                    send_orchestration_instruction_over_channel(
+                        sender,
+                        OrchestrationInstruction::Destroy(vec![mirror.to_orchestration_resource()]),
+                    ).await.context("requesting the destruction of mirror")?;
+                }
+            }
+            // mirrors are only in the OVN network schema
+            StateNetwork::Ovs(_) => {}
+        }
         Ok(())
     }
 }
@@ -379,8 +529,8 @@ pub async fn reapply_acl_action(
     http_client: &Client,
     server_conn: &String,
 ) -> anyhow::Result<()> {
-    // TODO - There is an assumption that only the ACL rules were changed, this needs to be
-    //  handled properly when implementing delta changes
+    // only the ACL section of the yaml is applied here, any other network changes are applied by
+    // up using the state delta
 
     // get ovn logical representation from state
     let current_network = match &current_state.network {
@@ -402,6 +552,7 @@ pub async fn reapply_acl_action(
         .collect();
     let yaml_acl_switch_list: HashSet<_> = new_network.acl
         .iter()
+        .filter(|(_, acl)| matches!(acl._type, ACLRecordType::Switch))
         .map(|(_, acl)| {
             &acl.entity_name
         })
@@ -409,6 +560,13 @@ pub async fn reapply_acl_action(
 
     ensure_yaml_acl_switches_already_exist(current_switch_list, yaml_acl_switch_list)?;
 
+    // the port groups can only contain switch ports that have already been deployed
+    for port_group in new_network.port_groups.values() {
+        if let Some(port) = port_group.ports.iter().find(|port| !current_network.switch_ports.contains_key(*port)) {
+            bail!("port group {} has the switch port {port} that doesn't exist in the current state, run up to deploy it", &port_group.name);
+        }
+    }
+
     send_orchestration_instruction_over_channel(
         sender,
         OrchestrationInstruction::Init {
@@ -417,38 +575,27 @@ pub async fn reapply_acl_action(
         },
     ).await.context("sending Init request to server")?;
 
-    // if Ok, then destroy current acl rules, then create the new ones
-    match &current_state.network {
-        StateNetwork::Ovn(ovn_state) => {
-            for (_, acl_record) in &ovn_state.acl {
-                send_orchestration_instruction_over_channel(
-                    sender,
-                    OrchestrationInstruction::Destroy(vec![acl_record.to_orchestration_resource()]),
-                ).await.context("requesting the destruction of ACL")?;
-            }
-        }
-        StateNetwork::Ovs(_) => unimplemented!(),
-    }
+    // if Ok, destroy the acl rules that were removed or changed, then create the new ones
+    let mut acl_diff = OvnNetworkDiff::new(current_network, new_network);
+    acl_diff.retain(|resource| matches!(resource,
+        OrchestrationResourceNetwork::ACL(_)
+        | OrchestrationResourceNetwork::PortGroup(_)
+        | OrchestrationResourceNetwork::AddressSet(_)
+    ));
+    acl_diff.request_destroy_action(sender).await?;
+    acl_diff.request_create_action(sender).await?;
 
-    // create a new state
-    let new_state = State::new(&logical_testbed)
-        .context("Creating state from logical testbed")?;
-    match &new_state.network {
+    // if successful update the current state, only the ACL part, then save to disk
+    let mut previous_state = read_previous_state_request(&http_client, &server_conn, &project_name).await?;
+    match &mut previous_state.network {
         StateNetwork::Ovn(ovn_state) => {
-            for (_, acl_record) in &ovn_state.acl {
-                send_orchestration_instruction_over_channel(
-                    sender,
-                    OrchestrationInstruction::Deploy(vec![acl_record.to_orchestration_resource()]),
-                ).await.context("requesting the creation of ACL")?;
-            }
+            ovn_state.acl = new_network.acl.clone();
+            ovn_state.port_groups = new_network.port_groups.clone();
+            ovn_state.address_sets = new_network.address_sets.clone();
         }
-        StateNetwork::Ovs(_) => unimplemented!(),
+        StateNetwork::Ovs(_) => unreachable!(),
     }
 
-    // if successful update the current state, only the ACL part, then save to disk
-    let mut previous_state = read_previous_state_request(&http_client, &server_conn, &project_name).await?;
-    previous_state.network = new_state.network;
-
     write_state_request(&http_client, &server_conn, &project_name, &previous_state)
         .await
         .context("Sending the state json file to server to save to disk.")?;
@@ -1,484 +1,252 @@
-// use anyhow::{bail, Context};
-// use async_trait::async_trait;
-// use virt::connect::Connect;
-// use virt::network::Network;
-// use crate::orchestration::{OrchestrationCommon, OrchestrationTask, run_subprocess_command, run_subprocess_command_allow_fail, run_testbed_orchestration_command, run_testbed_orchestration_command_allow_fail};
-// use crate::state::{StateOvsNetwork};
-// use crate::state::load_balancing::BridgeConnection;
+use std::future::Future;
+use anyhow::Context;
+use async_trait::async_trait;
+use tokio::sync::mpsc::Sender;
+use crate::orchestration::{OrchestrationCommon, OrchestrationTask};
+use crate::orchestration::api::{OrchestrationInstruction, OrchestrationProtocol, OrchestrationResource, OrchestrationResourceNetworkType, OrchestrationResourceOvsNetwork};
+use crate::orchestration::websocket::send_orchestration_instruction_over_channel;
+use crate::ovn::OvnCommand;
+use crate::state::{StateOvsBridge, StateOvsNetwork};
+use crate::state::load_balancing::BridgeConnection;
+use crate::state::orchestration_tasks::ovn_network::{ovn_run_cmd, ovn_run_cmd_allow_fail};
+use crate::vec_of_strings;
 
-// TODO - leaving this as we may need it again once we reimplement OVS network backend
+// the OVS network components know which testbed host they are on, so the testbed host in the
+// config given to the `OvnCommand` functions is not used
 
-// // this creates the base libvirt network
-// #[async_trait]
-// impl OrchestrationTask for StateOvsNetwork {
-//     async fn create_action(&self, common: OrchestrationCommon) -> anyhow::Result<()> {
-//         tracing::info!("deploying libvirt network");
-//
-//         // scope the libvirt connection so that it is dropped when exited scopr as it is not thread safe
-//         let create_libvirt_network = async {
-//             let conn = Connect::open("qemu:///system")
-//                 .context("Connecting to qemu:///system").expect("Connecting to libvirt");
-//             let res = Network::define_xml(&conn, &self.libvirt_network_xml);
-//             match res {
-//                 Ok(_) => Ok(()),
-//                 Err(err) => {
-//                     tracing::warn!("error in defining network, probably already exists, error: {:#}", err);
-//                     let expected_err = "already exists with uuid".to_string();
-//                     if !err.to_string().trim().contains(&expected_err) {
-//                         // error in joining blocking thread
-//                         Err(err)
-//                     } else {
-//                         Ok(())
-//                     }
-//
-//                 }
-//             }
-//         }.await;
-//         match create_libvirt_network {
-//             Ok(..) => {}
-//             Err(e) => {
-//                 // skip error if already defined
-//                 let expected_err = "already exists with uuid".to_string();
-//
-//                 if !e.to_string().trim().contains(&expected_err) {
-//                     // error in joining blocking thread
-//                     tracing::error!("{:#}", e);
-//                     bail!("could not define network in libvirt, quitting")
-//                 }
-//             }
-//         }
-//
-//         let project_name = &common.project_name;
-//         let libvirt_network_name = format!("{project_name}-network");
-//         let br_name = &self.livbirt_network_bridge_name;
-//         let veth_peer_left = format!("{project_name}-veth0");
-//         let veth_peer_right = format!("{project_name}-veth1");
-//         // run the commands
-//         let cmd = vec!["virsh", "net-start", &libvirt_network_name];
-//         run_subprocess_command_allow_fail(
-//             "sudo",
-//             cmd,
-//             false).await?;
-//         let cmd = vec!["virsh", "net-autostart", &libvirt_network_name];
-//         run_subprocess_command(
-//             "sudo",
-//             cmd,
-//             false).await?;
-//         // might exist
-//         let cmd = vec!["ip", "link", "add", &veth_peer_left, "type", "veth", "peer", "name", &veth_peer_right];
-//         run_subprocess_command_allow_fail(
-//             "sudo",
-//             cmd,
-//             false).await?;
-//         let cmd = vec!["ip", "link", "set", "up", "dev", &veth_peer_left];
-//         run_subprocess_command_allow_fail(
-//             "sudo",
-//             cmd,
-//             false).await?;
-//         let cmd = vec!["ip", "link", "set", "up", "dev", &veth_peer_right];
-//         run_subprocess_command_allow_fail(
-//             "sudo",
-//             cmd,
-//             false).await?;
-//         // continue
-//         let cmd = vec!["ip", "link", "set", &veth_peer_right, "master", &br_name];
-//         run_subprocess_command_allow_fail(
-//             "sudo",
-//             cmd,
-//             false).await?;
-//
-//         tracing::info!("finished deploying libvirt network");
-//
-//         Ok(())
-//
-//     }
-//
-//     async fn destroy_action(&self, common: OrchestrationCommon) -> anyhow::Result<()> {
-//
-//         tracing::info!("destroying libvirt network");
-//
-//         let project_name = &common.project_name;
-//         let libvirt_network_name = format!("{project_name}-network");
-//         let veth_peer_left = format!("{project_name}-veth0");
-//         // run delete commands for libvirt network
-//         let cmd = vec!["ip", "link", "delete", &veth_peer_left];
-//         run_subprocess_command_allow_fail(
-//             "sudo",
-//             cmd,
-//             false).await?;
-//         let cmd = vec!["virsh", "net-destroy", &libvirt_network_name];
-//         run_subprocess_command_allow_fail(
-//             "sudo",
-//             cmd,
-//             false).await?;
-//         let cmd = vec!["virsh", "net-undefine", &libvirt_network_name];
-//         run_subprocess_command_allow_fail(
-//             "sudo",
-//             cmd,
-//             false).await?;
-//
-//         tracing::info!("finished destroying libvirt network");
-//
-//         Ok(())
-//     }
-// }
+impl StateOvsNetwork {
+    /// Get all the resources in the network, in the order they must be created
+    pub fn to_orchestration_resources(&self) -> Vec<OrchestrationResource> {
+        let bridges = self.bridges.values()
+            .map(|bridge| bridge.to_orchestration_resource());
+        let connections = self.connections.iter()
+            .map(|connection| connection.to_orchestration_resource());
+        bridges.chain(connections).collect()
+    }
+}
 
+#[async_trait]
+impl OrchestrationTask for StateOvsNetwork {
+    async fn create_action(&self, common: &OrchestrationCommon) -> anyhow::Result<()> {
+        tracing::info!("deploying OVS bridges");
+        for bridge in self.bridges.values() {
+            bridge.create_command(&ovn_run_cmd, (None, common.clone())).await?;
+        }
+        for connection in &self.connections {
+            connection.create_command(&ovn_run_cmd, (None, common.clone())).await?;
+        }
+        Ok(())
+    }
 
-// // this creates the OVS bridges
-// #[async_trait]
-// impl OrchestrationTask for StateTestbedInterface {
-//     async fn create_action(&self, common: OrchestrationCommon) -> anyhow::Result<()> {
-//
-//         let bridge_name = &self.name;
-//         let project_name = &common.project_name;
-//         let ovs_bridge_name = format!("{project_name}-{bridge_name}");
-//         let testbed_host = self.testbed_host.as_ref().unwrap();
-//         let testbed_host_ip = common.testbed_hosts.get(testbed_host)
-//             .expect("could not find testbed host config for bridge")
-//             .ip.clone();
-//         let controller = format!("tcp:{testbed_host_ip}:6653");
-//
-//         // create bridge
-//         let cmd = vec!["ovs-vsctl", "--may-exist", "add-br", &ovs_bridge_name];
-//         run_testbed_orchestration_command(
-//             &common,
-//             testbed_host,
-//             "sudo",
-//             cmd,
-//             false).await?;
-//         // set openflow protocol
-//         let cmd = vec!["ovs-vsctl", "set", "bridge", &ovs_bridge_name, "protocol=OpenFlow13"];
-//         run_testbed_orchestration_command(
-//             &common,
-//             testbed_host,
-//             "sudo",
-//             cmd,
-//             false).await?;
-//         // set controller
-//         let cmd = vec!["ovs-vsctl", "set-controller", &ovs_bridge_name, &controller];
-//         run_testbed_orchestration_command(
-//             &common,
-//             testbed_host,
-//             "sudo",
-//             cmd,
-//             false).await?;
-//
-//         Ok(())
-//     }
-//
-//     async fn destroy_action(&self, common: OrchestrationCommon) -> anyhow::Result<()> {
-//
-//         // let bridge_name = &self.name;
-//         // let project_name = &common.project_name;
-//         // let ovs_bridge_name = format!("{project_name}-{bridge_name}");
-//         // let testbed_host = self.testbed_host.as_ref().unwrap();
-//         //
-//         // if self.name.eq(&common.external_bridge) {
-//         //     let veth_peer_left = format!("{project_name}-veth0");
-//         //
-//         //     let cmd = vec!["ovs-vsctl", "del-port", &ovs_bridge_name, &veth_peer_left];
-//         //     run_testbed_orchestration_command_allow_fail(
-//         //         &common,
-//         //         testbed_host,
-//         //         "sudo",
-//         //         cmd,
-//         //         false).await?;
-//         // }
-//         //
-//         // let cmd = vec!["ovs-vsctl", "del-br", &ovs_bridge_name];
-//         // run_testbed_orchestration_command_allow_fail(
-//         //     &common,
-//         //     testbed_host,
-//         //     "sudo",
-//         //     cmd,
-//         //     false).await?;
-//
-//         Ok(())
-//     }
-// }
-//
-// // this creates the connections between OVS bridges, if across testbed hosts
-// #[async_trait]
-// impl OrchestrationTask for PhysicalBridgeConnections {
-//     async fn create_action(&self, common: OrchestrationCommon) -> anyhow::Result<()> {
-//
-//         // loop through all the testbed hosts
-//         for (logical_host, testbed_host_data) in common.testbed_hosts.iter() {
-//             let testbed_host = logical_host;
-//             let testbed_host_nic = &testbed_host_data.testbed_nic;
-//
-//             // count geneve interfaces per host to make sure all interfaces on the ovs tunnel with the geneve tunnel are unique
-//             let mut geneve_interface_counter = 0;
-//
-//             // each connection may or may not be a tunnel
-//             for (_, connection_info) in self.0.as_ref().unwrap().iter() {
-//                 match connection_info {
-//                     BridgeConnection::Ovs { source_br, target_br, source_veth, target_veth, testbed_host, .. } => {
-//                         let cmd = vec!["ip", "link", "add", &source_veth, "type", "veth", "peer", "name", &target_veth];
-//                         run_testbed_orchestration_command(
-//                             &common,
-//                             testbed_host,
-//                             "sudo",
-//                             cmd,
-//                             false).await?;
-//
-//                         // bring up bridges
-//                         let cmd = vec!["ip", "link", "set", "up", "dev", &source_veth];
-//                         run_testbed_orchestration_command(
-//                             &common,
-//                             testbed_host,
-//                             "sudo",
-//                             cmd,
-//                             false).await?;
-//                         let cmd = vec!["ip", "link", "set", "up", "dev", &target_veth];
-//                         run_testbed_orchestration_command(
-//                             &common,
-//                             testbed_host,
-//                             "sudo",
-//                             cmd,
-//                             false).await?;
-//                         // add ports to bridges
-//                         let cmd = vec!["ovs-vsctl", "--may-exist", "add-port", &source_br, &source_veth];
-//                         run_testbed_orchestration_command(
-//                             &common,
-//                             testbed_host,
-//                             "sudo",
-//                             cmd,
-//                             false).await?;
-//
-//                         let cmd = vec!["ovs-vsctl", "--may-exist", "add-port", &target_br, &target_veth];
-//                         run_testbed_orchestration_command(
-//                             &common,
-//                             testbed_host,
-//                             "sudo",
-//                             cmd,
-//                             false).await?;
-//                     }
-//                     BridgeConnection::Tunnel { source_br, target_br, source_remote_ip, target_remote_ip, key, testbed_host_source, testbed_host_target, .. } => {
-//                         let key_res = key.as_ref().unwrap();
-//                         if testbed_host_source.eq(testbed_host) {
-//                             let src_remote_ip = source_remote_ip.as_ref().unwrap();
-//                             let src_nic = &testbed_host_nic;
-//                             // target remote ip here is the local (to the host in iteration) unique tunnel ip
-//                             let local_tunnel_ip = target_remote_ip.as_ref().unwrap();
-//                             let local_tunnel_ip_wmask = format!("{local_tunnel_ip}/24");
-//
-//                             let cmd = vec!["ip", "addr", "add", &local_tunnel_ip_wmask, "dev", &src_nic];
-//                             let ip_add_res = run_testbed_orchestration_command(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await;
-//                             if ip_add_res.is_err() {
-//                                 let err = ip_add_res.unwrap_err();
-//                                 let expected_err = "RTNETLINK answers: File exists".to_string();
-//                                 if !err.to_string().trim().contains(&expected_err) {
-//                                     // if not this expected error
-//                                     tracing::error!("{}", err.to_string());
-//                                     bail!("could not set ip on NIC")
-//                                 } else {
-//                                     tracing::warn!("ignoring ip address add error (RTNETLINK answers: File exists)")
-//                                 }
-//                             }
-//
-//                             let geneve_geneve_interface_counter = format!("geneve{geneve_interface_counter}");
-//                             let options_remote_ip = format!("options:remote_ip={src_remote_ip}");
-//                             let options_key = format!("options:key={key_res}");
-//                             let cmd = vec!["ovs-vsctl", "--may-exist", "add-port", &source_br, &geneve_geneve_interface_counter, "--", "set", "interface", &geneve_geneve_interface_counter, "type=geneve", &options_remote_ip, &options_key];
-//                             run_testbed_orchestration_command(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//
-//                             let cmd = vec!["ip", "link", "set", &src_nic, "mtu", "2000"];
-//                             run_testbed_orchestration_command(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//                             geneve_interface_counter += 1;
-//                         }
-//                         // configure "right" of tunnel
-//                         if testbed_host_target.eq(testbed_host) {
-//                             let tgt_remote_ip = target_remote_ip.as_ref().unwrap();
-//                             let tgt_nic = &testbed_host_nic;
-//                             // source remote ip here is the local (to the host in iteration) unique tunnel ip
-//                             let local_tunnel_ip = source_remote_ip.as_ref().unwrap();
-//
-//                             let local_tunnel_ip_wmask = format!("{local_tunnel_ip}/24");
-//                             let cmd = vec!["ip", "addr", "add", &local_tunnel_ip_wmask, "dev", &tgt_nic];
-//                             let ip_add_res = run_testbed_orchestration_command(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await;
-//                             if ip_add_res.is_err() {
-//                                 let err = ip_add_res.unwrap_err();
-//                                 let expected_err = "RTNETLINK answers: File exists".to_string();
-//                                 if !err.to_string().trim().contains(&expected_err) {
-//                                     // if not this expected error
-//                                     tracing::error!("{}", err.to_string());
-//                                     bail!("could not set ip on NIC")
-//                                 } else {
-//                                     tracing::warn!("ignoring ip address add error (RTNETLINK answers: File exists)")
-//                                 }
-//                             }
-//
-//                             let geneve_geneve_interface_counter = format!("geneve{geneve_interface_counter}");
-//                             let options_remote_ip = format!("options:remote_ip={tgt_remote_ip}");
-//                             let options_key = format!("options:key={key_res}");
-//                             let cmd = vec!["ovs-vsctl", "--may-exist", "add-port", &target_br, &geneve_geneve_interface_counter, "--", "set", "interface", &geneve_geneve_interface_counter, "type=geneve", &options_remote_ip, &options_key];
-//                             run_testbed_orchestration_command(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//
-//                             let cmd = vec!["ip", "link", "set", &tgt_nic, "mtu", "2000"];
-//                             run_testbed_orchestration_command(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//
-//                             geneve_interface_counter += 1;
-//                         }
-//                     }
-//                 }
-//             }
-//         }
-//
-//         Ok(())
-//     }
-//
-//     async fn destroy_action(&self, common: OrchestrationCommon) -> anyhow::Result<()> {
-//         // loop through all the testbed hosts
-//         for (logical_host, testbed_host_data) in common.testbed_hosts.iter() {
-//             let testbed_host = logical_host;
-//             let testbed_host_nic = &testbed_host_data.testbed_nic;
-//
-//
-//             // count geneve interfaces per host to make sure all interfaces on the ovs tunnel with the geneve tunnel are unique
-//             let mut geneve_interface_counter = 0;
-//             for (_, connection_info) in self.0.as_ref().unwrap().iter() {
-//                 match connection_info {
-//                     BridgeConnection::Ovs {
-//                         name: _,
-//                         source_br,
-//                         target_br,
-//                         source_veth,
-//                         target_veth,
-//                         ip: _,
-//                         testbed_host,
-//                     } => {
-//                         // match the bridge connection to the current testbed host variable in the loop
-//                         if testbed_host.eq(testbed_host) {
-//                             // writeln!(ovs_create_file, "{}", format!("# pair = {name}").as_str())?;
-//                             // connect the bridges to the veths
-//
-//                             let cmd = vec!["ip", "link", "delete", &source_veth];
-//                             run_testbed_orchestration_command_allow_fail(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//                             // bring up bridges
-//
-//                             let cmd = vec!["ovs-vsctl", "del-port", &source_br, &source_veth];
-//                             run_testbed_orchestration_command_allow_fail(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//
-//                             let cmd = vec!["ovs-vsctl", "del-port", &target_br, &target_veth];
-//                             run_testbed_orchestration_command_allow_fail(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//
-//                         }
-//                     }
-//                     BridgeConnection::Tunnel {
-//                         source_br,
-//                         target_br,
-//                         source_remote_ip,
-//                         target_remote_ip,
-//                         key: _,
-//                         source_br_ip: _,
-//                         target_br_ip: _,
-//                         testbed_host_source,
-//                         testbed_host_target,
-//                     } => {
-//                         // configure "left" of tunnel
-//                         if testbed_host_source.eq(testbed_host) {
-//                             let src_nic = &testbed_host_nic;
-//                             // target remote ip here is the local (to the host in iteration) unique tunnel ip
-//                             let local_tunnel_ip = target_remote_ip.as_ref().unwrap();
-//
-//                             let local_tunnel_ip_wmask = format!("{local_tunnel_ip}/24");
-//                             let cmd = vec!["ip", "addr", "del", &local_tunnel_ip_wmask, "dev", &src_nic];
-//                             run_testbed_orchestration_command_allow_fail(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//
-//                             let geneve_geneve_interface_counter = format!("geneve{geneve_interface_counter}");
-//                             let cmd = vec!["ovs-vsctl", "del-port", &source_br, &geneve_geneve_interface_counter];
-//                             run_testbed_orchestration_command_allow_fail(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//
-//
-//                             geneve_interface_counter += 1;
-//                         }
-//                         // configure "right" of tunnel
-//                         if testbed_host_target.eq(testbed_host) {
-//                             let tgt_nic = &testbed_host_nic;
-//                             // source remote ip here is the local (to the host in iteration) unique tunnel ip
-//                             let local_tunnel_ip = source_remote_ip.as_ref().unwrap();
-//
-//                             let local_tunnel_ip_wmask = format!("{local_tunnel_ip}/24");
-//                             let cmd = vec!["ip", "addr", "del", &local_tunnel_ip_wmask, "dev", &tgt_nic];
-//                             run_testbed_orchestration_command_allow_fail(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//
-//                             let geneve_geneve_interface_counter = format!("geneve{geneve_interface_counter}");
-//                             let cmd = vec!["ovs-vsctl", "del-port", &target_br, &geneve_geneve_interface_counter];
-//                             run_testbed_orchestration_command_allow_fail(
-//                                 &common,
-//                                 testbed_host,
-//                                 "sudo",
-//                                 cmd,
-//                                 false).await?;
-//
-//
-//                             geneve_interface_counter += 1;
-//                         }
-//                     }
-//                 }
-//             }
-//         }
-//         Ok(())
-//     }
-// }
+    async fn destroy_action(&self, common: &OrchestrationCommon) -> anyhow::Result<()> {
+        tracing::info!("destroying OVS bridges");
+        for connection in &self.connections {
+            connection.destroy_command(&ovn_run_cmd_allow_fail, (None, common.clone())).await?;
+        }
+        for bridge in self.bridges.values() {
+            bridge.destroy_command(&ovn_run_cmd_allow_fail, (None, common.clone())).await?;
+        }
+        Ok(())
+    }
+
+    async fn request_create_action(&self, _common: &OrchestrationCommon, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
+        tracing::info!("requesting to deploy OVS bridges");
+        for bridge in self.bridges.values() {
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::Deploy(vec![bridge.to_orchestration_resource()]),
+            ).await.context("requesting the creation of ovs bridge")?;
+        }
+        for connection in &self.connections {
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::Deploy(vec![connection.to_orchestration_resource()]),
+            ).await.context("requesting the creation of ovs bridge link")?;
+        }
+        Ok(())
+    }
+
+    async fn request_destroy_action(&self, _common: &OrchestrationCommon, sender: &mut Sender<OrchestrationProtocol>) -> anyhow::Result<()> {
+        tracing::info!("requesting to destroy OVS bridges");
+        for connection in &self.connections {
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::Destroy(vec![connection.to_orchestration_resource()]),
+            ).await.context("requesting the destruction of ovs bridge link")?;
+        }
+        for bridge in self.bridges.values() {
+            send_orchestration_instruction_over_channel(
+                sender,
+                OrchestrationInstruction::Destroy(vec![bridge.to_orchestration_resource()]),
+            ).await.context("requesting the destruction of ovs bridge")?;
+        }
+        Ok(())
+    }
+}
+
+impl StateOvsBridge {
+    pub fn to_orchestration_resource(&self) -> OrchestrationResource {
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovs(OrchestrationResourceOvsNetwork::Bridge(self.clone())))
+    }
+}
+
+#[async_trait]
+impl OvnCommand for StateOvsBridge {
+    async fn create_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("creating OVS bridge {} on testbed host {}", &self.name, &self.testbed_host);
+        // without a controller the bridge is a normal learning switch
+        f(vec_of_strings!["ovs-vsctl", "--may-exist", "add-br", &self.name], (Some(self.testbed_host.clone()), config.1)).await
+    }
+
+    async fn destroy_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        tracing::info!("destroying OVS bridge {} on testbed host {}", &self.name, &self.testbed_host);
+        f(vec_of_strings!["ovs-vsctl", "--if-exists", "del-br", &self.name], (Some(self.testbed_host.clone()), config.1)).await
+    }
+}
+
+impl BridgeConnection {
+    pub fn to_orchestration_resource(&self) -> OrchestrationResource {
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovs(OrchestrationResourceOvsNetwork::Connection(self.clone())))
+    }
+
+    /// The bridges at each end of the link
+    pub fn bridges(&self) -> (&String, &String) {
+        match self {
+            BridgeConnection::Veth { source_br, target_br, .. } => (source_br, target_br),
+            BridgeConnection::Tunnel { source_br, target_br, .. } => (source_br, target_br),
+        }
+    }
+}
+
+#[async_trait]
+impl OvnCommand for BridgeConnection {
+    async fn create_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        match self {
+            BridgeConnection::Veth { source_br, target_br, source_veth, target_veth, testbed_host } => {
+                tracing::info!("creating veth pair between OVS bridges {} and {}", source_br, target_br);
+                let remote_config = (Some(testbed_host.clone()), config.1);
+                let res = f(vec_of_strings!["ip", "link", "add", source_veth, "type", "veth", "peer", "name", target_veth], remote_config.clone()).await;
+                if let Err(err) = res {
+                    // the veth pair may remain from a previous deployment
+                    if !err.to_string().contains("File exists") {
+                        return Err(err);
+                    }
+                    tracing::warn!("veth pair {} already exists, continuing...", source_veth);
+                }
+                f(vec_of_strings!["ip", "link", "set", source_veth, "up"], remote_config.clone()).await?;
+                f(vec_of_strings!["ip", "link", "set", target_veth, "up"], remote_config.clone()).await?;
+                f(vec_of_strings![
+                    "ovs-vsctl", "--may-exist", "add-port", source_br, source_veth,
+                    "--", "--may-exist", "add-port", target_br, target_veth
+                ], remote_config).await
+            }
+            BridgeConnection::Tunnel { tunnel_type, source_br, target_br, port, source_host_ip, target_host_ip, key, testbed_host_source, testbed_host_target } => {
+                tracing::info!("creating {} tunnel between OVS bridges {} and {}", tunnel_type, source_br, target_br);
+                // each end of the tunnel points at the other testbed host
+                let ends = [
+                    (source_br, target_host_ip, testbed_host_source),
+                    (target_br, source_host_ip, testbed_host_target),
+                ];
+                let mut res = String::new();
+                for (bridge, remote_ip, testbed_host) in ends {
+                    res = f(vec_of_strings![
+                        "ovs-vsctl", "--may-exist", "add-port", bridge, port,
+                        "--", "set", "Interface", port, format!("type={tunnel_type}"),
+                        format!("options:remote_ip={remote_ip}"), format!("options:key={key}")
+                    ], (Some(testbed_host.clone()), config.1.clone())).await?;
+                }
+                Ok(res)
+            }
+        }
+    }
+
+    async fn destroy_command<F>(&self, f: impl Fn(Vec<String>, (Option<String>, OrchestrationCommon)) -> F + Send + Sync, config: (Option<String>, OrchestrationCommon)) -> anyhow::Result<String>
+        where
+            F: Future<Output=anyhow::Result<String>> + Send
+    {
+        match self {
+            BridgeConnection::Veth { source_br, target_br, source_veth, target_veth, testbed_host } => {
+                tracing::info!("destroying veth pair between OVS bridges {} and {}", source_br, target_br);
+                let remote_config = (Some(testbed_host.clone()), config.1);
+                f(vec_of_strings![
+                    "ovs-vsctl", "--if-exists", "del-port", source_br, source_veth,
+                    "--", "--if-exists", "del-port", target_br, target_veth
+                ], remote_config.clone()).await?;
+                // deleting one end of the pair deletes both
+                f(vec_of_strings!["ip", "link", "delete", source_veth], remote_config).await
+            }
+            BridgeConnection::Tunnel { tunnel_type, source_br, target_br, port, testbed_host_source, testbed_host_target, .. } => {
+                tracing::info!("destroying {} tunnel between OVS bridges {} and {}", tunnel_type, source_br, target_br);
+                f(vec_of_strings!["ovs-vsctl", "--if-exists", "del-port", source_br, port], (Some(testbed_host_source.clone()), config.1.clone())).await?;
+                f(vec_of_strings!["ovs-vsctl", "--if-exists", "del-port", target_br, port], (Some(testbed_host_target.clone()), config.1)).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kvm_compose_schemas::kvm_compose_yaml::network::bridge::TunnelType;
+    use crate::ovn::test_ovn_run_cmd;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ovs_bridge() {
+        let bridge = StateOvsBridge {
+            name: "br-test0".into(),
+            subnet: "10.0.0.0/24".into(),
+            testbed_host: "host1".into(),
+        };
+        let create_cmd = bridge.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(create_cmd, vec_of_strings!["ovs-vsctl", "--may-exist", "add-br", "br-test0"].join(" "));
+        let destroy_cmd = bridge.destroy_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(destroy_cmd, vec_of_strings!["ovs-vsctl", "--if-exists", "del-br", "br-test0"].join(" "));
+    }
+
+    #[tokio::test]
+    async fn test_bridge_connection() {
+        let veth = BridgeConnection::Veth {
+            source_br: "br-test0".into(),
+            target_br: "br-test1".into(),
+            source_veth: "ln-test00".into(),
+            target_veth: "ln-test01".into(),
+            testbed_host: "host1".into(),
+        };
+        let create_cmd = veth.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        let expected_cmd = vec_of_strings![
+            "ovs-vsctl", "--may-exist", "add-port", "br-test0", "ln-test00",
+            "--", "--may-exist", "add-port", "br-test1", "ln-test01"
+        ].join(" ");
+        assert_eq!(create_cmd, expected_cmd);
+
+        let tunnel = BridgeConnection::Tunnel {
+            tunnel_type: TunnelType::Vxlan,
+            source_br: "br-test0".into(),
+            target_br: "br-test1".into(),
+            port: "ln-test10".into(),
+            source_host_ip: "192.168.0.1".into(),
+            target_host_ip: "192.168.0.2".into(),
+            key: 42,
+            testbed_host_source: "host1".into(),
+            testbed_host_target: "host2".into(),
+        };
+        // the last command is the target end, pointing back at the source host
+        let create_cmd = tunnel.create_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        let expected_cmd = vec_of_strings![
+            "ovs-vsctl", "--may-exist", "add-port", "br-test1", "ln-test10",
+            "--", "set", "Interface", "ln-test10", "type=vxlan", "options:remote_ip=192.168.0.1", "options:key=42"
+        ].join(" ");
+        assert_eq!(create_cmd, expected_cmd);
+        let destroy_cmd = tunnel.destroy_command(&test_ovn_run_cmd, (None, OrchestrationCommon::default())).await.unwrap();
+        assert_eq!(destroy_cmd, vec_of_strings!["ovs-vsctl", "--if-exists", "del-port", "br-test1", "ln-test10"].join(" "));
+    }
+}
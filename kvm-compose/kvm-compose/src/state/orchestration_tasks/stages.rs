@@ -5,17 +5,17 @@ use crate::orchestration::api::{OrchestrationInstruction, OrchestrationProtocol,
 use crate::orchestration::websocket::{send_orchestration_instruction_over_channel};
 use crate::orchestration::{OrchestrationCommon};
 use crate::state::orchestration_tasks::guests::{get_main_testbed_name};
-use crate::state::State;
+use crate::state::StateTestbedGuestList;
 
 pub async fn deploy_guest_stage(
-    state: &State,
+    guests: &StateTestbedGuestList,
     sender: &mut Sender<OrchestrationProtocol>,
     // receiver: &mut Receiver<OrchestrationProtocol>,
 ) -> anyhow::Result<()> {
     tracing::info!("Stage: deploying guests");
 
     let mut orchestration_resources_deploy_guests = Vec::new();
-    for (_guest_name, guest_data) in state.testbed_guests.0.iter() {
+    for (_guest_name, guest_data) in guests.0.iter() {
         match &guest_data.guest_type.guest_type {
             GuestType::Libvirt(_) => {
                 if !guest_data.is_golden_image {
@@ -53,14 +53,14 @@ pub async fn deploy_guest_stage(
 }
 
 pub async fn destroy_guest_stage(
-    state: &State,
+    guests: &StateTestbedGuestList,
     sender: &mut Sender<OrchestrationProtocol>,
     // receiver: &mut Receiver<OrchestrationProtocol>,
 ) -> anyhow::Result<()> {
     tracing::info!("Stage: destroying guests");
 
     let mut orchestration_resources_destroy_guests = Vec::new();
-    for (_guest_name, guest_data) in state.testbed_guests.0.iter() {
+    for (_guest_name, guest_data) in guests.0.iter() {
         match &guest_data.guest_type.guest_type {
             GuestType::Libvirt(_) => {
                 if !guest_data.is_golden_image {
@@ -99,14 +99,14 @@ pub async fn destroy_guest_stage(
 
 /// Filter all guests that are a "backing image" and request image setup
 pub async fn setup_backing_image_stage(
-    state: &State,
+    guests: &StateTestbedGuestList,
     sender: &mut Sender<OrchestrationProtocol>,
     // receiver: &mut Receiver<OrchestrationProtocol>,
 ) -> anyhow::Result<()> {
 
     let mut orchestration_resources = Vec::new();
 
-    for (_guest_name, guest_data) in state.testbed_guests.0.iter() {
+    for (_guest_name, guest_data) in guests.0.iter() {
         if guest_data.is_golden_image {
             match &guest_data.guest_type.guest_type {
                 GuestType::Libvirt(_) => {
@@ -135,14 +135,14 @@ pub async fn setup_backing_image_stage(
 
 /// Filter all guests that are "clones" of a "backing image" and request image setup
 pub async fn setup_linked_clones_stage(
-    state: &State,
+    guests: &StateTestbedGuestList,
     sender: &mut Sender<OrchestrationProtocol>,
     // receiver: &mut Receiver<OrchestrationProtocol>,
 ) -> anyhow::Result<()> {
 
     let mut orchestration_resources = Vec::new();
 
-    for (_guest_name, guest_data) in state.testbed_guests.0.iter() {
+    for (_guest_name, guest_data) in guests.0.iter() {
         match &guest_data.guest_type.guest_type {
             GuestType::Libvirt(libvirt) => {
                 if libvirt.is_clone_of.is_some() {
@@ -169,13 +169,13 @@ pub async fn setup_linked_clones_stage(
 
 /// For all guests, push the image to remote testbed hosts if applicable
 pub async fn push_guest_images_stage(
-    state: &State,
+    guests: &StateTestbedGuestList,
     sender: &mut Sender<OrchestrationProtocol>,
     // receiver: &mut Receiver<OrchestrationProtocol>,
 ) -> anyhow::Result<()> {
     let mut orchestration_resources = Vec::new();
 
-    for (_guest_name, guest_data) in state.testbed_guests.0.iter() {
+    for (_guest_name, guest_data) in guests.0.iter() {
         match &guest_data.guest_type.guest_type {
             GuestType::Libvirt(_) => {
                 orchestration_resources.push(
@@ -203,7 +203,7 @@ pub async fn push_guest_images_stage(
 }
 
 pub async fn push_backing_guest_images_stage(
-    state: &State,
+    guests: &StateTestbedGuestList,
     common: &OrchestrationCommon,
     sender: &mut Sender<OrchestrationProtocol>,
     // receiver: &mut Receiver<OrchestrationProtocol>,
@@ -213,7 +213,7 @@ pub async fn push_backing_guest_images_stage(
     // based on `calculate_backing_images_to_push`
 
     let main_testbed_name = get_main_testbed_name(common);
-    for (_guest_name, guest_data) in state.testbed_guests.0.iter() {
+    for (_guest_name, guest_data) in guests.0.iter() {
         match &guest_data.guest_type.guest_type {
             GuestType::Libvirt(libvirt) => {
                 // check if guest is a clone and if not on the main testbed
@@ -242,14 +242,14 @@ pub async fn push_backing_guest_images_stage(
 }
 
 pub async fn rebase_clone_images_stage(
-    state: &State,
+    guests: &StateTestbedGuestList,
     common: &OrchestrationCommon,
     sender: &mut Sender<OrchestrationProtocol>,
     // receiver: &mut Receiver<OrchestrationProtocol>,
 ) -> anyhow::Result<()> {
     let mut orchestration_resources = Vec::new();
 
-    for (_guest_name, guest_data) in state.testbed_guests.0.iter() {
+    for (_guest_name, guest_data) in guests.0.iter() {
         // only rebase on remote testbeds
         if !guest_data.testbed_host.as_ref().unwrap().eq(&get_main_testbed_name(&common)) {
             match &guest_data.guest_type.guest_type {
@@ -277,13 +277,13 @@ pub async fn rebase_clone_images_stage(
 }
 
 pub async fn run_guest_setup_scripts_stage(
-    state: &State,
+    guests: &StateTestbedGuestList,
     sender: &mut Sender<OrchestrationProtocol>,
     // receiver: &mut Receiver<OrchestrationProtocol>,
 ) -> anyhow::Result<()> {
     let mut orchestration_resources = Vec::new();
 
-    for (_guest_name, guest_data) in state.testbed_guests.0.iter() {
+    for (_guest_name, guest_data) in guests.0.iter() {
         match &guest_data.guest_type.guest_type {
             GuestType::Libvirt(_) => {
                 orchestration_resources.push(
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Formatter;
+use serde::Serialize;
+use serde_json::Value;
+use crate::orchestration::api::{OrchestrationResource, OrchestrationResourceNetwork, OrchestrationResourceNetworkType, OrchestrationResourceOvsNetwork};
+use crate::state::{State, StateNetwork};
+
+/// The action that `up` would take for a resource
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanAction {
+    Create,
+    Destroy,
+    Modify,
+}
+
+impl PlanAction {
+    fn symbol(&self) -> &'static str {
+        match self {
+            PlanAction::Create => "+",
+            PlanAction::Destroy => "-",
+            PlanAction::Modify => "~",
+        }
+    }
+}
+
+/// A single change to a resource, the resource is described by `OrchestrationResource::name()`
+/// of the new resource, or the old resource if it is destroyed
+#[derive(Serialize, Debug, Clone)]
+pub struct PlanChange {
+    pub action: PlanAction,
+    pub resource: String,
+    /// for a modified resource, the fields that are different
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed_fields: Vec<String>,
+}
+
+/// This is a dry run of `up`, it lists every guest and network resource that would be created,
+/// destroyed or modified when comparing the stored state of a deployment to the state generated
+/// from the kvm-compose.yaml.
+#[derive(Serialize, Debug, Default)]
+pub struct Plan {
+    pub changes: Vec<PlanChange>,
+}
+
+impl Plan {
+    /// Create the plan from the stored state, if there is one, and the new state. If there is no
+    /// stored state then everything will be created.
+    pub fn new(old_state: Option<&State>, new_state: &State) -> Self {
+        let old_resources = match old_state {
+            Some(state) => get_state_resources(state),
+            None => Vec::new(),
+        };
+        Self::from_resources(old_resources, get_state_resources(new_state))
+    }
+
+    /// Compare two lists of resources, resources are matched using their id so that a modified
+    /// resource is not shown as destroyed and created
+    pub fn from_resources(
+        old_resources: Vec<OrchestrationResource>,
+        new_resources: Vec<OrchestrationResource>,
+    ) -> Self {
+        let old_resources: BTreeMap<String, (String, Value)> = old_resources.iter()
+            .map(|r| (r.id(), (r.name(), resource_value(r))))
+            .collect();
+        let new_resources: BTreeMap<String, (String, Value)> = new_resources.iter()
+            .map(|r| (r.id(), (r.name(), resource_value(r))))
+            .collect();
+
+        let mut changes = Vec::new();
+        for (id, (old_name, old_value)) in old_resources.iter() {
+            match new_resources.get(id) {
+                None => changes.push(PlanChange {
+                    action: PlanAction::Destroy,
+                    resource: old_name.clone(),
+                    changed_fields: Vec::new(),
+                }),
+                Some((new_name, new_value)) => {
+                    if old_value != new_value {
+                        changes.push(PlanChange {
+                            action: PlanAction::Modify,
+                            resource: new_name.clone(),
+                            changed_fields: changed_fields(old_value, new_value),
+                        });
+                    }
+                }
+            }
+        }
+        for (id, (new_name, _)) in new_resources.iter() {
+            if !old_resources.contains_key(id) {
+                changes.push(PlanChange {
+                    action: PlanAction::Create,
+                    resource: new_name.clone(),
+                    changed_fields: Vec::new(),
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.resource.cmp(&b.resource));
+        Self { changes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Count the number of changes for the given action
+    pub fn count(&self, action: PlanAction) -> usize {
+        self.changes.iter()
+            .filter(|c| c.action == action)
+            .count()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes. The deployment matches the configuration.");
+        }
+        for change in &self.changes {
+            if change.changed_fields.is_empty() {
+                writeln!(f, "  {} {}", change.action.symbol(), change.resource)?;
+            } else {
+                writeln!(f, "  {} {} (changed: {})", change.action.symbol(), change.resource, change.changed_fields.join(", "))?;
+            }
+        }
+        writeln!(
+            f,
+            "Plan: {} to create, {} to modify, {} to destroy.",
+            self.count(PlanAction::Create),
+            self.count(PlanAction::Modify),
+            self.count(PlanAction::Destroy),
+        )
+    }
+}
+
+/// Get all the guests and network resources in the state
+fn get_state_resources(state: &State) -> Vec<OrchestrationResource> {
+    let mut resources: Vec<_> = state.testbed_guests.0.values()
+        .map(|guest| OrchestrationResource::Guest(guest.clone()))
+        .collect();
+    match &state.network {
+        StateNetwork::Ovn(ovn) => resources.extend(ovn.to_orchestration_resources()),
+        StateNetwork::Ovs(ovs) => resources.extend(ovs.to_orchestration_resources()),
+    }
+    resources
+}
+
+/// Get the serialised form of the resource to compare. Routers contain their routes, gateways and
+/// NAT rules which are resources in their own right, so these are removed from the router.
+fn resource_value(resource: &OrchestrationResource) -> Value {
+    match resource {
+        OrchestrationResource::Guest(guest) => serde_json::to_value(guest),
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovn(ovn)) => match ovn {
+            OrchestrationResourceNetwork::Switch(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::SwitchPort(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::Router(r) => serde_json::to_value(r).map(|mut v| {
+                if let Some(obj) = v.as_object_mut() {
+                    obj.remove("routing");
+                    obj.remove("external_gateway");
+                    obj.remove("nat");
+                }
+                v
+            }),
+            OrchestrationResourceNetwork::RouterPort(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::OvsPort(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::DhcpOption(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::ExternalGateway(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::Nat(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::Route(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::ACL(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::FlowExport(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::Mirror(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::PortGroup(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::AddressSet(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::LoadBalancer(r) => serde_json::to_value(r),
+            OrchestrationResourceNetwork::DnsRecords(r) => serde_json::to_value(r),
+        },
+        OrchestrationResource::Network(OrchestrationResourceNetworkType::Ovs(ovs)) => match ovs {
+            OrchestrationResourceOvsNetwork::Bridge(r) => serde_json::to_value(r),
+            OrchestrationResourceOvsNetwork::Connection(r) => serde_json::to_value(r),
+        },
+    }.unwrap_or(Value::Null)
+}
+
+/// List the top level fields that are different between the two values
+fn changed_fields(old_value: &Value, new_value: &Value) -> Vec<String> {
+    match (old_value.as_object(), new_value.as_object()) {
+        (Some(old_obj), Some(new_obj)) => {
+            let mut fields: Vec<String> = old_obj.keys()
+                .chain(new_obj.keys())
+                .filter(|k| old_obj.get(*k) != new_obj.get(*k))
+                .cloned()
+                .collect();
+            fields.sort();
+            fields.dedup();
+            fields
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+    use crate::ovn::components::logical_switch::LogicalSwitch;
+    use crate::ovn::components::port_group::LogicalPortGroup;
+    use super::*;
+
+    fn switch(name: &str, third_octet: u8) -> OrchestrationResource {
+        LogicalSwitch::new(name.into(), IpAddr::V4(Ipv4Addr::new(10, 0, third_octet, 0)), 24)
+            .to_orchestration_resource()
+    }
+
+    #[test]
+    fn test_plan_create_modify_destroy() {
+        let old = vec![switch("sw0", 0), switch("sw1", 1)];
+        let new = vec![switch("sw0", 5), switch("sw2", 2)];
+        let plan = Plan::from_resources(old, new);
+        assert_eq!(plan.changes.len(), 3);
+        assert_eq!(plan.count(PlanAction::Create), 1);
+        assert_eq!(plan.count(PlanAction::Modify), 1);
+        assert_eq!(plan.count(PlanAction::Destroy), 1);
+        let modified = plan.changes.iter()
+            .find(|c| c.action == PlanAction::Modify)
+            .unwrap();
+        assert_eq!(modified.resource, "Ovn Logical Switch sw0");
+        assert_eq!(modified.changed_fields, vec!["subnet".to_string()]);
+    }
+
+    #[test]
+    fn test_plan_modify_keeps_identity() {
+        let port_group = |ports: Vec<&str>| LogicalPortGroup {
+            name: "test_web".into(),
+            ports: ports.into_iter().map(String::from).collect(),
+        }.to_orchestration_resource();
+        // the name shows the number of ports, adding a port must not destroy the port group
+        let plan = Plan::from_resources(
+            vec![port_group(vec!["test-sw0-web-0"])],
+            vec![port_group(vec!["test-sw0-web-0", "test-sw0-web2-0"])],
+        );
+        assert_eq!(plan.changes.len(), 1);
+        assert_eq!(plan.changes[0].action, PlanAction::Modify);
+        assert_eq!(plan.changes[0].resource, "Ovn Port Group test_web with 2 ports");
+        assert_eq!(plan.changes[0].changed_fields, vec!["ports".to_string()]);
+    }
+
+    #[test]
+    fn test_plan_no_changes() {
+        let plan = Plan::from_resources(vec![switch("sw0", 0)], vec![switch("sw0", 0)]);
+        assert!(plan.is_empty());
+        assert_eq!(format!("{plan}"), "No changes. The deployment matches the configuration.\n");
+    }
+}
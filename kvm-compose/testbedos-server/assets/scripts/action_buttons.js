@@ -27,8 +27,7 @@ $(document).ready(function() {
                 });
             },
             error: function (error) {
-
-                alert(error.responseText);
+                alert(validationErrorText(error));
             }
         });
     })
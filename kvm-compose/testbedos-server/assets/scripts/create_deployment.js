@@ -24,7 +24,7 @@ $(document).ready(function() {
                 validation_yaml_badge.addClass("text-bg-danger");
                 validation_yaml_badge.html('Invalid Yaml');
 
-                alert(error.responseText);
+                alert(validationErrorText(error));
             }
         });
     });
@@ -90,4 +90,4 @@ $(document).ready(function() {
         });
     });
 
-});
\ No newline at end of file
+});
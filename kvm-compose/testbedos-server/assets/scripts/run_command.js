@@ -796,6 +796,11 @@ function get_button_command_json(button_id, selectedOption, selectedToolOption,
     }
 
     command_json['sub_command'] = sub_command;
+    // optional var file to interpolate the kvm-compose.yaml, relative to the project folder
+    let var_file = $('#varFileInput').val().trim();
+    if (var_file !== '') {
+        command_json['var_file'] = var_file;
+    }
     return command_json;
 }
 
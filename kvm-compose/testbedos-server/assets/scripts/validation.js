@@ -0,0 +1,9 @@
+// format the validation report from the server as one error per line
+function validationErrorText(error) {
+    if (error.responseJSON && error.responseJSON.errors) {
+        return error.responseJSON.errors.map(function (e) {
+            return e.path === '' ? e.message : e.path + ': ' + e.message;
+        }).join('\n');
+    }
+    return error.responseText;
+}
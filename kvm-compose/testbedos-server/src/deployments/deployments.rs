@@ -8,6 +8,8 @@ use serde_yaml::Value;
 use anyhow::{Result, Context, Error};
 use nix::unistd::{Gid, Uid};
 use kvm_compose_schemas::kvm_compose_yaml::Config;
+use kvm_compose_schemas::kvm_compose_yaml::validation::ValidationReport;
+use kvm_compose_schemas::settings::TestbedClusterConfig;
 use crate::AppState;
 
 #[derive(Serialize, Deserialize)]
@@ -46,12 +48,43 @@ pub async fn validate_project_name(
     }
 }
 
-pub fn validate_yaml(
+pub async fn validate_yaml(
+    db_config: &Arc<AppState>,
     yaml: String,
-) -> (StatusCode, String) {
-    match string_to_yaml(yaml) {
-        Ok(_) => (StatusCode::OK, "".to_string()),
-        Err(err) => (StatusCode::BAD_REQUEST, format!("{err:#}")),
+) -> (StatusCode, ValidationReport) {
+    // the cluster config is used to check the testbed hosts and chassis in the network, but the
+    // rest of the yaml can still be validated without it
+    let cluster_config = db_config.config_db
+        .read()
+        .await
+        .get_cluster_config()
+        .await
+        .ok();
+    let report = yaml_validation_report(yaml, cluster_config.as_ref());
+    if report.is_valid() {
+        (StatusCode::OK, report)
+    } else {
+        (StatusCode::BAD_REQUEST, report)
+    }
+}
+
+/// Validate the yaml, if the yaml cannot be parsed then the report will have the single parse
+/// error with an empty path, otherwise it contains every semantic error in the config.
+pub fn yaml_validation_report(
+    body: String,
+    cluster_config: Option<&TestbedClusterConfig>,
+) -> ValidationReport {
+    let parsed = serde_yaml::from_str::<Value>(&body)
+        .with_context(|| "Parsing raw YAML")
+        .and_then(|yaml_value| validate_guest_types(&yaml_value))
+        .and_then(|_| serde_yaml::from_str::<Config>(&body).with_context(|| "Parsing Config YAML"));
+    match parsed {
+        Ok(config) => config.validation_report(cluster_config),
+        Err(err) => {
+            let mut report = ValidationReport::default();
+            report.push("", format!("{err:#}"));
+            report
+        }
     }
 }
 
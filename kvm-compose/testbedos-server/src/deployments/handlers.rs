@@ -10,6 +10,7 @@ use tokio::io::AsyncReadExt;
 use kvm_compose_schemas::handlers::PrettyQueryParams;
 use crate::deployments::deployments::{ProjectAndPath, validate_project_name, validate_yaml};
 use kvm_compose_lib::state::State as KvmComposeState;
+use kvm_compose_schemas::kvm_compose_yaml::Config;
 
 /// List all deployments the database contains.
 /// Requires a read lock on the database.
@@ -141,13 +142,20 @@ pub async fn get_deployment_yaml(
 }
 
 pub async fn validate_yaml_endpoint(
+    State(db_config): State<Arc<AppState>>,
     body: String,
 ) -> Result<impl IntoResponse, AppError> {
-    // check if the given yaml over a POST request is valid in the testbed schema
+    // check if the given yaml over a POST request is valid in the testbed schema, the response
+    // contains every error found with the path to the field in the yaml
+    let (status, report) = validate_yaml(&db_config, body).await;
 
-    let result = validate_yaml(body);
+    Ok((status, Json(report)))
+}
 
-    Ok(result)
+/// Serve the JSON Schema of the kvm-compose.yaml, so that editors can autocomplete and lint the
+/// yaml
+pub async fn get_yaml_schema() -> Result<impl IntoResponse, AppError> {
+    Ok(Json(Config::json_schema()))
 }
 
 pub async fn validate_project_name_handler(
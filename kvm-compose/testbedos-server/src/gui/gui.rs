@@ -28,9 +28,9 @@ pub async fn create_deployment_files(
     let (uid, gid) = get_home_folder_user_group(testbed_user.clone()).await?;
 
     // validate yaml
-    let valid_yaml = validate_yaml(config.yaml.clone());
-    if !valid_yaml.0.is_success() {
-        bail!("Yaml was not valid");
+    let (status, report) = validate_yaml(db_config, config.yaml.clone()).await;
+    if !status.is_success() {
+        bail!("Yaml was not valid, {report}");
     }
 
     // add yaml to folder
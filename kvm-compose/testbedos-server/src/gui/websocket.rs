@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::path::Path;
 use std::sync::Arc;
 use anyhow::{bail, Context, Error};
 use axum::extract::ws::{CloseFrame, Message, WebSocket};
@@ -90,6 +91,9 @@ async fn run(
     // need to recreate the Opts struct
     let opts = Opts {
         input: "kvm-compose.yaml".to_string(), // assume from GUI always this
+        // the var file is relative to the project folder, as the server is not run from it
+        var_file: gui_command.var_file.as_ref()
+            .map(|var_file| Path::new(&deployment.project_location).join(var_file).to_string_lossy().to_string()),
         project_name: Some(gui_command.project_name.clone()),
         verbosity: Some("Info".to_string()),
         sub_command: gui_command.sub_command,
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+use axum::extract::{Path, State};
+use axum::{Json, response::IntoResponse};
+use kvm_compose_schemas::image_models::{ImportImage, PruneImages};
+use crate::{AppError, AppState};
+use crate::images;
+
+/// List the images on the testbed host and what references them.
+/// Requires a read lock on the deployment database.
+pub async fn list_images(
+    State(db_config): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let list = images::list_images(&db_config).await?;
+    Ok(Json(list))
+}
+
+/// Copy an image file on the testbed host into the image store.
+pub async fn import_image(
+    Json(request): Json<ImportImage>,
+) -> Result<impl IntoResponse, AppError> {
+    let image = images::import_image(&request).await?;
+    Ok(Json(image))
+}
+
+/// Remove the images that are not referenced by any deployment.
+/// Requires a read lock on the deployment database.
+pub async fn prune_images(
+    State(db_config): State<Arc<AppState>>,
+    Json(request): Json<PruneImages>,
+) -> Result<impl IntoResponse, AppError> {
+    let result = images::prune_images(&db_config, &request).await?;
+    Ok(Json(result))
+}
+
+/// Remove an unreferenced image from the image store.
+/// Requires a read lock on the deployment database.
+pub async fn delete_image(
+    State(db_config): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let image = images::remove_image(&db_config, &name).await?;
+    Ok(Json(image))
+}
@@ -0,0 +1,407 @@
+use std::collections::BTreeMap;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::{bail, Context};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use kvm_compose_schemas::deployment_models::{Deployment, DeploymentState};
+use kvm_compose_schemas::image_models::{human_readable_size, ImageList, ImageLocation, ImageReference, ImportImage, PruneImages, PruneResult, StoredImage};
+use kvm_compose_schemas::kvm_compose_yaml::machines::GuestType;
+use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt::LibvirtGuestOptions;
+use kvm_compose_schemas::kvm_compose_yaml::machines::libvirt_image_download::{checksum_marker, sha256_file, CloudImage, CloudImageCatalog, CloudImageSource, CLOUD_IMAGE_CATALOG_FILE};
+use kvm_compose_schemas::{TESTBED_IMAGES_FOLDER, TESTBED_SETTINGS_FOLDER};
+use crate::AppState;
+use crate::deployments::get_state_json;
+
+pub mod handlers;
+
+/// The file extensions of the disk images that are created in the artefacts folders
+const IMAGE_EXTENSIONS: [&str; 4] = ["img", "qcow2", "iso", "raw"];
+
+/// The qcow2 magic number, `QFI\xfb`, at the start of every qcow2 image
+const QCOW2_MAGIC: [u8; 4] = [0x51, 0x46, 0x49, 0xfb];
+
+/// List the images in the image store and in the artefacts folder of every deployment, along with
+/// what is referencing them. References come from the state of each deployment and from the
+/// backing files of qcow2 images, so that the base image of a linked clone is never treated as
+/// unused. Deployments without a state, or that are currently running orchestration, reference
+/// all of their artefacts as it is not known which are in use.
+pub async fn list_images(db_config: &Arc<AppState>) -> anyhow::Result<ImageList> {
+    let deployments = db_config.deployment_config_db.read().await
+        .list_deployments().await?;
+    let deployments: BTreeMap<_, _> = deployments.deployments.into_iter().collect();
+
+    let mut images = scan_folder(Path::new(TESTBED_IMAGES_FOLDER), ImageLocation::Store).await?;
+    let mut references = Vec::new();
+    for (name, deployment) in deployments {
+        let artefacts_folder = PathBuf::from(&deployment.project_location).join("artefacts");
+        let artefact_images = scan_folder(
+            &artefacts_folder,
+            ImageLocation::Artefacts { deployment: name.clone() },
+        ).await?;
+        references.extend(deployment_references(&deployment, &artefact_images).await?);
+        images.extend(artefact_images);
+    }
+
+    for image in &images {
+        if let Some(backing_file) = qcow2_backing_file(Path::new(&image.path)).await? {
+            references.push((normalise(&backing_file).await, ImageReference {
+                deployment: None,
+                reason: format!("backing file of {}", image.path),
+            }));
+        }
+    }
+
+    for image in images.iter_mut() {
+        let path = PathBuf::from(&image.path);
+        image.references = references.iter()
+            .filter(|(reference_path, _)| reference_path.eq(&path))
+            .map(|(_, reference)| reference.clone())
+            .collect();
+    }
+    Ok(ImageList { images })
+}
+
+/// Remove the unreferenced images, only the image store is pruned unless artefacts are included
+pub async fn prune_images(db_config: &Arc<AppState>, request: &PruneImages) -> anyhow::Result<PruneResult> {
+    let list = list_images(db_config).await?;
+    let mut result = PruneResult::default();
+    for image in list.images {
+        if image.is_referenced() {
+            continue;
+        }
+        if !request.include_artefacts && image.location != ImageLocation::Store {
+            continue;
+        }
+        if request.dry_run {
+            tracing::info!("would remove unreferenced image {}", image.path);
+        } else {
+            tracing::info!("removing unreferenced image {}", image.path);
+        }
+        result.freed_bytes += image.size_bytes;
+        result.removed.push(image);
+    }
+    if !request.dry_run {
+        let catalog_path = PathBuf::from(TESTBED_SETTINGS_FOLDER).join(CLOUD_IMAGE_CATALOG_FILE);
+        remove_images(&result.removed, &catalog_path).await?;
+    }
+    tracing::info!("pruned {} images, freeing {}", result.removed.len(), human_readable_size(result.freed_bytes));
+    Ok(result)
+}
+
+/// Remove an image from the image store by its file name or the name it was imported with. The
+/// image is also removed from the testbed cloud image catalog if it was imported.
+pub async fn remove_image(db_config: &Arc<AppState>, name: &str) -> anyhow::Result<StoredImage> {
+    let list = list_images(db_config).await?;
+    let Some(image) = list.images.into_iter()
+        .filter(|i| i.location == ImageLocation::Store)
+        .find(|i| i.name.eq(name) || Path::new(&i.name).file_stem().is_some_and(|s| s.eq(name))) else {
+        bail!("there is no image '{name}' in the image store");
+    };
+    if image.is_referenced() {
+        let reasons: Vec<_> = image.references.iter()
+            .map(|r| match &r.deployment {
+                Some(deployment) => format!("{deployment}: {}", r.reason),
+                None => r.reason.clone(),
+            })
+            .collect();
+        bail!("image '{name}' is still referenced by {}", reasons.join(", "));
+    }
+    let catalog_path = PathBuf::from(TESTBED_SETTINGS_FOLDER).join(CLOUD_IMAGE_CATALOG_FILE);
+    remove_images(std::slice::from_ref(&image), &catalog_path).await?;
+    tracing::info!("removed image {}", image.path);
+    Ok(image)
+}
+
+/// Copy an image file on the testbed host into the image store and add it to the testbed cloud
+/// image catalog, pinned to the SHA256 of the copy. If the testbed has no catalog file yet, one is
+/// created with the default images so that they stay available.
+pub async fn import_image(request: &ImportImage) -> anyhow::Result<StoredImage> {
+    let source = PathBuf::from(&request.path);
+    if !source.is_absolute() || !source.is_file() {
+        bail!("{:?} is not an image file on the testbed host", request.path);
+    }
+    let name = match &request.name {
+        Some(name) => name.clone(),
+        None => source.file_stem()
+            .and_then(|s| s.to_str())
+            .context("getting the image name from the file name")?
+            .to_string(),
+    };
+
+    let catalog_path = PathBuf::from(TESTBED_SETTINGS_FOLDER).join(CLOUD_IMAGE_CATALOG_FILE);
+    let mut catalog = if catalog_path.is_file() {
+        CloudImageCatalog::read(&catalog_path).await?
+    } else {
+        CloudImageCatalog::default_images()
+    };
+    if catalog.get(&name).is_ok() {
+        bail!("cloud image '{name}' is already in the catalog");
+    }
+
+    let images_folder = PathBuf::from(TESTBED_IMAGES_FOLDER);
+    let destination = images_folder.join(format!("{name}.img"));
+    let mut image = CloudImage {
+        name: name.clone(),
+        source: CloudImageSource::Path(destination.clone()),
+        sha256: None,
+        sha256_url: None,
+        os_variant: request.os_variant.clone(),
+        cloud_init: Default::default(),
+    };
+    image.validate()?;
+    if destination.exists() {
+        bail!("{destination:?} already exists in the image store");
+    }
+
+    // copy next to the destination first so a failed copy never leaves a broken image in the store
+    tokio::fs::create_dir_all(&images_folder).await?;
+    let tmp_path = images_folder.join(format!(".import-{name}"));
+    tracing::info!("importing {source:?} into the image store as '{name}'");
+    tokio::fs::copy(&source, &tmp_path)
+        .await
+        .with_context(|| format!("copying {source:?} into the image store"))?;
+    let sha256 = sha256_file(&tmp_path).await?;
+    tokio::fs::rename(&tmp_path, &destination).await?;
+    tokio::fs::write(checksum_marker(&destination), &sha256).await?;
+
+    image.sha256 = Some(sha256);
+    catalog.images.push(image);
+    write_catalog(&catalog_path, &catalog).await?;
+
+    let size_bytes = tokio::fs::metadata(&destination).await?.len();
+    Ok(StoredImage {
+        name: format!("{name}.img"),
+        path: destination.to_string_lossy().to_string(),
+        location: ImageLocation::Store,
+        size_bytes,
+        references: Vec::new(),
+    })
+}
+
+async fn write_catalog(path: &Path, catalog: &CloudImageCatalog) -> anyhow::Result<()> {
+    tokio::fs::write(path, serde_json::to_string_pretty(catalog)?)
+        .await
+        .with_context(|| format!("writing cloud image catalog {path:?}"))
+}
+
+/// Remove the image files, and any of them that were imported into the image store from the cloud
+/// image catalog
+async fn remove_images(images: &[StoredImage], catalog_path: &Path) -> anyhow::Result<()> {
+    for image in images {
+        remove_image_file(image).await?;
+    }
+    if catalog_path.is_file() {
+        let mut catalog = CloudImageCatalog::read(catalog_path).await?;
+        let entries = catalog.images.len();
+        catalog.images.retain(|i| !images.iter()
+            .any(|image| i.source == CloudImageSource::Path(PathBuf::from(&image.path))));
+        if catalog.images.len() != entries {
+            write_catalog(catalog_path, &catalog).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn remove_image_file(image: &StoredImage) -> anyhow::Result<()> {
+    let path = PathBuf::from(&image.path);
+    tokio::fs::remove_file(&path)
+        .await
+        .with_context(|| format!("removing image {path:?}"))?;
+    let marker = checksum_marker(&path);
+    if marker.is_file() {
+        tokio::fs::remove_file(&marker).await?;
+    }
+    Ok(())
+}
+
+/// Get the images in the folder, hidden files such as in progress downloads are skipped
+async fn scan_folder(folder: &Path, location: ImageLocation) -> anyhow::Result<Vec<StoredImage>> {
+    if !folder.is_dir() {
+        return Ok(Vec::new());
+    }
+    let folder = normalise(folder).await;
+    let mut images = Vec::new();
+    let mut entries = tokio::fs::read_dir(&folder)
+        .await
+        .with_context(|| format!("reading images in {folder:?}"))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_image = path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| IMAGE_EXTENSIONS.contains(&e));
+        if name.starts_with('.') || !is_image {
+            continue;
+        }
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        images.push(StoredImage {
+            name,
+            path: path.to_string_lossy().to_string(),
+            location: location.clone(),
+            size_bytes: metadata.len(),
+            references: Vec::new(),
+        });
+    }
+    images.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(images)
+}
+
+/// Get the images referenced by the deployment's guests, this is the cloud image from the catalog,
+/// the reference image for existing disk and iso guests, and the guest's disks in the artefacts
+async fn deployment_references(
+    deployment: &Deployment,
+    artefact_images: &[StoredImage],
+) -> anyhow::Result<Vec<(PathBuf, ImageReference)>> {
+    let reference = |reason: String| ImageReference {
+        deployment: Some(deployment.name.clone()),
+        reason,
+    };
+    let reference_all = |reason: &str| artefact_images.iter()
+        .map(|i| (PathBuf::from(&i.path), reference(reason.to_string())))
+        .collect::<Vec<_>>();
+
+    if deployment.state == DeploymentState::Running {
+        return Ok(reference_all("orchestration is running"));
+    }
+    let state = match get_state_json(deployment.clone()).await {
+        Ok(state) => state,
+        Err(_) => return Ok(reference_all("deployment has no state")),
+    };
+    let project_location = PathBuf::from(&deployment.project_location);
+    let catalog = CloudImageCatalog::load(Some(&project_location))
+        .await
+        .with_context(|| format!("loading the cloud image catalog for deployment {}", deployment.name))?;
+
+    let mut references = Vec::new();
+    for guest in state.testbed_guests.0.values() {
+        let guest_name = &guest.guest_type.name;
+        if let GuestType::Libvirt(libvirt) = &guest.guest_type.guest_type {
+            if let LibvirtGuestOptions::CloudImage { name, .. } = &libvirt.libvirt_type {
+                if let Ok(cloud_image) = catalog.get(name) {
+                    let path = cloud_image.store_path(Path::new(TESTBED_IMAGES_FOLDER));
+                    references.push((normalise(&path).await, reference(format!("cloud image of guest {guest_name}"))));
+                }
+            }
+        }
+        let reference_image = guest.extra_info.reference_image.as_ref().map(PathBuf::from);
+        if let Some(reference_image) = &reference_image {
+            references.push((normalise(reference_image).await, reference(format!("reference image of guest {guest_name}"))));
+        }
+        // guest disks are prefixed by the guest name, apart from existing disks which keep the
+        // name of the reference image
+        let prefix = format!("{guest_name}-");
+        for image in artefact_images {
+            let is_copy_of_reference = reference_image.as_ref()
+                .and_then(|r| r.file_name())
+                .is_some_and(|r| r.to_string_lossy().eq(&image.name));
+            if image.name.starts_with(&prefix) || is_copy_of_reference {
+                references.push((PathBuf::from(&image.path), reference(format!("disk of guest {guest_name}"))));
+            }
+        }
+    }
+    Ok(references)
+}
+
+/// Get the backing file of a qcow2 image, or `None` if the file is not a qcow2 image or has no
+/// backing file. Relative backing files are relative to the image.
+async fn qcow2_backing_file(image: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let mut file = tokio::fs::File::open(image)
+        .await
+        .with_context(|| format!("opening image {image:?}"))?;
+    let mut header = [0u8; 20];
+    if file.read_exact(&mut header).await.is_err() {
+        return Ok(None);
+    }
+    let Some((offset, size)) = parse_qcow2_backing_file_location(&header) else {
+        return Ok(None);
+    };
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut backing_file = vec![0u8; size as usize];
+    file.read_exact(&mut backing_file)
+        .await
+        .with_context(|| format!("reading backing file name of {image:?}"))?;
+    let backing_file = PathBuf::from(String::from_utf8_lossy(&backing_file).to_string());
+    if backing_file.is_relative() {
+        let parent = image.parent().map(Path::to_path_buf).unwrap_or_default();
+        return Ok(Some(parent.join(backing_file)));
+    }
+    Ok(Some(backing_file))
+}
+
+/// Get the offset and size of the backing file name from the qcow2 header, the offset is a big
+/// endian u64 at byte 8 and the size is a big endian u32 at byte 16
+fn parse_qcow2_backing_file_location(header: &[u8; 20]) -> Option<(u64, u32)> {
+    if header[0..4] != QCOW2_MAGIC {
+        return None;
+    }
+    let offset = u64::from_be_bytes(header[8..16].try_into().ok()?);
+    let size = u32::from_be_bytes(header[16..20].try_into().ok()?);
+    if offset == 0 || size == 0 {
+        return None;
+    }
+    Some((offset, size))
+}
+
+/// Resolve symlinks and relative parts so paths to the same file can be compared, if the file
+/// does not exist the path is left as it is
+async fn normalise(path: &Path) -> PathBuf {
+    tokio::fs::canonicalize(path).await.unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_qcow2_backing_file_location() {
+        let mut header = [0u8; 20];
+        assert_eq!(parse_qcow2_backing_file_location(&header), None);
+        header[0..4].copy_from_slice(&QCOW2_MAGIC);
+        assert_eq!(parse_qcow2_backing_file_location(&header), None);
+        header[8..16].copy_from_slice(&512u64.to_be_bytes());
+        header[16..20].copy_from_slice(&42u32.to_be_bytes());
+        assert_eq!(parse_qcow2_backing_file_location(&header), Some((512, 42)));
+    }
+
+    #[tokio::test]
+    async fn test_remove_images_cleans_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let catalog_path = dir.path().join(CLOUD_IMAGE_CATALOG_FILE);
+        let mut catalog = CloudImageCatalog::default_images();
+        let mut stored = Vec::new();
+        for name in ["imported_a", "imported_b", "imported_c"] {
+            let path = dir.path().join(format!("{name}.img"));
+            tokio::fs::write(&path, name).await.unwrap();
+            catalog.images.push(CloudImage {
+                name: name.to_string(),
+                source: CloudImageSource::Path(path.clone()),
+                sha256: None,
+                sha256_url: None,
+                os_variant: None,
+                cloud_init: Default::default(),
+            });
+            stored.push(StoredImage {
+                name: format!("{name}.img"),
+                path: path.to_string_lossy().to_string(),
+                location: ImageLocation::Store,
+                size_bytes: name.len() as u64,
+                references: Vec::new(),
+            });
+        }
+        write_catalog(&catalog_path, &catalog).await.unwrap();
+
+        // as prune removes every unreferenced image at once
+        remove_images(&stored[..2], &catalog_path).await.unwrap();
+        let catalog = CloudImageCatalog::read(&catalog_path).await.unwrap();
+        assert!(catalog.get("imported_a").is_err());
+        assert!(catalog.get("imported_b").is_err());
+        assert!(catalog.get("imported_c").is_ok());
+        assert!(catalog.get("ubuntu_22_04").is_ok());
+        assert!(!Path::new(&stored[0].path).exists());
+        assert!(Path::new(&stored[2].path).exists());
+    }
+}
@@ -0,0 +1,132 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+use anyhow::{bail, Context};
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::AsyncReadExt;
+use tokio::sync::Mutex;
+use kvm_compose_lib::analysis_tools::live_capture::start_live_capture;
+use kvm_compose_lib::state::orchestration_tasks::get_orchestration_common;
+use kvm_compose_schemas::capture::LiveCaptureRequest;
+use crate::AppState;
+use crate::orchestration::websocket::process_potential_cancel_token;
+
+/// This function handles a live capture requested by the client. The client sends a
+/// `LiveCaptureRequest` and the capture is streamed back as binary messages of pcapng data until
+/// either the client disconnects, sends a cancellation token or the capture process ends.
+pub async fn handle_capture_socket(
+    socket: WebSocket,
+    db_config: Arc<AppState>,
+) {
+    match run_capture(socket, db_config).await {
+        Ok(_) => {
+            tracing::info!("end of live capture websocket, closing socket");
+        }
+        Err(err) => {
+            tracing::error!("live capture failed");
+            err.chain().for_each(|cause| tracing::error!("because: {}", cause));
+        }
+    }
+}
+
+async fn run_capture(
+    socket: WebSocket,
+    db_config: Arc<AppState>,
+) -> anyhow::Result<()> {
+    let (sender, mut receiver) = socket.split();
+    let sender = Arc::new(Mutex::new(sender));
+
+    let Some(Ok(Message::Binary(b))) = receiver.next().await
+        else {
+            bail!("did not get a live capture request from client");
+        };
+    let request: LiveCaptureRequest = serde_json::from_slice(&b)
+        .context("deserialising live capture request")?;
+    tracing::info!("starting live capture for {:?}", &request);
+
+    let capture = async {
+        let state = db_config.deployment_config_db
+            .read()
+            .await
+            .get_state(request.project_name.clone())
+            .await
+            .context("getting state from provider")?;
+        let kvm_compose_config = db_config.config_db
+            .read()
+            .await
+            .get_cluster_config()
+            .await
+            .context("getting testbed cluster config for live capture")?;
+        let common = get_orchestration_common(&state, false, false, false, kvm_compose_config).await?;
+        let capture = start_live_capture(&request.capture, &state, &common).await?;
+        anyhow::Ok((capture, common))
+    }.await;
+    let (mut capture, common) = match capture {
+        Ok(capture) => capture,
+        Err(err) => {
+            let _ = sender.lock().await.send(Message::Close(Some(CloseFrame {
+                code: 1011, // this is error
+                reason: Cow::from(format!("Could not start the capture: {err:#}")),
+            }))).await;
+            bail!(err);
+        }
+    };
+    let mut stdout = capture.child.stdout.take()
+        .context("getting the output of the capture process")?;
+
+    let mut buf = vec![0u8; 65536];
+    let mut capture_ended = false;
+    loop {
+        tokio::select! {
+            read = stdout.read(&mut buf) => {
+                let read = read.context("reading capture output")?;
+                if read == 0 {
+                    capture_ended = true;
+                    break;
+                }
+                if sender.lock().await.send(Message::Binary(buf[..read].to_vec())).await.is_err() {
+                    tracing::info!("client disconnected from live capture");
+                    break;
+                }
+            }
+            msg = receiver.next() => {
+                // the client only sends a close or a cancellation token during a capture, and if
+                // the connection dropped the capture must stop too
+                let close = match msg {
+                    Some(Ok(msg)) => process_potential_cancel_token(msg, sender.clone())
+                        .await
+                        .unwrap_or(true),
+                    _ => true,
+                };
+                if close {
+                    tracing::info!("client ended live capture");
+                    break;
+                }
+            }
+        }
+    }
+
+    if capture_ended {
+        // the capture process exited by itself, so tell the client why
+        capture.remove_pid_file(&common).await?;
+        let output = capture.child.wait_with_output().await.context("waiting for capture process")?;
+        let (code, reason) = if output.status.success() {
+            (1000, "Capture ended".to_string())
+        } else {
+            (1011, format!("Capture failed: {}", String::from_utf8_lossy(&output.stderr).trim()))
+        };
+        let _ = sender.lock().await.send(Message::Close(Some(CloseFrame {
+            code,
+            reason: Cow::from(reason),
+        }))).await;
+    } else {
+        // killing the local process does not stop dumpcap on the testbed host
+        capture.stop(&common).await.context("stopping capture")?;
+        // connection might already be closed by client
+        let _ = sender.lock().await.send(Message::Close(Some(CloseFrame {
+            code: 1000,
+            reason: Cow::from("Live capture stopped, connection closed"),
+        }))).await;
+    }
+    Ok(())
+}
@@ -6,6 +6,7 @@ use axum::response::IntoResponse;
 use axum_extra::headers::UserAgent;
 use crate::{AppError, AppState};
 use crate::gui::websocket::handle_gui_orchestration_socket;
+use crate::orchestration::capture::handle_capture_socket;
 use crate::orchestration::websocket::handle_orchestration_socket;
 
 pub async fn orchestration_websocket_handler(
@@ -30,6 +31,18 @@ pub async fn orchestration_websocket_handler(
     }))
 }
 
+/// Stream a live packet capture of a guest to the client, see `handle_capture_socket`
+pub async fn capture_websocket_handler(
+    State(db_config): State<Arc<AppState>>,
+    ws: WebSocketUpgrade,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> Result<impl IntoResponse, AppError> {
+    tracing::info!("live capture client connected, {addr}");
+
+    Ok(ws.on_upgrade(move |socket| {
+        handle_capture_socket(socket, db_config.clone())
+    }))
+}
 
 // pub async fn preflight_setup(
 //     State(db_config): State<Arc<AppState>>,
@@ -6,10 +6,12 @@ use crate::orchestration::handlers::*;
 
 pub mod handlers;
 pub mod websocket;
+pub mod capture;
 
 pub fn add_orchestration_handlers() -> Router<Arc<AppState>> {
     Router::new()
         // .route("/setup", post(preflight_setup))
         .route("/ws", get(orchestration_websocket_handler))
         .route("/gui", get(gui_orchestration_websocket_handler))
+        .route("/capture", get(capture_websocket_handler))
 }
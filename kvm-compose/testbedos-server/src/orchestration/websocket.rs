@@ -290,8 +290,13 @@ async fn run(
         }
         Err(err) => {
             tracing::error!("error in orchestration websocket: {err:#}");
-            // set state to failed with the deployment command attempted
-            deployment.state = DeploymentState::Failed(deployment_command);
+            // set state to failed with the deployment command attempted, unless the command did
+            // not change the testbed, the failure is then only reported to the client
+            deployment.state = if deployment_command.is_read_only() {
+                previous_state
+            } else {
+                DeploymentState::Failed(deployment_command)
+            };
             db_config.deployment_config_db
                 .write()
                 .await
@@ -414,7 +419,7 @@ async fn process_client_instruction(
     }
 }
 
-async fn process_potential_cancel_token(
+pub(crate) async fn process_potential_cancel_token(
     maybe_cancel_token: Message,
     loop_sender_cancel: Arc<Mutex<SplitSink<WebSocket, Message>>>,
 ) -> anyhow::Result<bool> {
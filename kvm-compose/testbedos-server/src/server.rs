@@ -1,5 +1,5 @@
 use std::process::exit;
-use axum::{Router, routing::{get, post}, ServiceExt};
+use axum::{Router, routing::{delete, get, post}, ServiceExt};
 use std::net::SocketAddr;
 use tokio::process::{Command};
 use std::sync::Arc;
@@ -25,6 +25,7 @@ use testbedos_lib::cluster::ovn::{set_up_cluster_client_check_cron_jobs, set_up_
 use testbedos_lib::config::db::get_cluster_config_db;
 use testbedos_lib::config::provider::TestbedConfigProvider;
 use testbedos_lib::gui::add_gui_handlers;
+use testbedos_lib::images::handlers::{delete_image, import_image, list_images, prune_images};
 use testbedos_lib::logging::setup_orchestration_log_cleanup;
 use testbedos_lib::orchestration::add_orchestration_handlers;
 use testbedos_lib::resource_monitoring::handlers::*;
@@ -195,6 +196,7 @@ pub fn main_app(app_state: Arc<AppState>) -> Router {
         .route("/api/cluster/:name", get(check_membership))
         .route("/api/validate/yaml", post(validate_yaml_endpoint))
         .route("/api/validate/projectname", post(validate_project_name_handler))
+        .route("/api/schema/yaml", get(get_yaml_schema))
         .route(
             "/api/deployments",
             get(list_deployments).post(create_deployment),
@@ -209,6 +211,10 @@ pub fn main_app(app_state: Arc<AppState>) -> Router {
         )
         // .route("/api/deployments/:name/action", post(action_deployment))
         .route("/api/deployments/:name/state", get(get_state).post(set_state))
+        .route("/api/images", get(list_images))
+        .route("/api/images/import", post(import_image))
+        .route("/api/images/prune", post(prune_images))
+        .route("/api/images/:name", delete(delete_image))
         .route("/api/metrics/prometheus/hosts", get(prometheus_scrape_endpoint_for_hosts))
         .route("/api/metrics/prometheus/libvirt", get(prometheus_scrape_endpoint_for_libvirt))
         .route("/api/metrics/prometheus/android", get(prometheus_scrape_endpoint_for_android))